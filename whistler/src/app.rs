@@ -1,28 +1,155 @@
 use iced::keyboard::Key;
 use iced::window;
-use iced::widget::{button, column, container, markdown, mouse_area, row, scrollable, stack, text, text_input};
-use iced::widget::text_editor::{Content, Action};
+use iced::widget::text_editor::{self, Edit};
+use iced::widget::{button, column, container, markdown, mouse_area, row, scrollable, text, text_input};
+use iced::futures::channel::mpsc;
+use iced::futures::{SinkExt, Stream, StreamExt};
 use iced::{Background, Color, Element, Event, Length, Subscription};
-use std::path::PathBuf;
-
+use lsp_types::error_codes::{CONTENT_MODIFIED, REQUEST_CANCELLED};
+use lsp_types::notification::{Notification, PublishDiagnostics};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, CodeActionRequest, CodeActionResolveRequest, Completion as CompletionRequest, DocumentSymbolRequest,
+    ExecuteCommand, Formatting as FormattingRequest, GotoDefinition, GotoImplementation, GotoTypeDefinition, HoverRequest, InlayHintRequest,
+    PrepareRenameRequest, RangeFormatting, References, Rename as RenameRequest, Request as _, ResolveCompletionItem,
+    SignatureHelpRequest,
+};
+use lsp_types::{
+    ApplyWorkspaceEditParams, CodeActionContext, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionTriggerKind, CompletionContext, CompletionParams, CompletionResponse,
+    CompletionTriggerKind, DocumentFormattingParams, DocumentSymbolParams, DocumentSymbolResponse, DocumentRangeFormattingParams, ExecuteCommandParams,
+    FormattingOptions, GotoDefinitionParams, GotoDefinitionResponse, HoverParams,
+    HoverProviderCapability, InlayHintParams, OneOf, PrepareRenameResponse, PublishDiagnosticsParams,
+    ReferenceContext, ReferenceParams, RenameParams, SignatureHelpContext, SignatureHelpParams,
+    SignatureHelpTriggerKind, TextDocumentIdentifier, TextDocumentPositionParams, Url, WorkspaceEdit,
+};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::completion::{
+    BufferWords, Completion, CompletionItem, CompletionKind, CompletionProvider, FilePaths, Request, ServerItems,
+};
+use crate::config::{self, Config};
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::diff::TextDiff;
+use crate::document::{byte_index, column_from_utf16, is_word_char, Action, Document, InlayHint, Position, TextEdit};
+use crate::hover::{Hover, Signature};
 use crate::message::Message;
-use crate::file_tree::FileTree;
+use crate::file_tree::{self, FileEntry, FileTree};
+use crate::format::{self, Formatting};
+use crate::git::{self, Hunk, Statuses};
+use crate::indent::{self, Indentation};
+use crate::lsp::{self, ResponseError, ServerId, Servers};
+use crate::outline::{self, Crumb, Symbol};
+use crate::refactor::{self, CodeActionMenu, EditPreview, FileChange, Rename};
+use crate::search::{self, SearchMatch};
+use crate::session::Session;
+use crate::snippets::{self, Expansion, Snippets};
+use crate::symbol_index::{self, IndexedSymbol};
 use crate::theme::*;
 use crate::ui::{
     create_editor, editor_container_style, empty_editor, file_finder_item_style,
-    file_finder_panel_style, search_input_style, search_panel_style, status_bar_style,
-    tab_bar_style, tab_button_style, tab_close_button_style, tree_button_style, view_sidebar,
+    file_finder_panel_style, highlight_code_blocks, search_input_style, search_panel_style,
+    status_bar_style, tab_bar_style, tab_button_style, tab_close_button_style, tree_button_style,
+    hunk_offset, view_breadcrumbs, view_code_actions, view_diff, view_edit_preview, view_rename, view_sidebar, CodeHighlights,
+    ExplorerView, FileMenu, GitLines, HistoryView, OutlineView, Popups, PreviewViewer, SidebarView, SourceControlView, symbol_icon,
 };
 
 #[derive(Debug)]
 pub enum TabKind {
     Editor {
-        content: Content,
+        content: Box<Document>,
         modified: bool,
     },
     Preview {
         md_items: Vec<markdown::Item>,
+        code_highlights: CodeHighlights,
     },
+    /// Two texts compared. A preview one shows what a workspace edit waiting to be applied
+    /// does to a file.
+    Diff {
+        diff: Box<TextDiff>,
+        scroll_id: iced::widget::Id,
+        preview: bool,
+    },
+}
+
+/// How long a file goes unedited before its outline is worked out again.
+const OUTLINE_DELAY: Duration = Duration::from_millis(300);
+
+/// How long a file goes unedited before where it can be folded is worked out again.
+const FOLDS_DELAY: Duration = Duration::from_millis(300);
+
+/// The keys that finish a Cmd+K chord, pressed with Cmd.
+const CHORD_KEYS: &[&str] = &["f", "j", "b", "0", "1", "2", "3", "4", "5", "6", "7"];
+
+/// How long a file goes unedited before who changed its lines is worked out again.
+const BLAME_DELAY: Duration = Duration::from_millis(500);
+
+/// How long the files on disk go without changing before the tree and git status are read again.
+const FILES_CHANGED_DELAY: Duration = Duration::from_millis(300);
+
+/// What's worked out again about a file once it's gone a moment without an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Refresh {
+    Outline,
+    Folds,
+    Blame,
+}
+
+impl Refresh {
+    fn delay(self) -> Duration {
+        match self {
+            Refresh::Outline => OUTLINE_DELAY,
+            Refresh::Folds => FOLDS_DELAY,
+            Refresh::Blame => BLAME_DELAY,
+        }
+    }
+
+    fn message(self, path: PathBuf) -> Message {
+        match self {
+            Refresh::Outline => Message::RefreshOutline(path),
+            Refresh::Folds => Message::RefreshFolds(path),
+            Refresh::Blame => Message::RefreshBlame(path),
+        }
+    }
+}
+
+/// How long formatting from a command can take. Saving waits `editor.formatOnSaveTimeout` instead.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a request to a language server was for, so its response can be put to use.
+enum LspRequest {
+    /// Suggestions for the cursor at `at`, and whether they were asked for with Ctrl+Space
+    Completion { path: PathBuf, at: Position, explicit: bool },
+    /// The rest of the selected completion item, sent as it was
+    ResolveCompletion(Box<lsp_types::CompletionItem>),
+    /// The edits that come with an item accepted before its details arrived
+    ResolveAccepted(PathBuf),
+    /// Where a symbol is defined or used: what's being looked for, like "References", and the symbol
+    Locations { label: &'static str, symbol: String },
+    /// What's known about the symbol at `at`, for the tooltip
+    Hover { path: PathBuf, at: Position },
+    /// The signature of the call the cursor is in
+    SignatureHelp { path: PathBuf },
+    /// The labels shown in between a file's text, for the version it had when they were asked for
+    InlayHints { path: PathBuf, version: i32 },
+    /// Whether the symbol at `at` can be renamed, and what it's called
+    PrepareRename { path: PathBuf, at: Position },
+    /// The edits that rename a symbol, previewed before they're made
+    Rename { title: String },
+    /// The actions on offer for the selection, for the menu
+    CodeActions,
+    /// The edit of an action that was picked before it came with one
+    ResolveCodeAction,
+    /// A server command, which sends back any edits it makes as a request of its own
+    ExecuteCommand,
+    /// The edits that format a file, or the selected lines of it
+    Format(Formatting),
+    /// The symbols in a file for its outline, as of the version they were asked for
+    DocumentSymbols { path: PathBuf, version: i32 },
 }
 
 #[derive(Debug)]
@@ -39,8 +166,56 @@ pub struct App {
     // Editor
     cursor_line: usize,
     cursor_col: usize,
+    config: Config,
+    session: Session,
+    snippets: Snippets,
+    chord_pending: bool, // Cmd+K was pressed and the next shortcut finishes the chord
+    indent_menu_visible: bool,
+    completion: Option<Completion>,
+    completion_docs: Vec<markdown::Item>, // The selected completion item's documentation
+    server_completions: Option<ServerItems>,
+    completion_request: Option<(ServerId, i64)>, // The request whose answer opens the popup
+    servers: Servers,
+    lsp_requests: HashMap<(ServerId, i64), LspRequest>,
+    diagnostics: Diagnostics,
+    hover: Option<(PathBuf, Hover)>, // What the language server said about the symbol under the mouse
+    hover_request: Option<(ServerId, i64)>, // The request whose answer shows the hover
+    signature: Option<(PathBuf, Signature)>, // The signature help for the call the cursor is in
+    pending_goto: Option<(PathBuf, Position)>, // Where to put the cursor once this file has opened
+    git_bases: HashMap<PathBuf, git::Base>, // What git has of each open file it tracks
+    git_changes: HashMap<PathBuf, Vec<Hunk>>, // How each of those differs from it now
+    hunk_view: Option<(PathBuf, usize)>, // The change whose marker was clicked, shown under it
+    git_blames: HashMap<PathBuf, git::Blame>, // Who last changed each line of each open file git tracks
+    blame_gutter: bool, // Whether the blame goes in the gutter, besides after the cursor's line
+    file_history: Option<(PathBuf, Option<Vec<git::FileCommit>>)>, // The commits that changed a file, if it's in a repository
+    // Rename and code actions
+    rename: Option<Rename>,
+    rename_input_id: iced::widget::Id,
+    edit_preview: Option<EditPreview>, // A workspace edit waiting to be applied or discarded
+    code_actions: Option<CodeActionMenu>,
+    code_action_input_id: iced::widget::Id,
+    // Problems panel
+    problems_visible: bool,
+    problems_filter: String,
+    problems_hidden: HashSet<Severity>,
     // Sidebar
     file_tree: Option<FileTree>,
+    git_status: Option<Statuses>, // None outside a git repository
+    files_changed: bool, // Files changed on disk and the tree and git status are about to be read again
+    refreshes_due: HashMap<(PathBuf, Refresh), Instant>, // When each refresh waiting for a file to go unedited is due
+    sidebar_view: SidebarView,
+    outlines: HashMap<PathBuf, Vec<Symbol>>, // The symbols in each open file
+    breadcrumb_menu: Option<(usize, Vec<Crumb>)>, // The breadcrumb whose dropdown is open, and what it lists
+    file_menu: Option<PathBuf>, // The file in the tree whose right-click menu is open
+    compare_selected: Option<PathBuf>, // The file picked to compare another with
+    // Source control
+    commit_message: text_editor::Content,
+    amend: bool,
+    scm_diffs: HashMap<(PathBuf, bool), git::FileDiff>, // The files opened up to show their staged or unstaged hunks
+    branch_menu: bool,
+    new_branch: Option<String>, // The name of the branch being created, while it's typed
+    new_branch_input_id: iced::widget::Id,
+    scm_error: Option<String>, // Why the last thing done in the Source Control view failed
     sidebar_visible: bool,
     sidebar_width: f32,
     resizing_sidebar: bool,
@@ -50,13 +225,16 @@ pub struct App {
     search_visible: bool,
     search_query: String,
     search_results: Vec<crate::search::SearchResult>,
+    search_title: Option<String>, // Shown instead of the query when the results are a symbol's locations
     search_input_id: iced::widget::Id,
     // File finder
     file_finder_visible: bool,
     file_finder_query: String,
     file_finder_results: Vec<(i64, String, PathBuf)>,
+    symbol_finder_results: Vec<IndexedSymbol>, // What a `#` query matched, instead of files
     file_finder_selected: usize,
     all_workspace_files: Vec<(String, PathBuf)>,
+    symbol_index: Vec<IndexedSymbol>, // The definitions in the workspace, found in the background
     recent_files: Vec<PathBuf>,
     file_finder_input_id: iced::widget::Id,
 }
@@ -68,7 +246,52 @@ impl Default for App {
             active_tab: None,
             cursor_line: 1,
             cursor_col: 1,
+            config: Config::load(),
+            session: Session::load(),
+            snippets: Snippets::load(None),
+            chord_pending: false,
+            indent_menu_visible: false,
+            completion: None,
+            completion_docs: Vec::new(),
+            server_completions: None,
+            completion_request: None,
+            servers: Servers::default(),
+            lsp_requests: HashMap::new(),
+            diagnostics: Diagnostics::default(),
+            hover: None,
+            hover_request: None,
+            signature: None,
+            pending_goto: None,
+            git_bases: HashMap::new(),
+            git_changes: HashMap::new(),
+            hunk_view: None,
+            git_blames: HashMap::new(),
+            blame_gutter: false,
+            file_history: None,
+            rename: None,
+            rename_input_id: iced::widget::Id::unique(),
+            edit_preview: None,
+            code_actions: None,
+            code_action_input_id: iced::widget::Id::unique(),
+            problems_visible: false,
+            problems_filter: String::new(),
+            problems_hidden: HashSet::new(),
             file_tree: None,
+            git_status: None,
+            files_changed: false,
+            refreshes_due: HashMap::new(),
+            sidebar_view: SidebarView::default(),
+            outlines: HashMap::new(),
+            breadcrumb_menu: None,
+            file_menu: None,
+            compare_selected: None,
+            commit_message: text_editor::Content::new(),
+            amend: false,
+            scm_diffs: HashMap::new(),
+            branch_menu: false,
+            new_branch: None,
+            new_branch_input_id: iced::widget::Id::unique(),
+            scm_error: None,
             sidebar_visible: true,
             sidebar_width: SIDEBAR_DEFAULT_WIDTH,
             resizing_sidebar: false,
@@ -77,12 +300,15 @@ impl Default for App {
             search_visible: false,
             search_query: String::new(),
             search_results: Vec::new(),
+            search_title: None,
             search_input_id: iced::widget::Id::unique(),
             file_finder_visible: false,
             file_finder_query: String::new(),
             file_finder_results: Vec::new(),
+            symbol_finder_results: Vec::new(),
             file_finder_selected: 0,
             all_workspace_files: Vec::new(),
+            symbol_index: Vec::new(),
             recent_files: Vec::new(),
             file_finder_input_id: iced::widget::Id::unique(),
         }
@@ -95,22 +321,76 @@ impl App {
             // The below section basically just creates "instances" for each message,
             // declaring the actual action that each of them does.
             Message::EditorAction(action) => { // This one records a keystroke in the editor
+                // Tab after a snippet prefix expands the snippet instead of indenting
+                let action = match action {
+                    Action::Edit(Edit::Indent) => self.snippet_at_cursor().unwrap_or(action),
+                    action => action,
+                };
+                // Typing a word or a path opens the completion popup, and typing or deleting keeps
+                // it up to date. Scrolling leaves it alone and anything else closes it.
+                let completing = self.completion.is_some();
+                let refresh = match &action {
+                    Action::Edit(Edit::Insert(c)) => completing || is_word_char(*c) || *c == '/',
+                    Action::Edit(Edit::Backspace) => completing,
+                    _ => false,
+                };
+                // So does a char the language server asks to be told about, like `.`
+                let trigger = match &action {
+                    Action::Edit(Edit::Insert(c)) if self.is_trigger_character(*c) => Some(*c),
+                    _ => None,
+                };
+                let keep = matches!(action, Action::Scroll { .. } | Action::ScrollTo { .. });
+                // Signature help opens on chars like `(` and follows the cursor around while it's open
+                let signature_trigger = match &action {
+                    Action::Edit(Edit::Insert(c)) if self.is_signature_trigger(*c) => Some(*c),
+                    _ => None,
+                };
+                let follow_signature = !keep && self.signature.is_some();
+                let is_edit = action.is_edit();
+                if !keep {
+                    self.close_hover();
+                    self.breadcrumb_menu = None;
+                }
                 if let Some(idx) = self.active_tab {
-                    if let Some(tab) = self.tabs.get_mut(idx) {
-                        if let TabKind::Editor { ref mut content, ref mut modified } = tab.kind {
-                            let action = match action {
-                            Action::Scroll { lines } => Action::Scroll { lines: lines / 5},
-                            other => other,
-                        };
-                        let _ = content.perform(action);
-                        *modified = true;
-                        let cursor = content.cursor();
-                        self.cursor_line = cursor.position.line + 1;
-                        self.cursor_col = cursor.position.column + 1;
+                    if let Some(tab) = self.tabs.get_mut(idx)
+                        && let TabKind::Editor { ref mut content, ref mut modified } = tab.kind
+                    {
+                        if action.is_edit() {
+                            *modified = true;
                         }
+                        let is_fold = action.is_fold();
+                        content.perform(action);
+                        if is_fold {
+                            self.session.set_folds(tab.path.clone(), content.folded_lines());
+                            self.session.save();
+                        }
+                        let cursor = content.cursor();
+                        self.cursor_line = cursor.line + 1;
+                        self.cursor_col = cursor.column + 1;
                     }
+                    self.sync_tab(idx);
+                }
+                if refresh || trigger.is_some() {
+                    self.completion = self.complete(false);
+                    self.request_completion(trigger, false);
+                    self.show_completion_details();
+                } else if !keep {
+                    self.close_completion();
+                }
+                if signature_trigger.is_some() || follow_signature {
+                    self.request_signature_help(signature_trigger, follow_signature);
+                }
+                match (is_edit, self.active_tab.and_then(|idx| self.tabs.get(idx))) {
+                    (true, Some(tab)) => {
+                        let path = tab.path.clone();
+                        iced::Task::batch([
+                            self.refresh_later(path.clone(), Refresh::Outline),
+                            self.refresh_later(path.clone(), Refresh::Folds),
+                            self.refresh_later(path, Refresh::Blame),
+                        ])
+                    }
+                    _ => iced::Task::none(),
                 }
-                iced::Task::none()
             }
             Message::FolderToggled(path) => { // Checks if a folder was clicked
                 if let Some(ref mut tree) = self.file_tree {
@@ -138,7 +418,8 @@ impl App {
             }
             Message::TabClosed(idx) => {  // To close a tab using the "x" button
                 if idx < self.tabs.len() {
-                    self.tabs.remove(idx); // Just removes a tab at that index
+                    let tab = self.tabs.remove(idx); // Just removes a tab at that index
+                    self.close_tab(&tab);
                     if self.tabs.is_empty() {
                         self.active_tab = None; // Avoid errors by setting active tab to none if none exist
                     } else if let Some(active) = self.active_tab {
@@ -153,7 +434,8 @@ impl App {
             }
             Message::CloseActiveTab => { // Closes only the active tab (this is only used once in the code for the keyboard shortcut)
                 if let Some(idx) = self.active_tab {
-                    self.tabs.remove(idx);
+                    let tab = self.tabs.remove(idx);
+                    self.close_tab(&tab);
                     if self.tabs.is_empty() {
                         self.active_tab = None; // If there are no tabs, set active tab to none to avoid errors
                     } else if idx >= self.tabs.len() {
@@ -173,22 +455,34 @@ impl App {
                     .unwrap_or_default()
                     .to_string_lossy()
                     .to_string();
+                let ext = path.extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("");
+                let mut document = Document::with_text(&content, ext);
+                let settings = self.config.editor_for(ext);
+                let detected = settings.detect_indentation.then(|| indent::detect(document.lines())).flatten();
+                document.set_indentation(detected.unwrap_or_else(|| settings.indentation()));
+                if let Some(folds) = self.session.folds.get(&path) {
+                    document.restore_folds(folds); // Folds the same regions as last time the file was open
+                }
                 self.tabs.push(Tab {
-                    path,
+                    path: path.clone(),
                     name,
                     kind: TabKind::Editor {
-                        content: Content::with_text(&content),
+                        content: Box::new(document),
                         modified: false,
                     },
                 });
                 self.active_tab = Some(self.tabs.len() - 1);
-                iced::Task::none()
+                self.open_tab(self.tabs.len() - 1);
+                self.refresh_outline(self.tabs.len() - 1);
+                iced::Task::batch([load_git_base(path), self.go_to_pending(), self.load_history(false)])
             }
             Message::TabSelected(idx) => {
                 if idx < self.tabs.len() {
                     self.active_tab = Some(idx);
                 }
-                iced::Task::none()
+                self.load_history(false)
             }
             Message::FileTreeRefresh => {
                 if let Some(ref mut tree) = self.file_tree {
@@ -196,6 +490,348 @@ impl App {
                 }
                 iced::Task::none()
             }
+            Message::FilesChanged => {
+                // Saving or checking out touches lots of files at once, so wait for it to be over
+                if self.files_changed {
+                    return iced::Task::none();
+                }
+                self.files_changed = true;
+                delay(FILES_CHANGED_DELAY, Message::RefreshGitStatus)
+            }
+            Message::RefreshGitStatus => {
+                self.files_changed = false;
+                let Some(ref mut tree) = self.file_tree else { return iced::Task::none(); };
+                tree.refresh();
+                load_git_status(tree.root.clone())
+            }
+            Message::GitStatusLoaded(root, statuses) => {
+                if self.file_tree.as_ref().is_none_or(|tree| tree.root != root) {
+                    return iced::Task::none();
+                }
+                self.git_status = statuses;
+                // The hunks shown in the Source Control view, for the files that still have them
+                let listed = |path: &PathBuf, staged: bool| {
+                    let files = self.git_status.as_ref().map(|status| if staged { &status.staged } else { &status.unstaged });
+                    files.is_some_and(|files| files.iter().any(|(file, _)| file == path))
+                };
+                let shown: Vec<(PathBuf, bool)> = self.scm_diffs.keys().filter(|(path, staged)| listed(path, *staged)).cloned().collect();
+                self.scm_diffs = shown
+                    .into_iter()
+                    .filter_map(|(path, staged)| Some(((path.clone(), staged), git::diff(&path, staged).ok()?)))
+                    .collect();
+                // Staging or committing changes what the open files are compared with
+                let open: HashSet<PathBuf> = self
+                    .tabs
+                    .iter()
+                    .filter(|tab| matches!(tab.kind, TabKind::Editor { .. }))
+                    .map(|tab| tab.path.clone())
+                    .collect();
+                let history = self.load_history(true);
+                iced::Task::batch(open.into_iter().map(load_git_base).chain([history]))
+            }
+            Message::GitBaseLoaded(path, base) => {
+                if !self.tabs.iter().any(|tab| tab.path == path && matches!(tab.kind, TabKind::Editor { .. })) {
+                    return iced::Task::none();
+                }
+                let tracked = base.is_some();
+                match base {
+                    Some(base) => self.git_bases.insert(path.clone(), base),
+                    None => self.git_bases.remove(&path),
+                };
+                self.update_changes(&path);
+                // Staging and committing change who the lines are blamed on too
+                if !tracked {
+                    self.git_blames.remove(&path);
+                    return iced::Task::none();
+                }
+                self.load_blame(&path)
+            }
+            Message::RefreshBlame(path) => {
+                if let Some(wait) = self.wait_for_edits(&path, Refresh::Blame) {
+                    return wait;
+                }
+                self.load_blame(&path)
+            }
+            Message::BlameLoaded(path, version, blame) => {
+                // Lines have moved since, so it'd be blaming the wrong ones
+                if self.document_at(&path).is_none_or(|document| document.version() != version) {
+                    return iced::Task::none();
+                }
+                match blame {
+                    Some(blame) => self.git_blames.insert(path, blame),
+                    None => self.git_blames.remove(&path),
+                };
+                iced::Task::none()
+            }
+            Message::ToggleBlame => {
+                self.blame_gutter = !self.blame_gutter;
+                iced::Task::none()
+            }
+            Message::HistoryLoaded(path, commits) => {
+                self.file_history = Some((path, commits));
+                iced::Task::none()
+            }
+            Message::HistoryCommitChosen(id) => {
+                let Some((path, _)) = &self.file_history else { return iced::Task::none(); };
+                let path = path.clone();
+                let (before, after) = match git::commit_change(&path, &id) {
+                    Ok(change) => change,
+                    Err(e) => {
+                        eprintln!("Failed to read {} at {}: {}", path.display(), &id[..7], e);
+                        return iced::Task::none();
+                    }
+                };
+                let short = &id[..7];
+                let name = format!("{} ({})", file_name(&path), short);
+                let diff = TextDiff::new((format!("{}^", short), &before), (short.to_string(), &after), &extension(&path));
+                self.open_diff(path, name, diff, false);
+                iced::Task::none()
+            }
+            Message::ShowHunk(line) => {
+                let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return iced::Task::none(); };
+                let hunks = self.git_changes.get(&tab.path).map(Vec::as_slice).unwrap_or_default();
+                let Some(index) = hunks.iter().position(|hunk| hunk.is_on(line)) else { return iced::Task::none(); };
+                // Clicking the marker again closes it
+                let shown = Some((tab.path.clone(), index));
+                self.hunk_view = if self.hunk_view == shown { None } else { shown };
+                self.close_hover();
+                iced::Task::none()
+            }
+            Message::RevertHunk => {
+                let Some((path, index)) = self.hunk_view.take() else { return iced::Task::none(); };
+                let Some(edit) = self.revert_edit(&path, index) else { return iced::Task::none(); };
+                self.edit_file(&path, vec![edit]);
+                iced::Task::none()
+            }
+            Message::StageHunk => {
+                let Some((path, index)) = self.hunk_view.take() else { return iced::Task::none(); };
+                let document = self.tabs.iter().find_map(|tab| match &tab.kind {
+                    TabKind::Editor { content, .. } if tab.path == path => Some(content),
+                    _ => None,
+                });
+                let (Some(document), Some(base), Some(hunk)) =
+                    (document, self.git_bases.get(&path), self.git_changes.get(&path).and_then(|hunks| hunks.get(index)))
+                else {
+                    return iced::Task::none();
+                };
+                if let Err(e) = git::stage_hunk(&path, base, hunk, document.lines()) {
+                    eprintln!("Failed to stage the change: {}", e);
+                    return iced::Task::none();
+                }
+                match &self.file_tree {
+                    Some(tree) => load_git_status(tree.root.clone()),
+                    None => load_git_base(path),
+                }
+            }
+            Message::CloseHunk => {
+                self.hunk_view = None;
+                iced::Task::none()
+            }
+            Message::NextChange if self.active_diff().is_some() => self.go_to_hunk(true),
+            Message::PreviousChange if self.active_diff().is_some() => self.go_to_hunk(false),
+            Message::NextChange => self.go_to_change(true),
+            Message::PreviousChange => self.go_to_change(false),
+            Message::FileMenuToggled(path) => {
+                self.file_menu = path;
+                iced::Task::none()
+            }
+            Message::CompareWithSaved(path) => {
+                self.file_menu = None;
+                let saved = match std::fs::read_to_string(&path) {
+                    Ok(saved) => saved,
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", path.display(), e);
+                        return iced::Task::none();
+                    }
+                };
+                let current = self.document_at(&path).map(Document::text).unwrap_or_else(|| saved.clone());
+                let name = file_name(&path);
+                let diff = TextDiff::new(("Saved".to_string(), &saved), ("Current".to_string(), &current), &extension(&path));
+                self.open_diff(path, format!("{} (Saved ↔ Current)", name), diff, false);
+                iced::Task::none()
+            }
+            Message::SelectForCompare(path) => {
+                self.file_menu = None;
+                self.compare_selected = Some(path);
+                iced::Task::none()
+            }
+            Message::CompareWithSelected(path) => {
+                self.file_menu = None;
+                let Some(selected) = self.compare_selected.clone() else { return iced::Task::none(); };
+                let (Some(old), Some(new)) = (self.current_text(&selected), self.current_text(&path)) else {
+                    return iced::Task::none();
+                };
+                let (old_name, new_name) = (file_name(&selected), file_name(&path));
+                let name = format!("{} ↔ {}", old_name, new_name);
+                let diff = TextDiff::new((old_name, &old), (new_name, &new), &extension(&path));
+                self.open_diff(path, name, diff, false);
+                iced::Task::none()
+            }
+            Message::OpenChanges(path, staged) => {
+                self.file_menu = None;
+                let changes = match git::diff(&path, staged) {
+                    Ok(changes) => changes,
+                    Err(e) => {
+                        eprintln!("Failed to read the changes to {}: {}", path.display(), e);
+                        return iced::Task::none();
+                    }
+                };
+                let (old, new) = if staged { ("HEAD", "Index") } else { ("Index", "Working Tree") };
+                let name = format!("{} ({})", file_name(&path), new);
+                let diff = TextDiff::new(
+                    (old.to_string(), &changes.old.lines.join("\n")),
+                    (new.to_string(), &changes.new.lines.join("\n")),
+                    &extension(&path),
+                );
+                self.open_diff(path, name, diff, false);
+                iced::Task::none()
+            }
+            Message::PreviewFileDiff(path) => {
+                let Some(preview) = &self.edit_preview else { return iced::Task::none(); };
+                let edits = preview.changes.iter().find_map(|change| match change {
+                    FileChange::Edit { path: edited, edits } if *edited == path => Some(edits.clone()),
+                    _ => None,
+                });
+                let (Some(edits), Some(before)) = (edits, self.current_text(&path)) else { return iced::Task::none(); };
+                let title = preview.title.clone();
+                let mut after = Document::with_text(&before, "");
+                let edits = edits.into_iter().map(|edit| TextEdit::from_lsp(edit, &after)).collect();
+                after.perform(Action::ApplyEdits(edits));
+                let name = format!("{} (Preview)", file_name(&path));
+                let diff = TextDiff::new(("Current".to_string(), &before), (title, &after.text()), &extension(&path));
+                self.open_diff(path, name, diff, true);
+                iced::Task::none()
+            }
+            Message::DiffLayoutToggled | Message::DiffUnchangedToggled => {
+                let Some(TabKind::Diff { diff, .. }) = self.active_tab.and_then(|idx| self.tabs.get_mut(idx)).map(|tab| &mut tab.kind) else {
+                    return iced::Task::none();
+                };
+                match message {
+                    Message::DiffLayoutToggled => diff.side_by_side = !diff.side_by_side,
+                    _ => diff.collapsed = !diff.collapsed,
+                }
+                // The rows moved, so the hunk that was being looked at goes back in view
+                self.scroll_to_hunk()
+            }
+            Message::FileDiffToggled(path, staged) => {
+                let key = (path, staged);
+                if self.scm_diffs.remove(&key).is_some() {
+                    return iced::Task::none();
+                }
+                match git::diff(&key.0, staged) {
+                    Ok(diff) => {
+                        self.scm_diffs.insert(key, diff);
+                    }
+                    Err(e) => self.scm_error = Some(e.message().to_string()),
+                }
+                iced::Task::none()
+            }
+            Message::StageFile(path) => self.after_git(git::stage(&path)),
+            Message::UnstageFile(path) => self.after_git(git::unstage(&path)),
+            Message::StageAll => {
+                let files = self.git_status.as_ref().map(|status| status.unstaged.as_slice()).unwrap_or_default();
+                let result = files.iter().try_for_each(|(path, _)| git::stage(path));
+                self.after_git(result)
+            }
+            Message::UnstageAll => {
+                let files = self.git_status.as_ref().map(|status| status.staged.as_slice()).unwrap_or_default();
+                let result = files.iter().try_for_each(|(path, _)| git::unstage(path));
+                self.after_git(result)
+            }
+            Message::StageFileHunk(path, index) => {
+                let Some(diff) = self.scm_diffs.get(&(path.clone(), false)) else { return iced::Task::none(); };
+                let Some(hunk) = diff.hunks.get(index) else { return iced::Task::none(); };
+                let result = git::stage_hunk(&path, &diff.old, hunk, &diff.new.lines);
+                self.after_git(result)
+            }
+            Message::UnstageFileHunk(path, index) => {
+                let Some(diff) = self.scm_diffs.get(&(path.clone(), true)) else { return iced::Task::none(); };
+                let Some(hunk) = diff.hunks.get(index) else { return iced::Task::none(); };
+                let result = git::unstage_hunk(&path, diff, hunk);
+                self.after_git(result)
+            }
+            Message::DiscardFile(path) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                let confirm = rfd::AsyncMessageDialog::new()
+                    .set_level(rfd::MessageLevel::Warning)
+                    .set_title("Discard Changes")
+                    .set_description(format!("Discard the changes to {}? They can't be got back.", name))
+                    .set_buttons(rfd::MessageButtons::OkCancel)
+                    .show();
+                iced::Task::perform(confirm, move |result| {
+                    matches!(result, rfd::MessageDialogResult::Ok | rfd::MessageDialogResult::Yes).then(|| path.clone())
+                })
+                .and_then(|path| iced::Task::done(Message::DiscardConfirmed(path)))
+            }
+            Message::DiscardConfirmed(path) => {
+                let result = git::discard(&path);
+                if result.is_ok() {
+                    self.reload_tabs(Some(&path));
+                }
+                self.after_git(result)
+            }
+            Message::CommitMessageEdited(action) => {
+                self.commit_message.perform(action);
+                iced::Task::none()
+            }
+            Message::AmendToggled(amend) => {
+                self.amend = amend;
+                // Starts from the last commit's message, the way `git commit --amend` does
+                if amend && self.commit_message.text().trim().is_empty()
+                    && let Some(message) = self.git_root().and_then(|root| git::last_message(&root))
+                {
+                    self.commit_message = text_editor::Content::with_text(&message);
+                }
+                iced::Task::none()
+            }
+            Message::Commit => {
+                let Some(root) = self.git_root() else { return iced::Task::none(); };
+                let result = git::commit(&root, &self.commit_message.text(), self.amend);
+                if result.is_ok() {
+                    self.commit_message = text_editor::Content::new();
+                    self.amend = false;
+                }
+                self.after_git(result)
+            }
+            Message::BranchMenuToggled => {
+                self.branch_menu = !self.branch_menu;
+                iced::Task::none()
+            }
+            Message::BranchChosen(name) => {
+                self.branch_menu = false;
+                let Some(root) = self.git_root() else { return iced::Task::none(); };
+                if self.git_status.as_ref().is_some_and(|status| status.head.as_ref() == Some(&name)) {
+                    return iced::Task::none();
+                }
+                let result = git::switch_branch(&root, &name);
+                if result.is_ok() {
+                    self.reload_tabs(None);
+                }
+                self.after_git(result)
+            }
+            Message::NewBranchStarted => {
+                self.branch_menu = false;
+                self.new_branch = Some(String::new());
+                iced::widget::operation::focus(self.new_branch_input_id.clone())
+            }
+            Message::NewBranchNameChanged(name) => {
+                self.new_branch = Some(name);
+                iced::Task::none()
+            }
+            Message::CreateBranch => {
+                let Some(name) = self.new_branch.take() else { return iced::Task::none(); };
+                let (Some(root), name) = (self.git_root(), name.trim()) else { return iced::Task::none(); };
+                if name.is_empty() {
+                    return iced::Task::none();
+                }
+                let result = git::create_branch(&root, name);
+                self.after_git(result)
+            }
+            Message::InitRepository => {
+                let Some(root) = self.git_root() else { return iced::Task::none(); };
+                let result = git::init(&root);
+                self.after_git(result)
+            }
             Message::OpenFolderDialog => {
                 iced::Task::perform(
                     async {
@@ -215,39 +851,54 @@ impl App {
             }
             Message::FolderOpened(path) => {
                 self.file_tree = Some(FileTree::new(path.clone()));
+                self.snippets = Snippets::load(Some(&path)); // Picks up the workspace's own snippets
                 self.all_workspace_files = crate::search::collect_all_files(&path);
+                self.symbol_index.clear();
+                self.git_status = None;
+                // The servers were started for the old workspace, so start over in the new one
+                self.servers.shutdown_all();
+                self.diagnostics.clear();
+                for idx in 0..self.tabs.len() {
+                    self.open_tab(idx);
+                }
+                iced::Task::batch([index_symbols(path.clone()), load_git_status(path)])
+            }
+            Message::SymbolsIndexed(path, symbols) => {
+                // Unless it was for a folder that's been closed since
+                if self.file_tree.as_ref().is_some_and(|tree| path.starts_with(&tree.root)) {
+                    self.symbol_index.retain(|symbol| !symbol.path.starts_with(&path));
+                    self.symbol_index.extend(symbols);
+                }
                 iced::Task::none()
             }
             Message::SaveFile => {
-                if let Some(idx) = self.active_tab {
-                    if let Some(tab) = self.tabs.get(idx) {
-                        if let TabKind::Editor { ref content, .. } = tab.kind {
-                            let path = tab.path.clone();
-                        let content = content.text();
-                        return iced::Task::perform(
-                            async move {
-                                std::fs::write(&path, content)
-                                    .map_err(|e| e.to_string())
-                            },
-                            Message::FileSaved,
-                        );
-                        }
-                    }
+                let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return iced::Task::none(); };
+                let ext = tab.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if self.config.editor_for(ext).format_on_save {
+                    // Formatting saves the file once it's done, or once it's given up
+                    return self.format(None, true);
                 }
-                iced::Task::none()
+                let path = tab.path.clone();
+                self.save_file(&path)
             }
 
-            Message::FileSaved(result) => {
+            Message::FileSaved(path, result) => {
                 if let Err(e) = result {
                     eprintln!("Failed to save file: {}", e);
-                } else if let Some(idx) = self.active_tab {
-                    if let Some(tab) = self.tabs.get_mut(idx){
-                        if let TabKind::Editor { ref mut modified, .. } = tab.kind {
-                            *modified = false;
-                        }
+                    return iced::Task::none();
+                }
+                for tab in self.tabs.iter_mut().filter(|tab| tab.path == path) {
+                    if let TabKind::Editor { ref mut modified, .. } = tab.kind {
+                        *modified = false;
                     }
                 }
-                iced::Task::none()
+                // Its definitions and where it stands with git may have changed
+                match &self.file_tree {
+                    Some(tree) if path.starts_with(&tree.root) => {
+                        iced::Task::batch([index_symbols(path), load_git_status(tree.root.clone())])
+                    }
+                    _ => iced::Task::none(),
+                }
             }
 
             Message::SidebarResizeStart => {
@@ -286,21 +937,21 @@ impl App {
             }
 
             Message::PreviewMarkdown => {
-                if let Some(idx) = self.active_tab {
-                    if let Some(tab) = self.tabs.get(idx) {
-                        if let TabKind::Editor { ref content, .. } = tab.kind {
-                            let text = content.text();
-                            let md_items: Vec<markdown::Item> = markdown::parse(&text).collect();
-                            let preview_name = format!("Preview: {}", tab.name);
-                            let path = tab.path.clone();
-                            self.tabs.push(Tab {
-                                path,
-                                name: preview_name,
-                                kind: TabKind::Preview { md_items },
-                            });
-                            self.active_tab = Some(self.tabs.len() - 1);
-                        }
-                    }
+                if let Some(idx) = self.active_tab
+                    && let Some(tab) = self.tabs.get(idx)
+                    && let TabKind::Editor { ref content, .. } = tab.kind
+                {
+                    let text = content.text();
+                    let md_items: Vec<markdown::Item> = markdown::parse(&text).collect();
+                    let code_highlights = highlight_code_blocks(&md_items);
+                    let preview_name = format!("Preview: {}", tab.name);
+                    let path = tab.path.clone();
+                    self.tabs.push(Tab {
+                        path,
+                        name: preview_name,
+                        kind: TabKind::Preview { md_items, code_highlights },
+                    });
+                    self.active_tab = Some(self.tabs.len() - 1);
                 }
                 iced::Task::none()
             }
@@ -310,11 +961,14 @@ impl App {
             }
 
             Message::ToggleSearch => {
-                if self.search_visible {
+                if self.search_visible && self.search_title.is_none() {
                     self.search_visible = false;
                     self.search_query.clear();
                     self.search_results.clear();
                 } else {
+                    // Locations from a language server make way for a search
+                    self.search_title = None;
+                    self.search_results.clear();
                     self.search_visible = true;
                     return iced::widget::operation::focus(self.search_input_id.clone());
                 }
@@ -347,26 +1001,12 @@ impl App {
                 iced::Task::none()
             }
 
-            Message::SearchResultClicked(path, _line_number) => {
+            Message::SearchResultClicked(path, position) => {
                 self.search_visible = false;
                 self.search_query.clear();
                 self.search_results.clear();
-
-                if let Some(ref mut tree) = self.file_tree {
-                    tree.select(path.clone());
-                }
-                if let Some(idx) = self.tabs.iter().position(|t| t.path == path) {
-                    self.active_tab = Some(idx);
-                    return iced::Task::none();
-                }
-                iced::Task::perform(
-                    async move {
-                        let content = std::fs::read_to_string(&path)
-                            .unwrap_or_else(|_| String::from("Could not read file"));
-                        (path, content)
-                    },
-                    |(path, content)| Message::FileOpened(path, content),
-                )
+                self.search_title = None;
+                self.open_at(path, position)
             }
 
             Message::ToggleFileFinder => {
@@ -374,6 +1014,7 @@ impl App {
                 if !self.file_finder_visible {
                     self.file_finder_query.clear();
                     self.file_finder_results.clear();
+                    self.symbol_finder_results.clear();
                     self.file_finder_selected = 0;
                     return iced::Task::none();
                 }
@@ -384,8 +1025,15 @@ impl App {
                 self.file_finder_query = query.clone();
                 self.file_finder_selected = 0;
 
+                self.symbol_finder_results.clear();
                 if query.is_empty() {
                     self.file_finder_results.clear();
+                } else if let Some(symbol) = query.strip_prefix('#') {
+                    self.file_finder_results.clear();
+                    self.symbol_finder_results = crate::search::fuzzy_rank(symbol, &self.symbol_index, |symbol| &symbol.name, 20)
+                        .into_iter()
+                        .map(|(_, symbol)| symbol.clone())
+                        .collect();
                 } else {
                     self.file_finder_results = crate::search::fuzzy_find_files(
                         &query,
@@ -397,11 +1045,18 @@ impl App {
             }
 
             Message::FileFinderNavigate(delta) => {
+                // The arrows move through the code action menu too, while it's open
+                if let Some(menu) = &mut self.code_actions {
+                    menu.navigate(delta);
+                    return iced::Task::none();
+                }
                 if !self.file_finder_visible {
                     return iced::Task::none();
                 }
                 let count = if self.file_finder_query.is_empty() {
                     self.recent_files.len()
+                } else if self.file_finder_query.starts_with('#') {
+                    self.symbol_finder_results.len()
                 } else {
                     self.file_finder_results.len()
                 };
@@ -415,10 +1070,17 @@ impl App {
             }
 
             Message::FileFinderSelect => {
+                if let Some(menu) = &self.code_actions {
+                    return match menu.selected_action() {
+                        Some(index) => self.update(Message::CodeActionChosen(index)),
+                        None => iced::Task::none(),
+                    };
+                }
                 if !self.file_finder_visible {
                     return iced::Task::none();
                 }
 
+                let symbol = self.symbol_finder_results.get(self.file_finder_selected).cloned();
                 let path = if self.file_finder_query.is_empty() {
                     self.recent_files.get(self.file_finder_selected).cloned()
                 } else {
@@ -430,102 +1092,530 @@ impl App {
                 self.file_finder_visible = false;
                 self.file_finder_query.clear();
                 self.file_finder_results.clear();
+                self.symbol_finder_results.clear();
                 self.file_finder_selected = 0;
 
+                if let Some(symbol) = symbol {
+                    return self.open_at(symbol.path, symbol.at);
+                }
                 if let Some(path) = path {
                     return self.update(Message::FileClicked(path));
                 }
                 iced::Task::none()
             }
 
-            Message::EscapePressed => {
-                if self.file_finder_visible {
-                    self.file_finder_visible = false;
-                    self.file_finder_query.clear();
-                    self.file_finder_results.clear();
-                    self.file_finder_selected = 0;
-                } else if self.search_visible {
-                    self.search_visible = false;
-                    self.search_query.clear();
-                    self.search_results.clear();
-                }
+            Message::ChordStarted => {
+                self.chord_pending = true;
                 iced::Task::none()
             }
-        }
-    }
-
-    pub fn view(&self) -> Element<'_, Message> {
-        use iced::widget::stack;
-
-        let tab_bar = self.view_tab_bar();
-        let editor_widget = self.view_editor();
-        let status_bar = self.view_status_bar();
-
-        let editor_container = if self.active_tab.is_some() {
-            container(column![tab_bar, editor_widget, status_bar])
-        } else {
-            self.view_welcome_screen()
-        }
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .style(editor_container_style);
-
-        let editor_area = container(editor_container)
-            .padding(10)
-            .width(Length::Fill);
-
-        let base_content: Element<'_, Message> = if self.sidebar_visible {
-            let sidebar = view_sidebar(self.file_tree.as_ref(), self.sidebar_width);
-
-            let resize_zone = mouse_area(
-                container(text(""))
-                    .width(Length::Fixed(RESIZE_HIT_WIDTH))
-                    .height(Length::Fill)
-            )
-            .on_press(Message::SidebarResizeStart)
-            .interaction(iced::mouse::Interaction::ResizingHorizontally);
-
-            row![editor_area, resize_zone, sidebar].into()
-        } else {
-            editor_area.into()
-        };
-
-        let wrapped = container(base_content)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .style(|_theme| container::Style {
-                background: Some(Background::Color(THEME.bg_editor)),
-                ..Default::default()
-            });
-
-        if self.file_finder_visible {
-            stack![wrapped, self.view_file_finder_overlay()].into()
-        } else if self.search_visible {
-            let search_panel = container(self.view_search_panel())
-                .padding(iced::Padding { top: 20.0, right: 0.0, bottom: 0.0, left: 20.0 })
-                .width(Length::Fill)
-                .height(Length::Fill);
-
-            stack![wrapped, search_panel].into()
-        } else {
-            wrapped.into()
-        }
-    }
-
-    pub fn subscription(&self) -> Subscription<Message> {
-        iced::event::listen_with(|event, _status, _id| {
-            match event {
-                Event::Mouse(iced::mouse::Event::CursorMoved { position }) => {
-                    Some(Message::SidebarResizing(position.x))
+            Message::ChordCancelled => {
+                self.chord_pending = false;
+                iced::Task::none()
+            }
+            Message::ChordKey(key) => {
+                if !std::mem::take(&mut self.chord_pending) {
+                    return iced::Task::none();
                 }
-                Event::Mouse(iced::mouse::Event::ButtonReleased(iced::mouse::Button::Left)) => {
-                    Some(Message::SidebarResizeEnd)
+                // Cmd+K followed by Cmd+0 / Cmd+J / Cmd+1..7, the same chords as VS Code
+                if key == 'i' {
+                    // Cmd+K Cmd+I shows the hover for the cursor, for when there's no mouse to rest
+                    if let Some(cursor) = self.active_document().map(Document::cursor) {
+                        self.request_hover(cursor);
+                    }
+                    return iced::Task::none();
                 }
-                Event::Keyboard(iced::keyboard::Event::KeyPressed {
-                    key,
-                    modifiers,
+                if key == 'f' {
+                    return self.update(Message::FormatSelection);
+                }
+                if key == 'b' {
+                    return self.update(Message::ToggleBlame);
+                }
+                let action = match key {
+                    '0' => Action::FoldAll,
+                    'j' => Action::UnfoldAll,
+                    level @ '1'..='7' => Action::FoldLevel(level as usize - '0' as usize),
+                    _ => return iced::Task::none(),
+                };
+                self.update(Message::EditorAction(action))
+            }
+            Message::ToggleIndentMenu => {
+                self.indent_menu_visible = !self.indent_menu_visible;
+                iced::Task::none()
+            }
+            Message::IndentationChosen(action) => {
+                self.indent_menu_visible = false;
+                self.update(Message::EditorAction(action))
+            }
+            Message::TriggerCompletion => {
+                self.completion = self.complete(true);
+                self.request_completion(None, true);
+                self.show_completion_details();
+                iced::Task::none()
+            }
+            Message::CompletionNavigate(delta) => {
+                if let Some(completion) = &mut self.completion {
+                    completion.navigate(delta);
+                }
+                self.show_completion_details();
+                iced::Task::none()
+            }
+            Message::CompletionAccept => {
+                let Some(item) = self.completion.as_ref().map(|completion| completion.selected().clone()) else {
+                    return iced::Task::none();
+                };
+                self.close_completion();
+                let expansion = match item.snippet {
+                    true => self.expand(&item.insert),
+                    false => Some(Expansion::plain(&item.insert)),
+                };
+                let Some(expansion) = expansion else { return iced::Task::none(); };
+                let mut task = self.update(Message::EditorAction(Action::InsertSnippet { prefix: item.replace, expansion }));
+                // Edits elsewhere go in after, which moves the cursor and tab stops along with them
+                if !item.additional_edits.is_empty() {
+                    task = task.chain(self.update(Message::EditorAction(Action::ApplyEdits(item.additional_edits))));
+                } else if let Some(origin) = item.origin.filter(|origin| !origin.resolved)
+                    && let Some(path) = self.active_tab.and_then(|idx| self.tabs.get(idx)).map(|tab| tab.path.clone())
+                {
+                    self.resolve_completion(origin.server, origin.item, LspRequest::ResolveAccepted(path));
+                }
+                // Going into a folder goes straight on to what's in it
+                if item.kind == CompletionKind::Folder {
+                    self.completion = self.complete(false);
+                }
+                task
+            }
+            Message::CompletionDismiss => {
+                self.close_completion();
+                iced::Task::none()
+            }
+            Message::LspConnected(events) => {
+                self.servers.connect(Arc::new(move |event| {
+                    // The receiver only goes away with the app
+                    let _ = events.unbounded_send(event);
+                }));
+                for idx in 0..self.tabs.len() {
+                    self.open_tab(idx);
+                }
+                iced::Task::none()
+            }
+            Message::Lsp(event) => {
+                self.servers.handle(&event);
+                if let lsp::Event::Response { server, id, result } = event {
+                    return self.lsp_response(server, id, result);
+                }
+                if let lsp::Event::Request { server, id, method, params } = event {
+                    // The only request the client leaves to the app
+                    if method == ApplyWorkspaceEdit::METHOD {
+                        let applied = match serde_json::from_value::<ApplyWorkspaceEditParams>(params) {
+                            Ok(params) => self.apply_workspace_edit(refactor::changes(params.edit)),
+                            Err(e) => {
+                                eprintln!("Invalid workspace edit: {}", e);
+                                false
+                            }
+                        };
+                        if let Some(client) = self.servers.get(server) {
+                            client.respond(id, json!({ "applied": applied }));
+                        }
+                    }
+                    return iced::Task::none();
+                }
+                if let lsp::Event::Initialized { server, .. } = event {
+                    // Files opened while it started up get their hints and symbols now that it's known to give them
+                    for idx in 0..self.tabs.len() {
+                        let uri = Url::from_file_path(&self.tabs[idx].path).ok();
+                        if uri.is_some_and(|uri| self.servers.server_for(&uri) == Some(server)) {
+                            self.request_inlay_hints(idx);
+                            self.refresh_outline(idx);
+                        }
+                    }
+                }
+                if let lsp::Event::Notification { server, method, params } = event {
+                    if method == "window/showMessage" {
+                        let name = self.servers.get(server).map_or("Language server", |client| client.name());
+                        if let Some(message) = params.get("message").and_then(|message| message.as_str()) {
+                            eprintln!("{}: {}", name, message);
+                        }
+                    } else if method == PublishDiagnostics::METHOD {
+                        match serde_json::from_value::<PublishDiagnosticsParams>(params) {
+                            Ok(params) => self.publish_diagnostics(params),
+                            Err(e) => eprintln!("Invalid diagnostics: {}", e),
+                        }
+                    }
+                }
+                iced::Task::none()
+            }
+            Message::SidebarViewChanged(view) => {
+                self.sidebar_view = view;
+                self.sidebar_visible = true;
+                self.load_history(false)
+            }
+            Message::RefreshOutline(path) => {
+                if let Some(wait) = self.wait_for_edits(&path, Refresh::Outline) {
+                    return wait;
+                }
+                let open = self.tabs.iter().position(|tab| tab.path == path && matches!(tab.kind, TabKind::Editor { .. }));
+                let Some(idx) = open else { return iced::Task::none(); };
+                self.refresh_outline(idx);
+                iced::Task::none()
+            }
+            Message::RefreshFolds(path) => {
+                if let Some(wait) = self.wait_for_edits(&path, Refresh::Folds) {
+                    return wait;
+                }
+                let open = self.tabs.iter_mut().find(|tab| tab.path == path && matches!(tab.kind, TabKind::Editor { .. }));
+                let Some(Tab { kind: TabKind::Editor { content, .. }, .. }) = open else { return iced::Task::none(); };
+                content.refresh_fold_regions();
+                iced::Task::none()
+            }
+            Message::BreadcrumbClicked(index) => {
+                self.breadcrumb_menu = match self.breadcrumb_menu {
+                    Some((open, _)) if open == index => None,
+                    _ => Some((index, self.breadcrumb_siblings(index))),
+                };
+                iced::Task::none()
+            }
+            Message::BreadcrumbChosen(crumb) => {
+                self.breadcrumb_menu = None;
+                match crumb {
+                    Crumb::Folder(path) => {
+                        if let Some(ref mut tree) = self.file_tree {
+                            tree.reveal(&path);
+                        }
+                        self.sidebar_visible = true;
+                        self.sidebar_view = SidebarView::Explorer;
+                        iced::Task::none()
+                    }
+                    Crumb::File(path) => self.update(Message::FileClicked(path)),
+                    Crumb::Symbol { at, .. } => self.update(Message::EditorAction(Action::GoTo(at))),
+                }
+            }
+            Message::BreadcrumbDismissed => {
+                self.breadcrumb_menu = None;
+                iced::Task::none()
+            }
+            Message::ToggleProblems => {
+                self.problems_visible = !self.problems_visible;
+                iced::Task::none()
+            }
+            Message::ProblemsFilterChanged(filter) => {
+                self.problems_filter = filter;
+                iced::Task::none()
+            }
+            Message::ProblemsSeverityToggled(severity) => {
+                if !self.problems_hidden.remove(&severity) {
+                    self.problems_hidden.insert(severity);
+                }
+                iced::Task::none()
+            }
+            Message::ProblemClicked(path, position) => self.open_at(path, position),
+            Message::GoToDefinition => {
+                self.request_locations::<GotoDefinition>("Definitions", definition_params);
+                iced::Task::none()
+            }
+            Message::GoToTypeDefinition => {
+                self.request_locations::<GotoTypeDefinition>("Type definitions", definition_params);
+                iced::Task::none()
+            }
+            Message::GoToImplementation => {
+                self.request_locations::<GotoImplementation>("Implementations", definition_params);
+                iced::Task::none()
+            }
+            Message::FindReferences => {
+                self.request_locations::<References>("References", |position| ReferenceParams {
+                    text_document_position: position,
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                    context: ReferenceContext { include_declaration: true },
+                });
+                iced::Task::none()
+            }
+            Message::HoverAt(Some(at)) => {
+                let shown = self.hover.as_ref().is_some_and(|(path, hover)| {
+                    self.active_tab.and_then(|idx| self.tabs.get(idx)).is_some_and(|tab| tab.path == *path) && hover.contains(at)
+                });
+                if !shown {
+                    self.close_hover();
+                    self.request_hover(at);
+                }
+                iced::Task::none()
+            }
+            Message::HoverAt(None) => {
+                self.close_hover();
+                iced::Task::none()
+            }
+            Message::TriggerSignatureHelp => {
+                self.request_signature_help(None, self.signature.is_some());
+                iced::Task::none()
+            }
+            Message::RenameSymbol => self.start_rename(),
+            Message::RenameInputChanged(name) => {
+                if let Some(rename) = &mut self.rename {
+                    rename.new_name = name;
+                }
+                iced::Task::none()
+            }
+            Message::RenameSubmit => {
+                if let Some(rename) = self.rename.take() {
+                    self.request_rename(rename);
+                }
+                iced::Task::none()
+            }
+            Message::RenameCancel => {
+                self.rename = None;
+                iced::Task::none()
+            }
+            Message::ApplyEditPreview => {
+                if let Some(preview) = self.edit_preview.take() {
+                    self.apply_workspace_edit(preview.changes);
+                }
+                self.close_preview_diffs();
+                iced::Task::none()
+            }
+            Message::DiscardEditPreview => {
+                self.edit_preview = None;
+                self.close_preview_diffs();
+                iced::Task::none()
+            }
+            Message::ShowCodeActions => {
+                self.request_code_actions();
+                iced::Task::none()
+            }
+            Message::CodeActionFilterChanged(filter) => {
+                if let Some(menu) = &mut self.code_actions {
+                    menu.filter = filter;
+                    menu.selected = 0;
+                }
+                iced::Task::none()
+            }
+            Message::CodeActionChosen(index) => {
+                let Some(menu) = self.code_actions.take() else { return iced::Task::none(); };
+                let server = menu.server;
+                match menu.actions.into_iter().nth(index) {
+                    Some(CodeActionOrCommand::Command(command)) => self.execute_command(server, command),
+                    Some(CodeActionOrCommand::CodeAction(action)) => self.run_code_action(server, action, true),
+                    None => {}
+                }
+                iced::Task::none()
+            }
+            Message::CodeActionsClose => {
+                self.code_actions = None;
+                iced::Task::none()
+            }
+            Message::FormatDocument => self.format(None, false),
+            Message::FormatSelection => {
+                let Some(selection) = self.active_document().map(Document::selection) else { return iced::Task::none(); };
+                let (start, end) = (selection.start(), selection.end());
+                // A selection that ends at the start of a line doesn't take that line with it
+                let last = if end.column == 0 && end.line > start.line { end.line - 1 } else { end.line };
+                self.format(Some((start.line, last)), false)
+            }
+            Message::Formatted(formatting, result) => {
+                match result {
+                    Ok(text) => {
+                        let edits = self
+                            .document_at(&formatting.path)
+                            .filter(|document| document.version() == formatting.version)
+                            .map(|document| format::edits(document, &text, formatting.lines));
+                        match edits {
+                            Some(edits) => self.edit_file(&formatting.path, edits),
+                            None => eprintln!("{} changed while it was being formatted", formatting.path.display()),
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to format: {}", e),
+                }
+                match formatting.save {
+                    true => self.save_file(&formatting.path),
+                    false => iced::Task::none(),
+                }
+            }
+            Message::FormatTimedOut(formatting) => {
+                let pending = self.lsp_requests.iter().find_map(|(key, request)| match request {
+                    LspRequest::Format(sent) if sent.path == formatting.path && sent.version == formatting.version => Some(*key),
+                    _ => None,
+                });
+                // Already answered, and saved along with it
+                let Some(key) = pending else { return iced::Task::none(); };
+                self.lsp_requests.remove(&key);
+                eprintln!("Formatting {} took too long, saving it as it is", formatting.path.display());
+                self.save_file(&formatting.path)
+            }
+            Message::EscapePressed => {
+                if self.breadcrumb_menu.is_some() {
+                    self.breadcrumb_menu = None;
+                } else if self.branch_menu || self.new_branch.is_some() {
+                    self.branch_menu = false;
+                    self.new_branch = None;
+                } else if self.file_menu.is_some() {
+                    self.file_menu = None;
+                } else if self.edit_preview.is_some() {
+                    self.edit_preview = None;
+                    self.close_preview_diffs();
+                } else if self.rename.is_some() {
+                    self.rename = None;
+                } else if self.code_actions.is_some() {
+                    self.code_actions = None;
+                } else if self.completion.is_some() {
+                    self.close_completion();
+                } else if self.hover.is_some() || self.signature.is_some() {
+                    self.close_hover();
+                    self.signature = None;
+                } else if self.hunk_view.is_some() {
+                    self.hunk_view = None;
+                } else if self.indent_menu_visible {
+                    self.indent_menu_visible = false;
+                } else if self.file_finder_visible {
+                    self.file_finder_visible = false;
+                    self.file_finder_query.clear();
+                    self.file_finder_results.clear();
+                    self.symbol_finder_results.clear();
+                    self.file_finder_selected = 0;
+                } else if self.search_visible {
+                    self.search_visible = false;
+                    self.search_query.clear();
+                    self.search_results.clear();
+                    self.search_title = None;
+                }
+                iced::Task::none()
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        use iced::widget::stack;
+
+        let tab_bar = self.view_tab_bar();
+        let editor_widget = self.view_editor();
+        let status_bar = self.view_status_bar();
+
+        let breadcrumbs = view_breadcrumbs(self.breadcrumbs(), self.breadcrumb_menu.as_ref());
+
+        let editor_container = if self.active_tab.is_some() {
+            container(column![tab_bar, breadcrumbs, editor_widget, status_bar])
+        } else {
+            self.view_welcome_screen()
+        }
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(editor_container_style);
+
+        let editor_column = if self.problems_visible {
+            column![editor_container, self.view_problems_panel()].spacing(10)
+        } else {
+            column![editor_container]
+        };
+        let editor_area = container(editor_column)
+            .padding(10)
+            .width(Length::Fill);
+
+        let base_content: Element<'_, Message> = if self.sidebar_visible {
+            let document = self.active_document();
+            let outline = OutlineView {
+                symbols: document.map(|_| self.active_tab.and_then(|idx| self.outlines.get(&self.tabs[idx].path)).map_or(&[][..], Vec::as_slice)),
+                cursor: document.map(Document::cursor).unwrap_or_default(),
+            };
+            let source_control = SourceControlView {
+                root: self.file_tree.as_ref().map(|tree| tree.root.as_path()),
+                status: self.git_status.as_ref(),
+                commit_message: &self.commit_message,
+                amend: self.amend,
+                expanded: &self.scm_diffs,
+                branch_menu: self.branch_menu,
+                new_branch: self.new_branch.as_deref(),
+                new_branch_input_id: &self.new_branch_input_id,
+                error: self.scm_error.as_deref(),
+            };
+            let history = match (self.active_tab.and_then(|idx| self.tabs.get(idx)), &self.file_history) {
+                (None, _) => HistoryView::NoFile,
+                (Some(tab), Some((path, Some(commits)))) if *path == tab.path => HistoryView::Commits(path, commits),
+                (Some(tab), Some((path, None))) if *path == tab.path => HistoryView::NotInRepository,
+                _ => HistoryView::Loading,
+            };
+            let explorer = ExplorerView {
+                tree: self.file_tree.as_ref(),
+                git_status: self.git_status.as_ref(),
+                menu: FileMenu { open: self.file_menu.as_deref(), compare: self.compare_selected.as_deref() },
+            };
+            let sidebar = view_sidebar(
+                self.sidebar_view,
+                explorer,
+                outline,
+                source_control,
+                history,
+                self.sidebar_width,
+            );
+
+            let resize_zone = mouse_area(
+                container(text(""))
+                    .width(Length::Fixed(RESIZE_HIT_WIDTH))
+                    .height(Length::Fill)
+            )
+            .on_press(Message::SidebarResizeStart)
+            .interaction(iced::mouse::Interaction::ResizingHorizontally);
+
+            row![editor_area, resize_zone, sidebar].into()
+        } else {
+            editor_area.into()
+        };
+
+        let wrapped = container(base_content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .style(|_theme| container::Style {
+                background: Some(Background::Color(THEME.bg_editor)),
+                ..Default::default()
+            });
+
+        let root = self.file_tree.as_ref().map(|tree| tree.root.as_path());
+        // The preview steps aside while one of its files' diffs is being looked at
+        let previewing_diff = self.active_tab.and_then(|idx| self.tabs.get(idx)).is_some_and(|tab| matches!(tab.kind, TabKind::Diff { preview: true, .. }));
+        if let (Some(preview), false) = (&self.edit_preview, previewing_diff) {
+            stack![wrapped, view_edit_preview(preview, root)].into()
+        } else if let Some(rename) = &self.rename {
+            stack![wrapped, view_rename(rename, &self.rename_input_id)].into()
+        } else if let Some(menu) = &self.code_actions {
+            stack![wrapped, view_code_actions(menu, &self.code_action_input_id)].into()
+        } else if self.file_finder_visible {
+            stack![wrapped, self.view_file_finder_overlay()].into()
+        } else if self.indent_menu_visible {
+            stack![wrapped, self.view_indent_menu()].into()
+        } else if self.search_visible {
+            let search_panel = container(self.view_search_panel())
+                .padding(iced::Padding { top: 20.0, right: 0.0, bottom: 0.0, left: 20.0 })
+                .width(Length::Fill)
+                .height(Length::Fill);
+
+            stack![wrapped, search_panel].into()
+        } else {
+            wrapped.into()
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        let watcher = match &self.file_tree {
+            Some(tree) => Subscription::run_with(tree.root.clone(), watch_files),
+            None => Subscription::none(),
+        };
+        Subscription::batch([Subscription::run(lsp_events), self.event_subscription(), chord_subscription(), watcher])
+    }
+
+    fn event_subscription(&self) -> Subscription<Message> {
+        iced::event::listen_with(|event, status, _id| {
+            match event {
+                Event::Mouse(iced::mouse::Event::CursorMoved { position }) => {
+                    Some(Message::SidebarResizing(position.x))
+                }
+                Event::Mouse(iced::mouse::Event::ButtonReleased(iced::mouse::Button::Left)) => {
+                    Some(Message::SidebarResizeEnd)
+                }
+                Event::Keyboard(iced::keyboard::Event::KeyPressed {
+                    key,
+                    modifiers,
                     ..
                 }) => {
+                    // Alt+F5 is the editor's while it has focus, and diff tabs' otherwise
+                    if key == Key::Named(iced::keyboard::key::Named::F5) && modifiers.alt() && status == iced::event::Status::Ignored {
+                        return Some(if modifiers.shift() { Message::PreviousChange } else { Message::NextChange });
+                    }
                     let navigation_msg = match &key {
                         Key::Named(iced::keyboard::key::Named::Escape) =>
                             Some(Message::EscapePressed),
@@ -544,18 +1634,20 @@ impl App {
 
                     if let Key::Character(c) = &key {
                         if modifiers.command() && modifiers.control() {
-                            match c.as_str() {
-                                "f" => return Some(Message::ToggleFullscreen(window::Mode::Fullscreen)),
-                                _ => {}
+                            if c.as_str() == "f" {
+                                return Some(Message::ToggleFullscreen(window::Mode::Fullscreen));
                             }
                         } else if modifiers.command() && modifiers.shift() {
                             match c.as_str() {
                                 "v" | "V" => return Some(Message::PreviewMarkdown),
                                 "f" | "F" => return Some(Message::ToggleSearch),
+                                "m" | "M" => return Some(Message::ToggleProblems),
                                 _ => {}
                             }
                         } else if modifiers.command() {
                             match c.as_str() {
+                                "k" => return Some(Message::ChordStarted),
+                                c if CHORD_KEYS.contains(&c) => return c.chars().next().map(Message::ChordKey),
                                 "r" => return Some(Message::ToggleSidebar),
                                 "o" => return Some(Message::OpenFolderDialog),
                                 "w" => return Some(Message::CloseActiveTab),
@@ -569,159 +1661,1517 @@ impl App {
                 }
                 _ => None,
             }
-        })
+        })
+    }
+
+    fn view_tab_bar(&self) -> Element<'_, Message> {
+        if self.tabs.is_empty() {
+            return container(text("")).into();
+        }
+
+        let tabs: Vec<Element<'_, Message>> = self.tabs
+            .iter()
+            .enumerate()
+            .map(|(idx, tab)| {
+                let is_active = self.active_tab == Some(idx);
+                let is_modified = matches!(&tab.kind, TabKind::Editor { modified: true, .. });
+                let close_icon = if is_modified {
+                    text("●").size(10).color(THEME.text_muted)
+                } else {
+                    text("x").size(10).color(THEME.text_dim)
+                };
+
+                button(
+                    row![
+                        text(&tab.name).size(12).color(THEME.text_muted),
+                        button(close_icon)
+                            .style(tab_close_button_style)
+                            .on_press(Message::TabClosed(idx))
+                            .padding(2),
+                    ]
+                    .spacing(8)
+                    .align_y(iced::Alignment::Center)
+                )
+                .style(tab_button_style(is_active))
+                .on_press(Message::TabSelected(idx))
+                .padding(iced::Padding { top: 8.0, right: 16.0, bottom: 8.0, left: 16.0 })
+                .into()
+            })
+            .collect();
+
+        container(row(tabs).spacing(6))
+            .padding(iced::Padding { top: 8.0, right: 12.0, bottom: 8.0, left: 12.0 })
+            .width(Length::Fill)
+            .style(tab_bar_style)
+            .into()
+    }
+
+    fn view_search_panel(&self) -> Element<'_, Message> {
+        let header: Element<'_, Message> = match &self.search_title {
+            Some(title) => container(text(title).size(13).color(THEME.text_secondary)).padding(10).into(),
+            None => text_input("Search across workspace...", &self.search_query)
+                .id(self.search_input_id.clone())
+                .on_input(Message::SearchQueryChanged)
+                .style(search_input_style)
+                .size(13)
+                .padding(10)
+                .width(Length::Fill)
+                .into(),
+        };
+        // A symbol's locations are all worth seeing, unlike every match of a search
+        let shown = if self.search_title.is_some() { usize::MAX } else { 3 };
+
+        let mut content_col = column![header].spacing(6);
+
+        if !self.search_results.is_empty() {
+            let mut result_items: Vec<Element<'_, Message>> = Vec::new();
+
+            for result in &self.search_results {
+                result_items.push(
+                    container(
+                        text(&result.file_name)
+                            .size(11)
+                            .color(THEME.text_secondary)
+                    )
+                    .padding(iced::Padding { top: 6.0, right: 6.0, bottom: 2.0, left: 6.0 })
+                    .into()
+                );
+
+                for m in result.matches.iter().take(shown) {
+                    let line_text = format!("  {}:  {}", m.line_number, m.line_content.trim());
+                    let path = result.path.clone();
+                    let position = Position::new(m.line_number - 1, m.column);
+
+                    result_items.push(
+                        button(
+                            text(line_text)
+                                .size(11)
+                                .color(THEME.text_muted)
+                        )
+                        .style(tree_button_style)
+                        .on_press(Message::SearchResultClicked(path, position))
+                        .padding(iced::Padding { top: 3.0, right: 6.0, bottom: 3.0, left: 12.0 })
+                        .width(Length::Fill)
+                        .into()
+                    );
+                }
+
+                if result.matches.len() > shown {
+                    result_items.push(
+                        container(
+                            text(format!("  ... and {} more", result.matches.len() - shown))
+                                .size(10)
+                                .color(THEME.text_dim)
+                        )
+                        .padding(iced::Padding { top: 1.0, right: 6.0, bottom: 2.0, left: 12.0 })
+                        .into()
+                    );
+                }
+            }
+
+            let results_scroll = scrollable(
+                column(result_items).spacing(1)
+            )
+            .height(Length::Shrink);
+
+            content_col = content_col.push(
+                container(results_scroll).max_height(400.0)
+            );
+        }
+
+        container(content_col)
+            .width(Length::Fixed(320.0))
+            .padding(10)
+            .style(search_panel_style)
+            .into()
+    }
+
+    fn view_editor(&self) -> Element<'_, Message> {
+        if let Some(idx) = self.active_tab
+            && let Some(tab) = self.tabs.get(idx)
+        {
+            match &tab.kind {
+                TabKind::Editor { content, .. } => {
+                    let ext = tab.path.extension()
+                        .and_then(|e| e.to_str())
+                        .unwrap_or("");
+                    let hover = self.hover.as_ref().filter(|(path, _)| *path == tab.path).map(|(_, hover)| hover);
+                    let signature = self.signature.as_ref().filter(|(path, _)| *path == tab.path).map(|(_, signature)| signature);
+                    let changes = self.git_changes.get(&tab.path).map(Vec::as_slice).unwrap_or_default();
+                    let hunk = self
+                        .hunk_view
+                        .as_ref()
+                        .filter(|(path, _)| *path == tab.path)
+                        .and_then(|(_, index)| Some((changes.get(*index)?, self.git_bases.get(&tab.path)?.lines.as_slice())));
+                    return create_editor(
+                        content,
+                        ext,
+                        self.config.editor_for(ext),
+                        Popups {
+                            completion: self.completion.as_ref(),
+                            completion_docs: &self.completion_docs,
+                            hover,
+                            signature,
+                            hunk,
+                        },
+                        self.diagnostics.for_file(&tab.path),
+                        GitLines { changes, blame: self.git_blames.get(&tab.path), blame_gutter: self.blame_gutter },
+                    );
+                }
+                TabKind::Preview { md_items, code_highlights } => {
+                    return scrollable(
+                        markdown::view_with(
+                            md_items,
+                            markdown::Settings::with_style(markdown::Style::from_palette(
+                                iced::theme::Palette::CATPPUCCIN_MOCHA,
+                            )),
+                            &PreviewViewer { highlights: code_highlights },
+                        )
+                    )
+                    .height(Length::Fill)
+                    .into();
+                }
+                TabKind::Diff { diff, scroll_id, preview } => return view_diff(diff, scroll_id, *preview),
+            }
+        }
+        empty_editor()
+    }
+
+    /// The snippet whose prefix is right before the cursor, as the action that expands it.
+    fn snippet_at_cursor(&self) -> Option<Action> {
+        let tab = self.tabs.get(self.active_tab?)?;
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return None; };
+        let selection = document.selection();
+        if !selection.is_empty() || document.selections().len() > 1 {
+            return None;
+        }
+
+        let extension = tab.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let line = document.line(selection.head.line);
+        let before = &line[..crate::document::byte_index(line, selection.head.column)];
+        let (snippet, prefix) = self.snippets.matching(extension, before)?;
+        Some(Action::InsertSnippet { prefix, expansion: self.expand(&snippet.body)? })
+    }
+
+    /// Parses a snippet body for the active tab, filling in its variables.
+    fn expand(&self, body: &str) -> Option<Expansion> {
+        let tab = self.tabs.get(self.active_tab?)?;
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return None; };
+        let context = snippets::Context {
+            document,
+            path: &tab.path,
+            workspace: self.file_tree.as_ref().map(|tree| tree.root.as_path()),
+            extension: tab.path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+        };
+        Some(Expansion::parse(body, &context))
+    }
+
+    /// Asks the completion providers about the word at the cursor in the active tab.
+    fn complete(&self, explicit: bool) -> Option<Completion> {
+        let tab = self.tabs.get(self.active_tab?)?;
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return None; };
+        if document.selections().len() > 1 || !document.selection().is_empty() {
+            return None;
+        }
+        let others = self
+            .tabs
+            .iter()
+            .filter(|other| other.path != tab.path)
+            .filter_map(|other| match &other.kind {
+                TabKind::Editor { content, .. } => Some(content.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let request = Request {
+            document,
+            path: &tab.path,
+            extension: tab.path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+            others,
+            explicit,
+        };
+        let mut providers: Vec<&dyn CompletionProvider> = vec![&BufferWords, &self.snippets, &FilePaths];
+        if let Some(server_completions) = &self.server_completions {
+            providers.push(server_completions);
+        }
+        Completion::new(&request, &providers)
+    }
+
+    fn close_completion(&mut self) {
+        self.completion = None;
+        self.completion_docs.clear();
+        self.completion_request = None;
+    }
+
+    /// Shows the selected completion item's documentation, and asks its server for the rest of
+    /// its details if they were left out.
+    fn show_completion_details(&mut self) {
+        self.completion_docs.clear();
+        let Some(item) = self.completion.as_ref().map(|completion| completion.selected()) else { return; };
+        if let Some(documentation) = &item.documentation {
+            self.completion_docs = markdown::parse(documentation).collect();
+        }
+        let Some(origin) = item.origin.as_ref().filter(|origin| !origin.resolved) else { return; };
+        let asked = self.lsp_requests.values().any(|request| {
+            matches!(request, LspRequest::ResolveCompletion(sent) if **sent == origin.item)
+        });
+        if !asked {
+            let (server, sent) = (origin.server, origin.item.clone());
+            self.resolve_completion(server, sent.clone(), LspRequest::ResolveCompletion(Box::new(sent)));
+        }
+    }
+
+    /// Sends `completionItem/resolve`, if the server has it.
+    fn resolve_completion(&mut self, server: ServerId, item: lsp_types::CompletionItem, request: LspRequest) {
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let resolves = client
+            .capabilities()
+            .and_then(|capabilities| capabilities.completion_provider.as_ref()?.resolve_provider)
+            .unwrap_or(false);
+        if resolves {
+            let id = client.request::<ResolveCompletionItem>(item);
+            self.lsp_requests.insert((server, id), request);
+        }
+    }
+
+    /// The active tab's language server, and the cursor the way requests about it need it.
+    fn cursor_params(&self) -> Option<(ServerId, TextDocumentPositionParams)> {
+        self.position_params(self.active_document()?.cursor())
+    }
+
+    /// The active tab's language server, and a position in it the way requests need it.
+    fn position_params(&self, at: Position) -> Option<(ServerId, TextDocumentPositionParams)> {
+        let tab = self.tabs.get(self.active_tab?)?;
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return None; };
+        let uri = Url::from_file_path(&tab.path).ok()?;
+        let server = self.servers.server_for(&uri)?;
+        let position = lsp_types::Position { line: at.line as u32, character: document.utf16_column(at) as u32 };
+        Some((server, TextDocumentPositionParams { text_document: TextDocumentIdentifier { uri }, position }))
+    }
+
+    /// Whether typing `c` asks the active tab's server for completions, like `.` does in most languages.
+    fn is_trigger_character(&self, c: char) -> bool {
+        let Some((server, _)) = self.cursor_params() else { return false; };
+        self.servers
+            .get(server)
+            .and_then(|client| client.capabilities()?.completion_provider.as_ref()?.trigger_characters.as_ref())
+            .is_some_and(|characters| characters.iter().any(|trigger| trigger.ends_with(c)))
+    }
+
+    /// Asks the active tab's server for completions at the cursor, unless what it suggested last
+    /// time still applies. Its answer opens the popup if nothing closed it in the meantime.
+    fn request_completion(&mut self, trigger: Option<char>, explicit: bool) {
+        let Some((server, position)) = self.cursor_params() else { return; };
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return; };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return; };
+        let cursor = document.cursor();
+        let line = document.line(cursor.line);
+        let in_word = line[..byte_index(line, cursor.column)].chars().next_back().is_some_and(is_word_char);
+        let known = self.server_completions.as_ref().filter(|items| items.typed_since(document, &tab.path).is_some());
+        let trigger_kind = match (trigger, known) {
+            (Some(_), _) => CompletionTriggerKind::TRIGGER_CHARACTER,
+            (None, Some(items)) if items.incomplete => CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS,
+            (None, Some(_)) if !explicit => return,
+            _ if !in_word && !explicit => return,
+            _ => CompletionTriggerKind::INVOKED,
+        };
+
+        let path = tab.path.clone();
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let id = client.request::<CompletionRequest>(CompletionParams {
+            text_document_position: position,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: Some(CompletionContext { trigger_kind, trigger_character: trigger.map(String::from) }),
+        });
+        self.lsp_requests.insert((server, id), LspRequest::Completion { path, at: cursor, explicit });
+        self.completion_request = Some((server, id));
+    }
+
+    fn close_hover(&mut self) {
+        self.hover = None;
+        self.hover_request = None;
+    }
+
+    /// Asks the active tab's server about the symbol at `at`. Its answer shows in a tooltip.
+    fn request_hover(&mut self, at: Position) {
+        let Some((server, position)) = self.position_params(at) else { return; };
+        let Some(path) = self.active_tab.and_then(|idx| self.tabs.get(idx)).map(|tab| tab.path.clone()) else { return; };
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let hovers = client
+            .capabilities()
+            .and_then(|capabilities| capabilities.hover_provider.as_ref())
+            .is_some_and(|provider| !matches!(provider, HoverProviderCapability::Simple(false)));
+        if !hovers {
+            return;
+        }
+        let id = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: position,
+            work_done_progress_params: Default::default(),
+        });
+        self.lsp_requests.insert((server, id), LspRequest::Hover { path, at });
+        self.hover_request = Some((server, id));
+    }
+
+    /// Whether typing `c` opens signature help on the active tab's server, like `(` does in most languages.
+    fn is_signature_trigger(&self, c: char) -> bool {
+        let Some((server, _)) = self.cursor_params() else { return false; };
+        self.servers
+            .get(server)
+            .and_then(|client| client.capabilities()?.signature_help_provider.as_ref()?.trigger_characters.as_ref())
+            .is_some_and(|characters| characters.iter().any(|trigger| trigger.ends_with(c)))
+    }
+
+    /// Asks the active tab's server for the signature of the call the cursor is in. `retrigger`
+    /// is set when the help is already showing and the cursor moved or the text changed.
+    fn request_signature_help(&mut self, trigger: Option<char>, retrigger: bool) {
+        let Some((server, position)) = self.cursor_params() else { return; };
+        let Some(path) = self.active_tab.and_then(|idx| self.tabs.get(idx)).map(|tab| tab.path.clone()) else { return; };
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        if client.capabilities().is_none_or(|capabilities| capabilities.signature_help_provider.is_none()) {
+            return;
+        }
+        let trigger_kind = match (trigger, retrigger) {
+            (Some(_), _) => SignatureHelpTriggerKind::TRIGGER_CHARACTER,
+            (None, true) => SignatureHelpTriggerKind::CONTENT_CHANGE,
+            (None, false) => SignatureHelpTriggerKind::INVOKED,
+        };
+        let id = client.request::<SignatureHelpRequest>(SignatureHelpParams {
+            context: Some(SignatureHelpContext {
+                trigger_kind,
+                trigger_character: trigger.map(String::from),
+                is_retrigger: retrigger,
+                active_signature_help: None,
+            }),
+            text_document_position_params: position,
+            work_done_progress_params: Default::default(),
+        });
+        self.lsp_requests.insert((server, id), LspRequest::SignatureHelp { path });
+    }
+
+    /// Asks a tab's server for the inlay hints of the whole file, if it gives them.
+    fn request_inlay_hints(&mut self, idx: usize) {
+        let Some(tab) = self.tabs.get(idx) else { return; };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return; };
+        let Ok(uri) = Url::from_file_path(&tab.path) else { return; };
+        let Some(server) = self.servers.server_for(&uri) else { return; };
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let hints = client
+            .capabilities()
+            .and_then(|capabilities| capabilities.inlay_hint_provider.as_ref())
+            .is_some_and(|provider| !matches!(provider, OneOf::Left(false)));
+        if !hints {
+            return;
+        }
+        let last = document.line_count() - 1;
+        let end = Position::new(last, document.line_len(last));
+        let range = lsp_types::Range {
+            start: lsp_types::Position::default(),
+            end: lsp_types::Position { line: last as u32, character: document.utf16_column(end) as u32 },
+        };
+        let id = client.request::<InlayHintRequest>(InlayHintParams {
+            text_document: TextDocumentIdentifier { uri },
+            range,
+            work_done_progress_params: Default::default(),
+        });
+        let request = LspRequest::InlayHints { path: tab.path.clone(), version: document.version() };
+        self.lsp_requests.insert((server, id), request);
+    }
+
+    /// Puts a server's answer to a request to use.
+    fn lsp_response(&mut self, server: ServerId, id: i64, result: Result<Value, ResponseError>) -> iced::Task<Message> {
+        let Some(request) = self.lsp_requests.remove(&(server, id)) else { return iced::Task::none(); };
+        let value = match result {
+            Ok(value) => value,
+            Err(e) => {
+                // Servers give up on requests the edits since have made pointless, which is fine
+                if e.code != CONTENT_MODIFIED && e.code != REQUEST_CANCELLED {
+                    let name = self.servers.get(server).map_or("Language server", |client| client.name());
+                    eprintln!("{}: {}", name, e.message);
+                }
+                // Saving doesn't wait on formatting that failed
+                if let LspRequest::Format(formatting) = request
+                    && formatting.save
+                {
+                    return self.save_file(&formatting.path);
+                }
+                return iced::Task::none();
+            }
+        };
+        match request {
+            LspRequest::Completion { path, at, explicit } => {
+                match serde_json::from_value::<Option<CompletionResponse>>(value) {
+                    Ok(response) => self.server_completed(server, id, path, at, explicit, response),
+                    Err(e) => eprintln!("Invalid completions: {}", e),
+                }
+            }
+            LspRequest::ResolveCompletion(sent) => match serde_json::from_value::<lsp_types::CompletionItem>(value) {
+                Ok(item) => self.completion_resolved(server, &sent, item),
+                Err(e) => eprintln!("Invalid completion item: {}", e),
+            },
+            LspRequest::Locations { label, symbol } => match serde_json::from_value::<Option<GotoDefinitionResponse>>(value) {
+                Ok(response) => return self.show_locations(label, symbol, response),
+                Err(e) => eprintln!("Invalid locations: {}", e),
+            },
+            LspRequest::ResolveAccepted(path) => match serde_json::from_value::<lsp_types::CompletionItem>(value) {
+                Ok(item) => return self.apply_additional_edits(&path, item),
+                Err(e) => eprintln!("Invalid completion item: {}", e),
+            },
+            LspRequest::Hover { path, at } => match serde_json::from_value::<Option<lsp_types::Hover>>(value) {
+                // Unless the mouse has moved on since
+                Ok(response) if self.hover_request == Some((server, id)) => {
+                    self.hover_request = None;
+                    let hover = response.zip(self.document_at(&path)).and_then(|(hover, document)| Hover::from_lsp(hover, document, at));
+                    self.hover = hover.map(|hover| (path, hover));
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Invalid hover: {}", e),
+            },
+            LspRequest::SignatureHelp { path } => match serde_json::from_value::<Option<lsp_types::SignatureHelp>>(value) {
+                Ok(response) => self.signature = response.and_then(Signature::from_lsp).map(|signature| (path, signature)),
+                Err(e) => eprintln!("Invalid signature help: {}", e),
+            },
+            LspRequest::InlayHints { path, version } => match serde_json::from_value::<Option<Vec<lsp_types::InlayHint>>>(value) {
+                Ok(hints) => {
+                    let Some(TabKind::Editor { content, .. }) = self.tabs.iter_mut().find(|tab| tab.path == path).map(|tab| &mut tab.kind) else {
+                        return iced::Task::none();
+                    };
+                    // Hints for older text would land in the wrong places, and newer ones are on the way
+                    if content.version() == version {
+                        let hints = hints.unwrap_or_default().into_iter().map(|hint| InlayHint::from_lsp(hint, content)).collect();
+                        content.set_inlay_hints(hints);
+                    }
+                }
+                Err(e) => eprintln!("Invalid inlay hints: {}", e),
+            },
+            LspRequest::DocumentSymbols { path, version } => match serde_json::from_value::<Option<DocumentSymbolResponse>>(value) {
+                Ok(response) => {
+                    // Symbols for older text would be in the wrong places, and newer ones are on the way
+                    let Some(document) = self.document_at(&path).filter(|document| document.version() == version) else {
+                        return iced::Task::none();
+                    };
+                    let symbols = response.map(|response| outline::from_lsp(response, document)).unwrap_or_default();
+                    self.outlines.insert(path, symbols);
+                }
+                Err(e) => eprintln!("Invalid document symbols: {}", e),
+            },
+            LspRequest::PrepareRename { path, at } => match serde_json::from_value::<Option<PrepareRenameResponse>>(value) {
+                Ok(Some(response)) => {
+                    let Some(document) = self.document_at(&path) else { return iced::Task::none(); };
+                    let position = |position: lsp_types::Position| {
+                        document.position_from_utf16(position.line as usize, position.character as usize)
+                    };
+                    let name = match response {
+                        PrepareRenameResponse::Range(range) => document.text_range(position(range.start), position(range.end)),
+                        PrepareRenameResponse::RangeWithPlaceholder { placeholder, .. } => placeholder,
+                        PrepareRenameResponse::DefaultBehavior { .. } => {
+                            let (start, end) = document.word_bounds(at);
+                            document.text_range(start, end)
+                        }
+                    };
+                    return self.show_rename(path, at, name);
+                }
+                Ok(None) => eprintln!("Nothing to rename here"),
+                Err(e) => eprintln!("Invalid rename range: {}", e),
+            },
+            LspRequest::Rename { title } => match serde_json::from_value::<Option<WorkspaceEdit>>(value) {
+                Ok(Some(edit)) => self.preview_workspace_edit(title, edit),
+                Ok(None) => eprintln!("{}: nothing to change", title),
+                Err(e) => eprintln!("Invalid rename edits: {}", e),
+            },
+            LspRequest::CodeActions => match serde_json::from_value::<Option<Vec<CodeActionOrCommand>>>(value) {
+                Ok(actions) => {
+                    let actions = actions.unwrap_or_default();
+                    if actions.is_empty() {
+                        eprintln!("No code actions available");
+                        return iced::Task::none();
+                    }
+                    self.code_actions = Some(CodeActionMenu::new(server, actions));
+                    return iced::widget::operation::focus(self.code_action_input_id.clone());
+                }
+                Err(e) => eprintln!("Invalid code actions: {}", e),
+            },
+            LspRequest::ResolveCodeAction => match serde_json::from_value::<lsp_types::CodeAction>(value) {
+                Ok(action) => self.run_code_action(server, action, false),
+                Err(e) => eprintln!("Invalid code action: {}", e),
+            },
+            LspRequest::ExecuteCommand => {}
+            LspRequest::Format(formatting) => {
+                match serde_json::from_value::<Option<Vec<lsp_types::TextEdit>>>(value) {
+                    Ok(edits) => {
+                        let edits = self
+                            .document_at(&formatting.path)
+                            .filter(|document| document.version() == formatting.version)
+                            .map(|document| edits.unwrap_or_default().into_iter().map(|edit| TextEdit::from_lsp(edit, document)).collect());
+                        match edits {
+                            Some(edits) => self.edit_file(&formatting.path, edits),
+                            None => eprintln!("{} changed while it was being formatted", formatting.path.display()),
+                        }
+                    }
+                    Err(e) => eprintln!("Invalid formatting edits: {}", e),
+                }
+                if formatting.save {
+                    return self.save_file(&formatting.path);
+                }
+            }
+        }
+        iced::Task::none()
+    }
+
+    /// Asks the active tab's server what the symbol at the cursor is called, if it wants to be
+    /// asked first, or else opens the rename box on the word there.
+    fn start_rename(&mut self) -> iced::Task<Message> {
+        let Some((server, position)) = self.cursor_params() else { return iced::Task::none(); };
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return iced::Task::none(); };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return iced::Task::none(); };
+        let (path, at) = (tab.path.clone(), document.cursor());
+        let (start, end) = document.word_bounds(at);
+        let word = document.text_range(start, end);
+        let Some(client) = self.servers.get_mut(server) else { return iced::Task::none(); };
+        let provider = client.capabilities().and_then(|capabilities| capabilities.rename_provider.clone());
+        match provider {
+            None | Some(OneOf::Left(false)) => iced::Task::none(),
+            Some(OneOf::Right(options)) if options.prepare_provider == Some(true) => {
+                let id = client.request::<PrepareRenameRequest>(position);
+                self.lsp_requests.insert((server, id), LspRequest::PrepareRename { path, at });
+                iced::Task::none()
+            }
+            Some(_) => self.show_rename(path, at, word),
+        }
+    }
+
+    fn show_rename(&mut self, path: PathBuf, at: Position, name: String) -> iced::Task<Message> {
+        self.rename = Some(Rename { path, at, old_name: name.clone(), new_name: name });
+        let input = self.rename_input_id.clone();
+        iced::widget::operation::focus(input.clone()).chain(iced::widget::operation::select_all(input))
+    }
+
+    /// Asks for the edits that give a symbol its new name, unless the name didn't change.
+    fn request_rename(&mut self, rename: Rename) {
+        let new_name = rename.new_name.trim();
+        if new_name.is_empty() || new_name == rename.old_name {
+            return;
+        }
+        let active = self.active_tab.and_then(|idx| self.tabs.get(idx));
+        if active.is_none_or(|tab| tab.path != rename.path) {
+            return;
+        }
+        let Some((server, position)) = self.position_params(rename.at) else { return; };
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let id = client.request::<RenameRequest>(RenameParams {
+            text_document_position: position,
+            new_name: new_name.to_string(),
+            work_done_progress_params: Default::default(),
+        });
+        let title = format!("Rename {} to {}", rename.old_name, new_name);
+        self.lsp_requests.insert((server, id), LspRequest::Rename { title });
+    }
+
+    /// Shows what a workspace edit will change, with each changed line before and after.
+    fn preview_workspace_edit(&mut self, title: String, edit: WorkspaceEdit) {
+        let changes = refactor::changes(edit);
+        let mut files = Vec::new();
+        for change in &changes {
+            let FileChange::Edit { path, edits } = change else { continue; };
+            let read;
+            let document = match self.document_at(path) {
+                Some(document) => document,
+                None => {
+                    read = Document::with_text(&std::fs::read_to_string(path).unwrap_or_default(), "");
+                    &read
+                }
+            };
+            let edits: Vec<TextEdit> = edits.iter().map(|edit| TextEdit::from_lsp(edit.clone(), document)).collect();
+            files.push((path.clone(), refactor::preview_lines(document, &edits)));
+        }
+        self.edit_preview = Some(EditPreview { title, changes, files });
+    }
+
+    /// Makes a workspace edit's changes. Open files are edited in their tabs, one undo step each,
+    /// and the rest on disk. Returns whether every change could be made.
+    fn apply_workspace_edit(&mut self, changes: Vec<FileChange>) -> bool {
+        let mut applied = true;
+        let mut fail = |what: String, e: std::io::Error| {
+            eprintln!("Failed to {}: {}", what, e);
+            applied = false;
+        };
+        for change in changes {
+            match change {
+                FileChange::Edit { path, edits } => {
+                    if let Some(document) = self.document_at(&path) {
+                        let edits = edits.into_iter().map(|edit| TextEdit::from_lsp(edit, document)).collect();
+                        self.edit_file(&path, edits);
+                        continue;
+                    }
+                    let result = std::fs::read_to_string(&path).and_then(|text| {
+                        let mut document = Document::with_text(&text, "");
+                        let edits = edits.into_iter().map(|edit| TextEdit::from_lsp(edit, &document)).collect();
+                        document.perform(Action::ApplyEdits(edits));
+                        std::fs::write(&path, document.text())
+                    });
+                    if let Err(e) = result {
+                        fail(format!("edit {}", path.display()), e);
+                    }
+                }
+                FileChange::Create(path) => {
+                    let result = match path.parent() {
+                        _ if path.exists() => Ok(()),
+                        Some(parent) => std::fs::create_dir_all(parent).and_then(|_| std::fs::write(&path, "")),
+                        None => std::fs::write(&path, ""),
+                    };
+                    if let Err(e) = result {
+                        fail(format!("create {}", path.display()), e);
+                    }
+                }
+                FileChange::Rename { from, to } => {
+                    if let Err(e) = std::fs::rename(&from, &to) {
+                        fail(format!("rename {}", from.display()), e);
+                        continue;
+                    }
+                    // Tabs follow their file to its new name, and its server hears about it as a new file
+                    for idx in 0..self.tabs.len() {
+                        let Some(rest) = self.tabs[idx].path.strip_prefix(&from).ok().map(Path::to_path_buf) else { continue; };
+                        if let Ok(uri) = Url::from_file_path(&self.tabs[idx].path) {
+                            self.servers.close(&uri);
+                        }
+                        let tab = &mut self.tabs[idx];
+                        tab.path = if rest.as_os_str().is_empty() { to.clone() } else { to.join(rest) };
+                        tab.name = tab.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        self.open_tab(idx);
+                    }
+                }
+                FileChange::Delete(path) => {
+                    let result = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+                    if let Err(e) = result {
+                        fail(format!("delete {}", path.display()), e);
+                    }
+                }
+            }
+        }
+        if let Some(ref mut tree) = self.file_tree {
+            tree.refresh();
+        }
+        applied
+    }
+
+    /// Makes edits to a file open in a tab, as one undo step.
+    fn edit_file(&mut self, path: &Path, edits: Vec<TextEdit>) {
+        let open = self.tabs.iter().position(|tab| tab.path == path && matches!(tab.kind, TabKind::Editor { .. }));
+        let Some(idx) = open else { return; };
+        let TabKind::Editor { ref mut content, ref mut modified } = self.tabs[idx].kind else { return; };
+        if edits.is_empty() {
+            return;
+        }
+        content.perform(Action::ApplyEdits(edits));
+        *modified = true;
+        if self.active_tab == Some(idx) {
+            let cursor = content.cursor();
+            self.cursor_line = cursor.line + 1;
+            self.cursor_col = cursor.column + 1;
+        }
+        content.refresh_fold_regions();
+        self.sync_tab(idx);
+        self.refresh_outline(idx);
+    }
+
+    /// Formats the active tab, or some of its lines, with its language server if it can and
+    /// with the language's formatter command if not. With `save`, the file is saved afterwards
+    /// whether that worked or not, and formatting gets `editor.formatOnSaveTimeout` to finish.
+    fn format(&mut self, lines: Option<(usize, usize)>, save: bool) -> iced::Task<Message> {
+        let Some(idx) = self.active_tab else { return iced::Task::none(); };
+        let Some(tab) = self.tabs.get(idx) else { return iced::Task::none(); };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return iced::Task::none(); };
+        let path = tab.path.clone();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_string();
+        let formatting = Formatting { path: path.clone(), version: document.version(), lines, save };
+        let timeout = match save {
+            true => Duration::from_millis(self.config.editor_for(&ext).format_on_save_timeout),
+            false => FORMAT_TIMEOUT,
+        };
+        let indentation = document.indentation();
+        let options = FormattingOptions { tab_size: indentation.size as u32, insert_spaces: !indentation.tabs, ..Default::default() };
+        let range = lines.map(|(first, last)| lsp_types::Range {
+            start: lsp_types::Position { line: first as u32, character: 0 },
+            end: lsp_types::Position {
+                line: last as u32,
+                character: document.utf16_column(Position::new(last, document.line_len(last))) as u32,
+            },
+        });
+        let text = document.text();
+
+        if let Some((server, position)) = self.cursor_params() {
+            let client = self.servers.get_mut(server);
+            let capabilities = client.as_ref().and_then(|client| client.capabilities());
+            let formats = capabilities
+                .and_then(|capabilities| capabilities.document_formatting_provider.as_ref())
+                .is_some_and(|provider| !matches!(provider, OneOf::Left(false)));
+            let formats_ranges = capabilities
+                .and_then(|capabilities| capabilities.document_range_formatting_provider.as_ref())
+                .is_some_and(|provider| !matches!(provider, OneOf::Left(false)));
+            let id = match (client, range) {
+                (Some(client), None) if formats => {
+                    Some(client.request::<FormattingRequest>(DocumentFormattingParams {
+                        text_document: position.text_document,
+                        options,
+                        work_done_progress_params: Default::default(),
+                    }))
+                }
+                (Some(client), Some(range)) if formats_ranges => {
+                    Some(client.request::<RangeFormatting>(DocumentRangeFormattingParams {
+                        text_document: position.text_document,
+                        range,
+                        options,
+                        work_done_progress_params: Default::default(),
+                    }))
+                }
+                _ => None,
+            };
+            if let Some(id) = id {
+                self.lsp_requests.insert((server, id), LspRequest::Format(formatting.clone()));
+                if !save {
+                    return iced::Task::none();
+                }
+                let (done, waited) = iced::futures::channel::oneshot::channel();
+                std::thread::spawn(move || {
+                    std::thread::sleep(timeout);
+                    let _ = done.send(());
+                });
+                return iced::Task::perform(async move { waited.await.ok() }, move |_| Message::FormatTimedOut(formatting.clone()));
+            }
+        }
+
+        if let Some(formatter) = self.config.formatter(&ext).cloned() {
+            return iced::Task::perform(
+                async move { format::run(&formatter, &path, text, timeout) },
+                move |result| Message::Formatted(formatting.clone(), result),
+            );
+        }
+        eprintln!("No formatter for {} files", if ext.is_empty() { "these" } else { ext.as_str() });
+        match save {
+            true => self.save_file(&path),
+            false => iced::Task::none(),
+        }
+    }
+
+    /// Writes a tab's text to its file.
+    fn save_file(&mut self, path: &Path) -> iced::Task<Message> {
+        let Some(TabKind::Editor { content, .. }) = self.tabs.iter().find(|tab| tab.path == path).map(|tab| &tab.kind) else {
+            return iced::Task::none();
+        };
+        let path = path.to_path_buf();
+        // Edits may have moved the folds, so save where they are now
+        self.session.set_folds(path.clone(), content.folded_lines());
+        self.session.save();
+        let text = content.text();
+        if let Ok(uri) = Url::from_file_path(&path) {
+            self.servers.save(&uri);
+        }
+        iced::Task::perform(
+            async move {
+                let result = std::fs::write(&path, text).map_err(|e| e.to_string());
+                (path, result)
+            },
+            |(path, result)| Message::FileSaved(path, result),
+        )
+    }
+
+    /// Asks the active tab's server for the quick fixes and refactorings on offer for the
+    /// selection, passing along the problems it covers.
+    fn request_code_actions(&mut self) {
+        let Some((server, position)) = self.cursor_params() else { return; };
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return; };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return; };
+        let selection = document.selection();
+        let (start, end) = (selection.start(), selection.end());
+        let lsp_position = |at: Position| lsp_types::Position { line: at.line as u32, character: document.utf16_column(at) as u32 };
+        let range = lsp_types::Range { start: lsp_position(start), end: lsp_position(end) };
+        let diagnostics = self
+            .diagnostics
+            .for_file(&tab.path)
+            .iter()
+            .filter(|diagnostic| diagnostic.contains(start) || (diagnostic.start < end && start < diagnostic.end))
+            .map(|diagnostic| diagnostic.original.clone())
+            .collect();
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let actions = client
+            .capabilities()
+            .and_then(|capabilities| capabilities.code_action_provider.as_ref())
+            .is_some_and(|provider| !matches!(provider, CodeActionProviderCapability::Simple(false)));
+        if !actions {
+            return;
+        }
+        let id = client.request::<CodeActionRequest>(CodeActionParams {
+            text_document: position.text_document,
+            range,
+            context: CodeActionContext { diagnostics, only: None, trigger_kind: Some(CodeActionTriggerKind::INVOKED) },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        self.lsp_requests.insert((server, id), LspRequest::CodeActions);
+    }
+
+    /// Applies a code action's edit and then runs its command. One that came without either is
+    /// resolved first, if `resolve` is set and the server can fill it in.
+    fn run_code_action(&mut self, server: ServerId, action: lsp_types::CodeAction, resolve: bool) {
+        if let Some(reason) = refactor::disabled_reason(&CodeActionOrCommand::CodeAction(action.clone())) {
+            eprintln!("{}: {}", action.title, reason);
+            return;
+        }
+        if resolve && action.edit.is_none() {
+            let resolves = self
+                .servers
+                .get(server)
+                .and_then(|client| client.capabilities())
+                .is_some_and(|capabilities| match &capabilities.code_action_provider {
+                    Some(CodeActionProviderCapability::Options(options)) => options.resolve_provider == Some(true),
+                    _ => false,
+                });
+            if let Some(client) = self.servers.get_mut(server).filter(|_| resolves) {
+                let id = client.request::<CodeActionResolveRequest>(action);
+                self.lsp_requests.insert((server, id), LspRequest::ResolveCodeAction);
+                return;
+            }
+        }
+        if let Some(edit) = action.edit {
+            self.apply_workspace_edit(refactor::changes(edit));
+        }
+        if let Some(command) = action.command {
+            self.execute_command(server, command);
+        }
+    }
+
+    /// Runs a command on the server, which applies its own edits through `workspace/applyEdit`.
+    fn execute_command(&mut self, server: ServerId, command: lsp_types::Command) {
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let id = client.request::<ExecuteCommand>(ExecuteCommandParams {
+            command: command.command,
+            arguments: command.arguments.unwrap_or_default(),
+            work_done_progress_params: Default::default(),
+        });
+        self.lsp_requests.insert((server, id), LspRequest::ExecuteCommand);
+    }
+
+    /// Keeps a server's completions, and shows them if they're what the popup is waiting for.
+    fn server_completed(
+        &mut self,
+        server: ServerId,
+        id: i64,
+        path: PathBuf,
+        at: Position,
+        explicit: bool,
+        response: Option<CompletionResponse>,
+    ) {
+        let Some(document) = self.document_at(&path) else { return; };
+        let (items, incomplete) = match response {
+            Some(CompletionResponse::Array(items)) => (items, false),
+            Some(CompletionResponse::List(list)) => (list.items, list.is_incomplete),
+            None => (Vec::new(), false),
+        };
+        // Items that don't say what they replace replace the word before the cursor
+        let line = document.line(at.line);
+        let word = line[..byte_index(line, at.column)].chars().rev().take_while(|c| is_word_char(*c)).count();
+        let items = items.into_iter().map(|item| CompletionItem::from_lsp(server, item, document, at, word)).collect();
+        self.server_completions = Some(ServerItems { path, at, items, incomplete });
+
+        if self.completion_request == Some((server, id)) {
+            self.completion_request = None;
+            // Items from the last answer that are still there stay selected
+            let selected = self.completion.as_ref().map(|completion| completion.selected().label.clone());
+            self.completion = self.complete(explicit);
+            if let (Some(completion), Some(selected)) = (&mut self.completion, selected) {
+                completion.selected = completion.items.iter().position(|ranked| ranked.item.label == selected).unwrap_or(0);
+            }
+            self.show_completion_details();
+        }
+    }
+
+    /// Fills in the details a server sent for an item, wherever it's shown.
+    fn completion_resolved(&mut self, server: ServerId, sent: &lsp_types::CompletionItem, item: lsp_types::CompletionItem) {
+        let Some(path) = self.server_completions.as_ref().map(|items| items.path.clone()) else { return; };
+        let Some(document) = self.tabs.iter().find_map(|tab| match &tab.kind {
+            TabKind::Editor { content, .. } if tab.path == path => Some(content.as_ref()),
+            _ => None,
+        }) else {
+            return;
+        };
+        let kept = self.server_completions.iter_mut().flat_map(|items| items.items.iter_mut());
+        let shown = self.completion.iter_mut().flat_map(|completion| completion.items.iter_mut().map(|ranked| &mut ranked.item));
+        for completion_item in kept.chain(shown) {
+            if completion_item.origin.as_ref().is_some_and(|origin| origin.server == server && origin.item == *sent) {
+                completion_item.resolve(server, item.clone(), document, true);
+            }
+        }
+        self.show_completion_details();
+    }
+
+    /// Applies the edits that come with an accepted item, if its file is still the active tab.
+    fn apply_additional_edits(&mut self, path: &Path, item: lsp_types::CompletionItem) -> iced::Task<Message> {
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)).filter(|tab| tab.path == path) else {
+            return iced::Task::none();
+        };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return iced::Task::none(); };
+        let edits: Vec<TextEdit> = item
+            .additional_text_edits
+            .unwrap_or_default()
+            .into_iter()
+            .map(|edit| TextEdit::from_lsp(edit, document))
+            .collect();
+        if edits.is_empty() {
+            return iced::Task::none();
+        }
+        self.update(Message::EditorAction(Action::ApplyEdits(edits)))
+    }
+
+    /// The document open for a file in some tab.
+    fn document_at(&self, path: &Path) -> Option<&Document> {
+        self.tabs.iter().find_map(|tab| match &tab.kind {
+            TabKind::Editor { content, .. } if tab.path == path => Some(content.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// The document in the active tab, if it's an editor.
+    fn active_document(&self) -> Option<&Document> {
+        match &self.tabs.get(self.active_tab?)?.kind {
+            TabKind::Editor { content, .. } => Some(content),
+            TabKind::Preview { .. } | TabKind::Diff { .. } => None,
+        }
+    }
+
+    /// Starts the language server for a tab's file if it has one, and opens the file on it.
+    fn open_tab(&mut self, idx: usize) {
+        let Some(tab) = self.tabs.get_mut(idx) else { return; };
+        let TabKind::Editor { ref mut content, .. } = tab.kind else { return; };
+        // The server gets the text as it is now, so earlier edits don't need sending
+        content.take_changes();
+        let ext = tab.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let (Some(server), Ok(uri)) = (self.config.language_server(ext), Url::from_file_path(&tab.path)) else {
+            return;
+        };
+        // Files outside the open folder get a server of their own, rooted next to them
+        let root = match &self.file_tree {
+            Some(tree) if tab.path.starts_with(&tree.root) => tree.root.clone(),
+            _ => tab.path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        if let Some(id) = self.servers.start(&server.command, &server.args, &root) {
+            self.servers.open(id, uri, &config::language_id(ext), content.version(), content.text());
+            self.request_inlay_hints(idx);
+        }
+    }
+
+    /// Keeps what a server found in a file, with its positions in the open tab's chars if there is one.
+    fn publish_diagnostics(&mut self, params: PublishDiagnosticsParams) {
+        let Ok(path) = params.uri.to_file_path() else { return; };
+        let document = self.document_at(&path);
+        let diagnostics = params.diagnostics.into_iter().map(|diagnostic| Diagnostic::from_lsp(diagnostic, document)).collect();
+        self.diagnostics.publish(path, diagnostics);
+    }
+
+    /// Opens a file the way clicking it in the tree does, then puts the cursor at `position`.
+    fn open_at(&mut self, path: PathBuf, position: Position) -> iced::Task<Message> {
+        self.pending_goto = Some((path.clone(), position));
+        let task = self.update(Message::FileClicked(path));
+        // Already open, so there's no FileOpened to wait for
+        task.chain(self.go_to_pending())
+    }
+
+    /// Asks the active tab's server where the symbol at the cursor is defined or used.
+    fn request_locations<R: lsp_types::request::Request>(
+        &mut self,
+        label: &'static str,
+        params: impl FnOnce(TextDocumentPositionParams) -> R::Params,
+    ) {
+        let Some((server, position)) = self.cursor_params() else { return; };
+        let symbol = self.active_document().map(|document| {
+            let (start, end) = document.word_bounds(document.cursor());
+            document.text_range(start, end)
+        });
+        let Some(client) = self.servers.get_mut(server) else { return; };
+        let id = client.request::<R>(params(position));
+        self.lsp_requests.insert((server, id), LspRequest::Locations { label, symbol: symbol.unwrap_or_default() });
+    }
+
+    /// Goes straight to a symbol's only location, or lists them all in the search panel.
+    fn show_locations(&mut self, label: &'static str, symbol: String, response: Option<GotoDefinitionResponse>) -> iced::Task<Message> {
+        let locations: Vec<(Url, lsp_types::Position)> = match response {
+            None => Vec::new(),
+            Some(GotoDefinitionResponse::Scalar(location)) => vec![(location.uri, location.range.start)],
+            Some(GotoDefinitionResponse::Array(locations)) => {
+                locations.into_iter().map(|location| (location.uri, location.range.start)).collect()
+            }
+            Some(GotoDefinitionResponse::Link(links)) => {
+                links.into_iter().map(|link| (link.target_uri, link.target_selection_range.start)).collect()
+            }
+        };
+
+        // Closed files are read once each, for the lines the locations are on
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let mut matches = Vec::new();
+        for (uri, position) in locations {
+            let Ok(path) = uri.to_file_path() else { continue; };
+            let line = position.line as usize;
+            let line_content = match self.document_at(&path) {
+                Some(document) => document.line(line).to_string(),
+                None => {
+                    let lines = files.entry(path.clone()).or_insert_with(|| {
+                        std::fs::read_to_string(&path).map(|text| text.lines().map(String::from).collect()).unwrap_or_default()
+                    });
+                    lines.get(line).cloned().unwrap_or_default()
+                }
+            };
+            let column = column_from_utf16(&line_content, position.character as usize);
+            matches.push((path, SearchMatch { line_number: line + 1, column, line_content }));
+        }
+
+        match matches.len() {
+            0 => eprintln!("No {} found for {}", label.to_lowercase(), symbol),
+            1 => {
+                let (path, found) = matches.remove(0);
+                return self.open_at(path, Position::new(found.line_number - 1, found.column));
+            }
+            _ => {
+                self.search_results = search::group_by_file(matches);
+                self.search_title = Some(format!("{}: {}", label, symbol));
+                self.search_query.clear();
+                self.search_visible = true;
+            }
+        }
+        iced::Task::none()
+    }
+
+    /// Moves the cursor to where `pending_goto` says, if its file is the active tab by now.
+    fn go_to_pending(&mut self) -> iced::Task<Message> {
+        let Some((path, position)) = self.pending_goto.take() else { return iced::Task::none(); };
+        let active = self.active_tab.and_then(|idx| self.tabs.get(idx));
+        if active.is_some_and(|tab| tab.path == path && matches!(tab.kind, TabKind::Editor { .. })) {
+            return self.update(Message::EditorAction(Action::GoTo(position)));
+        }
+        self.pending_goto = Some((path, position));
+        iced::Task::none()
+    }
+
+    /// Tells a tab's language server what changed in it.
+    /// Also marks what's changed in it since git's version.
+    fn sync_tab(&mut self, idx: usize) {
+        let Some(tab) = self.tabs.get_mut(idx) else { return; };
+        let TabKind::Editor { ref mut content, .. } = tab.kind else { return; };
+        let changes = content.take_changes();
+        if changes.is_empty() {
+            return;
+        }
+        let path = tab.path.clone();
+        if let Ok(uri) = Url::from_file_path(&path) {
+            let changes = changes
+                .into_iter()
+                .map(|change| lsp::Change { start: change.start, end: change.end, text: change.text })
+                .collect();
+            self.servers.change(&uri, content.version(), changes, || content.text());
+            self.request_inlay_hints(idx);
+        }
+        self.update_changes(&path);
+    }
+
+    /// The folder git commands run in, the open one.
+    fn git_root(&self) -> Option<PathBuf> {
+        self.file_tree.as_ref().map(|tree| tree.root.clone())
+    }
+
+    /// Reads the git status again after a git command worked, or shows why it didn't in the
+    /// Source Control view.
+    fn after_git(&mut self, result: Result<(), git2::Error>) -> iced::Task<Message> {
+        match result {
+            Ok(()) => {
+                self.scm_error = None;
+                self.update(Message::RefreshGitStatus)
+            }
+            Err(e) => {
+                self.scm_error = Some(e.message().to_string());
+                iced::Task::none()
+            }
+        }
+    }
+
+    /// Reads files open in tabs with nothing unsaved again, after git changed them on disk. It's
+    /// one edit, so it can be undone. Files that are gone stay open as they were.
+    fn reload_tabs(&mut self, only: Option<&Path>) {
+        let unmodified: Vec<PathBuf> = self
+            .tabs
+            .iter()
+            .filter(|tab| matches!(tab.kind, TabKind::Editor { modified: false, .. }))
+            .filter(|tab| only.is_none_or(|path| tab.path == path))
+            .map(|tab| tab.path.clone())
+            .collect();
+        for path in unmodified {
+            let Ok(text) = std::fs::read_to_string(&path) else { continue; };
+            let text = text.replace("\r\n", "\n");
+            let Some(idx) = self.tabs.iter().position(|tab| tab.path == path) else { continue; };
+            let TabKind::Editor { content, .. } = &self.tabs[idx].kind else { continue; };
+            if content.lines().join("\n") == text {
+                continue;
+            }
+            let last = content.line_count() - 1;
+            let end = Position { line: last, column: content.line_len(last) };
+            self.edit_file(&path, vec![TextEdit { start: Position { line: 0, column: 0 }, end, text }]);
+            if let TabKind::Editor { ref mut modified, .. } = self.tabs[idx].kind {
+                *modified = false;
+            }
+        }
     }
 
-    fn view_tab_bar(&self) -> Element<'_, Message> {
-        if self.tabs.is_empty() {
-            return container(text("")).into();
+    /// Compares an open file with what git has of it, for the markers in the gutter. The change
+    /// that was shown closes if they moved.
+    fn update_changes(&mut self, path: &Path) {
+        let document = self.tabs.iter().find_map(|tab| match &tab.kind {
+            TabKind::Editor { content, .. } if tab.path == path => Some(content),
+            _ => None,
+        });
+        let hunks = match (document, self.git_bases.get(path)) {
+            (Some(document), Some(base)) => git::hunks(&base.lines, document.lines()),
+            _ => Vec::new(),
+        };
+        if self.git_changes.get(path).is_some_and(|old| *old == hunks) {
+            return;
+        }
+        if self.hunk_view.as_ref().is_some_and(|(shown, _)| shown == path) {
+            self.hunk_view = None;
         }
+        self.git_changes.insert(path.to_path_buf(), hunks);
+    }
 
-        let tabs: Vec<Element<'_, Message>> = self.tabs
-            .iter()
-            .enumerate()
-            .map(|(idx, tab)| {
-                let is_active = self.active_tab == Some(idx);
-                let is_modified = matches!(&tab.kind, TabKind::Editor { modified: true, .. });
-                let close_icon = if is_modified {
-                    text("●").size(10).color(THEME.text_muted)
-                } else {
-                    text("x").size(10).color(THEME.text_dim)
-                };
+    /// The edit that puts a change's lines back the way git has them, taking the line breaks
+    /// around them along so whole lines come and go.
+    fn revert_edit(&self, path: &Path, index: usize) -> Option<TextEdit> {
+        let document = self.tabs.iter().find_map(|tab| match &tab.kind {
+            TabKind::Editor { content, .. } if tab.path == path => Some(content),
+            _ => None,
+        })?;
+        let hunk = self.git_changes.get(path)?.get(index)?;
+        let base = &self.git_bases.get(path)?.lines[hunk.base.clone()];
+        let (start, end) = (hunk.current.start, hunk.current.end);
+        let line_end = |line: usize| Position { line, column: document.line_len(line) };
+        let edit = if end < document.line_count() {
+            let text = base.iter().map(|line| format!("{}\n", line)).collect();
+            TextEdit { start: Position { line: start, column: 0 }, end: Position { line: end, column: 0 }, text }
+        } else if start > 0 {
+            // Down to the end of the file, so the line break before it goes instead
+            let text = base.iter().map(|line| format!("\n{}", line)).collect();
+            TextEdit { start: line_end(start - 1), end: line_end(end - 1), text }
+        } else {
+            TextEdit { start: Position { line: 0, column: 0 }, end: line_end(end - 1), text: base.join("\n") }
+        };
+        Some(edit)
+    }
 
-                button(
-                    row![
-                        text(&tab.name).size(12).color(THEME.text_muted),
-                        button(close_icon)
-                            .style(tab_close_button_style)
-                            .on_press(Message::TabClosed(idx))
-                            .padding(2),
-                    ]
-                    .spacing(8)
-                    .align_y(iced::Alignment::Center)
-                )
-                .style(tab_button_style(is_active))
-                .on_press(Message::TabSelected(idx))
-                .padding(iced::Padding { top: 8.0, right: 16.0, bottom: 8.0, left: 16.0 })
-                .into()
-            })
-            .collect();
+    /// Moves the cursor to the start of the next change after it, or the previous one before it,
+    /// going round to the other end of the file.
+    fn go_to_change(&mut self, forward: bool) -> iced::Task<Message> {
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return iced::Task::none(); };
+        let TabKind::Editor { content, .. } = &tab.kind else { return iced::Task::none(); };
+        let Some(hunks) = self.git_changes.get(&tab.path).filter(|hunks| !hunks.is_empty()) else { return iced::Task::none(); };
+        let line = content.cursor().line;
+        // Deleted lines at the very end are marked on the last line
+        let starts: Vec<usize> = hunks.iter().map(|hunk| hunk.current.start.min(content.line_count() - 1)).collect();
+        let target = match forward {
+            true => starts.iter().find(|&&start| start > line).or(starts.first()),
+            false => starts.iter().rev().find(|&&start| start < line).or(starts.last()),
+        };
+        let Some(&target) = target else { return iced::Task::none(); };
+        self.update(Message::EditorAction(Action::GoTo(Position { line: target, column: 0 })))
+    }
 
-        container(row(tabs).spacing(6))
-            .padding(iced::Padding { top: 8.0, right: 12.0, bottom: 8.0, left: 12.0 })
-            .width(Length::Fill)
-            .style(tab_bar_style)
-            .into()
+    /// The diff in the active tab, if it's a diff tab.
+    fn active_diff(&self) -> Option<&TextDiff> {
+        match &self.tabs.get(self.active_tab?)?.kind {
+            TabKind::Diff { diff, .. } => Some(diff),
+            _ => None,
+        }
     }
 
-    fn view_search_panel(&self) -> Element<'_, Message> {
-        let input = text_input("Search across workspace...", &self.search_query)
-            .id(self.search_input_id.clone())
-            .on_input(Message::SearchQueryChanged)
-            .style(search_input_style)
-            .size(13)
-            .padding(10)
-            .width(Length::Fill);
+    /// Moves the active diff tab on to its next or previous hunk, going round at the ends.
+    fn go_to_hunk(&mut self, forward: bool) -> iced::Task<Message> {
+        let Some(TabKind::Diff { diff, .. }) = self.active_tab.and_then(|idx| self.tabs.get_mut(idx)).map(|tab| &mut tab.kind) else {
+            return iced::Task::none();
+        };
+        if diff.navigate(forward).is_none() {
+            return iced::Task::none();
+        }
+        self.scroll_to_hunk()
+    }
 
-        let mut content_col = column![input].spacing(6);
+    /// Scrolls the active diff tab to the hunk it's at.
+    fn scroll_to_hunk(&self) -> iced::Task<Message> {
+        let Some(TabKind::Diff { diff, scroll_id, .. }) = self.active_tab.and_then(|idx| self.tabs.get(idx)).map(|tab| &tab.kind) else {
+            return iced::Task::none();
+        };
+        let Some(current) = diff.current else { return iced::Task::none(); };
+        let (_, starts) = diff.rows();
+        let offset = scrollable::AbsoluteOffset { x: None, y: Some(hunk_offset(starts[current])) };
+        iced::widget::operation::scroll_to(scroll_id.clone(), offset)
+    }
 
-        if !self.search_results.is_empty() {
-            let mut result_items: Vec<Element<'_, Message>> = Vec::new();
+    /// Opens a diff in a tab, or in the tab already showing the same comparison.
+    fn open_diff(&mut self, path: PathBuf, name: String, diff: TextDiff, preview: bool) {
+        let open = self.tabs.iter().position(|tab| tab.path == path && tab.name == name && matches!(tab.kind, TabKind::Diff { .. }));
+        let kind = TabKind::Diff { diff: Box::new(diff), scroll_id: iced::widget::Id::unique(), preview };
+        match open {
+            Some(idx) => {
+                self.tabs[idx].kind = kind;
+                self.active_tab = Some(idx);
+            }
+            None => {
+                self.tabs.push(Tab { path, name, kind });
+                self.active_tab = Some(self.tabs.len() - 1);
+            }
+        }
+    }
 
-            for result in &self.search_results {
-                result_items.push(
-                    container(
-                        text(&result.file_name)
-                            .size(11)
-                            .color(THEME.text_secondary)
-                    )
-                    .padding(iced::Padding { top: 6.0, right: 6.0, bottom: 2.0, left: 6.0 })
-                    .into()
-                );
+    /// A file's text as it is in its tab, or on disk if it isn't open.
+    fn current_text(&self, path: &Path) -> Option<String> {
+        if let Some(document) = self.document_at(path) {
+            return Some(document.text());
+        }
+        match std::fs::read_to_string(path) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
 
-                for m in result.matches.iter().take(3) {
-                    let line_text = format!("  {}:  {}", m.line_number, m.line_content.trim());
-                    let path = result.path.clone();
-                    let line_num = m.line_number;
+    /// Closes the diff tabs of a workspace edit's preview, once it's been applied or discarded.
+    fn close_preview_diffs(&mut self) {
+        for idx in (0..self.tabs.len()).rev() {
+            if matches!(self.tabs[idx].kind, TabKind::Diff { preview: true, .. }) {
+                let _ = self.update(Message::TabClosed(idx));
+            }
+        }
+    }
 
-                    result_items.push(
-                        button(
-                            text(line_text)
-                                .size(11)
-                                .color(THEME.text_muted)
-                        )
-                        .style(tree_button_style)
-                        .on_press(Message::SearchResultClicked(path, line_num))
-                        .padding(iced::Padding { top: 3.0, right: 6.0, bottom: 3.0, left: 12.0 })
-                        .width(Length::Fill)
-                        .into()
-                    );
-                }
+    /// Closes a tab's file on its language server, unless another tab still has it open.
+    fn close_tab(&mut self, tab: &Tab) {
+        let TabKind::Editor { content, .. } = &tab.kind else { return; };
+        // Edits since the last fold or save moved the folded lines along with them
+        self.session.set_folds(tab.path.clone(), content.folded_lines());
+        self.session.save();
+        let still_open = self.tabs.iter().any(|other| other.path == tab.path && matches!(other.kind, TabKind::Editor { .. }));
+        if let (false, Ok(uri)) = (still_open, Url::from_file_path(&tab.path)) {
+            self.servers.close(&uri);
+        }
+        if !still_open {
+            self.outlines.remove(&tab.path);
+            self.git_bases.remove(&tab.path);
+            self.git_changes.remove(&tab.path);
+            self.git_blames.remove(&tab.path);
+            if self.hunk_view.as_ref().is_some_and(|(path, _)| *path == tab.path) {
+                self.hunk_view = None;
+            }
+        }
+        self.breadcrumb_menu = None;
+    }
 
-                if result.matches.len() > 3 {
-                    result_items.push(
-                        container(
-                            text(format!("  ... and {} more", result.matches.len() - 3))
-                                .size(10)
-                                .color(THEME.text_dim)
-                        )
-                        .padding(iced::Padding { top: 1.0, right: 6.0, bottom: 2.0, left: 12.0 })
-                        .into()
-                    );
-                }
+    /// Works out the symbols in a tab's file for the outline and breadcrumbs, asking its
+    /// language server if it has one that can say and going by the grammar if not.
+    fn refresh_outline(&mut self, idx: usize) {
+        let Some(tab) = self.tabs.get(idx) else { return; };
+        let TabKind::Editor { content: document, .. } = &tab.kind else { return; };
+        let server = Url::from_file_path(&tab.path).ok().and_then(|uri| Some((self.servers.server_for(&uri)?, uri)));
+        if let Some((server, uri)) = server {
+            let client = self.servers.get_mut(server);
+            let capable = client
+                .as_ref()
+                .and_then(|client| client.capabilities())
+                .and_then(|capabilities| capabilities.document_symbol_provider.as_ref())
+                .is_some_and(|provider| !matches!(provider, OneOf::Left(false)));
+            if let (true, Some(client)) = (capable, client) {
+                let id = client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+                    text_document: TextDocumentIdentifier { uri },
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                });
+                let request = LspRequest::DocumentSymbols { path: tab.path.clone(), version: document.version() };
+                self.lsp_requests.insert((server, id), request);
+                return;
             }
+        }
+        let symbols = outline::from_syntax(document);
+        self.outlines.insert(tab.path.clone(), symbols);
+    }
 
-            let results_scroll = scrollable(
-                column(result_items).spacing(1)
-            )
-            .height(Length::Shrink);
+    /// Refreshes something about a file once it's gone the refresh's delay without an edit. An
+    /// edit while it's waiting only pushes the wait back, so typing doesn't start one per key.
+    fn refresh_later(&mut self, path: PathBuf, refresh: Refresh) -> iced::Task<Message> {
+        // Blame is only kept up to date for the files it's shown for
+        if refresh == Refresh::Blame && !self.git_blames.contains_key(&path) {
+            return iced::Task::none();
+        }
+        let due = Instant::now() + refresh.delay();
+        match self.refreshes_due.insert((path.clone(), refresh), due) {
+            Some(_) => iced::Task::none(),
+            None => delay(refresh.delay(), refresh.message(path)),
+        }
+    }
 
-            content_col = content_col.push(
-                container(results_scroll).max_height(400.0)
-            );
+    /// Waits out the rest of a refresh's delay when the file was edited while it waited, or
+    /// returns `None` once it's due.
+    fn wait_for_edits(&mut self, path: &Path, refresh: Refresh) -> Option<iced::Task<Message>> {
+        let key = (path.to_path_buf(), refresh);
+        let left = self.refreshes_due.get(&key)?.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            self.refreshes_due.remove(&key);
+            return None;
         }
+        Some(delay(left, refresh.message(key.0)))
+    }
 
-        container(content_col)
-            .width(Length::Fixed(320.0))
-            .padding(10)
-            .style(search_panel_style)
-            .into()
+    /// Works out who last changed each line of an open file, in the background, going by its
+    /// text as it is now.
+    fn load_blame(&self, path: &Path) -> iced::Task<Message> {
+        let Some(document) = self.document_at(path) else { return iced::Task::none(); };
+        let (text, version) = (document.text(), document.version());
+        let (done, loaded) = iced::futures::channel::oneshot::channel();
+        let file = path.to_path_buf();
+        std::thread::spawn(move || {
+            let _ = done.send(git::blame(&file, &text).ok());
+        });
+        let path = path.to_path_buf();
+        iced::Task::perform(async move { loaded.await.ok().flatten() }, move |blame| {
+            Message::BlameLoaded(path.clone(), version, blame)
+        })
     }
 
-    fn view_editor(&self) -> Element<'_, Message> {
-        if let Some(idx) = self.active_tab {
-            if let Some(tab) = self.tabs.get(idx) {
-                match &tab.kind {
-                    TabKind::Editor { content, .. } => {
-                        let ext = tab.path.extension()
-                            .and_then(|e| e.to_str())
-                            .unwrap_or("");
-                        return create_editor(content, ext);
-                    }
-                    TabKind::Preview { md_items } => {
-                        return scrollable(
-                            markdown::view(
-                                md_items,
-                                markdown::Settings::with_style(markdown::Style::from_palette(
-                                    iced::theme::Palette::CATPPUCCIN_MOCHA,
-                                )),
-                            )
-                            .map(Message::MarkdownLinkClicked)
-                        )
-                        .height(Length::Fill)
-                        .into();
-                    }
-                }
+    /// Reads the history of the active tab's file for the File History view, while it's shown.
+    /// It's read again when `reload` is set, and otherwise only for another file.
+    fn load_history(&mut self, reload: bool) -> iced::Task<Message> {
+        if self.sidebar_view != SidebarView::History {
+            return iced::Task::none();
+        }
+        let Some(path) = self.active_tab.and_then(|idx| self.tabs.get(idx)).map(|tab| tab.path.clone()) else {
+            return iced::Task::none();
+        };
+        if !reload && self.file_history.as_ref().is_some_and(|(file, _)| *file == path) {
+            return iced::Task::none();
+        }
+        let (done, loaded) = iced::futures::channel::oneshot::channel();
+        let file = path.clone();
+        std::thread::spawn(move || {
+            let _ = done.send(git::history(&file).ok());
+        });
+        iced::Task::perform(async move { loaded.await.ok().flatten() }, move |commits| {
+            Message::HistoryLoaded(path.clone(), commits)
+        })
+    }
+
+    /// The breadcrumbs for the active tab: the folders from the workspace down to its file,
+    /// then the symbols the cursor is in, outermost first.
+    fn breadcrumbs(&self) -> Vec<Crumb> {
+        let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return Vec::new(); };
+        let root = match &self.file_tree {
+            Some(tree) if tab.path.starts_with(&tree.root) => tree.root.as_path(),
+            _ => tab.path.parent().unwrap_or(Path::new("")),
+        };
+        let mut crumbs = Vec::new();
+        let mut folder = root.to_path_buf();
+        let folders = tab.path.strip_prefix(root).ok().and_then(Path::parent);
+        for component in folders.into_iter().flat_map(Path::components) {
+            folder.push(component);
+            crumbs.push(Crumb::Folder(folder.clone()));
+        }
+        crumbs.push(Crumb::File(tab.path.clone()));
+
+        if let (Some(document), Some(symbols)) = (self.active_document(), self.outlines.get(&tab.path)) {
+            let mut level = symbols.as_slice();
+            for index in outline::path_to(symbols, document.cursor()) {
+                crumbs.push(Crumb::symbol(&level[index]));
+                level = &level[index].children;
             }
         }
-        empty_editor()
+        crumbs
+    }
+
+    /// What a breadcrumb's dropdown lists: what else is in its folder, or the symbols next to it.
+    fn breadcrumb_siblings(&self, index: usize) -> Vec<Crumb> {
+        let crumbs = self.breadcrumbs();
+        match crumbs.get(index) {
+            Some(Crumb::Folder(path) | Crumb::File(path)) => {
+                let Some(folder) = path.parent() else { return Vec::new(); };
+                file_tree::scan_directory(folder)
+                    .into_iter()
+                    .map(|entry| match entry {
+                        FileEntry::Directory { path, .. } => Crumb::Folder(path),
+                        FileEntry::File { path, .. } => Crumb::File(path),
+                    })
+                    .collect()
+            }
+            Some(Crumb::Symbol { .. }) => {
+                let Some(tab) = self.active_tab.and_then(|idx| self.tabs.get(idx)) else { return Vec::new(); };
+                let (Some(document), Some(symbols)) = (self.active_document(), self.outlines.get(&tab.path)) else {
+                    return Vec::new();
+                };
+                // The symbols come after the folders and the file
+                let depth = index - crumbs.iter().filter(|crumb| !matches!(crumb, Crumb::Symbol { .. })).count();
+                let path = outline::path_to(symbols, document.cursor());
+                outline::level(symbols, &path[..depth]).iter().map(Crumb::symbol).collect()
+            }
+            None => Vec::new(),
+        }
     }
 
     fn view_status_bar(&self) -> Element<'_, Message> {
+        let position = text(format!("Ln {}, Col {}", self.cursor_line, self.cursor_col))
+            .size(10)
+            .color(THEME.text_placeholder);
+        // Clicking the indentation opens the commands to change or convert it
+        let indentation: Element<'_, Message> = match self.active_document() {
+            Some(document) => button(text(document.indentation().to_string()).size(10).color(THEME.text_placeholder))
+                .style(tree_button_style)
+                .padding(iced::Padding { top: 0.0, right: 6.0, bottom: 0.0, left: 6.0 })
+                .on_press(Message::ToggleIndentMenu)
+                .into(),
+            None => text("").into(),
+        };
+
+        // Clicking the counts opens the Problems panel
+        let problems = button(
+            text(format!(
+                "{} {}  {} {}",
+                Severity::Error.icon(),
+                self.diagnostics.count(Severity::Error),
+                Severity::Warning.icon(),
+                self.diagnostics.count(Severity::Warning),
+            ))
+            .size(10)
+            .color(THEME.text_placeholder),
+        )
+        .style(tree_button_style)
+        .padding(iced::Padding { top: 0.0, right: 6.0, bottom: 0.0, left: 6.0 })
+        .on_press(Message::ToggleProblems);
+
+        // The checked out branch, which opens the Source Control view
+        let branch: Element<'_, Message> = match self.git_status.as_ref().and_then(|status| status.head.as_ref()) {
+            Some(head) => button(text(format!("⎇ {}", head)).size(10).color(THEME.text_placeholder))
+                .style(tree_button_style)
+                .padding(iced::Padding { top: 0.0, right: 6.0, bottom: 0.0, left: 6.0 })
+                .on_press(Message::SidebarViewChanged(SidebarView::SourceControl))
+                .into(),
+            None => text("").into(),
+        };
+
+        // Turns the blame gutter on and off, for files git has a history of
+        let blame: Element<'_, Message> = match self.active_tab.and_then(|idx| self.tabs.get(idx)) {
+            Some(tab) if self.git_blames.contains_key(&tab.path) => {
+                let color = if self.blame_gutter { THEME.text_secondary } else { THEME.text_placeholder };
+                button(text("Blame").size(10).color(color))
+                    .style(tree_button_style)
+                    .padding(iced::Padding { top: 0.0, right: 6.0, bottom: 0.0, left: 6.0 })
+                    .on_press(Message::ToggleBlame)
+                    .into()
+            }
+            _ => text("").into(),
+        };
+
         container(
-            text(format!("Ln {}, Col {}", self.cursor_line, self.cursor_col))
-                .size(10)
-                .color(THEME.text_placeholder)
+            row![branch, blame, problems, position, iced::widget::Space::new().width(Length::Fill), indentation]
+                .align_y(iced::Alignment::Center)
         )
         .padding(iced::Padding { top: 4.0, right: 12.0, bottom: 6.0, left: 12.0 })
         .width(Length::Fill)
@@ -729,6 +3179,114 @@ impl App {
         .into()
     }
 
+    /// Every problem in the workspace, grouped by file, with the severities and files the filters
+    /// leave in.
+    fn view_problems_panel(&self) -> Element<'_, Message> {
+        let filter = self.problems_filter.to_lowercase();
+        let root = self.file_tree.as_ref().map(|tree| tree.root.as_path());
+        let relative = |path: &Path| -> String {
+            root.and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path).to_string_lossy().into_owned()
+        };
+        let color = |severity: Severity| match severity {
+            Severity::Error => THEME.error,
+            Severity::Warning => THEME.warning,
+            Severity::Information => THEME.info,
+            Severity::Hint => THEME.hint,
+        };
+
+        let toggles: Vec<Element<'_, Message>> = Severity::LISTED
+            .iter()
+            .map(|&severity| {
+                let label = format!("{} {} {}", severity.icon(), self.diagnostics.count(severity), severity.label());
+                button(text(label).size(11))
+                    .style(tab_button_style(!self.problems_hidden.contains(&severity)))
+                    .on_press(Message::ProblemsSeverityToggled(severity))
+                    .padding(iced::Padding { top: 3.0, right: 8.0, bottom: 3.0, left: 8.0 })
+                    .into()
+            })
+            .collect();
+        let header = row![
+            text("PROBLEMS").size(11).color(THEME.text_muted),
+            row(toggles).spacing(4),
+            text_input("Filter by file", &self.problems_filter)
+                .on_input(Message::ProblemsFilterChanged)
+                .style(search_input_style)
+                .size(11)
+                .padding(4)
+                .width(Length::Fixed(200.0)),
+            iced::widget::Space::new().width(Length::Fill),
+            button(text("x").size(11).color(THEME.text_dim))
+                .style(tab_close_button_style)
+                .on_press(Message::ToggleProblems)
+                .padding(2),
+        ]
+        .spacing(12)
+        .align_y(iced::Alignment::Center);
+
+        let mut items: Vec<Element<'_, Message>> = Vec::new();
+        for (path, diagnostics) in self.diagnostics.files() {
+            let name = relative(path);
+            if !filter.is_empty() && !name.to_lowercase().contains(&filter) {
+                continue;
+            }
+            let shown: Vec<&Diagnostic> = diagnostics
+                .iter()
+                .filter(|diagnostic| Severity::LISTED.contains(&diagnostic.severity))
+                .filter(|diagnostic| !self.problems_hidden.contains(&diagnostic.severity))
+                .collect();
+            if shown.is_empty() {
+                continue;
+            }
+
+            items.push(
+                container(
+                    row![
+                        text(name).size(11).color(THEME.text_secondary),
+                        text(shown.len().to_string()).size(10).color(THEME.text_dim),
+                    ]
+                    .spacing(8)
+                )
+                .padding(iced::Padding { top: 6.0, right: 6.0, bottom: 2.0, left: 6.0 })
+                .into()
+            );
+            for diagnostic in shown {
+                // Only the first line of the message, the rest is in the hover
+                let message = diagnostic.message.lines().next().unwrap_or_default().to_string();
+                let location = format!("[Ln {}, Col {}]", diagnostic.start.line + 1, diagnostic.start.column + 1);
+                items.push(
+                    button(
+                        row![
+                            text(diagnostic.severity.icon()).size(11).color(color(diagnostic.severity)),
+                            text(message).size(11).color(THEME.text_muted),
+                            text(diagnostic.origin().unwrap_or_default()).size(10).color(THEME.text_dim),
+                            text(location).size(10).color(THEME.text_dim),
+                        ]
+                        .spacing(8)
+                    )
+                    .style(tree_button_style)
+                    .on_press(Message::ProblemClicked(path.clone(), diagnostic.start))
+                    .padding(iced::Padding { top: 3.0, right: 6.0, bottom: 3.0, left: 16.0 })
+                    .width(Length::Fill)
+                    .into()
+                );
+            }
+        }
+        let list: Element<'_, Message> = if items.is_empty() {
+            container(text("No problems have been detected in the workspace.").size(11).color(THEME.text_dim))
+                .padding(6)
+                .into()
+        } else {
+            scrollable(column(items).spacing(1)).height(Length::Fill).into()
+        };
+
+        container(column![header, list].spacing(6))
+            .padding(10)
+            .width(Length::Fill)
+            .height(Length::Fixed(PROBLEMS_PANEL_HEIGHT))
+            .style(editor_container_style)
+            .into()
+    }
+
     fn view_welcome_screen(&self) -> iced::widget::Container<'_, Message> {
         let folder_name = self.file_tree
             .as_ref()
@@ -749,10 +3307,53 @@ impl App {
         .center_y(Length::Fill)
     }
 
+    fn view_indent_menu(&self) -> Element<'_, Message> {
+        let current = self.active_document().map(Document::indentation).unwrap_or_default();
+        let size = current.size;
+        let commands = [
+            ("Indent Using Tabs", Action::SetIndentation(Indentation { tabs: true, size })),
+            ("Indent Using 2 Spaces", Action::SetIndentation(Indentation { tabs: false, size: 2 })),
+            ("Indent Using 4 Spaces", Action::SetIndentation(Indentation { tabs: false, size: 4 })),
+            ("Indent Using 8 Spaces", Action::SetIndentation(Indentation { tabs: false, size: 8 })),
+            ("Convert Indentation to Spaces", Action::ConvertIndentation(Indentation { tabs: false, size })),
+            ("Convert Indentation to Tabs", Action::ConvertIndentation(Indentation { tabs: true, size })),
+        ];
+
+        let items: Vec<Element<'_, Message>> = commands
+            .into_iter()
+            .map(|(label, action)| {
+                let is_current = matches!(action, Action::SetIndentation(indentation) if indentation == current);
+                button(text(label).size(12))
+                    .style(file_finder_item_style(is_current))
+                    .on_press(Message::IndentationChosen(action))
+                    .padding(iced::Padding { top: 6.0, right: 10.0, bottom: 6.0, left: 10.0 })
+                    .width(Length::Fill)
+                    .into()
+            })
+            .collect();
+
+        let menu = container(column(items).spacing(2))
+            .padding(6)
+            .width(Length::Fixed(240.0))
+            .style(file_finder_panel_style);
+
+        // Opens upwards from the status bar, under the mouse
+        mouse_area(
+            container(menu)
+                .padding(iced::Padding { top: 0.0, right: 20.0, bottom: 36.0, left: 0.0 })
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .align_right(Length::Fill)
+                .align_bottom(Length::Fill)
+        )
+        .on_press(Message::ToggleIndentMenu)
+        .into()
+    }
+
     fn view_file_finder_overlay(&self) -> Element<'_, Message> {
         use iced::widget::{stack, center, Space, opaque};
 
-        let input = text_input("Go to file...", &self.file_finder_query)
+        let input = text_input("Go to file, or # for a symbol...", &self.file_finder_query)
             .id(self.file_finder_input_id.clone())
             .on_input(Message::FileFinderQueryChanged)
             .size(15)
@@ -808,6 +3409,32 @@ impl App {
                     .into()
                 );
             }
+        } else if self.file_finder_query.starts_with('#') {
+            let root = self.file_tree.as_ref().map(|tree| tree.root.as_path());
+            for (idx, symbol) in self.symbol_finder_results.iter().enumerate() {
+                let is_selected = idx == self.file_finder_selected;
+                let location = format!(
+                    "{}:{}",
+                    root.and_then(|root| symbol.path.strip_prefix(root).ok()).unwrap_or(&symbol.path).to_string_lossy(),
+                    symbol.at.line + 1,
+                );
+                items.push(
+                    button(
+                        row![
+                            symbol_icon(symbol.kind),
+                            text(&symbol.name).size(13).color(if is_selected { THEME.text_primary } else { THEME.text_muted }),
+                            text(location).size(11).color(THEME.text_dim),
+                        ]
+                        .spacing(10)
+                        .align_y(iced::Alignment::Center)
+                    )
+                    .style(file_finder_item_style(is_selected))
+                    .on_press(Message::SearchResultClicked(symbol.path.clone(), symbol.at))
+                    .padding(iced::Padding { top: 7.0, right: 10.0, bottom: 7.0, left: 10.0 })
+                    .width(Length::Fill)
+                    .into()
+                );
+            }
         } else {
             for (idx, (_score, display, abs_path)) in self.file_finder_results.iter().enumerate() {
                 let is_selected = idx == self.file_finder_selected;
@@ -871,3 +3498,134 @@ impl App {
         .into()
     }
 }
+
+/// Finds the definitions in a folder or file on a thread of its own, for the symbol finder.
+fn index_symbols(path: PathBuf) -> iced::Task<Message> {
+    let (done, indexed) = iced::futures::channel::oneshot::channel();
+    let root = path.clone();
+    std::thread::spawn(move || {
+        let _ = done.send(symbol_index::index(&root));
+    });
+    iced::Task::perform(async move { indexed.await.unwrap_or_default() }, move |symbols| Message::SymbolsIndexed(path.clone(), symbols))
+}
+
+/// Reads where the files in the repository `root` is in stand with git, on a thread of its own.
+fn load_git_status(root: PathBuf) -> iced::Task<Message> {
+    let (done, loaded) = iced::futures::channel::oneshot::channel();
+    let path = root.clone();
+    std::thread::spawn(move || {
+        // Folders outside a repository have no status, which isn't worth a message
+        let _ = done.send(git::statuses(&path).ok());
+    });
+    iced::Task::perform(async move { loaded.await.ok().flatten() }, move |statuses| Message::GitStatusLoaded(root.clone(), statuses))
+}
+
+/// Reads what git has of a file on a thread of its own, to compare it with.
+fn load_git_base(path: PathBuf) -> iced::Task<Message> {
+    let (done, loaded) = iced::futures::channel::oneshot::channel();
+    let file = path.clone();
+    std::thread::spawn(move || {
+        // Files outside a repository have nothing to compare with either
+        let _ = done.send(git::base(&file).ok().flatten());
+    });
+    iced::Task::perform(async move { loaded.await.ok().flatten() }, move |base| Message::GitBaseLoaded(path.clone(), base))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_string()
+}
+
+/// Sends `message` once `duration` has passed.
+fn delay(duration: Duration, message: Message) -> iced::Task<Message> {
+    let (done, waited) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = done.send(());
+    });
+    iced::Task::perform(async move { waited.await.ok() }, move |_| message.clone())
+}
+
+/// Ends a started Cmd+K chord on any key that isn't part of one, so a shortcut pressed later
+/// isn't taken as its second half.
+fn chord_subscription() -> Subscription<Message> {
+    use iced::keyboard::key::Named;
+
+    iced::event::listen_with(|event, _status, _id| {
+        let Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, .. }) = event else { return None; };
+        let part_of_chord = match &key {
+            Key::Named(named) => matches!(named, Named::Shift | Named::Control | Named::Alt | Named::Super | Named::Meta),
+            Key::Character(c) => modifiers.command() && !modifiers.shift() && (c.as_str() == "k" || CHORD_KEYS.contains(&c.as_str())),
+            Key::Unidentified => true,
+        };
+        (!part_of_chord).then_some(Message::ChordCancelled)
+    })
+}
+
+/// Watches the open folder for files changing on disk, including git's own files, so the tree
+/// and git status can keep up with what happens outside the editor.
+#[allow(clippy::ptr_arg)] // It's the subscription's data, which is a PathBuf
+fn watch_files(root: &PathBuf) -> impl Stream<Item = Message> + use<> {
+    use notify::Watcher;
+
+    let root = root.clone();
+    iced::stream::channel(100, async move |mut output| {
+        let (changed, mut changes) = mpsc::unbounded();
+        let watched = root.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let relevant = event.is_ok_and(|event| {
+                !event.kind.is_access() && event.paths.iter().any(|path| is_watched(&watched, path))
+            });
+            if relevant {
+                let _ = changed.unbounded_send(());
+            }
+        });
+        // Kept until the folder is closed, which is when this stream is dropped
+        let _watcher = match watcher.and_then(|mut watcher| watcher.watch(&root, notify::RecursiveMode::Recursive).map(|_| watcher)) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("Failed to watch {}: {}", root.display(), e);
+                return;
+            }
+        };
+        while changes.next().await.is_some() {
+            let _ = output.send(Message::FilesChanged).await;
+        }
+    })
+}
+
+/// Whether a change to `path` can change the tree or the git status. Build output doesn't, and
+/// inside `.git` only the index, HEAD and branches do.
+fn is_watched(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else { return false; };
+    let mut components = relative.components().map(|component| component.as_os_str().to_string_lossy());
+    match components.next().as_deref() {
+        Some(".git") => matches!(components.next().as_deref(), Some("index" | "HEAD" | "refs")),
+        Some("target" | "node_modules" | "__pycache__") => false,
+        _ => true,
+    }
+}
+
+/// The params of the requests that go to where a symbol is defined, its type, or its implementations.
+fn definition_params(position: TextDocumentPositionParams) -> GotoDefinitionParams {
+    GotoDefinitionParams {
+        text_document_position_params: position,
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    }
+}
+
+/// Hands the app somewhere for language servers to send their events, then passes the events on
+/// as they come.
+fn lsp_events() -> impl Stream<Item = Message> {
+    iced::stream::channel(100, async |mut output| {
+        let (events, mut received) = mpsc::unbounded();
+        let _ = output.send(Message::LspConnected(events)).await;
+        while let Some(event) = received.next().await {
+            let _ = output.send(Message::Lsp(event)).await;
+        }
+    })
+}
@@ -0,0 +1,448 @@
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use lsp_types::{CompletionItemKind, CompletionTextEdit, Documentation, InsertTextFormat};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::document::{byte_index, is_word_char, Document, Position, TextEdit};
+use crate::lsp::ServerId;
+
+/// How many items the popup keeps after ranking.
+const MAX_ITEMS: usize = 50;
+/// How many items the popup shows at once.
+pub const VISIBLE_ITEMS: usize = 10;
+
+/// What kind of thing an item completes to, shown next to its label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompletionKind {
+    #[default]
+    Word,
+    Snippet,
+    File,
+    Folder,
+    // The rest come from language servers
+    Text,
+    Method,
+    Function,
+    Constructor,
+    Field,
+    Variable,
+    Class,
+    Interface,
+    Module,
+    Property,
+    Unit,
+    Value,
+    Enum,
+    Keyword,
+    Color,
+    Reference,
+    EnumMember,
+    Constant,
+    Struct,
+    Event,
+    Operator,
+    TypeParameter,
+}
+
+impl CompletionKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CompletionKind::Word => "word",
+            CompletionKind::Snippet => "snippet",
+            CompletionKind::File => "file",
+            CompletionKind::Folder => "folder",
+            CompletionKind::Text => "text",
+            CompletionKind::Method => "method",
+            CompletionKind::Function => "function",
+            CompletionKind::Constructor => "constructor",
+            CompletionKind::Field => "field",
+            CompletionKind::Variable => "variable",
+            CompletionKind::Class => "class",
+            CompletionKind::Interface => "interface",
+            CompletionKind::Module => "module",
+            CompletionKind::Property => "property",
+            CompletionKind::Unit => "unit",
+            CompletionKind::Value => "value",
+            CompletionKind::Enum => "enum",
+            CompletionKind::Keyword => "keyword",
+            CompletionKind::Color => "color",
+            CompletionKind::Reference => "reference",
+            CompletionKind::EnumMember => "enum member",
+            CompletionKind::Constant => "constant",
+            CompletionKind::Struct => "struct",
+            CompletionKind::Event => "event",
+            CompletionKind::Operator => "operator",
+            CompletionKind::TypeParameter => "type parameter",
+        }
+    }
+
+    /// The letter shown before the label, Sublime style, since the editor font has no icons.
+    pub fn icon(&self) -> char {
+        match self {
+            CompletionKind::Word | CompletionKind::Text => 'w',
+            CompletionKind::Snippet => 's',
+            CompletionKind::File => '-',
+            CompletionKind::Folder => '/',
+            CompletionKind::Method => 'm',
+            CompletionKind::Function => 'f',
+            CompletionKind::Constructor => 'c',
+            CompletionKind::Field | CompletionKind::Property => 'p',
+            CompletionKind::Variable | CompletionKind::Value => 'v',
+            CompletionKind::Class => 'C',
+            CompletionKind::Interface => 'I',
+            CompletionKind::Module => 'M',
+            CompletionKind::Unit => 'u',
+            CompletionKind::Enum => 'E',
+            CompletionKind::Keyword => 'k',
+            CompletionKind::Color => '#',
+            CompletionKind::Reference => 'r',
+            CompletionKind::EnumMember => 'e',
+            CompletionKind::Constant => 'K',
+            CompletionKind::Struct => 'S',
+            CompletionKind::Event => '!',
+            CompletionKind::Operator => '+',
+            CompletionKind::TypeParameter => 'T',
+        }
+    }
+
+    /// Servers may leave the kind out, and then it's just text.
+    fn from_lsp(kind: Option<CompletionItemKind>) -> Self {
+        let Some(kind) = kind else { return CompletionKind::Text; };
+        match kind {
+            CompletionItemKind::METHOD => CompletionKind::Method,
+            CompletionItemKind::FUNCTION => CompletionKind::Function,
+            CompletionItemKind::CONSTRUCTOR => CompletionKind::Constructor,
+            CompletionItemKind::FIELD => CompletionKind::Field,
+            CompletionItemKind::VARIABLE => CompletionKind::Variable,
+            CompletionItemKind::CLASS => CompletionKind::Class,
+            CompletionItemKind::INTERFACE => CompletionKind::Interface,
+            CompletionItemKind::MODULE => CompletionKind::Module,
+            CompletionItemKind::PROPERTY => CompletionKind::Property,
+            CompletionItemKind::UNIT => CompletionKind::Unit,
+            CompletionItemKind::VALUE => CompletionKind::Value,
+            CompletionItemKind::ENUM => CompletionKind::Enum,
+            CompletionItemKind::KEYWORD => CompletionKind::Keyword,
+            CompletionItemKind::SNIPPET => CompletionKind::Snippet,
+            CompletionItemKind::COLOR => CompletionKind::Color,
+            CompletionItemKind::FILE => CompletionKind::File,
+            CompletionItemKind::REFERENCE => CompletionKind::Reference,
+            CompletionItemKind::FOLDER => CompletionKind::Folder,
+            CompletionItemKind::ENUM_MEMBER => CompletionKind::EnumMember,
+            CompletionItemKind::CONSTANT => CompletionKind::Constant,
+            CompletionItemKind::STRUCT => CompletionKind::Struct,
+            CompletionItemKind::EVENT => CompletionKind::Event,
+            CompletionItemKind::OPERATOR => CompletionKind::Operator,
+            CompletionItemKind::TYPE_PARAMETER => CompletionKind::TypeParameter,
+            _ => CompletionKind::Text,
+        }
+    }
+}
+
+/// One suggestion in the popup.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+    /// Markdown shown next to the popup while it's selected
+    pub documentation: Option<String>,
+    /// How many chars before the cursor it replaces
+    pub replace: usize,
+    /// What goes in instead
+    pub insert: String,
+    /// Whether `insert` is in snippet syntax
+    pub snippet: bool,
+    /// Edits elsewhere in the document that come with it, like an import
+    pub additional_edits: Vec<TextEdit>,
+    /// What a server wants it matched and ordered by, if not its label
+    pub filter_text: Option<String>,
+    pub sort_text: Option<String>,
+    /// The server's own item, if a server suggested it
+    pub origin: Option<ServerItem>,
+}
+
+/// A language server's completion item, kept to ask the server for the rest of it.
+#[derive(Debug, Clone)]
+pub struct ServerItem {
+    pub server: ServerId,
+    pub item: lsp_types::CompletionItem,
+    /// Whether the server has filled in its details already
+    pub resolved: bool,
+}
+
+impl CompletionItem {
+    /// Converts a server's item, suggested for the cursor at `at`. Its columns count UTF-16
+    /// code units, and it replaces the word before `at` unless it says otherwise.
+    pub fn from_lsp(server: ServerId, item: lsp_types::CompletionItem, document: &Document, at: Position, word: usize) -> Self {
+        let (insert, replace) = match &item.text_edit {
+            Some(edit) => {
+                let (range, text) = match edit {
+                    CompletionTextEdit::Edit(edit) => (edit.range, &edit.new_text),
+                    // Replacing what's after the cursor too is an option VS Code has, off by default
+                    CompletionTextEdit::InsertAndReplace(edit) => (edit.insert, &edit.new_text),
+                };
+                let start = document.position_from_utf16(range.start.line as usize, range.start.character as usize);
+                let replace = match start.line == at.line {
+                    true => at.column.saturating_sub(start.column),
+                    false => word,
+                };
+                (text.clone(), replace)
+            }
+            None => (item.insert_text.clone().unwrap_or_else(|| item.label.clone()), word),
+        };
+        let mut converted = Self {
+            label: item.label.clone(),
+            kind: CompletionKind::from_lsp(item.kind),
+            replace,
+            insert,
+            snippet: item.insert_text_format == Some(InsertTextFormat::SNIPPET),
+            filter_text: item.filter_text.clone(),
+            sort_text: item.sort_text.clone(),
+            ..Self::default()
+        };
+        converted.resolve(server, item, document, false);
+        converted
+    }
+
+    /// Takes the details from a server's item, which may have more of them after `completionItem/resolve`.
+    pub fn resolve(&mut self, server: ServerId, item: lsp_types::CompletionItem, document: &Document, resolved: bool) {
+        self.detail = item.detail.clone();
+        self.documentation = item.documentation.clone().map(|documentation| match documentation {
+            Documentation::String(text) => text,
+            Documentation::MarkupContent(content) => content.value,
+        });
+        self.additional_edits = item
+            .additional_text_edits
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|edit| TextEdit::from_lsp(edit, document))
+            .collect();
+        self.origin = Some(ServerItem { server, item, resolved });
+    }
+}
+
+/// What providers get to look at when asked for suggestions.
+pub struct Request<'a> {
+    pub document: &'a Document,
+    pub path: &'a Path,
+    pub extension: &'a str,
+    /// The documents in the other tabs, for words used elsewhere
+    pub others: Vec<&'a Document>,
+    /// Whether it was asked for with Ctrl+Space instead of by typing
+    pub explicit: bool,
+}
+
+impl Request<'_> {
+    /// The text on the cursor's line up to the cursor.
+    pub fn before_cursor(&self) -> &str {
+        let cursor = self.document.cursor();
+        let line = self.document.line(cursor.line);
+        &line[..byte_index(line, cursor.column)]
+    }
+
+    /// The part of a word typed so far, right before the cursor.
+    pub fn word(&self) -> &str {
+        let before = self.before_cursor();
+        let start = before.rfind(|c: char| !is_word_char(c)).map_or(0, |index| index + 1);
+        &before[start..]
+    }
+}
+
+/// Something that suggests completions: words, snippets, paths or a language server.
+pub trait CompletionProvider {
+    fn complete(&self, request: &Request) -> Vec<CompletionItem>;
+}
+
+/// Words from the current document and the other open ones.
+pub struct BufferWords;
+
+impl CompletionProvider for BufferWords {
+    fn complete(&self, request: &Request) -> Vec<CompletionItem> {
+        let typed = request.word();
+        if typed.is_empty() && !request.explicit {
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+        let documents = std::iter::once(request.document).chain(request.others.iter().copied());
+        for (index, document) in documents.enumerate() {
+            for line in document.lines() {
+                for word in line.split(|c: char| !is_word_char(c)) {
+                    // Numbers aren't worth suggesting, and neither is what's typed already
+                    if word.len() < 2 || word == typed || word.starts_with(|c: char| c.is_ascii_digit()) {
+                        continue;
+                    }
+                    if seen.insert(word) {
+                        items.push(CompletionItem {
+                            label: word.to_string(),
+                            kind: CompletionKind::Word,
+                            detail: (index > 0).then(|| "other tab".to_string()),
+                            replace: typed.chars().count(),
+                            insert: word.to_string(),
+                            ..CompletionItem::default()
+                        });
+                    }
+                }
+            }
+        }
+        items
+    }
+}
+
+/// Files and folders, inside a string that looks like a relative path.
+pub struct FilePaths;
+
+impl CompletionProvider for FilePaths {
+    fn complete(&self, request: &Request) -> Vec<CompletionItem> {
+        let before = request.before_cursor();
+        // The string the cursor is in, if an odd number of quotes come before it
+        let Some(quote) = before.rfind(['"', '\'', '`']) else { return Vec::new(); };
+        let quote_char = before[quote..].chars().next().unwrap_or('"');
+        if before[..quote].matches(quote_char).count() % 2 == 1 {
+            return Vec::new();
+        }
+        let typed = &before[quote + 1..];
+        let Some(slash) = typed.rfind('/') else { return Vec::new(); };
+        let (folder, name) = (&typed[..=slash], &typed[slash + 1..]);
+        let base = match folder.starts_with('/') {
+            true => Path::new(folder).to_path_buf(),
+            false => match request.path.parent() {
+                Some(parent) => parent.join(folder),
+                None => return Vec::new(),
+            },
+        };
+
+        let Ok(entries) = fs::read_dir(&base) else { return Vec::new(); };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if file_name.starts_with('.') && !name.starts_with('.') {
+                    return None;
+                }
+                let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+                Some(CompletionItem {
+                    insert: if is_dir { format!("{}/", file_name) } else { file_name.clone() },
+                    label: file_name,
+                    kind: if is_dir { CompletionKind::Folder } else { CompletionKind::File },
+                    replace: name.chars().count(),
+                    ..CompletionItem::default()
+                })
+            })
+            .collect()
+    }
+}
+
+/// What a language server suggested when it was last asked, for as long as the cursor stays
+/// in the word it was asked about.
+pub struct ServerItems {
+    pub path: PathBuf,
+    /// Where the cursor was when the server was asked
+    pub at: Position,
+    pub items: Vec<CompletionItem>,
+    /// Whether the server wants asking again as more is typed
+    pub incomplete: bool,
+}
+
+impl ServerItems {
+    /// How many chars have been typed since the server was asked, if the items still apply.
+    pub fn typed_since(&self, document: &Document, path: &Path) -> Option<usize> {
+        let cursor = document.cursor();
+        if path != self.path || cursor.line != self.at.line || cursor.column < self.at.column {
+            return None;
+        }
+        let line = document.line(cursor.line);
+        let typed = &line[byte_index(line, self.at.column)..byte_index(line, cursor.column)];
+        typed.chars().all(is_word_char).then(|| cursor.column - self.at.column)
+    }
+}
+
+impl CompletionProvider for ServerItems {
+    fn complete(&self, request: &Request) -> Vec<CompletionItem> {
+        let Some(typed) = self.typed_since(request.document, request.path) else { return Vec::new(); };
+        self.items
+            .iter()
+            .map(|item| CompletionItem { replace: item.replace + typed, ..item.clone() })
+            .collect()
+    }
+}
+
+/// An item that made it into the popup, with the chars of its label the query matched.
+#[derive(Debug, Clone)]
+pub struct Ranked {
+    pub item: CompletionItem,
+    pub matches: Vec<usize>,
+}
+
+/// The completion popup: every provider's items, best match first.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub items: Vec<Ranked>,
+    pub selected: usize,
+}
+
+impl Completion {
+    /// Asks every provider and ranks what they suggest against the text each item would
+    /// replace. `None` if nothing matches, so the popup closes.
+    pub fn new(request: &Request, providers: &[&dyn CompletionProvider]) -> Option<Self> {
+        let matcher = SkimMatcherV2::default();
+        let before = request.before_cursor();
+
+        let mut scored: Vec<(i64, Ranked)> = providers
+            .iter()
+            .flat_map(|provider| provider.complete(request))
+            .filter_map(|item| {
+                let query = last_chars(before, item.replace);
+                let (score, matches) = match (query.is_empty(), &item.filter_text) {
+                    (true, _) => (0, Vec::new()),
+                    // Matched against something else, so there's nothing in the label to pick out
+                    (false, Some(filter)) if *filter != item.label => (matcher.fuzzy_match(filter, query)?, Vec::new()),
+                    (false, _) => matcher.fuzzy_indices(&item.label, query)?,
+                };
+                Some((score, Ranked { item, matches }))
+            })
+            .collect();
+        if scored.is_empty() {
+            return None;
+        }
+
+        // Ties go to the order a server asked for, then to the shorter label, since it's closer
+        // to what's typed
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            let by_server = match (&a.item.sort_text, &b.item.sort_text) {
+                (Some(a), Some(b)) => a.cmp(b),
+                _ => Ordering::Equal,
+            };
+            b_score.cmp(a_score).then(by_server).then(a.item.label.len().cmp(&b.item.label.len()))
+        });
+        scored.truncate(MAX_ITEMS);
+        Some(Self { items: scored.into_iter().map(|(_, ranked)| ranked).collect(), selected: 0 })
+    }
+
+    /// Moves the selection, wrapping around at either end.
+    pub fn navigate(&mut self, delta: i32) {
+        let count = self.items.len() as i32;
+        self.selected = (self.selected as i32 + delta).rem_euclid(count) as usize;
+    }
+
+    pub fn selected(&self) -> &CompletionItem {
+        &self.items[self.selected].item
+    }
+
+    /// The first item shown, so the selected one stays in view.
+    pub fn first_visible(&self) -> usize {
+        self.selected.saturating_sub(VISIBLE_ITEMS - 1)
+    }
+}
+
+/// The last `count` chars of `text`.
+fn last_chars(text: &str, count: usize) -> &str {
+    let start = text.char_indices().rev().take(count).last().map_or(text.len(), |(index, _)| index);
+    &text[start..]
+}
+
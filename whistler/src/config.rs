@@ -0,0 +1,354 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::indent::Indentation;
+
+const SETTINGS_FILE: &str = "settings.json";
+/// The settings key that maps language ids to the servers started for them.
+const LANGUAGE_SERVERS_KEY: &str = "whistler.languageServers";
+/// The settings key that maps language ids to the formatters used when their server can't format.
+const FORMATTERS_KEY: &str = "whistler.formatters";
+
+/// VS Code language ids for the extensions where they differ, used for `"[language]"` sections.
+const LANGUAGE_IDS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("jsx", "javascriptreact"),
+    ("ts", "typescript"),
+    ("tsx", "typescriptreact"),
+    ("md", "markdown"),
+    ("htm", "html"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("sh", "shellscript"),
+    ("bash", "shellscript"),
+    ("zsh", "shellscript"),
+    ("yml", "yaml"),
+    ("rb", "ruby"),
+    ("kt", "kotlin"),
+];
+
+/// Where Whistler keeps its user settings, e.g. `~/.config/whistler` on Linux.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("whistler"))
+}
+
+/// Turns VS Code's JSON with comments into plain JSON: drops `//` and `/* */` comments and
+/// the trailing commas it allows before `}` and `]`.
+pub fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            json.push(c);
+            match c {
+                '\\' => json.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                json.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ('}' | ']', _) => {
+                // A comma with only whitespace between it and the closing bracket goes
+                let content = json.trim_end().len();
+                if json[..content].ends_with(',') {
+                    json.truncate(content - 1);
+                }
+                json.push(c);
+            }
+            _ => json.push(c),
+        }
+    }
+    json
+}
+
+/// The language id settings are looked up under, e.g. `rust` for `main.rs`.
+pub fn language_id(extension: &str) -> String {
+    let extension = extension.to_lowercase();
+    LANGUAGE_IDS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, id)| id.to_string())
+        .unwrap_or(extension)
+}
+
+/// How the gutter numbers lines, same values as VS Code's `editor.lineNumbers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    #[default]
+    On,
+    Relative,
+    Off,
+}
+
+/// When long lines wrap, same values as VS Code's `editor.wordWrap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WordWrap {
+    #[default]
+    Off,
+    /// At the edge of the editor
+    On,
+    /// At `editor.wordWrapColumn`
+    #[serde(rename = "wordWrapColumn")]
+    Column,
+    /// At whichever comes first
+    Bounded,
+}
+
+/// Which whitespace gets drawn, same values as VS Code's `editor.renderWhitespace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderWhitespace {
+    #[default]
+    None,
+    All,
+    Trailing,
+}
+
+/// The settings that can be changed per language.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EditorSettings {
+    #[serde(rename = "editor.lineNumbers")]
+    pub line_numbers: LineNumbers,
+    #[serde(rename = "editor.wordWrap")]
+    pub word_wrap: WordWrap,
+    #[serde(rename = "editor.wordWrapColumn")]
+    pub word_wrap_column: usize,
+    #[serde(rename = "editor.renderWhitespace")]
+    pub render_whitespace: RenderWhitespace,
+    #[serde(rename = "editor.guides.indentation")]
+    pub indent_guides: bool,
+    #[serde(rename = "editor.guides.highlightActiveIndentation")]
+    pub highlight_active_indent: bool,
+    #[serde(rename = "editor.tabSize")]
+    pub tab_size: usize,
+    #[serde(rename = "editor.insertSpaces")]
+    pub insert_spaces: bool,
+    /// Whether to go by what a file already uses instead of the two settings above
+    #[serde(rename = "editor.detectIndentation")]
+    pub detect_indentation: bool,
+    #[serde(rename = "editor.formatOnSave")]
+    pub format_on_save: bool,
+    /// How long saving waits for the formatter before it writes the file as it is, in milliseconds
+    #[serde(rename = "editor.formatOnSaveTimeout")]
+    pub format_on_save_timeout: u64,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            line_numbers: LineNumbers::default(),
+            word_wrap: WordWrap::default(),
+            word_wrap_column: 80,
+            render_whitespace: RenderWhitespace::default(),
+            indent_guides: true,
+            highlight_active_indent: true,
+            tab_size: 4,
+            insert_spaces: true,
+            detect_indentation: true,
+            format_on_save: false,
+            format_on_save_timeout: 2000,
+        }
+    }
+}
+
+impl EditorSettings {
+    /// How a new file with these settings is indented, before anything is detected.
+    pub fn indentation(&self) -> Indentation {
+        Indentation { tabs: !self.insert_spaces, size: self.tab_size.max(1) }
+    }
+}
+
+/// How to start the language server for a language, from `"whistler.languageServers"`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageServerSettings {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl LanguageServerSettings {
+    fn new(command: &str, args: &[&str]) -> Self {
+        Self { command: command.to_string(), args: args.iter().map(|arg| arg.to_string()).collect() }
+    }
+}
+
+/// The servers used when the settings don't say otherwise, the ones the README plans for.
+fn default_language_servers() -> HashMap<String, LanguageServerSettings> {
+    let typescript = LanguageServerSettings::new("typescript-language-server", &["--stdio"]);
+    HashMap::from([
+        ("rust".to_string(), LanguageServerSettings::new("rust-analyzer", &[])),
+        ("python".to_string(), LanguageServerSettings::new("pyright-langserver", &["--stdio"])),
+        ("typescript".to_string(), typescript.clone()),
+        ("typescriptreact".to_string(), typescript.clone()),
+        ("javascript".to_string(), typescript.clone()),
+        ("javascriptreact".to_string(), typescript),
+    ])
+}
+
+/// An external formatter for a language, from `"whistler.formatters"`. It gets the text on stdin
+/// and gives the formatted text back on stdout.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FormatterSettings {
+    pub command: String,
+    /// `${file}` in these is replaced with the path of the file being formatted, and `${edition}`
+    /// with the Rust edition its crate's Cargo.toml names
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The formatters used when the settings don't say otherwise.
+fn default_formatters() -> HashMap<String, FormatterSettings> {
+    let formatter = |command: &str, args: &[&str]| FormatterSettings {
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    };
+    let prettier = formatter("prettier", &["--stdin-filepath", "${file}"]);
+    let mut formatters = HashMap::from([
+        ("rust".to_string(), formatter("rustfmt", &["--edition", "${edition}"])),
+        ("python".to_string(), formatter("black", &["--quiet", "-"])),
+    ]);
+    for language in ["javascript", "javascriptreact", "typescript", "typescriptreact", "json", "css", "html"] {
+        formatters.insert(language.to_string(), prettier.clone());
+    }
+    formatters
+}
+
+/// User settings, read from a VS Code style `settings.json` with flat dotted keys.
+///
+/// Editor settings can be overridden per language in `"[rust]": { ... }` sections.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub editor: EditorSettings,
+    languages: HashMap<String, EditorSettings>,
+    language_servers: HashMap<String, LanguageServerSettings>,
+    formatters: HashMap<String, FormatterSettings>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            editor: EditorSettings::default(),
+            languages: HashMap::new(),
+            language_servers: default_language_servers(),
+            formatters: default_formatters(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the settings file, falling back to the defaults if it's missing or broken.
+    pub fn load() -> Self {
+        let Some(path) = config_dir().map(|dir| dir.join(SETTINGS_FILE)) else {
+            return Self::default();
+        };
+        let Ok(json) = fs::read_to_string(&path) else { return Self::default(); };
+        match serde_json::from_str::<Map<String, Value>>(&strip_jsonc(&json)) {
+            Ok(settings) => Self::from_settings(settings),
+            Err(e) => {
+                eprintln!("Failed to parse {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn from_settings(settings: Map<String, Value>) -> Self {
+        let parse = |settings: Map<String, Value>| {
+            serde_json::from_value(Value::Object(settings)).unwrap_or_else(|e| {
+                eprintln!("Invalid editor setting: {}", e);
+                EditorSettings::default()
+            })
+        };
+
+        // A server set to `null` turns that language's default off
+        let mut language_servers = default_language_servers();
+        if let Some(Value::Object(servers)) = settings.get(LANGUAGE_SERVERS_KEY) {
+            for (language, server) in servers {
+                match serde_json::from_value::<Option<LanguageServerSettings>>(server.clone()) {
+                    Ok(Some(server)) => {
+                        language_servers.insert(language.clone(), server);
+                    }
+                    Ok(None) => {
+                        language_servers.remove(language);
+                    }
+                    Err(e) => eprintln!("Invalid language server for {}: {}", language, e),
+                }
+            }
+        }
+
+        // Same for formatters
+        let mut formatters = default_formatters();
+        if let Some(Value::Object(configured)) = settings.get(FORMATTERS_KEY) {
+            for (language, formatter) in configured {
+                match serde_json::from_value::<Option<FormatterSettings>>(formatter.clone()) {
+                    Ok(Some(formatter)) => {
+                        formatters.insert(language.clone(), formatter);
+                    }
+                    Ok(None) => {
+                        formatters.remove(language);
+                    }
+                    Err(e) => eprintln!("Invalid formatter for {}: {}", language, e),
+                }
+            }
+        }
+
+        let mut languages = HashMap::new();
+        for (key, value) in &settings {
+            let (Some(language), Value::Object(overrides)) =
+                (key.strip_prefix('[').and_then(|key| key.strip_suffix(']')), value)
+            else {
+                continue;
+            };
+            // Language sections only list what they change, the rest comes from the top level
+            let mut merged = settings.clone();
+            merged.extend(overrides.clone());
+            languages.insert(language.to_string(), parse(merged));
+        }
+
+        Self { editor: parse(settings), languages, language_servers, formatters }
+    }
+
+    /// The editor settings for files with this extension.
+    pub fn editor_for(&self, extension: &str) -> &EditorSettings {
+        self.languages.get(&language_id(extension)).unwrap_or(&self.editor)
+    }
+
+    /// The language server for files with this extension, if there is one.
+    pub fn language_server(&self, extension: &str) -> Option<&LanguageServerSettings> {
+        self.language_servers.get(&language_id(extension))
+    }
+
+    /// The external formatter for files with this extension, if there is one.
+    pub fn formatter(&self, extension: &str) -> Option<&FormatterSettings> {
+        self.formatters.get(&language_id(extension))
+    }
+}
@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+use crate::document::{display_width, is_word_char, Document, Position};
+
+/// How far to look for the other half of a bracket pair before giving up.
+const BRACKET_SCAN_LINES: usize = 2000;
+/// Selections longer than this aren't searched for elsewhere in the file.
+const MAX_OCCURRENCE_LEN: usize = 200;
+
+const BRACKET_PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Everything the editor paints behind the text, worked out once per frame for the visible lines.
+#[derive(Debug, Default)]
+pub struct Decorations {
+    /// The lines with a cursor on them, unless it's selecting something
+    pub current_lines: Vec<usize>,
+    /// The bracket next to the cursor and the one it pairs with
+    pub brackets: Vec<Position>,
+    /// Other places where the selection, or the word under the cursor, shows up
+    pub occurrences: Vec<(Position, Position)>,
+    /// How far indent guides reach on each visible line, in display columns
+    pub guide_indents: Vec<usize>,
+    /// The guide of the block the cursor is in, as its display column and the lines it spans
+    pub active_guide: Option<(usize, Range<usize>)>,
+}
+
+impl Decorations {
+    pub fn compute(document: &Document, visible: Range<usize>) -> Self {
+        let selection = document.selection();
+
+        Self {
+            current_lines: document
+                .selections()
+                .iter()
+                .filter(|selection| selection.is_empty())
+                .map(|selection| selection.head.line)
+                .collect(),
+            brackets: matching_bracket(document, selection.head)
+                .map(|(at, other)| vec![at, other])
+                .unwrap_or_default(),
+            occurrences: occurrences(document, visible.clone()),
+            guide_indents: visible.clone().map(|line| guide_indent(document, line)).collect(),
+            active_guide: active_guide(document, selection.head.line),
+        }
+    }
+}
+
+/// Finds the bracket right after the cursor (or right before it) and its partner.
+fn matching_bracket(document: &Document, cursor: Position) -> Option<(Position, Position)> {
+    let line: Vec<char> = document.line(cursor.line).chars().collect();
+    let candidates = [Some(cursor.column), cursor.column.checked_sub(1)];
+
+    for column in candidates.into_iter().flatten() {
+        let Some(&c) = line.get(column) else { continue; };
+        let at = Position::new(cursor.line, column);
+        for (open, close) in BRACKET_PAIRS {
+            if c == open {
+                return find_close(document, at, open, close).map(|other| (at, other));
+            }
+            if c == close {
+                return find_open(document, at, open, close).map(|other| (at, other));
+            }
+        }
+    }
+    None
+}
+
+fn find_close(document: &Document, from: Position, open: char, close: char) -> Option<Position> {
+    let mut depth = 0usize;
+    let last = (from.line + BRACKET_SCAN_LINES).min(document.line_count() - 1);
+    for line in from.line..=last {
+        let skip = if line == from.line { from.column } else { 0 };
+        for (column, c) in document.line(line).chars().enumerate().skip(skip) {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(Position::new(line, column));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Finds the bracket that opens the one at `from`, skipping nested pairs.
+pub fn find_open(document: &Document, from: Position, open: char, close: char) -> Option<Position> {
+    let mut depth = 0usize;
+    let first = from.line.saturating_sub(BRACKET_SCAN_LINES);
+    for line in (first..=from.line).rev() {
+        let chars: Vec<char> = document.line(line).chars().collect();
+        let end = if line == from.line { from.column + 1 } else { chars.len() };
+        for column in (0..end).rev() {
+            let c = chars[column];
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(Position::new(line, column));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn indent_of(line: &str) -> Option<usize> {
+    let content = line.trim_start();
+    (!content.is_empty()).then(|| display_width(line[..line.len() - content.len()].chars(), 0))
+}
+
+/// The indentation guides are drawn up to. Blank lines take the shallower of the lines around
+/// them, so guides run through gaps inside a block but stop where it ends.
+fn guide_indent(document: &Document, line: usize) -> usize {
+    if let Some(indent) = indent_of(document.line(line)) {
+        return indent;
+    }
+    let first = line.saturating_sub(BRACKET_SCAN_LINES);
+    let last = (line + BRACKET_SCAN_LINES).min(document.line_count() - 1);
+    let above = (first..line).rev().find_map(|line| indent_of(document.line(line)));
+    let below = (line + 1..=last).find_map(|line| indent_of(document.line(line)));
+    above.unwrap_or(0).min(below.unwrap_or(0))
+}
+
+/// The guide of the innermost block around the cursor. On a line that opens a block,
+/// that's the block below it.
+fn active_guide(document: &Document, line: usize) -> Option<(usize, Range<usize>)> {
+    let indent = guide_indent(document, line);
+    let level = document.indentation().width();
+    let opens_block = line + 1 < document.line_count() && guide_indent(document, line + 1) > indent;
+    let column = if opens_block {
+        indent / level * level
+    } else if indent > 0 {
+        (indent - 1) / level * level
+    } else {
+        return None;
+    };
+
+    let inside = |line: usize| guide_indent(document, line) > column;
+    let first = if opens_block { line + 1 } else { line };
+    let last_line = (line + BRACKET_SCAN_LINES).min(document.line_count() - 1);
+    let start = (first.saturating_sub(BRACKET_SCAN_LINES)..first).rev().take_while(|&line| inside(line)).last().unwrap_or(first);
+    let end = (first..=last_line).take_while(|&line| inside(line)).last().unwrap_or(first);
+    Some((column, start..end + 1))
+}
+
+/// Looks for the selected text, or the word under a bare cursor, in the visible lines.
+fn occurrences(document: &Document, visible: Range<usize>) -> Vec<(Position, Position)> {
+    let selection = document.selection();
+    let (start, end, whole_word) = if selection.is_empty() {
+        let (start, end) = document.word_bounds(selection.head);
+        let is_word = document.line(start.line).chars().nth(start.column).is_some_and(is_word_char);
+        if !is_word {
+            return Vec::new();
+        }
+        (start, end, true)
+    } else {
+        (selection.start(), selection.end(), false)
+    };
+
+    if start.line != end.line || end.column - start.column > MAX_OCCURRENCE_LEN {
+        return Vec::new();
+    }
+    let needle = document.text_range(start, end);
+    if needle.trim().is_empty() {
+        return Vec::new();
+    }
+    let needle_len = needle.chars().count();
+
+    let mut found = Vec::new();
+    for line in visible {
+        let text = document.line(line);
+        for (byte, _) in text.match_indices(needle.as_str()) {
+            if whole_word {
+                let before = text[..byte].chars().next_back();
+                let after = text[byte + needle.len()..].chars().next();
+                if before.is_some_and(is_word_char) || after.is_some_and(is_word_char) {
+                    continue;
+                }
+            }
+            let column = text[..byte].chars().count();
+            let at = Position::new(line, column);
+            // The selections themselves are already painted, only mark the others
+            if !document.selections().iter().any(|selection| selection.start() == at) {
+                found.push((at, Position::new(line, column + needle_len)));
+            }
+        }
+    }
+    found
+}
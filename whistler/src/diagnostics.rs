@@ -0,0 +1,144 @@
+use lsp_types::{DiagnosticSeverity, NumberOrString};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::document::{Document, Position};
+
+/// How bad a diagnostic is, worst first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    /// Suggestions like "unused variable", shown faintly and left out of the Problems panel
+    Hint,
+}
+
+impl Severity {
+    /// The ones the Problems panel lists and can filter by.
+    pub const LISTED: [Severity; 3] = [Severity::Error, Severity::Warning, Severity::Information];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "Errors",
+            Severity::Warning => "Warnings",
+            Severity::Information => "Infos",
+            Severity::Hint => "Hints",
+        }
+    }
+
+    /// The symbol shown next to it in the Problems panel.
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Severity::Error => "✕",
+            Severity::Warning => "!",
+            Severity::Information => "i",
+            Severity::Hint => "…",
+        }
+    }
+
+    /// Servers may leave the severity out, and then it's up to the client. VS Code goes with error.
+    fn from_lsp(severity: Option<DiagnosticSeverity>) -> Self {
+        match severity {
+            Some(DiagnosticSeverity::WARNING) => Severity::Warning,
+            Some(DiagnosticSeverity::INFORMATION) => Severity::Information,
+            Some(DiagnosticSeverity::HINT) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A problem a language server found in a file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub start: Position,
+    pub end: Position,
+    pub severity: Severity,
+    pub message: String,
+    /// What reported it, e.g. `rustc` or `Pylance`, and its code for the problem
+    pub source: Option<String>,
+    pub code: Option<String>,
+    /// As the server sent it, to give back when asking it for fixes
+    pub original: lsp_types::Diagnostic,
+}
+
+impl Diagnostic {
+    /// Converts a server's diagnostic, whose columns count UTF-16 code units. Without the
+    /// document to go by they're taken as chars, which is right for most lines anyway.
+    pub fn from_lsp(diagnostic: lsp_types::Diagnostic, document: Option<&Document>) -> Self {
+        let position = |position: lsp_types::Position| {
+            let (line, units) = (position.line as usize, position.character as usize);
+            match document {
+                Some(document) => document.position_from_utf16(line, units),
+                None => Position::new(line, units),
+            }
+        };
+        Self {
+            start: position(diagnostic.range.start),
+            end: position(diagnostic.range.end),
+            severity: Severity::from_lsp(diagnostic.severity),
+            message: diagnostic.message.clone(),
+            source: diagnostic.source.clone(),
+            code: diagnostic.code.clone().map(|code| match code {
+                NumberOrString::Number(number) => number.to_string(),
+                NumberOrString::String(code) => code,
+            }),
+            original: diagnostic,
+        }
+    }
+
+    /// Whether it covers `position`. Empty ones cover the char after them.
+    pub fn contains(&self, position: Position) -> bool {
+        match self.start == self.end {
+            true => position == self.start,
+            false => self.start <= position && position < self.end,
+        }
+    }
+
+    /// Where it came from, the way VS Code puts it after the message: `rustc(E0308)`.
+    pub fn origin(&self) -> Option<String> {
+        match (&self.source, &self.code) {
+            (Some(source), Some(code)) => Some(format!("{}({})", source, code)),
+            (Some(source), None) => Some(source.clone()),
+            (None, Some(code)) => Some(code.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Every file's diagnostics, as the servers last published them.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    files: BTreeMap<PathBuf, Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    /// Replaces what's known about a file, since servers always send all of a file's diagnostics.
+    pub fn publish(&mut self, path: PathBuf, mut diagnostics: Vec<Diagnostic>) {
+        if diagnostics.is_empty() {
+            self.files.remove(&path);
+            return;
+        }
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.start, diagnostic.severity));
+        self.files.insert(path, diagnostics);
+    }
+
+    /// A file's diagnostics, in the order they come in the file.
+    pub fn for_file(&self, path: &Path) -> &[Diagnostic] {
+        self.files.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// The files with diagnostics, sorted by path.
+    pub fn files(&self) -> impl Iterator<Item = (&PathBuf, &Vec<Diagnostic>)> {
+        self.files.iter()
+    }
+
+    /// How many there are of a severity across the workspace.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.files.values().flatten().filter(|diagnostic| diagnostic.severity == severity).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
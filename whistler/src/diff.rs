@@ -0,0 +1,187 @@
+use similar::DiffTag;
+use std::ops::Range;
+
+use crate::git::{self, Hunk};
+use crate::syntax::{highlight_code, Highlight};
+
+/// The unchanged lines kept around each change when the rest are left out.
+const CONTEXT_LINES: usize = 3;
+
+/// One of the two texts being compared, with what to call it.
+#[derive(Debug)]
+pub struct Side {
+    pub title: String,
+    pub lines: Vec<String>,
+    highlights: Vec<Vec<(Range<usize>, Highlight)>>,
+    /// The parts of each changed line that differ from the line it pairs with on the other side
+    words: Vec<Vec<Range<usize>>>,
+}
+
+impl Side {
+    fn new(title: String, text: &str, extension: &str) -> Self {
+        // Split the way documents split their text, so unchanged lines compare equal
+        let lines: Vec<String> = text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect();
+        let highlights = highlight_code(extension, text);
+        let words = vec![Vec::new(); lines.len()];
+        Self { title, lines, highlights, words }
+    }
+
+    pub fn highlights(&self, line: usize) -> &[(Range<usize>, Highlight)] {
+        self.highlights.get(line).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn words(&self, line: usize) -> &[Range<usize>] {
+        &self.words[line]
+    }
+}
+
+/// A line of a diff as it's shown. Side by side, a changed row has a line from each side when
+/// there's one to pair up; inline, it only ever has one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    Same { old: usize, new: usize },
+    Changed { old: Option<usize>, new: Option<usize> },
+    /// Unchanged lines left out
+    Hidden(usize),
+}
+
+/// Two texts and the hunks between them, for a diff tab, along with how it's being looked at.
+#[derive(Debug)]
+pub struct TextDiff {
+    pub old: Side,
+    pub new: Side,
+    pub hunks: Vec<Hunk>,
+    pub side_by_side: bool,
+    /// Whether unchanged lines away from the changes are left out
+    pub collapsed: bool,
+    /// The hunk last gone to
+    pub current: Option<usize>,
+}
+
+impl TextDiff {
+    pub fn new(old: (String, &str), new: (String, &str), extension: &str) -> Self {
+        let (mut old, mut new) = (Side::new(old.0, old.1, extension), Side::new(new.0, new.1, extension));
+        let hunks = git::hunks(&old.lines, &new.lines);
+        for hunk in &hunks {
+            // Lines are paired up in order, like side by side
+            for (a, b) in hunk.base.clone().zip(hunk.current.clone()) {
+                (old.words[a], new.words[b]) = word_changes(&old.lines[a], &new.lines[b]);
+            }
+        }
+        Self { old, new, hunks, side_by_side: true, collapsed: true, current: None }
+    }
+
+    /// The rows to show, and the row each hunk starts on.
+    pub fn rows(&self) -> (Vec<Row>, Vec<usize>) {
+        let mut rows = Vec::new();
+        let mut starts = Vec::new();
+        let (mut old, mut new) = (0, 0);
+        for (index, hunk) in self.hunks.iter().enumerate() {
+            let before = if index == 0 { 0 } else { CONTEXT_LINES };
+            self.same_rows(old..hunk.base.start, new, before, CONTEXT_LINES, &mut rows);
+            starts.push(rows.len());
+            if self.side_by_side {
+                for i in 0..hunk.base.len().max(hunk.current.len()) {
+                    let pick = |range: &Range<usize>| (i < range.len()).then_some(range.start + i);
+                    rows.push(Row::Changed { old: pick(&hunk.base), new: pick(&hunk.current) });
+                }
+            } else {
+                rows.extend(hunk.base.clone().map(|line| Row::Changed { old: Some(line), new: None }));
+                rows.extend(hunk.current.clone().map(|line| Row::Changed { old: None, new: Some(line) }));
+            }
+            (old, new) = (hunk.base.end, hunk.current.end);
+        }
+        let before = if self.hunks.is_empty() { usize::MAX } else { CONTEXT_LINES };
+        self.same_rows(old..self.old.lines.len(), new, before, 0, &mut rows);
+        (rows, starts)
+    }
+
+    /// Rows for a run of unchanged lines, keeping `before` of them at the top and `after` at
+    /// the bottom if the middle's left out.
+    fn same_rows(&self, old: Range<usize>, new: usize, before: usize, after: usize, rows: &mut Vec<Row>) {
+        let len = old.len();
+        let row = |i: usize| Row::Same { old: old.start + i, new: new + i };
+        // Leaving out a line or two saves nothing over showing them
+        if !self.collapsed || len <= before.saturating_add(after).saturating_add(2) {
+            rows.extend((0..len).map(row));
+            return;
+        }
+        rows.extend((0..before).map(row));
+        rows.push(Row::Hidden(len - before - after));
+        rows.extend((len - after..len).map(row));
+    }
+
+    /// Goes to the next or previous hunk from the current one, around the end.
+    pub fn navigate(&mut self, forward: bool) -> Option<usize> {
+        let count = self.hunks.len();
+        if count == 0 {
+            return None;
+        }
+        let next = match (self.current, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(current), true) => (current + 1) % count,
+            (Some(current), false) => (current + count - 1) % count,
+        };
+        self.current = Some(next);
+        Some(next)
+    }
+}
+
+/// The byte ranges that differ between two versions of a line, word by word.
+fn word_changes(old: &str, new: &str) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let (old_tokens, new_tokens) = (tokens(old), tokens(new));
+    let offsets = |tokens: &[&str]| {
+        let mut offsets = vec![0];
+        offsets.extend(tokens.iter().scan(0, |end, token| {
+            *end += token.len();
+            Some(*end)
+        }));
+        offsets
+    };
+    let (old_offsets, new_offsets) = (offsets(&old_tokens), offsets(&new_tokens));
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old_tokens, &new_tokens) {
+        if op.tag() == DiffTag::Equal {
+            continue;
+        }
+        let (a, b) = (op.old_range(), op.new_range());
+        push_range(&mut removed, old_offsets[a.start]..old_offsets[a.end]);
+        push_range(&mut added, new_offsets[b.start]..new_offsets[b.end]);
+    }
+    (removed, added)
+}
+
+/// Adds a range, joining it onto the last one if they touch.
+fn push_range(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+/// Splits a line into words, runs of whitespace, and single other chars.
+fn tokens(line: &str) -> Vec<&str> {
+    let class = |c: char| match c {
+        c if c.is_alphanumeric() || c == '_' => 0,
+        c if c.is_whitespace() => 1,
+        _ => 2,
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let kind = class(c);
+        let end = match chars.peek() {
+            Some(&(next, d)) if kind == 2 || class(d) != kind => next,
+            Some(_) => continue,
+            None => line.len(),
+        };
+        tokens.push(&line[start..end]);
+        start = end;
+    }
+    tokens
+}
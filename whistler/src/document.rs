@@ -0,0 +1,2394 @@
+use iced::widget::text_editor::{Edit, Motion};
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use unicode_width::UnicodeWidthChar;
+
+use crate::decoration;
+use crate::folding::{self, FoldRegion};
+use crate::indent::{self, Indentation};
+use crate::snippets::Expansion;
+use crate::syntax::{self, HighlightCache};
+
+/// How many columns a tab character takes up on screen
+pub const TAB_WIDTH: usize = 4;
+/// Pairs only close automatically in front of these, so typing `(` before a word doesn't add `)`.
+const AUTO_CLOSE_BEFORE: &str = ";:.,=}])>";
+
+/// A place in the document. Columns are counted in chars, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+/// A cursor with an optional selection. When `anchor == head` nothing is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Position, // Where the selection started
+    pub head: Position, // Where the cursor currently is
+    goal: Option<usize>, // The display column to go back to when moving up/down past short lines
+}
+
+impl Selection {
+    pub fn caret(position: Position) -> Self {
+        Self { anchor: position, head: position, goal: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> Position {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> Position {
+        self.anchor.max(self.head)
+    }
+}
+
+/// Everything the editor widget can ask the document to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Move(Motion),
+    Select(Motion),
+    SelectWord,
+    SelectLine,
+    SelectAll,
+    Edit(Edit),
+    Click(Position),
+    Drag(Position),
+    /// Puts the cursor somewhere else in the file and scrolls it to the middle, e.g. for a problem
+    GoTo(Position),
+    /// A click on a line number, `extend` is set when shift is held
+    ClickLine { line: usize, extend: bool },
+    /// Dragging over the line numbers after a `ClickLine`
+    DragLine(usize),
+    Scroll { rows: f32, columns: f32 },
+    ScrollTo { row: f32 },
+    /// Alt+click, adds a cursor or removes the one that was clicked
+    AddCursor(Position),
+    /// Alt+drag, one selection per line between two `(line, display column)` corners
+    BoxSelect { from: (usize, usize), to: (usize, usize) },
+    /// Cmd+D, selects the word under the cursor or adds the next occurrence of the selection
+    SelectNextOccurrence,
+    /// Cmd+Shift+L, selects every occurrence of the selection
+    SelectAllOccurrences,
+    /// Goes back to only the primary cursor
+    CollapseCursors,
+    Undo,
+    Redo,
+    /// A click on a fold chevron in the gutter
+    ToggleFold(usize),
+    /// Folds the innermost open region around the cursor
+    Fold,
+    /// Unfolds the innermost folded region around the cursor
+    Unfold,
+    FoldAll,
+    UnfoldAll,
+    /// Folds every region nested `n` levels deep
+    FoldLevel(usize),
+    /// Changes how new indentation is typed, leaving the existing lines alone
+    SetIndentation(Indentation),
+    /// Rewrites the indentation of every line to tabs or spaces
+    ConvertIndentation(Indentation),
+    MoveLines { down: bool },
+    /// Duplicates the selected lines above or below themselves
+    CopyLines { down: bool },
+    DeleteLines,
+    JoinLines,
+    SortLines { descending: bool },
+    /// Swaps the characters around the cursor
+    Transpose,
+    ToggleLineComment,
+    ToggleBlockComment,
+    /// Replaces the `prefix` chars before the cursor with a snippet and starts at its first tab stop
+    InsertSnippet { prefix: usize, expansion: Expansion },
+    /// Replaces ranges that don't overlap, all in one undo step, e.g. the imports a completion adds
+    ApplyEdits(Vec<TextEdit>),
+    /// Deletes the selected text after it went to the clipboard, leaving bare cursors where they are
+    Cut,
+}
+
+impl Action {
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Action::Edit(_)
+                | Action::Undo
+                | Action::Redo
+                | Action::ConvertIndentation(_)
+                | Action::MoveLines { .. }
+                | Action::CopyLines { .. }
+                | Action::DeleteLines
+                | Action::JoinLines
+                | Action::SortLines { .. }
+                | Action::Transpose
+                | Action::ToggleLineComment
+                | Action::ToggleBlockComment
+                | Action::InsertSnippet { .. }
+                | Action::ApplyEdits(_)
+                | Action::Cut
+        )
+    }
+
+    pub fn is_fold(&self) -> bool {
+        matches!(
+            self,
+            Action::ToggleFold(_)
+                | Action::Fold
+                | Action::Unfold
+                | Action::FoldAll
+                | Action::UnfoldAll
+                | Action::FoldLevel(_)
+        )
+    }
+}
+
+/// Text to put in place of a range, with positions from before any of the edits it comes with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Converts a language server's edit, whose columns count UTF-16 code units.
+    pub fn from_lsp(edit: lsp_types::TextEdit, document: &Document) -> Self {
+        let position = |position: lsp_types::Position| {
+            document.position_from_utf16(position.line as usize, position.character as usize)
+        };
+        Self { start: position(edit.range.start), end: position(edit.range.end), text: edit.new_text }
+    }
+}
+
+/// An edit the way a language server hears about it: the `(line, column)` range it replaced,
+/// with columns in UTF-16 code units, and the text that went in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncChange {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
+}
+
+/// A label a language server wants shown in between the chars of a line, like the type of a
+/// variable or the name of an argument. It takes up room on screen but isn't part of the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlayHint {
+    /// The label goes right before the char at this position
+    pub position: Position,
+    pub label: String,
+}
+
+impl InlayHint {
+    /// Converts a server's hint, with the padding it asks for turned into spaces.
+    pub fn from_lsp(hint: lsp_types::InlayHint, document: &Document) -> Self {
+        let mut label = match hint.label {
+            lsp_types::InlayHintLabel::String(label) => label,
+            lsp_types::InlayHintLabel::LabelParts(parts) => parts.into_iter().map(|part| part.value).collect(),
+        };
+        if hint.padding_left == Some(true) {
+            label.insert(0, ' ');
+        }
+        if hint.padding_right == Some(true) {
+            label.push(' ');
+        }
+        let position = document.position_from_utf16(hint.position.line as usize, hint.position.character as usize);
+        Self { position, label }
+    }
+
+    /// How many display columns it takes up.
+    pub fn width(&self) -> usize {
+        display_width(self.label.chars(), 0)
+    }
+}
+
+/// One primitive change to the text, enough to replay it in either direction.
+#[derive(Debug, Clone)]
+struct Change {
+    at: Position,
+    removed: String,
+    inserted: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Typing,
+    Deleting,
+    Other,
+}
+
+impl EditKind {
+    fn of(edit: &Edit) -> Self {
+        match edit {
+            Edit::Insert(_) => EditKind::Typing,
+            Edit::Backspace | Edit::Delete => EditKind::Deleting,
+            _ => EditKind::Other,
+        }
+    }
+}
+
+/// The changes one undo reverts, with the cursors from before and after them.
+#[derive(Debug)]
+struct Step {
+    kind: EditKind,
+    changes: Vec<Change>,
+    before: Vec<Selection>,
+    after: Vec<Selection>,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    open: Option<Step>, // Runs of typing or deleting keep adding to this step until something else happens
+}
+
+impl History {
+    fn seal(&mut self) {
+        if let Some(step) = self.open.take()
+            && !step.changes.is_empty()
+        {
+            self.undo.push(step);
+        }
+    }
+}
+
+/// The text of an editor tab, along with its cursor and how far it's scrolled.
+pub struct Document {
+    lines: Vec<String>,
+    line_ending: &'static str,
+    selections: Vec<Selection>, // Sorted and never overlapping
+    primary: usize, // The selection the view follows, usually the last one added
+    line_anchor: Option<usize>, // The line a gutter click started on, for shift-click and dragging
+    scroll_row: Cell<f32>, // Pulled back up when rows go away, which a new wrap width does on layout
+    scroll_column: f32,
+    viewport: Cell<(usize, usize)>, // Visible (rows, columns), written by the widget on layout
+    highlights: RefCell<HighlightCache>,
+    history: History,
+    extension: String, // Picks the grammar used to find bracket blocks to fold
+    fold_regions: RefCell<Option<Vec<FoldRegion>>>, // Worked out when first needed, then kept in step with edits
+    fold_regions_outdated: bool, // Edited since they were worked out, see `refresh_fold_regions`
+    folds: Vec<FoldRegion>, // The collapsed regions, by start line
+    wrap_width: Cell<Option<usize>>, // Set by the widget, from the wrap setting and its size
+    indentation: Indentation, // What Tab and Enter indent with
+    layout: RefCell<Option<RowLayout>>,
+    snippet: Option<SnippetSession>, // The snippet whose tab stops Tab is moving through
+    version: i32, // Goes up with every change, for language servers
+    unsynced: Vec<SyncChange>, // Changes no language server has heard about yet
+    inlay_hints: Vec<InlayHint>, // Sorted, and moved along with the text until the server sends new ones
+}
+
+/// The tab stops of an expanded snippet, kept up to date as the text around them changes.
+#[derive(Debug)]
+struct SnippetSession {
+    /// The ranges of each stop, in the order Tab visits them, the final one last
+    stops: Vec<Vec<(Position, Position)>>,
+    current: usize,
+}
+
+/// How lines map to screen rows once folds and soft wrap are taken into account.
+#[derive(Debug)]
+struct RowLayout {
+    /// For each line, the columns where a wrapped row starts
+    breaks: Vec<Vec<usize>>,
+    /// For each line, its first row, plus the total number of rows at the end
+    starts: Vec<usize>,
+}
+
+impl Document {
+    pub fn with_text(text: &str, extension: &str) -> Self {
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let lines = text
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+
+        Self {
+            lines,
+            line_ending,
+            selections: vec![Selection::caret(Position::default())],
+            primary: 0,
+            line_anchor: None,
+            scroll_row: Cell::new(0.0),
+            scroll_column: 0.0,
+            viewport: Cell::new((1, 1)),
+            highlights: RefCell::new(HighlightCache::new()),
+            history: History::default(),
+            extension: extension.to_string(),
+            fold_regions: RefCell::new(None),
+            fold_regions_outdated: false,
+            folds: Vec::new(),
+            wrap_width: Cell::new(None),
+            indentation: Indentation::default(),
+            layout: RefCell::new(None),
+            snippet: None,
+            version: 0,
+            unsynced: Vec::new(),
+            inlay_hints: Vec::new(),
+        }
+    }
+
+    /// The whole text, joined with the line ending the file was opened with.
+    pub fn text(&self) -> String {
+        self.lines.join(self.line_ending)
+    }
+
+    /// The extension of the file it was opened from, which picks its grammar.
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn line(&self, index: usize) -> &str {
+        self.lines.get(index).map(String::as_str).unwrap_or("")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.selection().head
+    }
+
+    pub fn indentation(&self) -> Indentation {
+        self.indentation
+    }
+
+    /// Sets what Tab and Enter indent with, usually detected from the file when it's opened.
+    pub fn set_indentation(&mut self, indentation: Indentation) {
+        self.indentation = indentation;
+    }
+
+    /// The primary selection, the one scrolling and the status bar follow.
+    pub fn selection(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    /// The selected text, one line per selection when there are several.
+    pub fn selected_text(&self) -> Option<String> {
+        let parts: Vec<String> = self
+            .selections
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| self.text_range(selection.start(), selection.end()))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n"))
+    }
+
+    pub fn highlights(&self) -> &RefCell<HighlightCache> {
+        &self.highlights
+    }
+
+    pub fn scroll_row(&self) -> f32 {
+        self.scroll_row.get()
+    }
+
+    pub fn scroll_column(&self) -> f32 {
+        self.scroll_column
+    }
+
+    /// Called by the widget so keyboard navigation knows how much fits on screen.
+    pub fn set_viewport(&self, rows: usize, columns: usize) {
+        self.viewport.set((rows.max(1), columns.max(1)));
+    }
+
+    pub fn perform(&mut self, action: Action) {
+        if let Action::Edit(Edit::Indent | Edit::Unindent) = action
+            && self.snippet.is_some()
+        {
+            self.history.seal();
+            self.jump_tab_stop(action == Action::Edit(Edit::Unindent));
+            return;
+        }
+        if let Action::Edit(edit) = action {
+            self.transact(EditKind::of(&edit), |document| document.edit(edit));
+            self.unfold_around_cursors();
+            self.leave_snippet_if_outside();
+            self.clamp_scroll();
+            self.reveal_cursor();
+            return;
+        }
+        // Anything but another keystroke of the same kind ends the current undo step
+        self.history.seal();
+
+        if action.is_fold() {
+            // A chevron clicked in the gutter is on screen already, unlike the cursor may be
+            let clicked = matches!(action, Action::ToggleFold(_));
+            self.fold(action);
+            self.move_cursors_out_of_folds();
+            self.clamp_scroll();
+            if !clicked {
+                self.reveal_cursor();
+            }
+            return;
+        }
+        let scrolling = matches!(action, Action::Scroll { .. } | Action::ScrollTo { .. });
+
+        match action {
+            Action::Move(motion) => {
+                self.map_selections(|document, selection| {
+                    if !selection.is_empty() && matches!(motion, Motion::Left | Motion::Right) {
+                        // Arrowing out of a selection lands on its edge, like every other editor
+                        let edge = if motion == Motion::Left { selection.start() } else { selection.end() };
+                        Selection::caret(edge)
+                    } else {
+                        let (head, goal) = document.apply_motion(selection.head, selection.goal, motion);
+                        Selection { anchor: head, head, goal }
+                    }
+                });
+                self.reveal_cursor();
+            }
+            Action::Select(motion) => {
+                self.map_selections(|document, selection| {
+                    let (head, goal) = document.apply_motion(selection.head, selection.goal, motion);
+                    Selection { anchor: selection.anchor, head, goal }
+                });
+                self.reveal_cursor();
+            }
+            Action::SelectWord => {
+                let (start, end) = self.word_bounds(self.selection().head);
+                self.selections[self.primary] = Selection { anchor: start, head: end, goal: None };
+                self.merge_selections();
+            }
+            Action::SelectLine => {
+                let line = self.selection().head.line;
+                self.select_lines(line, line);
+            }
+            Action::SelectAll => {
+                let last = self.lines.len() - 1;
+                self.set_selection(Selection {
+                    anchor: Position::default(),
+                    head: Position::new(last, self.line_len(last)),
+                    goal: None,
+                });
+            }
+            Action::Edit(_)
+            | Action::ToggleFold(_)
+            | Action::Fold
+            | Action::Unfold
+            | Action::FoldAll
+            | Action::UnfoldAll
+            | Action::FoldLevel(_) => unreachable!("handled above"),
+            Action::Click(position) => {
+                self.set_selection(Selection::caret(self.clamp(position)));
+            }
+            Action::GoTo(position) => {
+                self.set_selection(Selection::caret(self.clamp(position)));
+                self.unfold_around_cursors();
+                self.reveal_cursor_centered();
+            }
+            Action::Drag(position) => {
+                let head = self.clamp(position);
+                let selection = &mut self.selections[self.primary];
+                selection.head = head;
+                selection.goal = None;
+                self.merge_selections();
+            }
+            Action::ClickLine { line, extend } => {
+                let line = line.min(self.lines.len() - 1);
+                let origin = match (extend, self.line_anchor) {
+                    (true, Some(origin)) => origin,
+                    (true, None) => self.selection().anchor.line,
+                    (false, _) => line,
+                };
+                self.line_anchor = Some(origin);
+                self.select_lines(origin, line);
+            }
+            Action::DragLine(line) => {
+                let line = line.min(self.lines.len() - 1);
+                let origin = self.line_anchor.unwrap_or(line);
+                self.select_lines(origin, line);
+            }
+            Action::Scroll { rows, columns } => {
+                self.scroll_row.set((self.scroll_row.get() + rows).clamp(0.0, self.max_scroll_row()));
+                if !self.is_wrapping() {
+                    self.scroll_column = (self.scroll_column + columns).max(0.0);
+                }
+            }
+            Action::ScrollTo { row } => {
+                self.scroll_row.set(row.clamp(0.0, self.max_scroll_row()));
+            }
+            Action::AddCursor(position) => {
+                let position = self.clamp(position);
+                let clicked = self.selections.iter().position(|s| s.start() <= position && position <= s.end());
+                match clicked {
+                    Some(index) if self.selections.len() > 1 => {
+                        self.selections.remove(index);
+                        self.primary = self.selections.len() - 1;
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.selections.push(Selection::caret(position));
+                        self.primary = self.selections.len() - 1;
+                        self.merge_selections();
+                    }
+                }
+            }
+            Action::BoxSelect { from, to } => self.select_box(from, to),
+            Action::SelectNextOccurrence => {
+                if self.selection().is_empty() {
+                    self.select_words();
+                } else if let Some((start, end)) = self.next_occurrence() {
+                    self.selections.push(Selection { anchor: start, head: end, goal: None });
+                    self.primary = self.selections.len() - 1;
+                    self.merge_selections();
+                }
+                self.reveal_cursor();
+            }
+            Action::SelectAllOccurrences => {
+                if self.selection().is_empty() {
+                    self.select_words();
+                }
+                let selection = self.selection();
+                let (needle, whole_word) = self.occurrence_query(selection);
+                let found = self.find_all(&needle, whole_word);
+                if !found.is_empty() {
+                    self.selections = found
+                        .into_iter()
+                        .map(|(anchor, head)| Selection { anchor, head, goal: None })
+                        .collect();
+                    self.primary = self
+                        .selections
+                        .iter()
+                        .position(|s| s.start() == selection.start())
+                        .unwrap_or(0);
+                }
+            }
+            Action::CollapseCursors => {
+                let primary = self.selection();
+                self.set_selection(primary);
+                self.snippet = None;
+            }
+            Action::Undo => {
+                self.snippet = None;
+                self.undo();
+            }
+            Action::Redo => {
+                self.snippet = None;
+                self.redo();
+            }
+            Action::SetIndentation(indentation) => self.indentation = indentation,
+            Action::ConvertIndentation(indentation) => {
+                self.transact(EditKind::Other, |document| document.convert_indentation(indentation));
+            }
+            Action::MoveLines { down } => self.line_command(|document| document.move_lines(down)),
+            Action::CopyLines { down } => self.line_command(|document| document.copy_lines(down)),
+            Action::DeleteLines => self.line_command(Self::delete_lines),
+            Action::JoinLines => self.line_command(Self::join_lines),
+            Action::SortLines { descending } => self.line_command(|document| document.sort_lines(descending)),
+            Action::Transpose => self.line_command(Self::transpose),
+            Action::ToggleLineComment => self.line_command(Self::toggle_line_comment),
+            Action::ToggleBlockComment => self.line_command(Self::toggle_block_comment),
+            Action::InsertSnippet { prefix, expansion } => {
+                self.transact(EditKind::Other, |document| document.insert_snippet(prefix, &expansion));
+                self.reveal_cursor();
+            }
+            Action::ApplyEdits(edits) => {
+                // Last first, so the positions of the ones before stay right. Of the ones at the
+                // same place the later goes in first, so the earlier ends up in front of it.
+                let mut edits: Vec<(usize, TextEdit)> = edits.into_iter().enumerate().collect();
+                edits.sort_by_key(|(index, edit)| std::cmp::Reverse((edit.start, *index)));
+                self.transact(EditKind::Other, |document| {
+                    for (_, edit) in edits {
+                        document.remove(edit.start, edit.end);
+                        document.insert(edit.start, &edit.text);
+                    }
+                });
+            }
+            Action::Cut => {
+                self.line_anchor = None;
+                self.transact(EditKind::Other, Self::delete_selected);
+                self.reveal_cursor();
+            }
+        }
+
+        // A cursor that ends up on a hidden line opens the folds around it
+        if !scrolling {
+            self.unfold_around_cursors();
+            self.leave_snippet_if_outside();
+        }
+        self.clamp_scroll();
+    }
+
+    /// Whether Tab is moving through the stops of a snippet.
+    pub fn in_snippet(&self) -> bool {
+        self.snippet.is_some()
+    }
+
+    /// The ranges of the tab stops still ahead, which get outlined so it's clear where Tab goes.
+    pub fn upcoming_tab_stops(&self) -> Vec<(Position, Position)> {
+        let Some(session) = &self.snippet else { return Vec::new(); };
+        session.stops[session.current + 1..].iter().flatten().copied().collect()
+    }
+
+    /// Expands a snippet at the primary cursor, indented like the line it goes on.
+    fn insert_snippet(&mut self, prefix: usize, expansion: &Expansion) {
+        self.line_anchor = None;
+        let selection = self.selection();
+        let start = match selection.is_empty() {
+            true => Position::new(selection.head.line, selection.head.column.saturating_sub(prefix)),
+            false => selection.start(),
+        };
+        self.set_selection(Selection::caret(start));
+        self.remove(start, selection.end());
+
+        let leading = indent::leading_whitespace(&self.lines[start.line]).to_string();
+        let expansion = expansion.indented(&leading, &self.indentation.unit());
+        self.insert(start, &expansion.text);
+
+        // Stops are char offsets into the text, which become positions once it's in place
+        let position = |offset: usize| {
+            let end = expansion.text.char_indices().nth(offset).map_or(expansion.text.len(), |(index, _)| index);
+            advance_position(start, &expansion.text[..end])
+        };
+        let stops = expansion
+            .tab_stops
+            .iter()
+            .map(|ranges| ranges.iter().map(|range| (position(range.start), position(range.end))).collect())
+            .collect();
+        self.snippet = Some(SnippetSession { stops, current: 0 });
+        self.select_tab_stop(0);
+    }
+
+    /// Moves to the next tab stop, or back to the previous one.
+    fn jump_tab_stop(&mut self, back: bool) {
+        let Some(session) = &self.snippet else { return; };
+        let current = match back {
+            true => session.current.saturating_sub(1),
+            false => session.current + 1,
+        };
+        self.select_tab_stop(current);
+        self.reveal_cursor();
+    }
+
+    /// Selects every range of a stop, so typing replaces them all at once. The last stop
+    /// is where the cursor ends up, and reaching it ends the snippet.
+    fn select_tab_stop(&mut self, index: usize) {
+        let Some(session) = &mut self.snippet else { return; };
+        let index = index.min(session.stops.len() - 1);
+        session.current = index;
+        self.selections = session.stops[index]
+            .iter()
+            .map(|&(anchor, head)| Selection { anchor, head, goal: None })
+            .collect();
+        self.primary = 0;
+        if index == session.stops.len() - 1 {
+            self.snippet = None;
+        }
+        self.merge_selections();
+    }
+
+    /// Ends the snippet once the cursor leaves the stop it's on.
+    fn leave_snippet_if_outside(&mut self) {
+        let Some(session) = &self.snippet else { return; };
+        let head = self.selection().head;
+        let inside = session.stops[session.current].iter().any(|&(start, end)| start <= head && head <= end);
+        if !inside {
+            self.snippet = None;
+        }
+    }
+
+    /// Runs one of the line commands as its own undo step, keeping the cursor in view.
+    fn line_command(&mut self, command: impl FnOnce(&mut Self)) {
+        self.line_anchor = None;
+        self.transact(EditKind::Other, command);
+        self.reveal_cursor();
+    }
+
+    /// Where the current text can be folded. After an edit these are the regions from before
+    /// it moved along with their lines, until `refresh_fold_regions` works them out again.
+    pub fn fold_regions(&self) -> Ref<'_, [FoldRegion]> {
+        if self.fold_regions.borrow().is_none() {
+            *self.fold_regions.borrow_mut() = Some(folding::fold_regions(&self.extension, &self.lines));
+        }
+        Ref::map(self.fold_regions.borrow(), |regions| regions.as_deref().unwrap_or_default())
+    }
+
+    /// Works out where the text can be folded again if it's been edited since. It parses the
+    /// whole file, so while typing it waits for a pause rather than running on every keystroke.
+    pub fn refresh_fold_regions(&mut self) {
+        if std::mem::take(&mut self.fold_regions_outdated) {
+            *self.fold_regions.get_mut() = Some(folding::fold_regions(&self.extension, &self.lines));
+        }
+    }
+
+    pub fn is_folded(&self, line: usize) -> bool {
+        self.folds.iter().any(|fold| fold.start == line)
+    }
+
+    /// The first line of every collapsed region, which is what gets saved between sessions.
+    pub fn folded_lines(&self) -> Vec<usize> {
+        self.folds.iter().map(|fold| fold.start).collect()
+    }
+
+    /// Collapses the regions starting on the given lines, e.g. the ones saved last session.
+    pub fn restore_folds(&mut self, lines: &[usize]) {
+        self.refresh_fold_regions();
+        let regions: Vec<FoldRegion> = self.fold_regions().iter().copied().collect();
+        self.folds = regions.into_iter().filter(|region| lines.contains(&region.start)).collect();
+        self.layout.get_mut().take();
+        self.clamp_scroll();
+    }
+
+    fn fold(&mut self, action: Action) {
+        self.refresh_fold_regions();
+        let regions: Vec<FoldRegion> = self.fold_regions().iter().copied().collect();
+        let line = self.selection().head.line;
+        // Regions are sorted by start, so the last one around the cursor is the innermost
+        let around = |region: &&FoldRegion| region.start <= line && line <= region.end;
+
+        match action {
+            Action::ToggleFold(line) => {
+                if self.is_folded(line) {
+                    self.folds.retain(|fold| fold.start != line);
+                } else if let Some(region) = regions.iter().find(|region| region.start == line) {
+                    self.folds.push(*region);
+                }
+            }
+            Action::Fold => {
+                if let Some(region) = regions.iter().filter(around).rfind(|region| !self.is_folded(region.start)) {
+                    self.folds.push(*region);
+                }
+            }
+            Action::Unfold => {
+                if let Some(fold) = self.folds.iter().filter(around).max_by_key(|fold| fold.start) {
+                    let start = fold.start;
+                    self.folds.retain(|fold| fold.start != start);
+                }
+            }
+            Action::FoldAll => self.folds = regions,
+            Action::UnfoldAll => self.folds.clear(),
+            Action::FoldLevel(level) => {
+                for region in regions.into_iter().filter(|region| region.level == level) {
+                    if !self.is_folded(region.start) {
+                        self.folds.push(region);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.folds.sort_by_key(|fold| fold.start);
+        self.layout.get_mut().take();
+    }
+
+    /// The hidden lines as inclusive ranges, with nested folds merged into their parents.
+    fn hidden_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for fold in &self.folds {
+            let (first, last) = (fold.start + 1, fold.end);
+            match ranges.last_mut() {
+                Some((_, end)) if first <= *end + 1 => *end = (*end).max(last),
+                _ => ranges.push((first, last)),
+            }
+        }
+        ranges
+    }
+
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.folds.iter().any(|fold| fold.start < line && line <= fold.end)
+    }
+
+    /// How many visible lines apart two lines are, not counting folded ones or wrapped rows.
+    pub fn visible_distance(&self, a: usize, b: usize) -> usize {
+        let (from, to) = if a <= b { (a, b) } else { (b, a) };
+        let hidden: usize = self
+            .hidden_ranges()
+            .iter()
+            .map(|&(first, last)| (last.min(to) + 1).saturating_sub(first.max(from + 1)))
+            .sum();
+        to - from - hidden
+    }
+
+    /// Sets how many columns fit before a line wraps, `None` to not wrap at all.
+    pub fn set_wrap_width(&self, width: Option<usize>) {
+        if self.wrap_width.replace(width) != width {
+            self.layout.borrow_mut().take();
+            self.clamp_scroll();
+        }
+    }
+
+    pub fn is_wrapping(&self) -> bool {
+        self.wrap_width.get().is_some()
+    }
+
+    /// Where each line sits on screen, worked out again after edits, folds or a new wrap width.
+    fn layout(&self) -> Ref<'_, RowLayout> {
+        if self.layout.borrow().is_none() {
+            let hidden = self.hidden_ranges();
+            let width = self.wrap_width.get();
+            let mut breaks = Vec::with_capacity(self.lines.len());
+            let mut starts = Vec::with_capacity(self.lines.len() + 1);
+            let mut row = 0;
+
+            for (index, line) in self.lines.iter().enumerate() {
+                starts.push(row);
+                if hidden.iter().any(|(first, last)| *first <= index && index <= *last) {
+                    breaks.push(Vec::new());
+                    continue;
+                }
+                let line_breaks = width.map(|width| wrap_points(line, width)).unwrap_or_default();
+                row += line_breaks.len() + 1;
+                breaks.push(line_breaks);
+            }
+            starts.push(row);
+            *self.layout.borrow_mut() = Some(RowLayout { breaks, starts });
+        }
+        Ref::map(self.layout.borrow(), |layout| layout.as_ref().unwrap())
+    }
+
+    /// How many rows the document takes on screen, with wrapped lines counting several times
+    /// and folded ones not at all.
+    pub fn row_count(&self) -> usize {
+        self.layout().starts.last().copied().unwrap_or(0).max(1)
+    }
+
+    /// The first screen row of a line. Hidden lines give the row of their fold.
+    pub fn row_of_line(&self, line: usize) -> usize {
+        let header = match self.hidden_ranges().iter().find(|(first, last)| *first <= line && line <= *last) {
+            Some((first, _)) => first - 1,
+            None => line,
+        };
+        self.layout().starts[header]
+    }
+
+    /// The line drawn on a screen row.
+    pub fn line_of_row(&self, row: usize) -> usize {
+        let layout = self.layout();
+        // Hidden lines take no rows, so the last line starting at or before `row` is the visible one
+        layout.starts[..self.lines.len()].partition_point(|&start| start <= row).saturating_sub(1)
+    }
+
+    /// The line on a screen row and the columns of it shown there.
+    pub fn row_segment(&self, row: usize) -> (usize, usize, usize) {
+        let line = self.line_of_row(row);
+        let layout = self.layout();
+        let breaks = &layout.breaks[line];
+        let index = (row - layout.starts[line]).min(breaks.len());
+        let start = if index == 0 { 0 } else { breaks[index - 1] };
+        let end = breaks.get(index).copied().unwrap_or_else(|| self.line_len(line));
+        (line, start, end)
+    }
+
+    /// The screen row of a position, and its on-screen column counted from the start of that row.
+    pub fn visual_position(&self, position: Position) -> (usize, usize) {
+        let first_row = self.row_of_line(position.line);
+        let layout = self.layout();
+        let breaks = layout.breaks.get(position.line).map(Vec::as_slice).unwrap_or_default();
+        let index = breaks.partition_point(|&start| start <= position.column);
+        let start = if index == 0 { 0 } else { breaks[index - 1] };
+        let column = self.display_column(position.line, position.column) - self.display_column(position.line, start);
+        (first_row + index, column)
+    }
+
+    /// Opens any fold that hides a cursor.
+    fn unfold_around_cursors(&mut self) {
+        let heads: Vec<usize> = self.selections.iter().map(|selection| selection.head.line).collect();
+        let count = self.folds.len();
+        self.folds.retain(|fold| !heads.iter().any(|&line| fold.start < line && line <= fold.end));
+        if self.folds.len() != count {
+            self.layout.get_mut().take();
+        }
+    }
+
+    /// After folding, cursors inside the fold move up to the line that stays visible.
+    fn move_cursors_out_of_folds(&mut self) {
+        let ranges = self.hidden_ranges();
+        self.map_selections(|document, selection| {
+            let line = selection.head.line;
+            match ranges.iter().find(|(first, last)| *first <= line && line <= *last) {
+                Some((first, _)) => {
+                    let header = first - 1;
+                    Selection::caret(Position::new(header, selection.head.column.min(document.line_len(header))))
+                }
+                None => selection,
+            }
+        });
+    }
+
+    /// Replaces every selection with a single one.
+    fn set_selection(&mut self, selection: Selection) {
+        self.selections = vec![selection];
+        self.primary = 0;
+    }
+
+    fn map_selections(&mut self, f: impl Fn(&Self, Selection) -> Selection) {
+        let selections = self.selections.iter().map(|selection| f(self, *selection)).collect();
+        self.selections = selections;
+        self.merge_selections();
+    }
+
+    /// Sorts the selections and joins the ones that ended up touching, keeping track of the primary one.
+    fn merge_selections(&mut self) {
+        let primary = self.selections[self.primary];
+        self.selections.sort_by_key(|selection| selection.start());
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(self.selections.len());
+        for selection in self.selections.drain(..) {
+            if let Some(last) = merged.last_mut()
+                && (selection.start() < last.end() || selection.start() == last.start())
+            {
+                let (start, end) = (last.start(), last.end().max(selection.end()));
+                *last = if last.head < last.anchor {
+                    Selection { anchor: end, head: start, goal: None }
+                } else {
+                    Selection { anchor: start, head: end, goal: None }
+                };
+                continue;
+            }
+            merged.push(selection);
+        }
+
+        self.primary = merged
+            .iter()
+            .position(|s| s.start() <= primary.head && primary.head <= s.end())
+            .unwrap_or(merged.len() - 1);
+        self.selections = merged;
+    }
+
+    /// Selects the word under every bare cursor, the first step of Cmd+D.
+    fn select_words(&mut self) {
+        self.map_selections(|document, selection| {
+            if !selection.is_empty() {
+                return selection;
+            }
+            let (start, end) = document.word_bounds(selection.head);
+            Selection { anchor: start, head: end, goal: None }
+        });
+    }
+
+    fn select_box(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let last_line = self.lines.len() - 1;
+        let (from_line, to_line) = (from.0.min(last_line), to.0.min(last_line));
+        let left = from.1.min(to.1);
+
+        let mut selections = Vec::new();
+        let mut primary = 0;
+        let lines: Vec<usize> = if from_line <= to_line {
+            (from_line..=to_line).collect()
+        } else {
+            (to_line..=from_line).rev().collect()
+        };
+        for line in lines {
+            // Lines too short to reach the box are left out, unless it has no width anyway
+            let width = self.display_column(line, self.line_len(line));
+            if width < left && from.1 != to.1 {
+                continue;
+            }
+            if line == to_line {
+                primary = selections.len();
+            }
+            selections.push(Selection {
+                anchor: Position::new(line, self.column_at_display(line, from.1)),
+                head: Position::new(line, self.column_at_display(line, to.1)),
+                goal: None,
+            });
+        }
+
+        if selections.is_empty() {
+            selections.push(Selection::caret(Position::new(to_line, self.column_at_display(to_line, to.1))));
+        }
+        self.selections = selections;
+        self.primary = primary.min(self.selections.len() - 1);
+        self.merge_selections();
+    }
+
+    /// What Cmd+D and Cmd+Shift+L look for: the selected text, as a whole word if that's what is selected.
+    fn occurrence_query(&self, selection: Selection) -> (String, bool) {
+        let (start, end) = (selection.start(), selection.end());
+        let needle = self.text_range(start, end);
+        let line: Vec<char> = self.lines[start.line].chars().collect();
+        let bounded = start.column == 0 || !line.get(start.column - 1).copied().is_some_and(is_word_char);
+        let end_line: Vec<char> = self.lines[end.line].chars().collect();
+        let bounded = bounded && !end_line.get(end.column).copied().is_some_and(is_word_char);
+        let whole_word = bounded && needle.chars().all(is_word_char);
+        (needle, whole_word)
+    }
+
+    /// The first occurrence after the primary selection that isn't selected yet, wrapping around.
+    fn next_occurrence(&self) -> Option<(Position, Position)> {
+        let selection = self.selection();
+        let (needle, whole_word) = self.occurrence_query(selection);
+        let found = self.find_all(&needle, whole_word);
+        let taken = |(start, _): &(Position, Position)| self.selections.iter().any(|s| s.start() == *start);
+
+        found
+            .iter()
+            .filter(|(start, _)| *start >= selection.end())
+            .chain(found.iter())
+            .find(|occurrence| !taken(occurrence))
+            .copied()
+    }
+
+    /// Every place `needle` appears in the document.
+    pub fn find_all(&self, needle: &str, whole_word: bool) -> Vec<(Position, Position)> {
+        if needle.is_empty() {
+            return Vec::new();
+        }
+        let text = self.lines.join("\n");
+        let mut found = Vec::new();
+        let mut position = Position::default();
+        let mut scanned = 0;
+
+        for (byte, _) in text.match_indices(needle) {
+            if whole_word {
+                let before = text[..byte].chars().next_back();
+                let after = text[byte + needle.len()..].chars().next();
+                if before.is_some_and(is_word_char) || after.is_some_and(is_word_char) {
+                    continue;
+                }
+            }
+            position = advance_position(position, &text[scanned..byte]);
+            scanned = byte;
+            found.push((position, advance_position(position, needle)));
+        }
+        found
+    }
+
+    /// Lets the last line scroll all the way up to the top, like VS Code does.
+    fn max_scroll_row(&self) -> f32 {
+        self.row_count().saturating_sub(1) as f32
+    }
+
+    /// Scrolls back up if the rows below the top of the view are gone, like after deleting or
+    /// folding lines, or widening a wrapped file.
+    fn clamp_scroll(&self) {
+        self.scroll_row.set(self.scroll_row.get().min(self.max_scroll_row()));
+    }
+
+    /// Scrolls just enough to bring the cursor on screen.
+    pub fn reveal_cursor(&mut self) {
+        let (rows, columns) = self.viewport.get();
+        let head = self.selection().head;
+
+        let row = self.visual_position(head).0 as f32;
+        if row < self.scroll_row.get() {
+            self.scroll_row.set(row);
+        } else if row + 1.0 > self.scroll_row.get() + rows as f32 {
+            self.scroll_row.set(row + 1.0 - rows as f32);
+        }
+        if self.is_wrapping() {
+            self.scroll_column = 0.0;
+            return;
+        }
+
+        let column = self.display_column(head.line, head.column) as f32;
+        if column < self.scroll_column {
+            self.scroll_column = (column - 4.0).max(0.0);
+        } else if column + 1.0 > self.scroll_column + columns as f32 {
+            self.scroll_column = column + 4.0 - columns as f32;
+        }
+    }
+
+    /// Scrolls the cursor's row to the middle of the screen, unless it's near the top.
+    fn reveal_cursor_centered(&mut self) {
+        let (rows, _) = self.viewport.get();
+        let row = self.visual_position(self.selection().head).0 as f32;
+        self.scroll_row.set((row - (rows / 2) as f32).clamp(0.0, self.max_scroll_row()));
+        self.reveal_cursor();
+    }
+
+    fn select_lines(&mut self, from: usize, to: usize) {
+        let (first, last) = (from.min(to), from.max(to));
+        let start = Position::new(first, 0);
+        let end = if last + 1 < self.lines.len() {
+            Position::new(last + 1, 0)
+        } else {
+            Position::new(last, self.line_len(last))
+        };
+        self.set_selection(if to < from {
+            Selection { anchor: end, head: start, goal: None }
+        } else {
+            Selection { anchor: start, head: end, goal: None }
+        });
+    }
+
+    /// Runs `f` as one undo step, or as part of the open one if it's the same kind of keystroke.
+    fn transact(&mut self, kind: EditKind, f: impl FnOnce(&mut Self)) {
+        let continues = kind != EditKind::Other
+            && self.history.open.as_ref().is_some_and(|step| step.kind == kind);
+        if !continues {
+            self.history.seal();
+            self.history.open = Some(Step {
+                kind,
+                changes: Vec::new(),
+                before: self.selections.clone(),
+                after: Vec::new(),
+            });
+        }
+
+        f(self);
+
+        if let Some(step) = &mut self.history.open {
+            step.after = self.selections.clone();
+        }
+        if kind == EditKind::Other {
+            self.history.seal();
+        }
+    }
+
+    fn record(&mut self, change: Change) {
+        // Runs after the text changed, but nothing before `at` moved and `removed` says how far it reached
+        let start = (change.at.line, self.utf16_column(change.at));
+        let end = match change.removed.rfind('\n') {
+            Some(last) => (start.0 + change.removed.matches('\n').count(), utf16_len(&change.removed[last + 1..])),
+            None => (start.0, start.1 + utf16_len(&change.removed)),
+        };
+        self.unsynced.push(SyncChange { start, end, text: change.inserted.clone() });
+        self.version += 1;
+
+        if let Some(step) = &mut self.history.open {
+            step.changes.push(change);
+            self.history.redo.clear();
+        }
+    }
+
+    fn undo(&mut self) {
+        let Some(step) = self.history.undo.pop() else { return; };
+        for change in step.changes.iter().rev() {
+            self.remove(change.at, advance_position(change.at, &change.inserted));
+            self.insert(change.at, &change.removed);
+        }
+        self.selections = step.before.clone();
+        self.primary = self.selections.len() - 1;
+        self.merge_selections();
+        self.history.redo.push(step);
+        self.reveal_cursor();
+    }
+
+    fn redo(&mut self) {
+        let Some(step) = self.history.redo.pop() else { return; };
+        for change in &step.changes {
+            self.remove(change.at, advance_position(change.at, &change.removed));
+            self.insert(change.at, &change.inserted);
+        }
+        self.selections = step.after.clone();
+        self.primary = self.selections.len() - 1;
+        self.merge_selections();
+        self.history.undo.push(step);
+        self.reveal_cursor();
+    }
+
+    /// Applies an edit at every cursor.
+    fn edit(&mut self, edit: Edit) {
+        self.line_anchor = None;
+
+        // Pasting as many lines as there are cursors gives each cursor its own line
+        let pasted = match &edit {
+            Edit::Paste(text) => Some(text.replace("\r\n", "\n")),
+            _ => None,
+        };
+        let pieces: Option<Vec<&str>> = pasted.as_deref().and_then(|text| {
+            let pieces: Vec<&str> = text.split('\n').collect();
+            (self.selections.len() > 1 && pieces.len() == self.selections.len()).then_some(pieces)
+        });
+
+        if let Edit::Indent | Edit::Unindent = edit {
+            self.indent_selections(edit == Edit::Unindent);
+            return;
+        }
+
+        for index in 0..self.selections.len() {
+            let selection = self.selections[index];
+            let caret = match &edit {
+                Edit::Insert(c) => {
+                    self.selections[index] = self.type_char(selection, *c);
+                    continue;
+                }
+                Edit::Paste(_) => {
+                    let text = match &pieces {
+                        Some(pieces) => pieces[index],
+                        None => pasted.as_deref().unwrap_or_default(),
+                    };
+                    self.replace(selection, text)
+                }
+                Edit::Enter => self.enter(selection),
+                Edit::Indent | Edit::Unindent => unreachable!("handled above"),
+                Edit::Backspace if selection.is_empty() => {
+                    let start = self.step_left(selection.head);
+                    // Inside an empty pair like `(|)` both halves go
+                    let end = match self.is_inside_empty_pair(selection.head) {
+                        true => Position::new(selection.head.line, selection.head.column + 1),
+                        false => selection.head,
+                    };
+                    self.remove(start, end);
+                    start
+                }
+                Edit::Delete if selection.is_empty() => {
+                    let end = self.step_right(selection.head);
+                    self.remove(selection.head, end);
+                    selection.head
+                }
+                Edit::Backspace | Edit::Delete => self.replace(selection, ""),
+            };
+            self.selections[index] = Selection::caret(caret);
+        }
+        self.merge_selections();
+    }
+
+    /// Deletes the text of every selection that has some.
+    fn delete_selected(&mut self) {
+        for index in 0..self.selections.len() {
+            let selection = self.selections[index];
+            if !selection.is_empty() {
+                self.selections[index] = Selection::caret(self.replace(selection, ""));
+            }
+        }
+        self.merge_selections();
+    }
+
+    /// Types a character, pairing brackets and quotes the way the language does. Typing an
+    /// opener with something selected wraps it, and typing a closer that's already there steps over it.
+    fn type_char(&mut self, selection: Selection, typed: char) -> Selection {
+        let pairs = syntax::auto_closing_pairs(&self.extension);
+        let close = pairs.iter().find(|(open, _)| *open == typed).map(|(_, close)| *close);
+
+        if !selection.is_empty() {
+            if let Some(close) = close {
+                let (start, end) = (selection.start(), selection.end());
+                self.insert(end, &close.to_string());
+                self.insert(start, &typed.to_string());
+                let start = Position::new(start.line, start.column + 1);
+                let end = if end.line == start.line { Position::new(end.line, end.column + 1) } else { end };
+                let (anchor, head) = if selection.anchor <= selection.head { (start, end) } else { (end, start) };
+                return Selection { anchor, head, goal: None };
+            }
+            let caret = self.replace(selection, &typed.to_string());
+            return Selection::caret(self.reindent_after_typing(caret, typed));
+        }
+
+        let head = selection.head;
+        let line = &self.lines[head.line];
+        let next = line.chars().nth(head.column);
+        let previous = head.column.checked_sub(1).and_then(|column| line.chars().nth(column));
+
+        if next == Some(typed) && pairs.iter().any(|(_, close)| *close == typed) {
+            return Selection::caret(Position::new(head.line, head.column + 1));
+        }
+        if let Some(close) = close {
+            let fits = next.is_none_or(|c| c.is_whitespace() || AUTO_CLOSE_BEFORE.contains(c));
+            // A quote right after a word is an apostrophe, and inside a string it ends the string
+            let is_quote = close == typed;
+            let opens_string = !is_quote
+                || !(previous.is_some_and(|c| is_word_char(c) || c == typed)
+                    || syntax::in_string_or_comment(&self.extension, line, head.column));
+            if fits && opens_string {
+                self.insert(head, &format!("{typed}{close}"));
+                return Selection::caret(Position::new(head.line, head.column + 1));
+            }
+        }
+
+        let caret = self.insert(head, &typed.to_string());
+        if typed == '>' {
+            let line = &self.lines[caret.line];
+            if let Some(tag) = syntax::closing_tag(&self.extension, &line[..byte_index(line, head.column)]) {
+                self.insert(caret, &tag);
+            }
+        }
+        Selection::caret(self.reindent_after_typing(caret, typed))
+    }
+
+    /// Whether the cursor sits between the two halves of a pair with nothing in between.
+    fn is_inside_empty_pair(&self, at: Position) -> bool {
+        let Some(column) = at.column.checked_sub(1) else { return false; };
+        let mut chars = self.lines[at.line].chars().skip(column);
+        let (Some(before), Some(after)) = (chars.next(), chars.next()) else { return false; };
+        syntax::auto_closing_pairs(&self.extension).contains(&(before, after))
+    }
+
+    /// Breaks the line at a selection, keeping its indentation and adding a level after
+    /// something that opens a block. Between a pair of brackets the closing one goes on a line of its own.
+    fn enter(&mut self, selection: Selection) -> Position {
+        let (start, end) = (selection.start(), selection.end());
+        let before = self.text_range(Position::new(start.line, 0), start);
+        let after = self.text_range(end, Position::new(end.line, self.line_len(end.line)));
+        let indent = indent::leading_whitespace(&before).to_string();
+
+        if indent::opens_block(&self.extension, &before) {
+            let inner = indent.clone() + &self.indentation.unit();
+            let between_brackets = match (before.trim_end().chars().last(), after.trim_start().chars().next()) {
+                (Some(open), Some(close)) => indent::opening_bracket(close) == Some(open),
+                _ => false,
+            };
+            let text = if between_brackets { format!("\n{}\n{}", inner, indent) } else { format!("\n{}", inner) };
+            self.replace(selection, &text);
+            Position::new(start.line + 1, inner.chars().count())
+        } else if indent::closes_block(&self.extension, &before) {
+            let width = display_width(indent.chars(), 0).saturating_sub(1) / self.indentation.width() * self.indentation.width();
+            self.replace(selection, &format!("\n{}", self.indentation.whitespace(width)))
+        } else {
+            self.replace(selection, &format!("\n{}", indent))
+        }
+    }
+
+    /// Lines a closing bracket typed at the start of a line up with the line that opened it,
+    /// and a Python `else:` up with its `if`. Returns where the cursor ends up.
+    fn reindent_after_typing(&mut self, caret: Position, typed: char) -> Position {
+        let line = &self.lines[caret.line];
+        let before = &line[..byte_index(line, caret.column.saturating_sub(1))];
+        let current = display_width(indent::leading_whitespace(line).chars(), 0);
+
+        let width = if let Some(open) = indent::opening_bracket(typed).filter(|_| before.trim().is_empty()) {
+            let at = Position::new(caret.line, caret.column - 1);
+            let Some(opened) = decoration::find_open(self, at, open, typed) else { return caret; };
+            display_width(indent::leading_whitespace(&self.lines[opened.line]).chars(), 0)
+        } else if typed == ':' && indent::is_dedent_clause(&self.extension, line) {
+            let previous = (0..caret.line).rev().map(|line| self.line(line)).find(|line| !line.trim().is_empty());
+            let previous = previous.map_or(0, |line| display_width(indent::leading_whitespace(line).chars(), 0));
+            // Only when it's still lined up with the block it ends
+            if current == 0 || previous < current {
+                return caret;
+            }
+            (current - 1) / self.indentation.width() * self.indentation.width()
+        } else {
+            return caret;
+        };
+        if width == current {
+            return caret;
+        }
+
+        let old_len = indent::leading_whitespace(line).chars().count();
+        let whitespace = self.indentation.whitespace(width);
+        self.remove(Position::new(caret.line, 0), Position::new(caret.line, old_len));
+        self.insert(Position::new(caret.line, 0), &whitespace);
+        Position::new(caret.line, caret.column - old_len + whitespace.chars().count())
+    }
+
+    /// Tab and Shift+Tab. Indents or outdents every line a selection touches, except that Tab
+    /// with nothing selected across lines types indentation at the cursor.
+    fn indent_selections(&mut self, outdent: bool) {
+        let mut done = None; // The last line handled, since several cursors can share one
+        for index in 0..self.selections.len() {
+            let selection = self.selections[index];
+            let (start, end) = (selection.start(), selection.end());
+
+            if !outdent && start.line == end.line {
+                let column = self.display_column(start.line, start.column);
+                let text = match self.indentation.tabs {
+                    true => "\t".to_string(),
+                    false => " ".repeat(self.indentation.size - column % self.indentation.size),
+                };
+                let caret = self.replace(selection, &text);
+                self.selections[index] = Selection::caret(caret);
+                continue;
+            }
+
+            // A selection ending at the start of a line doesn't include that line
+            let last = if end.line > start.line && end.column == 0 { end.line - 1 } else { end.line };
+            for line in start.line..=last {
+                if done.is_some_and(|done| line <= done) {
+                    continue;
+                }
+                if outdent {
+                    let removable = match self.lines[line].starts_with('\t') {
+                        true => 1,
+                        false => self.lines[line].chars().take(self.indentation.width()).take_while(|c| *c == ' ').count(),
+                    };
+                    self.remove(Position::new(line, 0), Position::new(line, removable));
+                } else if !self.lines[line].is_empty() {
+                    self.insert(Position::new(line, 0), &self.indentation.unit());
+                }
+            }
+            done = Some(last);
+        }
+        self.merge_selections();
+    }
+
+    /// Rewrites the leading whitespace of every line in another style. Levels are counted in
+    /// the current style, so a file indented by two spaces gets a tab per two spaces.
+    fn convert_indentation(&mut self, to: Indentation) {
+        let level = self.indentation.width();
+        for line in 0..self.lines.len() {
+            let indent = indent::leading_whitespace(&self.lines[line]).to_string();
+            if indent.is_empty() {
+                continue;
+            }
+            let width = display_width(indent.chars(), 0);
+            let converted = to.unit().repeat(width / level) + &" ".repeat(width % level);
+            if converted != indent {
+                self.remove(Position::new(line, 0), Position::new(line, indent.chars().count()));
+                self.insert(Position::new(line, 0), &converted);
+            }
+        }
+        self.indentation = to;
+    }
+
+    /// The lines each selection touches, merged where they overlap or meet, top to bottom.
+    /// A selection ending at the start of a line doesn't include that line.
+    fn line_blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<(usize, usize)> = Vec::new();
+        for selection in &self.selections {
+            let (start, end) = (selection.start(), selection.end());
+            let last = if end.line > start.line && end.column == 0 { end.line - 1 } else { end.line };
+            match blocks.last_mut() {
+                Some((_, previous)) if start.line <= *previous + 1 => *previous = (*previous).max(last),
+                _ => blocks.push((start.line, last)),
+            }
+        }
+        blocks
+    }
+
+    /// Which of `blocks` a selection belongs to.
+    fn block_of(blocks: &[(usize, usize)], selection: &Selection) -> usize {
+        let line = selection.start().line;
+        blocks.iter().position(|&(first, last)| first <= line && line <= last).unwrap_or(0)
+    }
+
+    /// Swaps the text of lines `first..=last` for `lines`.
+    fn replace_lines(&mut self, first: usize, last: usize, lines: &[String]) {
+        self.remove(Position::new(first, 0), Position::new(last, self.line_len(last)));
+        self.insert(Position::new(first, 0), &lines.join("\n"));
+    }
+
+    /// Alt+Up / Alt+Down, swaps the selected lines with the one above or below them.
+    fn move_lines(&mut self, down: bool) {
+        let blocks = self.line_blocks();
+        let last_line = self.lines.len() - 1;
+        if blocks.iter().any(|&(first, last)| if down { last == last_line } else { first == 0 }) {
+            return;
+        }
+        let selections = self.selections.clone();
+        for &(first, last) in &blocks {
+            let (from, to) = if down { (first, last + 1) } else { (first - 1, last) };
+            let mut lines = self.lines[from..=to].to_vec();
+            if down {
+                lines.rotate_right(1);
+            } else {
+                lines.rotate_left(1);
+            }
+            self.replace_lines(from, to, &lines);
+        }
+        let shift = |position: Position| {
+            let line = if down { position.line + 1 } else { position.line - 1 };
+            Position::new(line, position.column)
+        };
+        self.selections = selections
+            .into_iter()
+            .map(|selection| Selection { anchor: shift(selection.anchor), head: shift(selection.head), goal: None })
+            .collect();
+    }
+
+    /// Shift+Alt+Up / Shift+Alt+Down, duplicates the selected lines. The cursors end up on the
+    /// copy below when copying down, and stay where they were when copying up.
+    fn copy_lines(&mut self, down: bool) {
+        let blocks = self.line_blocks();
+        let selections = self.selections.clone();
+        for &(first, last) in blocks.iter().rev() {
+            let text = self.lines[first..=last].join("\n");
+            self.insert(Position::new(last, self.line_len(last)), &format!("\n{}", text));
+        }
+        self.selections = selections
+            .into_iter()
+            .map(|selection| {
+                let block = Self::block_of(&blocks, &selection);
+                // Every block above this one got longer by its own size
+                let mut offset: usize = blocks[..block].iter().map(|(first, last)| last - first + 1).sum();
+                if down {
+                    offset += blocks[block].1 - blocks[block].0 + 1;
+                }
+                let shift = |position: Position| Position::new(position.line + offset, position.column);
+                Selection { anchor: shift(selection.anchor), head: shift(selection.head), goal: None }
+            })
+            .collect();
+    }
+
+    /// Cmd+Shift+K, deletes the selected lines. The cursors keep their columns where they can.
+    fn delete_lines(&mut self) {
+        let blocks = self.line_blocks();
+        let selections = self.selections.clone();
+        for &(first, last) in blocks.iter().rev() {
+            if last + 1 < self.lines.len() {
+                self.remove(Position::new(first, 0), Position::new(last + 1, 0));
+            } else if first > 0 {
+                self.remove(Position::new(first - 1, self.line_len(first - 1)), Position::new(last, self.line_len(last)));
+            } else {
+                self.remove(Position::new(0, 0), Position::new(last, self.line_len(last)));
+            }
+        }
+        self.selections = selections
+            .into_iter()
+            .map(|selection| {
+                let block = Self::block_of(&blocks, &selection);
+                let removed: usize = blocks[..block].iter().map(|(first, last)| last - first + 1).sum();
+                let line = (blocks[block].0 - removed).min(self.lines.len() - 1);
+                Selection::caret(self.clamp(Position::new(line, selection.head.column)))
+            })
+            .collect();
+        self.merge_selections();
+    }
+
+    /// Ctrl+J, or Ctrl+Shift+J off macOS, joins each line with the next one, or all the selected
+    /// lines, with one space where they meet.
+    fn join_lines(&mut self) {
+        let blocks = self.line_blocks();
+        let mut joins = Vec::with_capacity(blocks.len()); // Where each block's last join happened
+        for &(first, last) in blocks.iter().rev() {
+            let last = last.max(first + 1).min(self.lines.len() - 1);
+            let mut joined = self.lines[first].trim_end().to_string();
+            let mut join_at = joined.chars().count();
+            for line in first + 1..=last {
+                let piece = self.lines[line].trim();
+                join_at = joined.chars().count();
+                if !piece.is_empty() {
+                    if !joined.is_empty() {
+                        joined.push(' ');
+                    }
+                    joined.push_str(piece);
+                }
+            }
+            self.replace_lines(first, last, &[joined]);
+            joins.push((first, last - first, join_at));
+        }
+
+        // Blocks were joined bottom to top, so count the lines removed above each one
+        joins.reverse();
+        let mut removed = 0;
+        self.selections = joins
+            .into_iter()
+            .map(|(first, joined, column)| {
+                let caret = Position::new(first - removed, column);
+                removed += joined;
+                Selection::caret(caret)
+            })
+            .collect();
+        self.primary = self.selections.len() - 1;
+        self.merge_selections();
+    }
+
+    /// Sorts the lines of each selection that spans more than one.
+    fn sort_lines(&mut self, descending: bool) {
+        let selections = self.selections.clone();
+        for (first, last) in self.line_blocks() {
+            let mut lines = self.lines[first..=last].to_vec();
+            lines.sort();
+            if descending {
+                lines.reverse();
+            }
+            if lines[..] != self.lines[first..=last] {
+                self.replace_lines(first, last, &lines);
+            }
+        }
+        // The lines moved under them, so each end might now be past the end of its line
+        self.selections = selections
+            .into_iter()
+            .map(|selection| Selection { anchor: self.clamp(selection.anchor), head: self.clamp(selection.head), goal: None })
+            .collect();
+    }
+
+    /// Ctrl+T, or Ctrl+Shift+T off macOS, swaps the characters on either side of each cursor, or the last two at the end of a line.
+    fn transpose(&mut self) {
+        for index in 0..self.selections.len() {
+            let head = self.selections[index].head;
+            let len = self.line_len(head.line);
+            if !self.selections[index].is_empty() || head.column == 0 || len < 2 {
+                continue;
+            }
+            let column = head.column.min(len - 1);
+            let (start, end) = (Position::new(head.line, column - 1), Position::new(head.line, column + 1));
+            let swapped: String = self.text_range(start, end).chars().rev().collect();
+            self.remove(start, end);
+            self.insert(start, &swapped);
+            self.selections[index] = Selection::caret(end);
+        }
+        self.merge_selections();
+    }
+
+    /// Cmd+/, comments the selected lines out with the language's line comment, or back in if
+    /// they all already are. Languages without one get each block of lines wrapped in a block comment.
+    fn toggle_line_comment(&mut self) {
+        let tokens = syntax::comment_tokens(&self.extension);
+        let Some(token) = tokens.line else {
+            let Some((open, close)) = tokens.block else { return; };
+            for (first, last) in self.line_blocks().into_iter().rev() {
+                let indent = indent::leading_whitespace(&self.lines[first]).chars().count();
+                self.toggle_block_comment_in(Position::new(first, indent), Position::new(last, self.line_len(last)), &open, &close);
+            }
+            return;
+        };
+
+        let marker = token.trim_end();
+        let lines: Vec<usize> = self
+            .line_blocks()
+            .into_iter()
+            .flat_map(|(first, last)| first..=last)
+            .filter(|&line| !self.lines[line].trim().is_empty())
+            .collect();
+        let commented = lines.iter().all(|&line| self.lines[line].trim_start().starts_with(marker));
+
+        for &line in &lines {
+            let indent = indent::leading_whitespace(&self.lines[line]).chars().count();
+            if commented {
+                let rest = self.lines[line].trim_start();
+                let len = marker.chars().count() + usize::from(rest[marker.len()..].starts_with(' '));
+                self.remove(Position::new(line, indent), Position::new(line, indent + len));
+            }
+        }
+        if !commented {
+            // At the shallowest indentation of the lines, so the markers line up
+            let column = lines
+                .iter()
+                .map(|&line| indent::leading_whitespace(&self.lines[line]).chars().count())
+                .min()
+                .unwrap_or(0);
+            for &line in &lines {
+                self.insert(Position::new(line, column), &token);
+            }
+        }
+    }
+
+    /// Shift+Alt+A, wraps each selection in a block comment, or unwraps it if it already is one.
+    /// With nothing selected, an empty comment is typed at the cursor.
+    fn toggle_block_comment(&mut self) {
+        let Some((open, close)) = syntax::comment_tokens(&self.extension).block else { return; };
+        for index in 0..self.selections.len() {
+            let selection = self.selections[index];
+            if selection.is_empty() {
+                let open = format!("{} ", open.trim());
+                self.insert(selection.head, &format!("{} {}", open, close.trim()));
+                let caret = Position::new(selection.head.line, selection.head.column + open.chars().count());
+                self.selections[index] = Selection::caret(caret);
+                continue;
+            }
+            let (start, end) = self.toggle_block_comment_in(selection.start(), selection.end(), &open, &close);
+            let (anchor, head) = if selection.anchor <= selection.head { (start, end) } else { (end, start) };
+            self.selections[index] = Selection { anchor, head, goal: None };
+        }
+        self.merge_selections();
+    }
+
+    /// Wraps `start..end` in a block comment, or unwraps it. Returns where the text that was
+    /// inside ends up.
+    fn toggle_block_comment_in(&mut self, start: Position, end: Position, open: &str, close: &str) -> (Position, Position) {
+        let (open, close) = (open.trim(), close.trim());
+        let text = self.text_range(start, end);
+        let (open_len, close_len) = (open.chars().count(), close.chars().count());
+
+        // The markers may sit just outside the selection, like right after commenting it
+        let before = self.text_range(Position::new(start.line, 0), start);
+        let after = self.text_range(end, Position::new(end.line, self.line_len(end.line)));
+        let lead = [format!("{} ", open), open.to_string()].into_iter().find(|marker| before.ends_with(marker.as_str()));
+        let trail = [format!(" {}", close), close.to_string()].into_iter().find(|marker| after.starts_with(marker.as_str()));
+        if let (Some(lead), Some(trail)) = (lead, trail) {
+            let (lead, trail) = (lead.chars().count(), trail.chars().count());
+            self.remove(end, Position::new(end.line, end.column + trail));
+            let outer = Position::new(start.line, start.column - lead);
+            self.remove(outer, start);
+            let end_column = end.column - if end.line == start.line { lead } else { 0 };
+            return (outer, Position::new(end.line, end_column));
+        }
+
+        if text.starts_with(open) && text.ends_with(close) && text.chars().count() >= open_len + close_len {
+            let inner = &text[open.len()..text.len() - close.len()];
+            let open_len = open_len + usize::from(inner.starts_with(' '));
+            let close_len = close_len + usize::from(inner.len() > 1 && inner.ends_with(' '));
+            self.remove(Position::new(end.line, end.column - close_len), end);
+            self.remove(start, Position::new(start.line, start.column + open_len));
+            let end_column = end.column - close_len - if end.line == start.line { open_len } else { 0 };
+            return (start, Position::new(end.line, end_column));
+        }
+
+        self.insert(end, &format!(" {}", close));
+        self.insert(start, &format!("{} ", open));
+        let inner_start = Position::new(start.line, start.column + open_len + 1);
+        let end_column = end.column + if end.line == start.line { open_len + 1 } else { 0 };
+        (inner_start, Position::new(end.line, end_column))
+    }
+
+    /// Swaps the text of a selection for `text` and returns where the cursor ends up.
+    fn replace(&mut self, selection: Selection, text: &str) -> Position {
+        let start = selection.start();
+        self.remove(start, selection.end());
+        self.insert(start, text)
+    }
+
+    /// Inserts `text` at `at` and returns the position right after it.
+    pub fn insert(&mut self, at: Position, text: &str) -> Position {
+        let at = self.clamp(at);
+        if text.is_empty() {
+            return at;
+        }
+        let line = &mut self.lines[at.line];
+        let tail = line.split_off(byte_index(line, at.column));
+
+        let mut pieces = text.split('\n');
+        let first = pieces.next().unwrap_or("");
+        line.push_str(first);
+
+        let mut new_lines: Vec<String> = pieces.map(String::from).collect();
+        let added = new_lines.len();
+        let end = match new_lines.last_mut() {
+            Some(last) => {
+                let end = Position::new(at.line + added, last.chars().count());
+                last.push_str(&tail);
+                end
+            }
+            None => {
+                let line = &mut self.lines[at.line];
+                let end = Position::new(at.line, at.column + first.chars().count());
+                line.push_str(&tail);
+                end
+            }
+        };
+
+        let insert_at = at.line + 1;
+        self.lines.splice(insert_at..insert_at, new_lines.drain(..));
+        self.highlights.get_mut().invalidate(at.line);
+        self.shift_selections(at, at, end);
+        self.shift_inlay_hints(at, at, end);
+        self.shift_folds(at.line, at.line, end.line);
+        self.record(Change { at, removed: String::new(), inserted: text.to_string() });
+        end
+    }
+
+    /// Removes the text between two positions and returns it.
+    pub fn remove(&mut self, start: Position, end: Position) -> String {
+        let (start, end) = (self.clamp(start.min(end)), self.clamp(start.max(end)));
+        if start == end {
+            return String::new();
+        }
+        let removed = self.text_range(start, end);
+
+        let tail = {
+            let last = &self.lines[end.line];
+            last[byte_index(last, end.column)..].to_string()
+        };
+        let first = &mut self.lines[start.line];
+        first.truncate(byte_index(first, start.column));
+        first.push_str(&tail);
+        self.lines.drain(start.line + 1..=end.line);
+
+        self.highlights.get_mut().invalidate(start.line);
+        self.shift_selections(start, end, start);
+        self.shift_inlay_hints(start, end, start);
+        self.shift_folds(start.line, end.line, start.line);
+        self.record(Change { at: start, removed: removed.clone(), inserted: String::new() });
+        removed
+    }
+
+    /// Moves folds below an edit along with their lines. Folds the edit reaches into are opened,
+    /// unless it only touched the line that stays visible.
+    fn shift_folds(&mut self, start: usize, old_end: usize, new_end: usize) {
+        // Regions around the edit grow or shrink with it, and ones it cuts through are gone
+        if let Some(regions) = self.fold_regions.get_mut() {
+            regions.retain_mut(|region| {
+                if region.end < start {
+                    return true;
+                }
+                if region.start > old_end {
+                    region.start = region.start + new_end - old_end;
+                } else if region.start > start || region.end < old_end {
+                    return false;
+                }
+                region.end = region.end + new_end - old_end;
+                region.end > region.start
+            });
+            self.fold_regions_outdated = true;
+        }
+        self.layout.get_mut().take();
+        self.folds.retain_mut(|fold| {
+            if fold.end < start {
+                true
+            } else if fold.start > old_end {
+                fold.start = fold.start + new_end - old_end;
+                fold.end = fold.end + new_end - old_end;
+                true
+            } else {
+                fold.start == start && old_end == start && new_end == start
+            }
+        });
+    }
+
+    /// Keeps every cursor on the same text after `start..old_end` was replaced by `start..new_end`.
+    fn shift_selections(&mut self, start: Position, old_end: Position, new_end: Position) {
+        let shift = |position| shift_position(position, start, old_end, new_end);
+        for selection in &mut self.selections {
+            selection.anchor = shift(selection.anchor);
+            selection.head = shift(selection.head);
+        }
+        // Text typed at either edge of the current tab stop goes into it, but only pushes the others along
+        if let Some(session) = &mut self.snippet {
+            for (index, ranges) in session.stops.iter_mut().enumerate() {
+                let current = index == session.current;
+                for (from, to) in ranges {
+                    let keeps_end = !current && *to == start && *from < *to;
+                    *from = if current && *from == start { *from } else { shift(*from) };
+                    *to = if keeps_end { *to } else { shift(*to) };
+                }
+            }
+        }
+    }
+
+    /// Moves inlay hints along with their text. Ones inside removed text go with it.
+    fn shift_inlay_hints(&mut self, start: Position, old_end: Position, new_end: Position) {
+        if self.inlay_hints.is_empty() {
+            return;
+        }
+        self.inlay_hints.retain(|hint| hint.position <= start || hint.position >= old_end);
+        for hint in &mut self.inlay_hints {
+            hint.position = shift_position(hint.position, start, old_end, new_end);
+        }
+    }
+
+    /// Replaces the inlay hints with the ones a server just sent.
+    pub fn set_inlay_hints(&mut self, mut hints: Vec<InlayHint>) {
+        hints.sort_by_key(|hint| hint.position);
+        self.inlay_hints = hints;
+    }
+
+    /// The inlay hints on a line, in order.
+    pub fn inlay_hints_on(&self, line: usize) -> &[InlayHint] {
+        let start = self.inlay_hints.partition_point(|hint| hint.position.line < line);
+        let end = self.inlay_hints.partition_point(|hint| hint.position.line <= line);
+        &self.inlay_hints[start..end]
+    }
+
+    pub fn text_range(&self, start: Position, end: Position) -> String {
+        let (start, end) = (self.clamp(start), self.clamp(end));
+        if start.line == end.line {
+            let line = &self.lines[start.line];
+            return line[byte_index(line, start.column)..byte_index(line, end.column)].to_string();
+        }
+
+        let mut text = String::new();
+        let first = &self.lines[start.line];
+        text.push_str(&first[byte_index(first, start.column)..]);
+        for line in &self.lines[start.line + 1..end.line] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        let last = &self.lines[end.line];
+        text.push('\n');
+        text.push_str(&last[..byte_index(last, end.column)]);
+        text
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.lines.get(line).map(|l| l.chars().count()).unwrap_or(0)
+    }
+
+    fn clamp(&self, position: Position) -> Position {
+        let line = position.line.min(self.lines.len() - 1);
+        Position::new(line, position.column.min(self.line_len(line)))
+    }
+
+    /// Bumped by every change, so a language server can tell which text it's looking at.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// The changes since the last call, oldest first.
+    pub fn take_changes(&mut self) -> Vec<SyncChange> {
+        std::mem::take(&mut self.unsynced)
+    }
+
+    /// A char column in UTF-16 code units, which is how language servers count.
+    pub fn utf16_column(&self, position: Position) -> usize {
+        let line = self.line(position.line);
+        utf16_len(&line[..byte_index(line, position.column)])
+    }
+
+    /// The position a language server means by a line and a UTF-16 column, clamped to the text.
+    pub fn position_from_utf16(&self, line: usize, units: usize) -> Position {
+        let line = line.min(self.lines.len() - 1);
+        Position::new(line, column_from_utf16(self.line(line), units))
+    }
+
+    /// The on-screen column of a char column, with tabs expanded and room made for inlay hints.
+    /// A cursor there goes before any hint at the same column.
+    pub fn display_column(&self, line: usize, column: usize) -> usize {
+        self.hinted_width(line, column, false)
+    }
+
+    /// Where the char at a column is drawn, which is after any inlay hint in front of it.
+    pub fn char_display_column(&self, line: usize, column: usize) -> usize {
+        self.hinted_width(line, column, true)
+    }
+
+    fn hinted_width(&self, line: usize, column: usize, past_hints: bool) -> usize {
+        let mut hints = self.inlay_hints_on(line).iter().peekable();
+        let mut width = 0;
+        for (index, c) in self.line(line).chars().take(column).enumerate() {
+            while let Some(hint) = hints.next_if(|hint| hint.position.column <= index) {
+                width += hint.width();
+            }
+            width = advance(width, c);
+        }
+        if past_hints {
+            width += hints.take_while(|hint| hint.position.column <= column).map(InlayHint::width).sum::<usize>();
+        }
+        width
+    }
+
+    /// The char column closest to an on-screen column, e.g. for mouse clicks. Clicks on an inlay
+    /// hint land before it.
+    pub fn column_at_display(&self, line: usize, display: usize) -> usize {
+        let mut hints = self.inlay_hints_on(line).iter().peekable();
+        let mut width = 0;
+        for (column, c) in self.line(line).chars().enumerate() {
+            while let Some(hint) = hints.next_if(|hint| hint.position.column <= column) {
+                width += hint.width();
+                if display < width {
+                    return column;
+                }
+            }
+            let next = advance(width, c);
+            if display < next {
+                // Tabs and wide chars take more than one column, snap to whichever edge is closer
+                return if display - width <= (next - width) / 2 { column } else { column + 1 };
+            }
+            width = next;
+        }
+        self.line_len(line)
+    }
+
+    fn apply_motion(&self, from: Position, goal: Option<usize>, motion: Motion) -> (Position, Option<usize>) {
+        let last_line = self.lines.len() - 1;
+        match motion {
+            Motion::Left => (self.step_left(from), None),
+            Motion::Right => (self.step_right(from), None),
+            Motion::Up | Motion::Down | Motion::PageUp | Motion::PageDown => {
+                // Moves by screen rows, so folded lines are stepped over and wrapped ones walked through
+                let (row, column) = self.visual_position(from);
+                let goal = goal.unwrap_or(column);
+                let (rows, _) = self.viewport.get();
+                let last_row = self.row_count() - 1;
+                let target = match motion {
+                    Motion::Up if row == 0 => return (Position::new(0, 0), None),
+                    Motion::Down if row == last_row => {
+                        return (Position::new(last_line, self.line_len(last_line)), None);
+                    }
+                    Motion::Up => row - 1,
+                    Motion::Down => row + 1,
+                    Motion::PageUp => row.saturating_sub(rows),
+                    _ => (row + rows).min(last_row),
+                };
+                (self.position_in_row(target, goal), Some(goal))
+            }
+            Motion::WordLeft => (self.word_left(from), None),
+            Motion::WordRight => (self.word_right(from), None),
+            Motion::Home => {
+                // Jumps to the first non-blank character, then to the real start of the line
+                let indent = self.lines[from.line].chars().take_while(|c| c.is_whitespace()).count();
+                let column = if from.column == indent { 0 } else { indent };
+                (Position::new(from.line, column), None)
+            }
+            Motion::End => (Position::new(from.line, self.line_len(from.line)), None),
+            Motion::DocumentStart => (Position::default(), None),
+            Motion::DocumentEnd => (Position::new(last_line, self.line_len(last_line)), None),
+        }
+    }
+
+    /// The position at an on-screen column of a screen row, staying within that row.
+    pub fn position_in_row(&self, row: usize, display: usize) -> Position {
+        let (line, start, end) = self.row_segment(row);
+        let column = self.column_at_display(line, self.display_column(line, start) + display);
+        // Except on a line's last row, the end column belongs to the row below
+        let last = if end < self.line_len(line) { end.saturating_sub(1).max(start) } else { end };
+        Position::new(line, column.min(last))
+    }
+
+    fn step_left(&self, from: Position) -> Position {
+        if from.column > 0 {
+            Position::new(from.line, from.column - 1)
+        } else if from.line > 0 {
+            Position::new(from.line - 1, self.line_len(from.line - 1))
+        } else {
+            from
+        }
+    }
+
+    fn step_right(&self, from: Position) -> Position {
+        if from.column < self.line_len(from.line) {
+            Position::new(from.line, from.column + 1)
+        } else if from.line + 1 < self.lines.len() {
+            Position::new(from.line + 1, 0)
+        } else {
+            from
+        }
+    }
+
+    fn word_left(&self, from: Position) -> Position {
+        if from.column == 0 {
+            return self.step_left(from);
+        }
+        let chars: Vec<char> = self.lines[from.line].chars().collect();
+        let mut column = from.column;
+        while column > 0 && chars[column - 1].is_whitespace() {
+            column -= 1;
+        }
+        if column > 0 {
+            let class = char_class(chars[column - 1]);
+            while column > 0 && char_class(chars[column - 1]) == class {
+                column -= 1;
+            }
+        }
+        Position::new(from.line, column)
+    }
+
+    fn word_right(&self, from: Position) -> Position {
+        let chars: Vec<char> = self.lines[from.line].chars().collect();
+        if from.column >= chars.len() {
+            return self.step_right(from);
+        }
+        let mut column = from.column;
+        while column < chars.len() && chars[column].is_whitespace() {
+            column += 1;
+        }
+        if column < chars.len() {
+            let class = char_class(chars[column]);
+            while column < chars.len() && char_class(chars[column]) == class {
+                column += 1;
+            }
+        }
+        Position::new(from.line, column)
+    }
+
+    /// The start and end of the word touching `at`.
+    pub fn word_bounds(&self, at: Position) -> (Position, Position) {
+        let chars: Vec<char> = self.lines[at.line].chars().collect();
+        if chars.is_empty() {
+            return (at, at);
+        }
+        // Prefer the char after the cursor, but fall back to the one before at the end of a word
+        let index = if at.column < chars.len() && (at.column == 0 || char_class(chars[at.column]) == CharClass::Word) {
+            at.column
+        } else {
+            at.column.saturating_sub(1).min(chars.len() - 1)
+        };
+        let class = char_class(chars[index]);
+        let mut start = index;
+        while start > 0 && char_class(chars[start - 1]) == class {
+            start -= 1;
+        }
+        let mut end = index + 1;
+        while end < chars.len() && char_class(chars[end]) == class {
+            end += 1;
+        }
+        (Position::new(at.line, start), Position::new(at.line, end))
+    }
+}
+
+impl fmt::Debug for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Document")
+            .field("lines", &self.lines.len())
+            .field("selections", &self.selections)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Whitespace
+    } else if is_word_char(c) {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// The columns where a line wraps to fit in `width` display columns, preferring to break after whitespace.
+fn wrap_points(line: &str, width: usize) -> Vec<usize> {
+    let width = width.max(1);
+    let mut breaks = Vec::new();
+    let (mut row_column, mut row_display) = (0, 0); // Where the current row starts
+    let mut after_space = None; // The last place the current row could break at
+    let mut display = 0;
+
+    for (column, c) in line.chars().enumerate() {
+        let next = advance(display, c);
+        if next - row_display > width && column > row_column {
+            let (column, display) = match after_space {
+                Some((space_column, space_display)) if space_column > row_column => (space_column, space_display),
+                _ => (column, display),
+            };
+            breaks.push(column);
+            (row_column, row_display) = (column, display);
+            after_space = None;
+        }
+        display = next;
+        if c == ' ' || c == '\t' {
+            after_space = Some((column + 1, display));
+        }
+    }
+    breaks
+}
+
+/// Where `position` ends up after `start..old_end` was replaced by `start..new_end`. Anything
+/// inside the replaced range goes to its start.
+fn shift_position(position: Position, start: Position, old_end: Position, new_end: Position) -> Position {
+    if position < start {
+        position
+    } else if position < old_end {
+        start
+    } else if position.line == old_end.line {
+        Position::new(new_end.line, new_end.column + position.column - old_end.column)
+    } else {
+        Position::new(position.line + new_end.line - old_end.line, position.column)
+    }
+}
+
+/// Where the cursor ends up after typing `text` at `at`.
+fn advance_position(at: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(last) => Position::new(at.line + text.matches('\n').count(), text[last + 1..].chars().count()),
+        None => Position::new(at.line, at.column + text.chars().count()),
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The char column of a UTF-16 column in a line, clamped to its end.
+pub fn column_from_utf16(line: &str, units: usize) -> usize {
+    let mut counted = 0;
+    line.chars()
+        .take_while(|c| {
+            counted += c.len_utf16();
+            counted <= units
+        })
+        .count()
+}
+
+/// Whether `c` can be part of an identifier-like word.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The display column reached after drawing `c` at display column `width`. CJK and most emoji
+/// take two columns, and combining marks none.
+fn advance(width: usize, c: char) -> usize {
+    if c == '\t' {
+        (width / TAB_WIDTH + 1) * TAB_WIDTH
+    } else {
+        width + c.width().unwrap_or(1)
+    }
+}
+
+/// How many display columns some chars take, starting at display column `start`.
+pub fn display_width(chars: impl Iterator<Item = char>, start: usize) -> usize {
+    chars.fold(start, advance)
+}
+
+/// Converts a char column into a byte offset inside `line`.
+pub fn byte_index(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map(|(byte, _)| byte)
+        .unwrap_or(line.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(document: &mut Document, text: &str) {
+        for c in text.chars() {
+            document.perform(Action::Edit(Edit::Insert(c)));
+        }
+    }
+
+    fn select(document: &mut Document, anchor: Position, head: Position) {
+        document.perform(Action::Click(anchor));
+        document.perform(Action::Drag(head));
+    }
+
+    fn regions(document: &Document) -> Vec<(usize, usize)> {
+        document.fold_regions().iter().map(|region| (region.start, region.end)).collect()
+    }
+
+    #[test]
+    fn wraps_after_whitespace() {
+        assert_eq!(wrap_points("hello world again", 8), vec![6, 12]);
+        // A word longer than the row breaks wherever it has to
+        assert_eq!(wrap_points("abcdefghij", 4), vec![4, 8]);
+        assert_eq!(wrap_points("short", 8), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn wraps_wide_chars_by_their_width() {
+        assert_eq!(wrap_points("日本語の文章", 4), vec![2, 4]);
+        let document = Document::with_text("日本語の文章", "txt");
+        document.set_wrap_width(Some(4));
+        assert_eq!(document.row_count(), 3);
+    }
+
+    #[test]
+    fn measures_wide_chars_and_tabs() {
+        let document = Document::with_text("a日b\tc", "txt");
+        assert_eq!(document.display_column(0, 1), 1);
+        assert_eq!(document.display_column(0, 2), 3);
+        assert_eq!(document.display_column(0, 3), 4);
+        assert_eq!(document.display_column(0, 4), 8);
+        // A click on either half of a wide char lands on its nearer edge
+        assert_eq!(document.column_at_display(0, 1), 1);
+        assert_eq!(document.column_at_display(0, 2), 1);
+        assert_eq!(document.column_at_display(0, 3), 2);
+
+        let emoji = Document::with_text("🦀 x", "txt");
+        assert_eq!(emoji.display_column(0, 1), 2);
+    }
+
+    #[test]
+    fn shifts_positions_around_a_replacement() {
+        let (start, old_end, new_end) = (Position::new(1, 2), Position::new(1, 5), Position::new(3, 1));
+        let shift = |position| shift_position(position, start, old_end, new_end);
+        assert_eq!(shift(Position::new(0, 9)), Position::new(0, 9));
+        assert_eq!(shift(Position::new(1, 2)), Position::new(1, 2));
+        assert_eq!(shift(Position::new(1, 3)), start);
+        assert_eq!(shift(Position::new(1, 7)), Position::new(3, 3));
+        assert_eq!(shift(Position::new(2, 4)), Position::new(4, 4));
+    }
+
+    #[test]
+    fn measures_inlay_hints_by_display_width() {
+        let hint = |label: &str| InlayHint { position: Position::new(0, 0), label: label.to_string() };
+        assert_eq!(hint(": i32").width(), 5);
+        assert_eq!(hint(": 文字列").width(), 8);
+    }
+
+    #[test]
+    fn groups_typing_into_one_undo_step() {
+        let mut document = Document::with_text("", "txt");
+        typed(&mut document, "abc");
+        document.perform(Action::Move(Motion::Left));
+        typed(&mut document, "x");
+        assert_eq!(document.text(), "abxc");
+
+        document.perform(Action::Undo);
+        assert_eq!(document.text(), "abc");
+        document.perform(Action::Undo);
+        assert_eq!(document.text(), "");
+        document.perform(Action::Redo);
+        assert_eq!(document.text(), "abc");
+        assert_eq!(document.cursor(), Position::new(0, 3));
+    }
+
+    #[test]
+    fn starts_a_new_undo_step_when_deleting_after_typing() {
+        let mut document = Document::with_text("", "txt");
+        typed(&mut document, "abc");
+        document.perform(Action::Edit(Edit::Backspace));
+        document.perform(Action::Undo);
+        assert_eq!(document.text(), "abc");
+    }
+
+    #[test]
+    fn types_at_every_cursor() {
+        let mut document = Document::with_text("one\ntwo\nthree", "txt");
+        document.perform(Action::Click(Position::new(0, 3)));
+        document.perform(Action::AddCursor(Position::new(1, 3)));
+        document.perform(Action::AddCursor(Position::new(2, 5)));
+        typed(&mut document, ";");
+        assert_eq!(document.text(), "one;\ntwo;\nthree;");
+        assert_eq!(document.selections().len(), 3);
+
+        document.perform(Action::Undo);
+        assert_eq!(document.text(), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn pastes_a_line_per_cursor() {
+        let mut document = Document::with_text("a\nb", "txt");
+        document.perform(Action::Click(Position::new(0, 1)));
+        document.perform(Action::AddCursor(Position::new(1, 1)));
+        document.perform(Action::Edit(Edit::Paste(std::sync::Arc::new("1\n2".to_string()))));
+        assert_eq!(document.text(), "a1\nb2");
+    }
+
+    #[test]
+    fn applies_edits_at_the_same_place_in_order() {
+        let mut document = Document::with_text("fn main() {}", "txt");
+        let insert = |text: &str| TextEdit { start: Position::new(0, 0), end: Position::new(0, 0), text: text.to_string() };
+        document.perform(Action::ApplyEdits(vec![insert("use a;\n"), insert("use b;\n")]));
+        assert_eq!(document.text(), "use a;\nuse b;\nfn main() {}");
+    }
+
+    #[test]
+    fn applies_edits_from_the_bottom_up() {
+        let mut document = Document::with_text("let x = 1;\nlet y = x;", "txt");
+        let edit = |line, start, end, text: &str| TextEdit {
+            start: Position::new(line, start),
+            end: Position::new(line, end),
+            text: text.to_string(),
+        };
+        document.perform(Action::ApplyEdits(vec![edit(0, 4, 5, "value"), edit(1, 8, 9, "value")]));
+        assert_eq!(document.text(), "let value = 1;\nlet y = value;");
+        document.perform(Action::Undo);
+        assert_eq!(document.text(), "let x = 1;\nlet y = x;");
+    }
+
+    #[test]
+    fn moves_fold_regions_along_until_refreshed() {
+        let mut document = Document::with_text("a\nif x:\n    b\n    c\nd", "py");
+        assert_eq!(regions(&document), [(1, 3)]);
+        document.perform(Action::Click(Position::new(0, 1)));
+        document.perform(Action::Edit(Edit::Enter));
+        assert_eq!(regions(&document), [(2, 4)]);
+
+        document.perform(Action::Click(Position::new(3, 5)));
+        document.perform(Action::Edit(Edit::Enter));
+        document.refresh_fold_regions();
+        assert_eq!(regions(&document), [(2, 5)]);
+    }
+
+    #[test]
+    fn scrolls_back_up_when_rows_go_away() {
+        let mut document = Document::with_text(&"word ".repeat(40), "txt");
+        document.set_wrap_width(Some(10));
+        document.perform(Action::ScrollTo { row: 19.0 });
+        assert_eq!(document.scroll_row(), 19.0);
+        document.set_wrap_width(Some(100));
+        assert_eq!(document.scroll_row(), 1.0);
+
+        let mut document = Document::with_text(&"line\n".repeat(50), "txt");
+        document.perform(Action::ScrollTo { row: 45.0 });
+        document.perform(Action::SelectAll);
+        document.perform(Action::Edit(Edit::Delete));
+        assert_eq!(document.scroll_row(), 0.0);
+    }
+
+    #[test]
+    fn cuts_only_what_is_selected() {
+        let mut document = Document::with_text("abc\ndef", "txt");
+        select(&mut document, Position::new(0, 0), Position::new(0, 2));
+        document.perform(Action::AddCursor(Position::new(1, 1)));
+        assert_eq!(document.selected_text().as_deref(), Some("ab"));
+        document.perform(Action::Cut);
+        assert_eq!(document.text(), "c\ndef");
+        assert_eq!(document.selections().len(), 2);
+    }
+
+    #[test]
+    fn keeps_selections_within_sorted_lines() {
+        let mut document = Document::with_text("ccc\na", "txt");
+        select(&mut document, Position::new(0, 3), Position::new(1, 1));
+        document.perform(Action::SortLines { descending: false });
+        assert_eq!(document.text(), "a\nccc");
+        let selection = document.selections()[0];
+        assert_eq!((selection.start(), selection.end()), (Position::new(0, 1), Position::new(1, 1)));
+    }
+}
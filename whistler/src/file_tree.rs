@@ -54,6 +54,21 @@ impl FileTree {
         self.selected = Some(path);
     }
 
+    pub fn reveal(&mut self, path: &Path) { // Expands every folder down to a path and selects it
+        let Ok(relative) = path.strip_prefix(&self.root) else { return; };
+        let mut folder = self.root.clone();
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            folder.push(component);
+            self.expanded.insert(folder.clone());
+            populate_children(&mut self.entries, &folder); // Parents first, so each one has its children loaded
+        }
+        if path.is_dir() {
+            self.expanded.insert(path.to_path_buf());
+            populate_children(&mut self.entries, path);
+        }
+        self.select(path.to_path_buf());
+    }
+
     pub fn refresh(&mut self) { // Refresh the directory to see if a new file is created
         self.entries = scan_directory(&self.root);
         let mut expanded: Vec<PathBuf> = self.expanded.iter().cloned().collect();
@@ -68,7 +83,7 @@ impl FileTree {
 const IGNORED_DIRS: &[&str] = &[".git", "node_modules", "target", ".DS_Store", "__pycache__", ".claude"];
 
 /// Scan a directory and return a list of FileEntry
-fn scan_directory(path: &Path) -> Vec<FileEntry> {
+pub fn scan_directory(path: &Path) -> Vec<FileEntry> {
     let mut entries = Vec::new(); // An empty vector of entires
 
     let Ok(read_dir) = fs::read_dir(path) else { // Reads directory contents
@@ -114,10 +129,10 @@ fn scan_directory(path: &Path) -> Vec<FileEntry> {
         }
     });
 
-    return entries;
+    entries
 }
 
-fn populate_children(entries: &mut Vec<FileEntry>, target: &Path) {
+fn populate_children(entries: &mut [FileEntry], target: &Path) {
     for entry in entries.iter_mut() {
         if let FileEntry::Directory {
             path,
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use crate::document::display_width;
+use crate::syntax::bracket_blocks;
+
+/// Comment openers that may come before a `region` marker, e.g. `// #region` or `<!-- #region -->`.
+const MARKER_PREFIXES: &[&str] = &["//", "#", "--", "<!--", "/*", ";", "%", "'"];
+
+/// Lines that can be collapsed together. `start` stays on screen, the lines after it up to `end` are hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoldRegion {
+    pub start: usize,
+    pub end: usize,
+    /// How deeply nested the region is, starting at 1 for the outermost ones
+    pub level: usize,
+}
+
+/// Works out where a file can be folded.
+///
+/// Indentation gives the baseline, bracket blocks from the grammar replace it where
+/// they start on the same line, and `#region` markers win over both.
+pub fn fold_regions(extension: &str, lines: &[String]) -> Vec<FoldRegion> {
+    let mut by_start: BTreeMap<usize, usize> = BTreeMap::new();
+
+    for (start, end) in indentation_regions(lines) {
+        by_start.insert(start, end);
+    }
+    for (open, close) in bracket_blocks(extension, lines) {
+        // The closing bracket's line stays visible, like the end of an indented block would
+        if close - 1 > open {
+            by_start.insert(open, close - 1);
+        }
+    }
+    for (start, end) in marker_regions(lines) {
+        by_start.insert(start, end);
+    }
+
+    let mut regions = Vec::with_capacity(by_start.len());
+    let mut enclosing: Vec<usize> = Vec::new(); // End lines of the regions we're currently inside
+    for (start, end) in by_start {
+        while enclosing.last().is_some_and(|&outer| outer < start) {
+            enclosing.pop();
+        }
+        regions.push(FoldRegion { start, end, level: enclosing.len() + 1 });
+        enclosing.push(end);
+    }
+    regions
+}
+
+fn indent_of(line: &str) -> Option<usize> {
+    let indent = line.chars().take_while(|c| c.is_whitespace()).collect::<String>();
+    (indent.len() < line.len()).then(|| display_width(indent.chars(), 0))
+}
+
+/// A line followed by more deeply indented ones folds down to the last of them.
+/// Blank lines are skipped, so trailing ones stay visible under the fold.
+fn indentation_regions(lines: &[String]) -> Vec<(usize, usize)> {
+    let indents: Vec<Option<usize>> = lines.iter().map(|line| indent_of(line)).collect();
+    let mut regions = Vec::new();
+    // Lines still waiting for their block to end, with their indentation
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut last_content = 0;
+
+    for (index, indent) in indents.iter().enumerate() {
+        let Some(indent) = *indent else { continue; };
+        while let Some(&(start, start_indent)) = open.last() {
+            if indent > start_indent {
+                break;
+            }
+            open.pop();
+            if last_content > start {
+                regions.push((start, last_content));
+            }
+        }
+        open.push((index, indent));
+        last_content = index;
+    }
+    for (start, _) in open {
+        if last_content > start {
+            regions.push((start, last_content));
+        }
+    }
+    regions
+}
+
+/// Whether `line` is a comment starting with `#region` / `region` (or the `end` version).
+fn marker(line: &str, name: &str) -> bool {
+    let trimmed = line.trim_start();
+    let Some(rest) = MARKER_PREFIXES.iter().find_map(|prefix| trimmed.strip_prefix(prefix)) else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('#').unwrap_or(rest);
+    rest.strip_prefix(name)
+        .is_some_and(|after| !after.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// Pairs up `#region` and `#endregion` comments.
+fn marker_regions(lines: &[String]) -> Vec<(usize, usize)> {
+    let mut open = Vec::new();
+    let mut regions = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if marker(line, "endregion") {
+            if let Some(start) = open.pop() {
+                regions.push((start, index));
+            }
+        } else if marker(line, "region") {
+            open.push(index);
+        }
+    }
+    regions
+}
@@ -0,0 +1,145 @@
+use similar::DiffTag;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::config::FormatterSettings;
+use crate::document::{Document, Position, TextEdit};
+
+/// A file being formatted: the version of its text that went to the formatter, and what to do
+/// with what comes back.
+#[derive(Debug, Clone)]
+pub struct Formatting {
+    pub path: PathBuf,
+    pub version: i32,
+    /// The first and last line of the selection, when only those are formatted
+    pub lines: Option<(usize, usize)>,
+    /// Whether the file gets saved once it's formatted, or once formatting fails
+    pub save: bool,
+}
+
+/// Runs an external formatter like rustfmt on `text`, which goes in on stdin and comes back out
+/// on stdout. `${file}` in its args is the file's path, for formatters like prettier that go by
+/// the extension, and `${edition}` the file's Rust edition. Gives up once `timeout` has passed.
+pub fn run(formatter: &FormatterSettings, path: &Path, text: String, timeout: Duration) -> Result<String, String> {
+    let edition = formatter.args.iter().any(|arg| arg.contains("${edition}")).then(|| rust_edition(path));
+    let args = formatter.args.iter().map(|arg| {
+        let arg = arg.replace("${file}", &path.to_string_lossy());
+        match &edition {
+            Some(edition) => arg.replace("${edition}", edition),
+            None => arg,
+        }
+    });
+    let mut command = Command::new(&formatter.command);
+    command.args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    // So it finds the project's rustfmt.toml or .prettierrc
+    if let Some(dir) = path.parent() {
+        command.current_dir(dir);
+    }
+    let mut child = command.spawn().map_err(|e| format!("{}: {}", formatter.command, e))?;
+
+    // The pipes get threads of their own, so a big file can't fill one up while another waits
+    let (Some(mut stdin), Some(mut stdout), Some(mut stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take()) else {
+        return Err(format!("{}: no pipes to talk to it through", formatter.command));
+    };
+    std::thread::spawn(move || {
+        let _ = stdin.write_all(text.as_bytes());
+    });
+    let errors = std::thread::spawn(move || {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors);
+        errors
+    });
+    let (sender, output) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = sender.send(stdout.read_to_string(&mut output).map(|_| output));
+    });
+
+    let output = match output.recv_timeout(timeout) {
+        Ok(output) => output.map_err(|e| format!("{}: {}", formatter.command, e)),
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("{} took longer than {}ms", formatter.command, timeout.as_millis()));
+        }
+    };
+    let status = child.wait().map_err(|e| format!("{}: {}", formatter.command, e))?;
+    if !status.success() {
+        let errors = errors.join().unwrap_or_default();
+        let message = errors.lines().find(|line| !line.trim().is_empty()).unwrap_or("no error message").to_string();
+        return Err(format!("{} failed ({}): {}", formatter.command, status, message));
+    }
+    output
+}
+
+/// The Rust edition of the crate a file is in, from the nearest Cargo.toml above it that names
+/// one, which covers crates taking theirs from the workspace. Plain rustfmt doesn't read
+/// Cargo.toml, and without one it formats as 2015, which is also Cargo's default.
+fn rust_edition(path: &Path) -> String {
+    path.ancestors()
+        .skip(1)
+        .filter_map(|dir| std::fs::read_to_string(dir.join("Cargo.toml")).ok())
+        .find_map(|manifest| {
+            manifest.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("edition")?.trim_start().strip_prefix('=')?;
+                Some(value.trim().strip_prefix('"')?.split('"').next()?.to_string())
+            })
+        })
+        .unwrap_or_else(|| "2015".to_string())
+}
+
+/// The edits that turn a document's text into `formatted`, one per run of changed lines, so
+/// the cursor and folds on lines it leaves alone stay where they are. With `lines`, only the
+/// changes that touch those lines are kept.
+pub fn edits(document: &Document, formatted: &str, lines: Option<(usize, usize)>) -> Vec<TextEdit> {
+    let old: Vec<&str> = document.lines().iter().map(String::as_str).collect();
+    let new: Vec<&str> = formatted.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+    let count = old.len();
+    let end_of = |line: usize| Position::new(line, document.line_len(line));
+
+    let mut edits = Vec::new();
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old, &new) {
+        if op.tag() == DiffTag::Equal {
+            continue;
+        }
+        let (removed, added) = (op.old_range(), op.new_range());
+        if let Some((first, last)) = lines {
+            // Lines put in right after the selection count as part of it
+            if removed.start > last + 1 || removed.end.max(removed.start + 1) <= first {
+                continue;
+            }
+        }
+        let joined = new[added.clone()].join("\n");
+        let edit = if removed.end < count {
+            // Whole lines, each with the line break after it
+            let text = if added.is_empty() { joined } else { joined + "\n" };
+            TextEdit { start: Position::new(removed.start, 0), end: Position::new(removed.end, 0), text }
+        } else if removed.start < count && (!added.is_empty() || removed.start == 0) {
+            // Up to the end of the text, which has no line break after it
+            TextEdit { start: Position::new(removed.start, 0), end: end_of(count - 1), text: joined }
+        } else if removed.start < count {
+            // The last lines go, along with the line break before them
+            TextEdit { start: end_of(removed.start - 1), end: end_of(count - 1), text: String::new() }
+        } else {
+            // Lines added after the last one
+            TextEdit { start: end_of(count - 1), end: end_of(count - 1), text: format!("\n{}", joined) }
+        };
+        edits.push(edit);
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_crates_edition() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/format.rs");
+        assert_eq!(rust_edition(&file), "2024");
+        assert_eq!(rust_edition(Path::new("/no/crate/here.rs")), "2015");
+    }
+}
@@ -0,0 +1,595 @@
+use git2::build::CheckoutBuilder;
+use git2::{Branch, BranchType, Commit, Repository, Status, StatusOptions};
+use similar::DiffTag;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Where a file stands with git. Later ones matter more, so a folder takes on the one that
+/// matters most among what's in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileStatus {
+    Ignored,
+    Untracked,
+    Added,
+    Deleted,
+    Modified,
+    Conflicted,
+}
+
+impl FileStatus {
+    /// The letter shown next to a file in the tree. Ignored files are only greyed out.
+    pub fn badge(self) -> Option<char> {
+        match self {
+            FileStatus::Ignored => None,
+            FileStatus::Untracked => Some('U'),
+            FileStatus::Added => Some('A'),
+            FileStatus::Deleted => Some('D'),
+            FileStatus::Modified => Some('M'),
+            FileStatus::Conflicted => Some('!'),
+        }
+    }
+
+    fn from_git(status: Status) -> Option<Self> {
+        let status = if status.is_conflicted() {
+            FileStatus::Conflicted
+        } else if status.is_ignored() {
+            FileStatus::Ignored
+        } else if status.is_wt_new() {
+            FileStatus::Untracked
+        } else if status.is_wt_deleted() || status.is_index_deleted() {
+            FileStatus::Deleted
+        } else if status.is_index_new() {
+            FileStatus::Added
+        } else if status.is_empty() {
+            return None;
+        } else {
+            FileStatus::Modified
+        };
+        Some(status)
+    }
+
+    /// How a file's staged version differs from its committed one.
+    fn staged(status: Status) -> Option<Self> {
+        if status.is_conflicted() {
+            None
+        } else if status.is_index_new() {
+            Some(FileStatus::Added)
+        } else if status.is_index_deleted() {
+            Some(FileStatus::Deleted)
+        } else if status.intersects(Status::INDEX_MODIFIED | Status::INDEX_RENAMED | Status::INDEX_TYPECHANGE) {
+            Some(FileStatus::Modified)
+        } else {
+            None
+        }
+    }
+
+    /// How a file on disk differs from its staged version.
+    fn unstaged(status: Status) -> Option<Self> {
+        if status.is_conflicted() {
+            Some(FileStatus::Conflicted)
+        } else if status.is_wt_new() {
+            Some(FileStatus::Untracked)
+        } else if status.is_wt_deleted() {
+            Some(FileStatus::Deleted)
+        } else if status.intersects(Status::WT_MODIFIED | Status::WT_RENAMED | Status::WT_TYPECHANGE) {
+            Some(FileStatus::Modified)
+        } else {
+            None
+        }
+    }
+}
+
+/// The status of every file in a repository that isn't simply committed, and of the folders
+/// they're in.
+#[derive(Debug, Clone, Default)]
+pub struct Statuses {
+    files: HashMap<PathBuf, FileStatus>,
+    folders: HashMap<PathBuf, FileStatus>,
+    /// Folders git ignores as a whole, which it doesn't list the contents of
+    ignored: Vec<PathBuf>,
+    /// The files with changes staged, and the ones with changes that aren't, for the Source
+    /// Control view
+    pub staged: Vec<(PathBuf, FileStatus)>,
+    pub unstaged: Vec<(PathBuf, FileStatus)>,
+    /// The checked out branch, or the commit if there isn't one
+    pub head: Option<String>,
+    pub branches: Vec<String>,
+}
+
+impl Statuses {
+    pub fn file(&self, path: &Path) -> Option<FileStatus> {
+        self.files.get(path).copied().or_else(|| self.ignored(path))
+    }
+
+    /// The status that matters most among what's in a folder.
+    pub fn folder(&self, path: &Path) -> Option<FileStatus> {
+        self.folders.get(path).copied().or_else(|| self.ignored(path))
+    }
+
+    fn ignored(&self, path: &Path) -> Option<FileStatus> {
+        self.ignored.iter().any(|folder| path.starts_with(folder)).then_some(FileStatus::Ignored)
+    }
+}
+
+/// Reads the status of the repository `root` is in. Fails if it isn't in one.
+pub fn statuses(root: &Path) -> Result<Statuses, git2::Error> {
+    let repo = Repository::discover(root)?;
+    let Some(workdir) = repo.workdir() else { return Ok(Statuses::default()); };
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true).include_ignored(true).recurse_ignored_dirs(false);
+
+    let mut statuses = Statuses { head: head_name(&repo), branches: branches(&repo)?, ..Statuses::default() };
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let (Some(path), Some(status)) = (entry.path(), FileStatus::from_git(entry.status())) else { continue; };
+        let is_folder = path.ends_with('/');
+        let path = workdir.join(path.trim_end_matches('/'));
+        if status == FileStatus::Ignored {
+            if is_folder {
+                statuses.ignored.push(path);
+            } else {
+                statuses.files.insert(path, status);
+            }
+            continue;
+        }
+        if let Some(staged) = FileStatus::staged(entry.status()) {
+            statuses.staged.push((path.clone(), staged));
+        }
+        if let Some(unstaged) = FileStatus::unstaged(entry.status()) {
+            statuses.unstaged.push((path.clone(), unstaged));
+        }
+        for folder in path.ancestors().skip(1).take_while(|folder| folder.starts_with(workdir)) {
+            let shown = statuses.folders.entry(folder.to_path_buf()).or_insert(status);
+            *shown = (*shown).max(status);
+        }
+        statuses.files.insert(path, status);
+    }
+    Ok(statuses)
+}
+
+/// The checked out branch's name, or the start of the commit's id if it isn't on one.
+fn head_name(repo: &Repository) -> Option<String> {
+    match repo.head() {
+        Ok(head) if head.is_branch() => head.shorthand().map(str::to_string),
+        Ok(head) => head.target().map(|id| id.to_string()[..7].to_string()),
+        // A new repository's branch has no commits yet, so HEAD points at nothing
+        Err(_) => repo.find_reference("HEAD").ok()?.symbolic_target()?.strip_prefix("refs/heads/").map(str::to_string),
+    }
+}
+
+fn branches(repo: &Repository) -> Result<Vec<String>, git2::Error> {
+    let mut names = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        if let Some(name) = branch?.0.name()? {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// What git has of a file, to show what's changed in it since: the staged version, or the
+/// committed one if it's been removed from the index.
+#[derive(Debug, Clone)]
+pub struct Base {
+    pub lines: Vec<String>,
+    line_ending: &'static str,
+}
+
+impl Base {
+    /// A file that isn't there.
+    fn empty() -> Self {
+        Self { lines: Vec::new(), line_ending: "\n" }
+    }
+
+    fn new(text: &str) -> Self {
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        // Split the way documents split their text, so unchanged lines compare equal
+        let lines = text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect();
+        Self { lines, line_ending }
+    }
+}
+
+/// Reads what git has of a file. There's nothing for files it doesn't track.
+pub fn base(path: &Path) -> Result<Option<Base>, git2::Error> {
+    let (repo, relative) = open(path)?;
+    match staged_version(&repo, &relative)? {
+        Some(base) => Ok(Some(base)),
+        None => committed_version(&repo, &relative),
+    }
+}
+
+fn staged_version(repo: &Repository, relative: &Path) -> Result<Option<Base>, git2::Error> {
+    match repo.index()?.get_path(relative, 0) {
+        Some(entry) => blob_version(repo, entry.id),
+        None => Ok(None),
+    }
+}
+
+fn committed_version(repo: &Repository, relative: &Path) -> Result<Option<Base>, git2::Error> {
+    match repo.head().and_then(|head| head.peel_to_tree()).and_then(|tree| tree.get_path(relative)) {
+        Ok(entry) => blob_version(repo, entry.id()),
+        Err(_) => Ok(None),
+    }
+}
+
+fn blob_version(repo: &Repository, id: git2::Oid) -> Result<Option<Base>, git2::Error> {
+    let blob = repo.find_blob(id)?;
+    // Binary files have no lines to compare
+    if blob.is_binary() {
+        return Ok(None);
+    }
+    Ok(Some(Base::new(&String::from_utf8_lossy(blob.content()))))
+}
+
+/// The repository a file is in and its path inside it.
+fn open(path: &Path) -> Result<(Repository, PathBuf), git2::Error> {
+    let repo = Repository::discover(path.parent().unwrap_or(path))?;
+    let workdir = repo.workdir().ok_or_else(|| git2::Error::from_str("the repository has no working tree"))?;
+    let relative = path.strip_prefix(workdir).map_err(|_| git2::Error::from_str("the file is outside the repository"))?.to_path_buf();
+    Ok((repo, relative))
+}
+
+/// How the lines of a hunk changed, which picks the colour of its marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// A run of lines that differ between a file and its [`Base`]: the lines it had there, and the
+/// lines it has now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub base: Range<usize>,
+    pub current: Range<usize>,
+}
+
+impl Hunk {
+    pub fn change(&self) -> LineChange {
+        match (self.base.is_empty(), self.current.is_empty()) {
+            (true, _) => LineChange::Added,
+            (_, true) => LineChange::Deleted,
+            _ => LineChange::Modified,
+        }
+    }
+
+    /// Whether a line has the hunk's marker. Deleted lines are marked between the lines on
+    /// either side of where they were.
+    pub fn is_on(&self, line: usize) -> bool {
+        match self.current.is_empty() {
+            true => line == self.current.start || line + 1 == self.current.start,
+            false => self.current.contains(&line),
+        }
+    }
+}
+
+/// The hunks that turn the lines of `base` into `current`, top to bottom.
+pub fn hunks(base: &[String], current: &[String]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, base, current) {
+        if op.tag() == DiffTag::Equal {
+            continue;
+        }
+        let (removed, added) = (op.old_range(), op.new_range());
+        // A deletion right before an insertion is one change to those lines
+        match hunks.last_mut() {
+            Some(last) if last.base.end == removed.start && last.current.end == added.start => {
+                last.base.end = removed.end;
+                last.current.end = added.end;
+            }
+            _ => hunks.push(Hunk { base: removed, current: added }),
+        }
+    }
+    hunks
+}
+
+/// Stages one hunk of a file: its index version gets the hunk's lines as they are now, and
+/// keeps the rest as it was.
+pub fn stage_hunk(path: &Path, base: &Base, hunk: &Hunk, current: &[String]) -> Result<(), git2::Error> {
+    write_index(path, &splice(base, hunk.base.clone(), &current[hunk.current.clone()]))
+}
+
+/// Takes one hunk of a file's staged changes back out of the index.
+pub fn unstage_hunk(path: &Path, diff: &FileDiff, hunk: &Hunk) -> Result<(), git2::Error> {
+    write_index(path, &splice(&diff.new, hunk.current.clone(), &diff.old.lines[hunk.base.clone()]))
+}
+
+/// The text of `base` with some of its lines swapped for others.
+fn splice(base: &Base, replaced: Range<usize>, with: &[String]) -> String {
+    let mut lines: Vec<&str> = base.lines[..replaced.start].iter().map(String::as_str).collect();
+    lines.extend(with.iter().map(String::as_str));
+    lines.extend(base.lines[replaced.end..].iter().map(String::as_str));
+    lines.join(base.line_ending)
+}
+
+/// Puts `text` in the index as a file's staged version, without touching the file on disk.
+fn write_index(path: &Path, text: &str) -> Result<(), git2::Error> {
+    let (repo, relative) = open(path)?;
+    let mut index = repo.index()?;
+    let Some(mut entry) = index.get_path(&relative, 0) else {
+        return Err(git2::Error::from_str("the file isn't in the index"));
+    };
+    entry.id = repo.blob(text.as_bytes())?;
+    entry.file_size = text.len() as u32;
+    // The file on disk no longer matches what's staged, so git mustn't go by its timestamps
+    entry.mtime = git2::IndexTime::new(0, 0);
+    entry.ctime = git2::IndexTime::new(0, 0);
+    index.add(&entry)?;
+    index.write()
+}
+
+/// Two versions of a file and the hunks between them: the committed and staged ones, or the
+/// staged one and the one on disk.
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub old: Base,
+    pub new: Base,
+    pub hunks: Vec<Hunk>,
+}
+
+/// What's staged of a file, or what isn't yet.
+pub fn diff(path: &Path, staged: bool) -> Result<FileDiff, git2::Error> {
+    let (repo, relative) = open(path)?;
+    let (old, new) = match staged {
+        true => (committed_version(&repo, &relative)?, staged_version(&repo, &relative)?),
+        false => {
+            let on_disk = std::fs::read(path).ok().map(|bytes| Base::new(&String::from_utf8_lossy(&bytes)));
+            (staged_version(&repo, &relative)?, on_disk)
+        }
+    };
+    let (old, new) = (old.unwrap_or_else(Base::empty), new.unwrap_or_else(Base::empty));
+    let hunks = hunks(&old.lines, &new.lines);
+    Ok(FileDiff { old, new, hunks })
+}
+
+/// Stages a file as it is on disk, or its deletion if it's gone.
+pub fn stage(path: &Path) -> Result<(), git2::Error> {
+    let (repo, relative) = open(path)?;
+    let mut index = repo.index()?;
+    match path.exists() {
+        true => index.add_path(&relative)?,
+        false => index.remove_path(&relative)?,
+    }
+    index.write()
+}
+
+/// Puts a file's staged version back to its committed one.
+pub fn unstage(path: &Path) -> Result<(), git2::Error> {
+    let (repo, relative) = open(path)?;
+    match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => repo.reset_default(Some(commit.as_object()), [relative.as_path()]),
+        // Nothing's been committed yet, so there's nothing to go back to
+        Err(_) => {
+            let mut index = repo.index()?;
+            index.remove_path(&relative)?;
+            index.write()
+        }
+    }
+}
+
+/// Throws away the changes to a file that aren't staged. Files git doesn't track are deleted.
+pub fn discard(path: &Path) -> Result<(), git2::Error> {
+    let (repo, relative) = open(path)?;
+    if repo.index()?.get_path(&relative, 0).is_none() {
+        return std::fs::remove_file(path).map_err(|e| git2::Error::from_str(&e.to_string()));
+    }
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().disable_pathspec_match(true).path(relative.as_path());
+    repo.checkout_index(None, Some(&mut checkout))
+}
+
+/// Commits what's staged, or replaces the last commit with it. Amending with no message keeps
+/// the last commit's.
+pub fn commit(root: &Path, message: &str, amend: bool) -> Result<(), git2::Error> {
+    let repo = Repository::discover(root)?;
+    let signature = repo.signature()?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let head = repo.head().and_then(|head| head.peel_to_commit()).ok();
+    let message = Some(message.trim()).filter(|message| !message.is_empty());
+    match (amend, head) {
+        (true, Some(head)) => {
+            head.amend(Some("HEAD"), None, Some(&signature), None, message, Some(&tree))?;
+        }
+        (true, None) => return Err(git2::Error::from_str("There's no commit to amend yet")),
+        (false, head) => {
+            let Some(message) = message else { return Err(git2::Error::from_str("Write a commit message first")); };
+            let unchanged = match &head {
+                Some(head) => head.tree_id() == tree.id(),
+                None => tree.is_empty(),
+            };
+            if unchanged {
+                return Err(git2::Error::from_str("There are no staged changes to commit"));
+            }
+            let parents: Vec<&Commit> = head.iter().collect();
+            repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        }
+    }
+    Ok(())
+}
+
+/// The last commit's message, to start an amend from.
+pub fn last_message(root: &Path) -> Option<String> {
+    let repo = Repository::discover(root).ok()?;
+    let head = repo.head().and_then(|head| head.peel_to_commit()).ok()?;
+    head.message().map(|message| message.trim_end().to_string())
+}
+
+/// Starts a branch at the current commit and checks it out. Before the first commit, it's where
+/// that commit goes instead.
+pub fn create_branch(root: &Path, name: &str) -> Result<(), git2::Error> {
+    let repo = Repository::discover(root)?;
+    if !Branch::name_is_valid(name)? {
+        return Err(git2::Error::from_str(&format!("'{}' isn't a valid branch name", name)));
+    }
+    if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+        repo.branch(name, &commit, false)?;
+    }
+    repo.set_head(&format!("refs/heads/{}", name))
+}
+
+/// Checks out a branch. Fails without touching anything if that would overwrite changes that
+/// aren't committed.
+pub fn switch_branch(root: &Path, name: &str) -> Result<(), git2::Error> {
+    let repo = Repository::discover(root)?;
+    let branch = repo.find_branch(name, BranchType::Local)?.into_reference();
+    let tree = branch.peel_to_tree()?;
+    repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))?;
+    repo.set_head(branch.name().ok_or_else(|| git2::Error::from_str("the branch name isn't UTF-8"))?)
+}
+
+/// Makes a folder a repository of its own.
+pub fn init(root: &Path) -> Result<(), git2::Error> {
+    Repository::init(root).map(|_| ())
+}
+
+/// The commit that last changed some lines, as blame shows it.
+#[derive(Debug, Clone)]
+pub struct BlameCommit {
+    /// The start of its id. Lines that aren't committed yet have none.
+    pub id: Option<String>,
+    pub author: String,
+    /// When it was made, in seconds since the epoch
+    pub time: i64,
+    pub summary: String,
+}
+
+/// Who last changed each line of a file, and in what commit.
+#[derive(Debug, Clone, Default)]
+pub struct Blame {
+    commits: Vec<BlameCommit>,
+    /// Each line's commit in `commits`
+    lines: Vec<usize>,
+}
+
+impl Blame {
+    pub fn line(&self, line: usize) -> Option<&BlameCommit> {
+        self.commits.get(*self.lines.get(line)?)
+    }
+
+    /// Whether a line was last changed in another commit than the line before it, which is
+    /// where the gutter names the commit.
+    pub fn starts_run(&self, line: usize) -> bool {
+        line == 0 || self.lines.get(line) != self.lines.get(line - 1)
+    }
+}
+
+/// Works out who last changed each line of `text`, the file's text as it is now. Lines that
+/// differ from the committed version aren't committed yet.
+pub fn blame(path: &Path, text: &str) -> Result<Blame, git2::Error> {
+    let (repo, relative) = open(path)?;
+    let committed = repo.blame_file(&relative, None)?;
+    let current = committed.blame_buffer(text.as_bytes())?;
+    let mut blame = Blame::default();
+    let mut seen: HashMap<git2::Oid, usize> = HashMap::new();
+    for hunk in current.iter() {
+        let id = hunk.final_commit_id();
+        let index = match seen.get(&id) {
+            Some(index) => *index,
+            None => {
+                let commit = match id.is_zero() {
+                    true => BlameCommit {
+                        id: None,
+                        author: "You".to_string(),
+                        time: chrono::Utc::now().timestamp(),
+                        summary: "Not committed yet".to_string(),
+                    },
+                    false => {
+                        let signature = hunk.final_signature();
+                        BlameCommit {
+                            id: Some(id.to_string()[..7].to_string()),
+                            author: signature.name().unwrap_or_default().to_string(),
+                            time: signature.when().seconds(),
+                            summary: repo.find_commit(id)?.summary().unwrap_or_default().to_string(),
+                        }
+                    }
+                };
+                blame.commits.push(commit);
+                seen.insert(id, blame.commits.len() - 1);
+                blame.commits.len() - 1
+            }
+        };
+        blame.lines.extend(std::iter::repeat_n(index, hunk.lines_in_hunk()));
+    }
+    Ok(blame)
+}
+
+/// A commit's date, in the local time zone.
+pub fn date(time: i64) -> String {
+    match chrono::DateTime::from_timestamp(time, 0) {
+        Some(time) => time.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string(),
+        None => String::new(),
+    }
+}
+
+/// How long ago a commit was made, roughly.
+pub fn age(time: i64) -> String {
+    let seconds = (chrono::Utc::now().timestamp() - time).max(0);
+    let (count, unit) = match seconds {
+        0..60 => return "just now".to_string(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        86400..2_592_000 => (seconds / 86400, "day"),
+        2_592_000..31_536_000 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// A commit in a file's history.
+#[derive(Debug, Clone)]
+pub struct FileCommit {
+    pub id: String,
+    pub author: String,
+    pub time: i64,
+    pub summary: String,
+}
+
+impl FileCommit {
+    pub fn short_id(&self) -> &str {
+        &self.id[..7]
+    }
+}
+
+/// The commits from HEAD back that changed a file, newest first. A commit counts if the file
+/// differs from its first parent's, so renames aren't followed.
+pub fn history(path: &Path) -> Result<Vec<FileCommit>, git2::Error> {
+    let (repo, relative) = open(path)?;
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    // Nothing's committed on a new branch, so there's no history to walk
+    if walk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    let entry = |commit: &Commit| commit.tree().ok()?.get_path(&relative).ok().map(|entry| entry.id());
+    let mut commits = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let before = commit.parent(0).ok().and_then(|parent| entry(&parent));
+        if entry(&commit) == before {
+            continue;
+        }
+        let author = commit.author();
+        commits.push(FileCommit {
+            id: commit.id().to_string(),
+            author: author.name().unwrap_or_default().to_string(),
+            time: author.when().seconds(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(commits)
+}
+
+/// A file's text before a commit and after it. It's empty on the side it wasn't there on.
+pub fn commit_change(path: &Path, id: &str) -> Result<(String, String), git2::Error> {
+    let (repo, relative) = open(path)?;
+    let commit = repo.find_commit(git2::Oid::from_str(id)?)?;
+    let version = |commit: Option<Commit>| -> Result<String, git2::Error> {
+        let Some(entry) = commit.and_then(|commit| commit.tree().ok()?.get_path(&relative).ok()) else {
+            return Ok(String::new());
+        };
+        Ok(blob_version(&repo, entry.id())?.map(|base| base.lines.join("\n")).unwrap_or_default())
+    };
+    Ok((version(commit.parent(0).ok())?, version(Some(commit))?))
+}
@@ -0,0 +1,101 @@
+use iced::widget::markdown;
+use lsp_types::{Documentation, HoverContents, MarkedString, ParameterLabel, SignatureHelp};
+use std::ops::Range;
+
+use crate::document::{byte_index, column_from_utf16, Document, Position};
+
+/// What a language server says about the symbol under the mouse, like its type and docs.
+#[derive(Debug)]
+pub struct Hover {
+    /// The range it's about, so it can close once the mouse moves off it
+    pub start: Position,
+    pub end: Position,
+    pub contents: Vec<markdown::Item>,
+}
+
+impl Hover {
+    /// Converts a server's hover at `at`, or `None` if there's nothing to show. Without a range
+    /// of its own it's about the word at `at`.
+    pub fn from_lsp(hover: lsp_types::Hover, document: &Document, at: Position) -> Option<Self> {
+        let text = match hover.contents {
+            HoverContents::Scalar(marked) => marked_markdown(marked),
+            HoverContents::Array(marked) => marked.into_iter().map(marked_markdown).collect::<Vec<_>>().join("\n\n---\n\n"),
+            HoverContents::Markup(markup) => markup.value,
+        };
+        if text.trim().is_empty() {
+            return None;
+        }
+        let position = |position: lsp_types::Position| {
+            document.position_from_utf16(position.line as usize, position.character as usize)
+        };
+        let (start, end) = match hover.range {
+            Some(range) => (position(range.start), position(range.end)),
+            None => document.word_bounds(at),
+        };
+        // Something has to be under the mouse for it to stay open
+        let (start, end) = if start < end { (start, end) } else { (at, Position::new(at.line, at.column + 1)) };
+        Some(Self { start, end, contents: markdown::parse(&text).collect() })
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position < self.end
+    }
+}
+
+/// The signature of the call the cursor is in, from a language server's signature help.
+#[derive(Debug)]
+pub struct Signature {
+    pub label: String,
+    /// The bytes of the label naming the parameter the cursor is at
+    pub active: Option<Range<usize>>,
+    /// Which of the function's overloads it is and how many there are, when there's more than one
+    pub overload: Option<(usize, usize)>,
+    /// The active parameter's documentation, then the function's
+    pub documentation: Vec<markdown::Item>,
+}
+
+impl Signature {
+    /// Converts a server's signature help, or `None` if it has no signatures, which means the
+    /// cursor has left the call.
+    pub fn from_lsp(help: SignatureHelp) -> Option<Self> {
+        let count = help.signatures.len();
+        let index = (help.active_signature.unwrap_or(0) as usize).min(count.checked_sub(1)?);
+        let signature = help.signatures.into_iter().nth(index)?;
+        let label = signature.label;
+        let active_parameter = signature.active_parameter.or(help.active_parameter).map(|active| active as usize);
+        let parameter = active_parameter.and_then(|active| signature.parameters?.into_iter().nth(active));
+
+        let active = parameter.as_ref().and_then(|parameter| match &parameter.label {
+            ParameterLabel::Simple(name) => label.find(name.as_str()).map(|start| start..start + name.len()),
+            ParameterLabel::LabelOffsets([start, end]) => {
+                let byte = |units: u32| byte_index(&label, column_from_utf16(&label, units as usize));
+                Some(byte(*start)..byte(*end))
+            }
+        });
+        let documentation: Vec<String> = parameter
+            .and_then(|parameter| parameter.documentation)
+            .into_iter()
+            .chain(signature.documentation)
+            .map(|documentation| match documentation {
+                Documentation::String(text) => text,
+                Documentation::MarkupContent(markup) => markup.value,
+            })
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+
+        Some(Self {
+            label,
+            active,
+            overload: (count > 1).then_some((index + 1, count)),
+            documentation: markdown::parse(&documentation.join("\n\n")).collect(),
+        })
+    }
+}
+
+/// Markdown for a piece of a hover, with code in a block of its language.
+fn marked_markdown(marked: MarkedString) -> String {
+    match marked {
+        MarkedString::String(text) => text,
+        MarkedString::LanguageString(code) => format!("```{}\n{}\n```", code.language, code.value),
+    }
+}
@@ -53,10 +53,10 @@ pub fn get_file_icon(filename: &str) -> String {
             }
         }
 
-        if let Some(ext) = Path::new(filename).extension().and_then(|e| e.to_str()) {
-            if let Some(icon_name) = theme.file_extensions.get(&ext.to_lowercase()) {
-                return icon_path(icon_name);
-            }
+        if let Some(ext) = Path::new(filename).extension().and_then(|e| e.to_str())
+            && let Some(icon_name) = theme.file_extensions.get(&ext.to_lowercase())
+        {
+            return icon_path(icon_name);
         }
 
         return icon_path(&theme.file);
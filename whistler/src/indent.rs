@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::document::TAB_WIDTH;
+
+/// Languages where a line ending in `:` opens a block.
+const COLON_BLOCK_EXTENSIONS: &[&str] = &["py", "pyi", "pyw", "yaml", "yml", "nim"];
+/// Python statements after which the next line goes back a level.
+const PYTHON_DEDENT_AFTER: &[&str] = &["return", "pass", "break", "continue", "raise"];
+/// Python clauses that line up with the `if` / `try` they belong to once their `:` is typed.
+const PYTHON_DEDENT_CLAUSES: &[&str] = &["else", "elif", "except", "finally"];
+
+const OPEN_BRACKETS: [char; 3] = ['{', '[', '('];
+const CLOSE_BRACKETS: [char; 3] = ['}', ']', ')'];
+
+/// How a file is indented: with tabs, or with `size` spaces per level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indentation {
+    pub tabs: bool,
+    pub size: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self { tabs: false, size: TAB_WIDTH }
+    }
+}
+
+impl Indentation {
+    /// The whitespace for one level.
+    pub fn unit(&self) -> String {
+        if self.tabs { "\t".to_string() } else { " ".repeat(self.size) }
+    }
+
+    /// How many display columns one level takes.
+    pub fn width(&self) -> usize {
+        if self.tabs { TAB_WIDTH } else { self.size }
+    }
+
+    /// The whitespace that indents a line by `width` display columns.
+    pub fn whitespace(&self, width: usize) -> String {
+        if self.tabs {
+            "\t".repeat(width / TAB_WIDTH) + &" ".repeat(width % TAB_WIDTH)
+        } else {
+            " ".repeat(width)
+        }
+    }
+}
+
+/// What the status bar shows, same wording as VS Code.
+impl fmt::Display for Indentation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tabs {
+            write!(f, "Tab Size: {}", TAB_WIDTH)
+        } else {
+            write!(f, "Spaces: {}", self.size)
+        }
+    }
+}
+
+/// Guesses how a file is indented, or `None` if nothing in it is.
+///
+/// Tabs win if more lines start with one than with spaces. Otherwise the size is the
+/// step that shows up most often between one line's indentation and the next.
+pub fn detect(lines: &[String]) -> Option<Indentation> {
+    let (mut tab_lines, mut space_lines) = (0, 0);
+    let mut steps = [0usize; 9];
+    let mut previous = 0;
+
+    for line in lines {
+        let content = line.trim_start();
+        if content.is_empty() {
+            continue;
+        }
+        let leading = &line[..line.len() - content.len()];
+        if leading.starts_with('\t') {
+            tab_lines += 1;
+            continue;
+        }
+        let spaces = leading.chars().take_while(|c| *c == ' ').count();
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        // Steps of one are usually alignment, like the ` *` of a doc comment
+        let step = spaces.abs_diff(previous);
+        if (2..steps.len()).contains(&step) {
+            steps[step] += 1;
+        }
+        previous = spaces;
+    }
+
+    if tab_lines == 0 && space_lines == 0 {
+        return None;
+    }
+    if tab_lines > space_lines {
+        return Some(Indentation { tabs: true, size: TAB_WIDTH });
+    }
+    let (size, _) = steps
+        .iter()
+        .enumerate()
+        .fold((TAB_WIDTH, 0), |best, (size, &count)| if count > best.1 { (size, count) } else { best });
+    Some(Indentation { tabs: false, size })
+}
+
+/// The leading whitespace of a line.
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Whether the text before the cursor opens a block, so Enter should indent the next line.
+pub fn opens_block(extension: &str, before: &str) -> bool {
+    let before = before.trim_end();
+    before.ends_with(OPEN_BRACKETS) || (before.ends_with(':') && COLON_BLOCK_EXTENSIONS.contains(&extension))
+}
+
+/// Whether the line ends its block, so Enter should go back a level.
+pub fn closes_block(extension: &str, line: &str) -> bool {
+    if !matches!(extension, "py" | "pyi" | "pyw") {
+        return false;
+    }
+    let first_word = line.trim_start().split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+    PYTHON_DEDENT_AFTER.contains(&first_word)
+}
+
+/// The bracket that `close` pairs with.
+pub fn opening_bracket(close: char) -> Option<char> {
+    CLOSE_BRACKETS.iter().position(|&c| c == close).map(|index| OPEN_BRACKETS[index])
+}
+
+/// Whether a line like `else:` was just finished and belongs a level further out.
+pub fn is_dedent_clause(extension: &str, line: &str) -> bool {
+    if !matches!(extension, "py" | "pyi" | "pyw") {
+        return false;
+    }
+    let line = line.trim();
+    let first_word = line.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+    line.ends_with(':') && PYTHON_DEDENT_CLAUSES.contains(&first_word)
+}
@@ -0,0 +1,354 @@
+use lsp_types::notification::{self, Notification};
+use lsp_types::request::{self, Request};
+use lsp_types::{
+    ClientCapabilities, ClientInfo, CodeActionCapabilityResolveSupport, CodeActionClientCapabilities,
+    CodeActionKindLiteralSupport, CodeActionLiteralSupport, CompletionClientCapabilities, CompletionItemCapability,
+    CompletionItemCapabilityResolveSupport, DocumentSymbolClientCapabilities, HoverClientCapabilities, InitializeParams, InitializeResult,
+    InlayHintClientCapabilities, MarkupKind, ParameterInformationSettings, RenameClientCapabilities,
+    ResourceOperationKind, ServerCapabilities, SignatureHelpClientCapabilities, SignatureInformationSettings,
+    TextDocumentClientCapabilities, TextDocumentSyncCapability, TextDocumentSyncClientCapabilities,
+    TextDocumentSyncKind, Url, WorkspaceClientCapabilities, WorkspaceEditClientCapabilities, WorkspaceFolder,
+};
+use serde_json::{json, Value};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use super::transport;
+
+/// The ids of the two requests the client sends itself. Everything else counts up from 1.
+const INITIALIZE_ID: i64 = 0;
+const SHUTDOWN_ID: i64 = -1;
+/// JSON-RPC's error code for requests the client doesn't handle.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Tells apart the servers that are running, in the events they send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServerId(pub usize);
+
+/// An error a server answered a request with.
+#[derive(Debug, Clone)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// What servers send, handed to the app as it arrives.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The server answered `initialize`, so it's ready for requests
+    Initialized { server: ServerId, capabilities: Box<ServerCapabilities> },
+    Notification { server: ServerId, method: String, params: Value },
+    /// A request only the app can answer, like `workspace/applyEdit`. It goes back with [`Client::respond`].
+    Request { server: ServerId, id: Value, method: String, params: Value },
+    Response { server: ServerId, id: i64, result: Result<Value, ResponseError> },
+    /// The process is gone, after a shutdown or a crash
+    Exited { server: ServerId },
+}
+
+/// Where a client's events go. It's called from the client's reader thread.
+pub type EventSink = Arc<dyn Fn(Event) + Send + Sync>;
+
+enum Outgoing {
+    Message(Value),
+    /// The server answered `initialize`, so whatever was held back can go
+    Initialized,
+}
+
+/// A connection to one language server process, talking JSON-RPC over its stdin and stdout.
+///
+/// Writing and reading happen on threads of their own, so a slow server never holds up the UI.
+/// Anything sent before the server has answered `initialize` waits until it has.
+pub struct Client {
+    name: String,
+    outgoing: mpsc::Sender<Outgoing>,
+    next_request: i64,
+    capabilities: Option<ServerCapabilities>,
+}
+
+impl Client {
+    /// Starts `command` in `root` and sends it `initialize`.
+    pub fn start(id: ServerId, command: &str, args: &[String], root: &Path, events: EventSink) -> io::Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("no pipes to the language server"));
+        };
+        let root_uri = Url::from_directory_path(root)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "workspace root isn't an absolute path"))?;
+
+        let (outgoing, queue) = mpsc::channel();
+        thread::spawn(move || write_loop(stdin, queue));
+        let replies = outgoing.clone();
+        let workspace = root_uri.clone();
+        thread::spawn(move || {
+            read_loop(id, BufReader::new(stdout), &replies, &workspace, &events);
+            // Already gone unless it stopped making sense, and then it's no use anyway
+            let _ = child.kill();
+            let _ = child.wait();
+            events(Event::Exited { server: id });
+        });
+
+        let client = Self { name: command.to_string(), outgoing, next_request: 1, capabilities: None };
+        client.send(json!({
+            "jsonrpc": "2.0",
+            "id": INITIALIZE_ID,
+            "method": request::Initialize::METHOD,
+            "params": initialize_params(root, root_uri),
+        }));
+        Ok(client)
+    }
+
+    /// The command the server was started with, e.g. `rust-analyzer`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// What the server said it can do, once it's initialized.
+    pub fn capabilities(&self) -> Option<&ServerCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: ServerCapabilities) {
+        self.capabilities = Some(capabilities);
+    }
+
+    /// Sends a request and returns its id, which the [`Event::Response`] to it carries.
+    pub fn request<R: Request>(&mut self, params: R::Params) -> i64 {
+        let id = self.next_request;
+        self.next_request += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": R::METHOD, "params": params }));
+        id
+    }
+
+    /// Answers an [`Event::Request`].
+    pub fn respond(&self, id: Value, result: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+    }
+
+    pub fn notify<N: Notification>(&self, params: N::Params) {
+        self.send(json!({ "jsonrpc": "2.0", "method": N::METHOD, "params": params }));
+    }
+
+    /// Whether changes have to go as the whole text instead of just what changed, because the
+    /// server asked for that or hasn't said yet. Every server understands the whole text.
+    pub fn wants_full_text(&self) -> bool {
+        let Some(capabilities) = &self.capabilities else { return true; };
+        let kind = match &capabilities.text_document_sync {
+            Some(TextDocumentSyncCapability::Kind(kind)) => Some(*kind),
+            Some(TextDocumentSyncCapability::Options(options)) => options.change,
+            None => None,
+        };
+        kind == Some(TextDocumentSyncKind::FULL)
+    }
+
+    /// Asks the server to shut down. It's told to exit once it has, and the client's threads
+    /// finish when the process does.
+    pub fn shutdown(&self) {
+        self.send(json!({ "jsonrpc": "2.0", "id": SHUTDOWN_ID, "method": request::Shutdown::METHOD, "params": null }));
+    }
+
+    fn send(&self, message: Value) {
+        // The writer only goes away with the process, and then there's nobody to tell
+        let _ = self.outgoing.send(Outgoing::Message(message));
+    }
+}
+
+/// Who we are and what we understand. UTF-16 positions, since every server supports them.
+fn initialize_params(root: &Path, root_uri: Url) -> Value {
+    let name = root.file_name().map_or_else(|| root.to_string_lossy(), |name| name.to_string_lossy()).into_owned();
+    #[allow(deprecated)] // `root_uri` is superseded by workspace folders, but older servers only read it
+    let params = InitializeParams {
+        process_id: Some(std::process::id()),
+        root_uri: Some(root_uri.clone()),
+        workspace_folders: Some(vec![WorkspaceFolder { uri: root_uri, name }]),
+        client_info: Some(ClientInfo { name: "Whistler".to_string(), version: Some(env!("CARGO_PKG_VERSION").to_string()) }),
+        capabilities: ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                synchronization: Some(TextDocumentSyncClientCapabilities {
+                    did_save: Some(true),
+                    ..Default::default()
+                }),
+                completion: Some(CompletionClientCapabilities {
+                    completion_item: Some(CompletionItemCapability {
+                        snippet_support: Some(true),
+                        documentation_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+                        // Auto-imports and docs can wait until an item is picked
+                        resolve_support: Some(CompletionItemCapabilityResolveSupport {
+                            properties: ["documentation", "detail", "additionalTextEdits"].map(String::from).to_vec(),
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                hover: Some(HoverClientCapabilities {
+                    content_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+                    ..Default::default()
+                }),
+                signature_help: Some(SignatureHelpClientCapabilities {
+                    signature_information: Some(SignatureInformationSettings {
+                        documentation_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+                        parameter_information: Some(ParameterInformationSettings { label_offset_support: Some(true) }),
+                        active_parameter_support: Some(true),
+                    }),
+                    ..Default::default()
+                }),
+                inlay_hint: Some(InlayHintClientCapabilities::default()),
+                // Nested symbols, so the outline doesn't have to work out what's inside what
+                document_symbol: Some(DocumentSymbolClientCapabilities {
+                    hierarchical_document_symbol_support: Some(true),
+                    ..Default::default()
+                }),
+                rename: Some(RenameClientCapabilities { prepare_support: Some(true), ..Default::default() }),
+                code_action: Some(CodeActionClientCapabilities {
+                    // Every kind there is, the menu shows them all alike
+                    code_action_literal_support: Some(CodeActionLiteralSupport {
+                        code_action_kind: CodeActionKindLiteralSupport {
+                            value_set: ["", "quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite", "source", "source.organizeImports"]
+                                .map(String::from)
+                                .to_vec(),
+                        },
+                    }),
+                    is_preferred_support: Some(true),
+                    disabled_support: Some(true),
+                    data_support: Some(true),
+                    // The edit of an action can wait until it's picked
+                    resolve_support: Some(CodeActionCapabilityResolveSupport { properties: vec!["edit".to_string()] }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            workspace: Some(WorkspaceClientCapabilities {
+                apply_edit: Some(true),
+                workspace_edit: Some(WorkspaceEditClientCapabilities {
+                    document_changes: Some(true),
+                    resource_operations: Some(vec![
+                        ResourceOperationKind::Create,
+                        ResourceOperationKind::Rename,
+                        ResourceOperationKind::Delete,
+                    ]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    serde_json::to_value(params).unwrap_or(Value::Null)
+}
+
+/// Writes messages to the server in order, holding back all but `initialize` and replies until
+/// the server has answered `initialize`.
+fn write_loop(mut stdin: ChildStdin, queue: mpsc::Receiver<Outgoing>) {
+    let mut initialized = false;
+    let mut held = Vec::new();
+    for outgoing in queue {
+        let ready = match outgoing {
+            Outgoing::Message(message) => {
+                let method = message.get("method").and_then(Value::as_str);
+                if initialized || method.is_none() || method == Some(request::Initialize::METHOD) {
+                    vec![message]
+                } else {
+                    held.push(message);
+                    continue;
+                }
+            }
+            Outgoing::Initialized => {
+                initialized = true;
+                let notification = json!({ "jsonrpc": "2.0", "method": notification::Initialized::METHOD, "params": {} });
+                std::iter::once(notification).chain(held.drain(..)).collect()
+            }
+        };
+        for message in ready {
+            if transport::write_message(&mut stdin, &message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Reads what the server sends until it closes its end, answering the requests it makes of
+/// the client and passing everything else on.
+fn read_loop(
+    server: ServerId,
+    mut stdout: BufReader<impl io::Read>,
+    replies: &mpsc::Sender<Outgoing>,
+    workspace: &Url,
+    events: &EventSink,
+) {
+    while let Ok(Some(mut message)) = transport::read_message(&mut stdout) {
+        let params = message.get_mut("params").map(Value::take).unwrap_or(Value::Null);
+        let method = message.get("method").and_then(Value::as_str).map(str::to_string);
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            (Some(method), Some(id)) if method == request::ApplyWorkspaceEdit::METHOD => {
+                events(Event::Request { server, id, method, params });
+            }
+            (Some(method), Some(id)) => {
+                let reply = match answer(&method, &params, workspace) {
+                    Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    None => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": METHOD_NOT_FOUND, "message": format!("{} isn't supported", method) },
+                    }),
+                };
+                let _ = replies.send(Outgoing::Message(reply));
+            }
+            (Some(method), None) => events(Event::Notification { server, method, params }),
+            (None, Some(id)) => match id.as_i64() {
+                Some(INITIALIZE_ID) => {
+                    let result = message.get_mut("result").map(Value::take).unwrap_or(Value::Null);
+                    let Ok(initialized) = serde_json::from_value::<InitializeResult>(result) else {
+                        eprintln!("Language server couldn't initialize: {}", message.get("error").unwrap_or(&Value::Null));
+                        return;
+                    };
+                    let _ = replies.send(Outgoing::Initialized);
+                    events(Event::Initialized { server, capabilities: Box::new(initialized.capabilities) });
+                }
+                Some(SHUTDOWN_ID) => {
+                    let exit = json!({ "jsonrpc": "2.0", "method": notification::Exit::METHOD, "params": null });
+                    let _ = replies.send(Outgoing::Message(exit));
+                }
+                Some(id) => {
+                    let result = match message.get_mut("error") {
+                        Some(error) => Err(ResponseError {
+                            code: error.get("code").and_then(Value::as_i64).unwrap_or_default(),
+                            message: error.get("message").and_then(Value::as_str).unwrap_or_default().to_string(),
+                        }),
+                        None => Ok(message.get_mut("result").map(Value::take).unwrap_or(Value::Null)),
+                    };
+                    events(Event::Response { server, id, result });
+                }
+                None => {}
+            },
+            (None, None) => {}
+        }
+    }
+}
+
+/// The answer to a request the server makes of the client, or `None` if it isn't one we handle.
+fn answer(method: &str, params: &Value, workspace: &Url) -> Option<Value> {
+    match method {
+        // No settings of our own to give, so every server goes with its defaults
+        request::WorkspaceConfiguration::METHOD => {
+            let items = params.get("items").and_then(Value::as_array).map_or(0, Vec::len);
+            Some(Value::Array(vec![Value::Null; items]))
+        }
+        request::WorkspaceFoldersRequest::METHOD => Some(json!([{ "uri": workspace, "name": "" }])),
+        request::WorkDoneProgressCreate::METHOD
+        | request::RegisterCapability::METHOD
+        | request::UnregisterCapability::METHOD
+        | request::ShowMessageRequest::METHOD => Some(Value::Null),
+        _ => None,
+    }
+}
@@ -0,0 +1,157 @@
+// Nothing in here uses the rest of the app, so the tests can build it on its own
+pub mod client;
+pub mod transport;
+
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    Position, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem, Url,
+    VersionedTextDocumentIdentifier,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+pub use client::{Client, Event, EventSink, ResponseError, ServerId};
+
+/// An edit to a document: the `(line, UTF-16 column)` range replaced, and what went in.
+pub struct Change {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
+}
+
+/// The language servers that are running, one per command and workspace root, and which
+/// documents each of them has open.
+#[derive(Default)]
+pub struct Servers {
+    events: Option<EventSink>,
+    clients: HashMap<ServerId, Client>,
+    started: HashMap<(String, PathBuf), ServerId>,
+    /// Servers that wouldn't start, so they aren't tried again for every file
+    failed: HashSet<(String, PathBuf)>,
+    documents: HashMap<Url, ServerId>,
+    next_id: usize,
+}
+
+impl Servers {
+    /// Gives the servers somewhere to send their events. Nothing starts before this.
+    pub fn connect(&mut self, events: EventSink) {
+        self.events = Some(events);
+    }
+
+    /// The server running `command` for `root`, started the first time it's asked for.
+    pub fn start(&mut self, command: &str, args: &[String], root: &Path) -> Option<ServerId> {
+        let key = (command.to_string(), root.to_path_buf());
+        if let Some(id) = self.started.get(&key) {
+            return Some(*id);
+        }
+        if self.failed.contains(&key) {
+            return None;
+        }
+
+        let id = ServerId(self.next_id);
+        self.next_id += 1;
+        match Client::start(id, command, args, root, self.events.clone()?) {
+            Ok(client) => {
+                self.clients.insert(id, client);
+                self.started.insert(key, id);
+                Some(id)
+            }
+            Err(e) => {
+                eprintln!("Failed to start language server {}: {}", command, e);
+                self.failed.insert(key);
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, id: ServerId) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: ServerId) -> Option<&mut Client> {
+        self.clients.get_mut(&id)
+    }
+
+    /// The server a document is open on.
+    pub fn server_for(&self, uri: &Url) -> Option<ServerId> {
+        self.documents.get(uri).copied()
+    }
+
+    /// Handles the events that change what the servers can do. The app still gets to see them.
+    pub fn handle(&mut self, event: &Event) {
+        match event {
+            Event::Initialized { server, capabilities } => {
+                if let Some(client) = self.clients.get_mut(server) {
+                    client.set_capabilities(capabilities.as_ref().clone());
+                }
+            }
+            Event::Exited { server } => {
+                self.clients.remove(server);
+                self.started.retain(|_, id| id != server);
+                self.documents.retain(|_, id| id != server);
+            }
+            Event::Notification { .. } | Event::Request { .. } | Event::Response { .. } => {}
+        }
+    }
+
+    pub fn open(&mut self, server: ServerId, uri: Url, language_id: &str, version: i32, text: String) {
+        let Some(client) = self.clients.get(&server) else { return; };
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem { uri: uri.clone(), language_id: language_id.to_string(), version, text },
+        });
+        self.documents.insert(uri, server);
+    }
+
+    /// Tells the document's server about edits, or sends the whole text if that's what it wants.
+    pub fn change(&self, uri: &Url, version: i32, changes: Vec<Change>, text: impl FnOnce() -> String) {
+        let Some(client) = self.server_for(uri).and_then(|server| self.clients.get(&server)) else { return; };
+        let content_changes = match client.wants_full_text() {
+            true => vec![TextDocumentContentChangeEvent { range: None, range_length: None, text: text() }],
+            false => changes
+                .into_iter()
+                .map(|change| TextDocumentContentChangeEvent {
+                    range: Some(Range { start: position(change.start), end: position(change.end) }),
+                    range_length: None,
+                    text: change.text,
+                })
+                .collect(),
+        };
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier { uri: uri.clone(), version },
+            content_changes,
+        });
+    }
+
+    pub fn save(&self, uri: &Url) {
+        let Some(client) = self.server_for(uri).and_then(|server| self.clients.get(&server)) else { return; };
+        client.notify::<DidSaveTextDocument>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            text: None,
+        });
+    }
+
+    pub fn close(&mut self, uri: &Url) {
+        let Some(server) = self.documents.remove(uri) else { return; };
+        if let Some(client) = self.clients.get(&server) {
+            client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+            });
+        }
+    }
+
+    /// Shuts every server down, e.g. when another folder is opened.
+    pub fn shutdown_all(&mut self) {
+        for client in self.clients.values() {
+            client.shutdown();
+        }
+        self.clients.clear();
+        self.started.clear();
+        self.failed.clear();
+        self.documents.clear();
+    }
+}
+
+fn position((line, character): (usize, usize)) -> Position {
+    Position { line: line as u32, character: character as u32 }
+}
@@ -0,0 +1,37 @@
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+const CONTENT_LENGTH: &str = "Content-Length:";
+
+/// Reads one JSON-RPC message, or `None` once the other side has closed the stream.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // Content-Type is the only other header, and it's always JSON
+        if let Some(value) = header.strip_prefix(CONTENT_LENGTH) {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Writes one JSON-RPC message with its header.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(writer, "{} {}\r\n\r\n{}", CONTENT_LENGTH, body.len(), body)?;
+    writer.flush()
+}
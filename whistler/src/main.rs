@@ -1,13 +1,30 @@
 use iced::window;
 
 mod app;
+mod completion;
+mod config;
+mod decoration;
+mod diagnostics;
+mod diff;
+mod document;
 mod message;
 mod file_tree;
+mod folding;
+mod format;
+mod git;
+mod hover;
 mod icons;
+mod indent;
+mod lsp;
+mod outline;
+mod symbol_index;
+mod refactor;
 mod resources;
 mod syntax;
 mod theme;
 mod search;
+mod snippets;
+mod session;
 mod ui;
 
 const FIRA_CODE: &[u8] = include_bytes!("../fonts/FiraCode/ttf/FiraCode-Regular.ttf");
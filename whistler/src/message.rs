@@ -1,6 +1,15 @@
-use iced::widget::text_editor::Action;
+use crate::diagnostics::Severity;
+use crate::document::{Action, Position};
+use crate::format::Formatting;
+use crate::git::{Base, Blame, FileCommit, Statuses};
+use crate::lsp;
+use crate::outline::Crumb;
+use crate::ui::SidebarView;
+use iced::futures::channel::mpsc::UnboundedSender;
+use iced::widget::text_editor;
 use std::path::PathBuf;
 use crate::search::SearchResult;
+use crate::symbol_index::IndexedSymbol;
 
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -11,11 +20,64 @@ pub enum Message {
     FileOpened(PathBuf, String),
     FolderToggled(PathBuf),
     FileTreeRefresh,
+    /// Something in the open folder changed on disk, then the git status read after it
+    FilesChanged,
+    RefreshGitStatus,
+    GitStatusLoaded(PathBuf, Option<Statuses>),
+    /// What git has of an open file, to mark what's changed in it since
+    GitBaseLoaded(PathBuf, Option<Base>),
+    /// Who last changed each line of an open file, worked out again once it's been left alone
+    /// for a moment, then as of the version it was worked out for
+    RefreshBlame(PathBuf),
+    BlameLoaded(PathBuf, i32, Option<Blame>),
+    ToggleBlame,
+    /// The File History view: the commits that changed the active file, and opening one
+    HistoryLoaded(PathBuf, Option<Vec<FileCommit>>),
+    HistoryCommitChosen(String),
+    /// Clicking a change marker, and what can be done with the change it opens
+    ShowHunk(usize),
+    RevertHunk,
+    StageHunk,
+    CloseHunk,
+    /// Alt+F5 and Shift+Alt+F5, the cursor to the next or previous change, or a diff tab to
+    /// its next or previous hunk
+    NextChange,
+    PreviousChange,
+    /// Diff tabs: what's compared, and how it's shown
+    FileMenuToggled(Option<PathBuf>),
+    CompareWithSaved(PathBuf),
+    SelectForCompare(PathBuf),
+    CompareWithSelected(PathBuf),
+    OpenChanges(PathBuf, bool),
+    PreviewFileDiff(PathBuf),
+    DiffLayoutToggled,
+    DiffUnchangedToggled,
+    /// The Source Control view: staging, unstaging and discarding files or their hunks
+    FileDiffToggled(PathBuf, bool),
+    StageFile(PathBuf),
+    UnstageFile(PathBuf),
+    StageAll,
+    UnstageAll,
+    StageFileHunk(PathBuf, usize),
+    UnstageFileHunk(PathBuf, usize),
+    DiscardFile(PathBuf),
+    DiscardConfirmed(PathBuf),
+    /// Then committing them
+    CommitMessageEdited(text_editor::Action),
+    AmendToggled(bool),
+    Commit,
+    /// And the branch menu
+    BranchMenuToggled,
+    BranchChosen(String),
+    NewBranchStarted,
+    NewBranchNameChanged(String),
+    CreateBranch,
+    InitRepository,
     ToggleSidebar,
     OpenFolderDialog,
     FolderOpened(PathBuf),
     SaveFile,
-    FileSaved(Result<(), String>),
+    FileSaved(PathBuf, Result<(), String>),
     /// Tab stuff
     TabSelected(usize),
     TabClosed(usize),
@@ -24,6 +86,15 @@ pub enum Message {
     SidebarResizeStart,
     SidebarResizing(f32),
     SidebarResizeEnd,
+    SidebarViewChanged(SidebarView),
+    /// The outline of a file, once it's gone unedited for a moment
+    RefreshOutline(PathBuf),
+    /// Where a file can be folded, worked out again once it's been left alone for a moment
+    RefreshFolds(PathBuf),
+    /// The breadcrumbs above the editor, whose segments open a dropdown of what's next to them
+    BreadcrumbClicked(usize),
+    BreadcrumbChosen(Crumb),
+    BreadcrumbDismissed,
     /// Markdown preview and other md stuff
     PreviewMarkdown,
     MarkdownLinkClicked(iced::widget::markdown::Uri),
@@ -31,13 +102,63 @@ pub enum Message {
     ToggleSearch,
     SearchQueryChanged(String),
     SearchCompleted(Vec<SearchResult>),
-    SearchResultClicked(PathBuf, usize), // (filepath, line number)
+    SearchResultClicked(PathBuf, Position),
     /// File finding
     ToggleFileFinder,
     FileFinderQueryChanged(String),
     FileFinderSelect, // Enter to open file
     FileFinderNavigate(i32), // This is to go up/down using arrow keys
+    SymbolsIndexed(PathBuf, Vec<IndexedSymbol>), // The definitions found in a folder or file, for `#` in the finder
     /// Fullscreen and window management stuff
     ToggleFullscreen(iced::window::Mode),
     EscapePressed,
+    /// Two-step shortcuts like Cmd+K Cmd+0
+    ChordStarted,
+    ChordKey(char),
+    /// A key that isn't part of a chord was pressed, which ends the one that was started
+    ChordCancelled,
+    /// The indentation picker in the status bar
+    ToggleIndentMenu,
+    IndentationChosen(Action),
+    /// The completion popup
+    TriggerCompletion,
+    CompletionNavigate(i32),
+    CompletionAccept,
+    CompletionDismiss,
+    /// Language servers: where their events go, then the events themselves
+    LspConnected(UnboundedSender<lsp::Event>),
+    Lsp(lsp::Event),
+    /// The Problems panel
+    ToggleProblems,
+    ProblemsFilterChanged(String),
+    ProblemsSeverityToggled(Severity),
+    ProblemClicked(PathBuf, Position),
+    /// Where the symbol at the cursor is defined, implemented or used, from its language server
+    GoToDefinition,
+    GoToTypeDefinition,
+    GoToImplementation,
+    FindReferences,
+    /// The mouse resting on a char, or moving off what the hover is about
+    HoverAt(Option<Position>),
+    /// Ctrl+Shift+Space, the signature of the call the cursor is in
+    TriggerSignatureHelp,
+    /// F2, renaming the symbol at the cursor across the workspace
+    RenameSymbol,
+    RenameInputChanged(String),
+    RenameSubmit,
+    RenameCancel,
+    /// The edits a rename makes, shown before they're applied
+    ApplyEditPreview,
+    DiscardEditPreview,
+    /// Cmd+., the quick fixes and refactorings on offer at the cursor
+    ShowCodeActions,
+    CodeActionFilterChanged(String),
+    CodeActionChosen(usize),
+    CodeActionsClose,
+    /// Shift+Alt+F and Cmd+K Cmd+F, then what an external formatter made of the text
+    FormatDocument,
+    FormatSelection,
+    Formatted(Formatting, Result<String, String>),
+    /// Saving stops waiting for a language server to format the file
+    FormatTimedOut(Formatting),
 }
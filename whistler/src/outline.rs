@@ -0,0 +1,224 @@
+use lsp_types::{DocumentSymbol, DocumentSymbolResponse, SymbolKind};
+use std::path::PathBuf;
+
+use crate::document::{Document, Position};
+use crate::syntax;
+
+/// Something defined in a file, like a function or a class, with what's defined inside it.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub detail: Option<String>,
+    pub kind: SymbolKind,
+    /// Where its whole definition starts and ends, to tell which symbols the cursor is in
+    pub start: Position,
+    pub end: Position,
+    /// Where its name is, which is where going to it puts the cursor
+    pub selection: Position,
+    pub children: Vec<Symbol>,
+}
+
+impl Symbol {
+    fn from_lsp(symbol: DocumentSymbol, document: &Document) -> Self {
+        let position = |position: lsp_types::Position| {
+            document.position_from_utf16(position.line as usize, position.character as usize)
+        };
+        let mut children: Vec<Symbol> =
+            symbol.children.unwrap_or_default().into_iter().map(|child| Symbol::from_lsp(child, document)).collect();
+        children.sort_by_key(|child| child.start);
+        Self {
+            name: symbol.name,
+            detail: symbol.detail.filter(|detail| !detail.is_empty()),
+            kind: symbol.kind,
+            start: position(symbol.range.start),
+            end: position(symbol.range.end),
+            selection: position(symbol.selection_range.start),
+            children,
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+}
+
+/// The letter shown before a symbol's name, the same ones the completion popup uses.
+pub fn icon(kind: SymbolKind) -> char {
+    match kind {
+        SymbolKind::FILE => '-',
+        SymbolKind::MODULE | SymbolKind::NAMESPACE | SymbolKind::PACKAGE => 'M',
+        SymbolKind::CLASS | SymbolKind::OBJECT => 'C',
+        SymbolKind::METHOD => 'm',
+        SymbolKind::PROPERTY | SymbolKind::FIELD => 'p',
+        SymbolKind::CONSTRUCTOR => 'c',
+        SymbolKind::ENUM => 'E',
+        SymbolKind::INTERFACE => 'I',
+        SymbolKind::FUNCTION => 'f',
+        SymbolKind::VARIABLE => 'v',
+        SymbolKind::CONSTANT => 'K',
+        SymbolKind::STRING => '#',
+        SymbolKind::ENUM_MEMBER => 'e',
+        SymbolKind::STRUCT => 'S',
+        SymbolKind::EVENT => '!',
+        SymbolKind::OPERATOR => '+',
+        SymbolKind::TYPE_PARAMETER => 'T',
+        _ => 'v',
+    }
+}
+
+/// A server's symbols for a file, nested the way they're defined. Servers that send a flat list
+/// get theirs nested by which ranges are inside which.
+pub fn from_lsp(response: DocumentSymbolResponse, document: &Document) -> Vec<Symbol> {
+    match response {
+        DocumentSymbolResponse::Nested(symbols) => {
+            let mut symbols: Vec<Symbol> = symbols.into_iter().map(|symbol| Symbol::from_lsp(symbol, document)).collect();
+            symbols.sort_by_key(|symbol| symbol.start);
+            symbols
+        }
+        DocumentSymbolResponse::Flat(symbols) => {
+            let position = |position: lsp_types::Position| {
+                document.position_from_utf16(position.line as usize, position.character as usize)
+            };
+            nest(
+                symbols
+                    .into_iter()
+                    .map(|symbol| Symbol {
+                        name: symbol.name,
+                        detail: symbol.container_name,
+                        kind: symbol.kind,
+                        start: position(symbol.location.range.start),
+                        end: position(symbol.location.range.end),
+                        selection: position(symbol.location.range.start),
+                        children: Vec::new(),
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Symbols from the grammar, for files without a language server. A definition reaches as
+/// far as the block that opens on its line.
+pub fn from_syntax(document: &Document) -> Vec<Symbol> {
+    let lines = document.lines();
+    let regions = document.fold_regions();
+    let symbols = syntax::defined_names(document.extension(), lines)
+        .into_iter()
+        .filter_map(|(line, range, scope)| {
+            let kind = kind_of_scope(&scope)?;
+            let text = &lines[line];
+            let column = text[..range.start].chars().count();
+            let end = regions.iter().find(|region| region.start == line).map_or(line, |region| {
+                // The closing bracket's line is part of it too, where there is one
+                let closing = lines.get(region.end + 1).is_some_and(|next| next.trim_start().starts_with(['}', ']', ')']));
+                if closing { region.end + 1 } else { region.end }
+            });
+            Some(Symbol {
+                name: text[range].trim().to_string(),
+                detail: None,
+                kind,
+                start: Position::new(line, 0),
+                end: Position::new(end, lines[end].chars().count()),
+                selection: Position::new(line, column),
+                children: Vec::new(),
+            })
+        })
+        .collect();
+    nest(symbols)
+}
+
+/// What kind of symbol a grammar's name scope is for. Names like tags and labels aren't
+/// symbols at all.
+pub fn kind_of_scope(scope: &str) -> Option<SymbolKind> {
+    let parts: Vec<&str> = scope.split('.').collect();
+    let has = |name: &str| parts.contains(&name);
+    let kind = if has("function") || has("macro") {
+        SymbolKind::FUNCTION
+    } else if has("method") {
+        SymbolKind::METHOD
+    } else if has("struct") {
+        SymbolKind::STRUCT
+    } else if has("enum") {
+        SymbolKind::ENUM
+    } else if has("trait") || has("interface") || has("protocol") {
+        SymbolKind::INTERFACE
+    } else if has("class") || has("impl") || has("type") {
+        SymbolKind::CLASS
+    } else if has("namespace") || has("module") {
+        SymbolKind::MODULE
+    } else if has("constant") {
+        SymbolKind::CONSTANT
+    } else if has("section") || has("heading") {
+        // Markdown headings, the way VS Code lists them
+        SymbolKind::STRING
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+/// Puts each symbol inside the last one before it whose range holds it.
+fn nest(mut flat: Vec<Symbol>) -> Vec<Symbol> {
+    flat.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    let mut top: Vec<Symbol> = Vec::new();
+    let mut open: Vec<Symbol> = Vec::new();
+    for symbol in flat {
+        while open.last().is_some_and(|parent| !(parent.contains(symbol.start) && symbol.end <= parent.end)) {
+            close(&mut open, &mut top);
+        }
+        open.push(symbol);
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut top);
+    }
+    top
+}
+
+/// Finishes the innermost open symbol, into its parent or the top level.
+fn close(open: &mut Vec<Symbol>, top: &mut Vec<Symbol>) {
+    let Some(symbol) = open.pop() else { return; };
+    match open.last_mut() {
+        Some(parent) => parent.children.push(symbol),
+        None => top.push(symbol),
+    }
+}
+
+/// The symbols `position` is inside, outermost first, as an index into each level.
+pub fn path_to(symbols: &[Symbol], position: Position) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut level = symbols;
+    // Symbols that hold each other end to end, like a one line function after another, go by the later one
+    while let Some(index) = level.iter().rposition(|symbol| symbol.contains(position)) {
+        path.push(index);
+        level = &level[index].children;
+    }
+    path
+}
+
+/// The symbols at the level under the ones `path` leads through, e.g. the top level for an
+/// empty path.
+pub fn level<'a>(symbols: &'a [Symbol], path: &[usize]) -> &'a [Symbol] {
+    path.iter().try_fold(symbols, |level, index| level.get(*index).map(|symbol| symbol.children.as_slice())).unwrap_or(&[])
+}
+
+
+/// A segment of the breadcrumbs above the editor, or one of the things its dropdown lists.
+#[derive(Debug, Clone)]
+pub enum Crumb {
+    Folder(PathBuf),
+    File(PathBuf),
+    Symbol { name: String, kind: SymbolKind, at: Position },
+}
+
+impl Crumb {
+    pub fn symbol(symbol: &Symbol) -> Self {
+        Crumb::Symbol { name: symbol.name.clone(), kind: symbol.kind, at: symbol.selection }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Crumb::Folder(path) | Crumb::File(path) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            Crumb::Symbol { name, .. } => name.clone(),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use lsp_types::{CodeActionOrCommand, DocumentChangeOperation, DocumentChanges, OneOf, ResourceOp, WorkspaceEdit};
+use std::path::PathBuf;
+
+use crate::document::{byte_index, Document, Position, TextEdit};
+use crate::lsp::ServerId;
+
+/// One thing a workspace edit does to the files of the workspace.
+#[derive(Debug, Clone)]
+pub enum FileChange {
+    /// Edits to a file's text, with positions the way the server sent them
+    Edit { path: PathBuf, edits: Vec<lsp_types::TextEdit> },
+    Create(PathBuf),
+    Rename { from: PathBuf, to: PathBuf },
+    Delete(PathBuf),
+}
+
+/// The changes a server's workspace edit makes, in the order they have to be made.
+pub fn changes(edit: WorkspaceEdit) -> Vec<FileChange> {
+    let path = |uri: &lsp_types::Url| uri.to_file_path().ok();
+    let text_edits = |edits: Vec<OneOf<lsp_types::TextEdit, lsp_types::AnnotatedTextEdit>>| {
+        edits
+            .into_iter()
+            .map(|edit| match edit {
+                OneOf::Left(edit) => edit,
+                OneOf::Right(annotated) => annotated.text_edit,
+            })
+            .collect()
+    };
+    // `documentChanges` wins when a server sends both
+    match edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => edits
+            .into_iter()
+            .filter_map(|edit| Some(FileChange::Edit { path: path(&edit.text_document.uri)?, edits: text_edits(edit.edits) }))
+            .collect(),
+        Some(DocumentChanges::Operations(operations)) => operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => {
+                    Some(FileChange::Edit { path: path(&edit.text_document.uri)?, edits: text_edits(edit.edits) })
+                }
+                DocumentChangeOperation::Op(ResourceOp::Create(create)) => Some(FileChange::Create(path(&create.uri)?)),
+                DocumentChangeOperation::Op(ResourceOp::Rename(rename)) => {
+                    Some(FileChange::Rename { from: path(&rename.old_uri)?, to: path(&rename.new_uri)? })
+                }
+                DocumentChangeOperation::Op(ResourceOp::Delete(delete)) => Some(FileChange::Delete(path(&delete.uri)?)),
+            })
+            .collect(),
+        None => {
+            let mut changes: Vec<FileChange> = edit
+                .changes
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(uri, edits)| Some(FileChange::Edit { path: path(&uri)?, edits }))
+                .collect();
+            // They come in a map, so put them in an order that doesn't change between runs
+            changes.sort_by(|a, b| match (a, b) {
+                (FileChange::Edit { path: a, .. }, FileChange::Edit { path: b, .. }) => a.cmp(b),
+                _ => std::cmp::Ordering::Equal,
+            });
+            changes
+        }
+    }
+}
+
+/// A line an edit changes, the way it reads before and after.
+#[derive(Debug, Clone)]
+pub struct PreviewLine {
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+/// What a workspace edit will do, shown before it's applied.
+#[derive(Debug)]
+pub struct EditPreview {
+    pub title: String,
+    pub changes: Vec<FileChange>,
+    /// Each file that's edited and the lines that change in it
+    pub files: Vec<(PathBuf, Vec<PreviewLine>)>,
+}
+
+impl EditPreview {
+    /// How many edits there are and across how many files, e.g. "12 changes in 3 files".
+    pub fn summary(&self) -> String {
+        let count: usize = self.files.iter().map(|(_, lines)| lines.len()).sum();
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        format!("{} change{} in {} file{}", count, plural(count), self.files.len(), plural(self.files.len()))
+    }
+}
+
+/// The lines of `document` some edits change, before and after. Edits that join or split lines
+/// show the line breaks they put in as `⏎`.
+pub fn preview_lines(document: &Document, edits: &[TextEdit]) -> Vec<PreviewLine> {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.start);
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while index < edits.len() {
+        let line = edits[index].start.line;
+        let before = document.line(line);
+        let mut after = String::new();
+        // How far into `before` has been copied, or `None` once an edit has run past its end
+        let mut copied = Some(0);
+        while let Some(edit) = edits.get(index).filter(|edit| edit.start.line == line) {
+            index += 1;
+            let Some(from) = copied else { continue; };
+            after.push_str(&before[byte_index(before, from)..byte_index(before, edit.start.column.max(from))]);
+            after.push_str(&edit.text.replace('\n', "⏎"));
+            if edit.end.line == line {
+                copied = Some(edit.end.column.max(from));
+            } else {
+                let end = document.line(edit.end.line);
+                after.push_str(&end[byte_index(end, edit.end.column)..]);
+                copied = None;
+            }
+        }
+        if let Some(from) = copied {
+            after.push_str(&before[byte_index(before, from)..]);
+        }
+        lines.push(PreviewLine { line, before: before.to_string(), after });
+    }
+    lines
+}
+
+/// The actions a server offered for the cursor or selection, to pick one from.
+#[derive(Debug)]
+pub struct CodeActionMenu {
+    pub server: ServerId,
+    pub actions: Vec<CodeActionOrCommand>,
+    pub filter: String,
+    /// Into the actions that match the filter
+    pub selected: usize,
+}
+
+impl CodeActionMenu {
+    /// Opens on the server's preferred action if it has one.
+    pub fn new(server: ServerId, actions: Vec<CodeActionOrCommand>) -> Self {
+        // With no filter yet, every action is shown
+        let selected = actions
+            .iter()
+            .position(|action| matches!(action, CodeActionOrCommand::CodeAction(action) if action.is_preferred == Some(true)))
+            .unwrap_or(0);
+        Self { server, actions, filter: String::new(), selected }
+    }
+
+    /// The actions whose titles contain the filter, with their index among all of them.
+    pub fn shown(&self) -> impl Iterator<Item = (usize, &CodeActionOrCommand)> {
+        let filter = self.filter.to_lowercase();
+        self.actions.iter().enumerate().filter(move |(_, action)| title(action).to_lowercase().contains(&filter))
+    }
+
+    pub fn navigate(&mut self, delta: i32) {
+        let count = self.shown().count();
+        if count > 0 {
+            self.selected = (self.selected as i32 + delta).rem_euclid(count as i32) as usize;
+        }
+    }
+
+    /// The index of the selected action among all of them.
+    pub fn selected_action(&self) -> Option<usize> {
+        self.shown().nth(self.selected).map(|(index, _)| index)
+    }
+}
+
+pub fn title(action: &CodeActionOrCommand) -> &str {
+    match action {
+        CodeActionOrCommand::Command(command) => &command.title,
+        CodeActionOrCommand::CodeAction(action) => &action.title,
+    }
+}
+
+/// Why an action can't be used right now, if the server says it can't.
+pub fn disabled_reason(action: &CodeActionOrCommand) -> Option<&str> {
+    match action {
+        CodeActionOrCommand::CodeAction(action) => action.disabled.as_ref().map(|disabled| disabled.reason.as_str()),
+        CodeActionOrCommand::Command(_) => None,
+    }
+}
+
+/// A symbol being renamed: where it is, and the name being typed for it.
+#[derive(Debug)]
+pub struct Rename {
+    pub path: PathBuf,
+    pub at: Position,
+    pub old_name: String,
+    pub new_name: String,
+}
@@ -12,12 +12,12 @@ pub fn resource_dir() -> PathBuf {
         let exe = exe.canonicalize().unwrap_or(exe);
         if let Some(macos_dir) = exe.parent() {
             // Check if we're inside a .app bundle: .../Contents/MacOS/<binary>
-            if macos_dir.ends_with("Contents/MacOS") {
-                if let Some(contents_dir) = macos_dir.parent() {
-                    let resources = contents_dir.join("Resources");
-                    if resources.is_dir() {
-                        return resources;
-                    }
+            if macos_dir.ends_with("Contents/MacOS")
+                && let Some(contents_dir) = macos_dir.parent()
+            {
+                let resources = contents_dir.join("Resources");
+                if resources.is_dir() {
+                    return resources;
                 }
             }
         }
@@ -1,10 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
 
 #[derive(Debug, Clone)]
 pub struct SearchMatch {
     pub line_number: usize,
+    pub column: usize, // Where the match starts, in chars
     pub line_content: String,
 }
 
@@ -15,20 +16,24 @@ pub struct SearchResult {
     pub matches: Vec<SearchMatch>,
 }
 
-pub fn search_workspace(root: &PathBuf, query: &str) -> Vec<SearchResult> {
+/// Every file and folder in the workspace, leaving out hidden ones and what git ignores.
+pub fn walk(root: &Path) -> ignore::Walk {
     use ignore::WalkBuilder;
-    use std::fs;
-
-    let query_lower = query.to_lowercase();
-    let mut results = Vec::new();
 
-    let walker = WalkBuilder::new(root)
+    WalkBuilder::new(root)
         .hidden(true)
         .git_ignore(true)
         .git_global(true)
-        .build();
+        .build()
+}
+
+pub fn search_workspace(root: &Path, query: &str) -> Vec<SearchResult> {
+    use std::fs;
+
+    let query_lower = query.to_lowercase();
+    let mut results = Vec::new();
 
-    for entry in walker.flatten() {
+    for entry in walk(root).flatten() {
         let path = entry.path();
 
         if !path.is_file() {
@@ -41,9 +46,11 @@ pub fn search_workspace(root: &PathBuf, query: &str) -> Vec<SearchResult> {
 
         let mut matches = Vec::new();
         for (line_idx, line) in content.lines().enumerate() {
-            if line.to_lowercase().contains(&query_lower) {
+            let line_lower = line.to_lowercase();
+            if let Some(start) = line_lower.find(&query_lower) {
                 matches.push(SearchMatch {
                     line_number: line_idx + 1,
+                    column: line_lower[..start].chars().count(),
                     line_content: line.to_string(),
                 });
             }
@@ -63,18 +70,31 @@ pub fn search_workspace(root: &PathBuf, query: &str) -> Vec<SearchResult> {
     results
 }
 
-pub fn collect_all_files(root: &PathBuf) -> Vec<(String, PathBuf)> {
-    use ignore::WalkBuilder;
+/// Puts places in files, like a symbol's references, in the shape search results come in:
+/// one result per file, in path order, with its matches from the top down.
+pub fn group_by_file(mut matches: Vec<(PathBuf, SearchMatch)>) -> Vec<SearchResult> {
+    matches.sort_by(|(a_path, a), (b_path, b)| {
+        a_path.cmp(b_path).then(a.line_number.cmp(&b.line_number)).then(a.column.cmp(&b.column))
+    });
+
+    let mut results: Vec<SearchResult> = Vec::new();
+    for (path, m) in matches {
+        match results.last_mut() {
+            Some(result) if result.path == path => result.matches.push(m),
+            _ => results.push(SearchResult {
+                file_name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                path,
+                matches: vec![m],
+            }),
+        }
+    }
+    results
+}
 
+pub fn collect_all_files(root: &Path) -> Vec<(String, PathBuf)> {
     let mut files = Vec::new();
 
-    let walker = WalkBuilder::new(root)
-        .hidden(true)
-        .git_ignore(true)
-        .git_global(true)
-        .build();
-
-    for entry in walker.flatten() {
+    for entry in walk(root).flatten() {
         let path = entry.path();
         if !path.is_file() {
             continue;
@@ -97,17 +117,28 @@ pub fn fuzzy_find_files(
     files: &[(String, PathBuf)],
     max_results: usize,
 ) -> Vec<(i64, String, PathBuf)> {
+    fuzzy_rank(query, files, |(display, _)| display, max_results)
+        .into_iter()
+        .map(|(score, (display, abs_path))| (score, display.clone(), abs_path.clone()))
+        .collect()
+}
+
+/// The best matches for `query` among `items`, going by the text `key` gives for each one,
+/// best first.
+pub fn fuzzy_rank<'a, T>(
+    query: &str,
+    items: &'a [T],
+    key: impl Fn(&T) -> &str,
+    max_results: usize,
+) -> Vec<(i64, &'a T)> {
     let matcher = SkimMatcherV2::default();
 
-    let mut scored: Vec<(i64, String, PathBuf)> = files
+    let mut scored: Vec<(i64, &T)> = items
         .iter()
-        .filter_map(|(display, abs_path)| {
-            matcher.fuzzy_match(display, query)
-                .map(|score| (score, display.clone(), abs_path.clone()))
-        })
+        .filter_map(|item| matcher.fuzzy_match(key(item), query).map(|score| (score, item)))
         .collect();
 
-    scored.sort_by(|a, b| b.0.cmp(&a.0));
+    scored.sort_by_key(|s| std::cmp::Reverse(s.0));
     scored.truncate(max_results);
     scored
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::config_dir;
+
+const SESSION_FILE: &str = "session.json";
+
+/// Editor state that should survive a restart, saved next to the user settings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// The first line of every folded region, per file
+    pub folds: HashMap<PathBuf, Vec<usize>>,
+}
+
+impl Session {
+    pub fn load() -> Self {
+        let Some(path) = config_dir().map(|dir| dir.join(SESSION_FILE)) else {
+            return Self::default();
+        };
+        let Ok(json) = fs::read_to_string(&path) else { return Self::default(); };
+        serde_json::from_str(&json).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let Some(dir) = config_dir() else { return; };
+        let Ok(json) = serde_json::to_string_pretty(self) else { return; };
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(SESSION_FILE), json)) {
+            eprintln!("Failed to save the session: {}", e);
+        }
+    }
+
+    /// Remembers which lines of `path` are folded, forgetting the file once nothing is.
+    pub fn set_folds(&mut self, path: PathBuf, lines: Vec<usize>) {
+        if lines.is_empty() {
+            self.folds.remove(&path);
+        } else {
+            self.folds.insert(path, lines);
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::Path;
+use std::str::Chars;
+
+use crate::completion::{CompletionItem, CompletionKind, CompletionProvider, Request};
+use crate::config::{self, language_id};
+use crate::document::{is_word_char, Document};
+use crate::syntax;
+
+const USER_SNIPPETS_DIR: &str = "snippets";
+const GLOBAL_SNIPPETS_EXTENSION: &str = "code-snippets";
+
+/// A snippet from a VS Code style snippets file.
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub name: String,
+    pub prefixes: Vec<String>,
+    pub body: String,
+    pub description: Option<String>,
+    /// The language ids it's offered in, or `None` for all of them
+    languages: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(one) => vec![one],
+            OneOrMany::Many(many) => many,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SnippetDefinition {
+    prefix: Option<OneOrMany>,
+    body: OneOrMany,
+    description: Option<OneOrMany>,
+    /// Comma separated language ids, only read from `.code-snippets` files
+    scope: Option<String>,
+}
+
+/// Every snippet Whistler knows about: the user's, the workspace's and the extensions'.
+#[derive(Debug, Default)]
+pub struct Snippets {
+    snippets: Vec<Snippet>,
+}
+
+impl Snippets {
+    /// Reads the snippets from extensions, then the user's, then the workspace's `.vscode` folder.
+    pub fn load(workspace: Option<&Path>) -> Self {
+        let mut snippets = Vec::new();
+
+        let extensions = crate::resources::resource_dir().join("extensions");
+        for package in fs::read_dir(&extensions).into_iter().flatten().flatten() {
+            for extension in fs::read_dir(package.path()).into_iter().flatten().flatten() {
+                snippets.extend(read_contributed(&extension.path()));
+            }
+        }
+
+        if let Some(dir) = config::config_dir().map(|dir| dir.join(USER_SNIPPETS_DIR)) {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                match path.extension().and_then(|e| e.to_str()) {
+                    // `rust.json` holds snippets for Rust only
+                    Some("json") => {
+                        let language = path.file_stem().and_then(|s| s.to_str()).map(str::to_string);
+                        snippets.extend(read_file(&path, language.map(|language| vec![language])));
+                    }
+                    Some(GLOBAL_SNIPPETS_EXTENSION) => snippets.extend(read_file(&path, None)),
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(workspace) = workspace {
+            for entry in fs::read_dir(workspace.join(".vscode")).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) == Some(GLOBAL_SNIPPETS_EXTENSION) {
+                    snippets.extend(read_file(&path, None));
+                }
+            }
+        }
+
+        Self { snippets }
+    }
+
+    /// The snippets offered in files with this extension.
+    pub fn for_extension(&self, extension: &str) -> impl Iterator<Item = &Snippet> {
+        let language = language_id(extension);
+        self.snippets.iter().filter(move |snippet| {
+            snippet.languages.as_ref().is_none_or(|languages| languages.contains(&language))
+        })
+    }
+
+    /// The snippet whose prefix the text before the cursor ends with, and how many chars
+    /// that prefix is. The longest prefix wins, and it has to start a word.
+    pub fn matching(&self, extension: &str, before: &str) -> Option<(&Snippet, usize)> {
+        self.for_extension(extension)
+            .flat_map(|snippet| snippet.prefixes.iter().map(move |prefix| (snippet, prefix)))
+            .filter(|(_, prefix)| !prefix.is_empty() && before.ends_with(prefix.as_str()))
+            .filter(|(_, prefix)| {
+                let rest = &before[..before.len() - prefix.len()];
+                let starts_word = prefix.chars().next().is_some_and(is_word_char);
+                !starts_word || !rest.chars().next_back().is_some_and(is_word_char)
+            })
+            .max_by_key(|(_, prefix)| prefix.len())
+            .map(|(snippet, prefix)| (snippet, prefix.chars().count()))
+    }
+}
+
+/// Snippets whose prefix looks like the word being typed.
+impl CompletionProvider for Snippets {
+    fn complete(&self, request: &Request) -> Vec<CompletionItem> {
+        let typed = request.word();
+        if typed.is_empty() && !request.explicit {
+            return Vec::new();
+        }
+        self.for_extension(request.extension)
+            .flat_map(|snippet| {
+                snippet.prefixes.iter().map(move |prefix| CompletionItem {
+                    label: prefix.clone(),
+                    kind: CompletionKind::Snippet,
+                    detail: Some(snippet.description.clone().unwrap_or_else(|| snippet.name.clone())),
+                    replace: typed.chars().count(),
+                    insert: snippet.body.clone(),
+                    snippet: true,
+                    ..CompletionItem::default()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the snippets an extension lists under `contributes.snippets` in its `package.json`.
+fn read_contributed(extension: &Path) -> Vec<Snippet> {
+    let Ok(json) = fs::read_to_string(extension.join("package.json")) else { return Vec::new(); };
+    let Ok(package) = serde_json::from_str::<Value>(&json) else { return Vec::new(); };
+    let Some(contributions) = package.pointer("/contributes/snippets").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    let mut snippets = Vec::new();
+    for contribution in contributions {
+        let Some(path) = contribution.get("path").and_then(Value::as_str) else { continue; };
+        let languages = match contribution.get("language") {
+            Some(Value::String(language)) => Some(vec![language.clone()]),
+            Some(Value::Array(languages)) => {
+                Some(languages.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            }
+            _ => None,
+        };
+        snippets.extend(read_file(&extension.join(path), languages));
+    }
+    snippets
+}
+
+/// Reads one snippets file. `languages` is who it's for when the file itself decides that,
+/// otherwise each snippet's `scope` does.
+fn read_file(path: &Path, languages: Option<Vec<String>>) -> Vec<Snippet> {
+    let Ok(json) = fs::read_to_string(path) else { return Vec::new(); };
+    let definitions: HashMap<String, SnippetDefinition> = match serde_json::from_str(&config::strip_jsonc(&json)) {
+        Ok(definitions) => definitions,
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    definitions
+        .into_iter()
+        .filter_map(|(name, definition)| {
+            let languages = languages.clone().or_else(|| {
+                definition
+                    .scope
+                    .map(|scope| scope.split(',').map(|language| language.trim().to_string()).collect())
+            });
+            Some(Snippet {
+                name,
+                prefixes: definition.prefix?.into_vec(),
+                body: definition.body.into_vec().join("\n"),
+                description: definition.description.map(|description| description.into_vec().join("\n")),
+                languages,
+            })
+        })
+        .collect()
+}
+
+/// Where a snippet is being expanded, for filling in variables like `$TM_FILENAME`.
+pub struct Context<'a> {
+    pub document: &'a Document,
+    pub path: &'a Path,
+    pub workspace: Option<&'a Path>,
+    pub extension: &'a str,
+}
+
+impl Context<'_> {
+    /// The value of a snippet variable, or `None` for ones Whistler doesn't know.
+    fn variable(&self, name: &str) -> Option<String> {
+        let document = self.document;
+        let cursor = document.cursor();
+        let now = chrono::Local::now();
+        let comments = || syntax::comment_tokens(self.extension);
+
+        let value = match name {
+            "TM_SELECTED_TEXT" => document.selected_text().unwrap_or_default(),
+            "TM_CURRENT_LINE" => document.line(cursor.line).to_string(),
+            "TM_CURRENT_WORD" => {
+                let (start, end) = document.word_bounds(cursor);
+                document.text_range(start, end)
+            }
+            "TM_LINE_INDEX" => cursor.line.to_string(),
+            "TM_LINE_NUMBER" => (cursor.line + 1).to_string(),
+            "TM_FILENAME" => self.path.file_name()?.to_string_lossy().into_owned(),
+            "TM_FILENAME_BASE" => self.path.file_stem()?.to_string_lossy().into_owned(),
+            "TM_DIRECTORY" => self.path.parent()?.to_string_lossy().into_owned(),
+            "TM_FILEPATH" => self.path.to_string_lossy().into_owned(),
+            "RELATIVE_FILEPATH" => match self.workspace.and_then(|root| self.path.strip_prefix(root).ok()) {
+                Some(relative) => relative.to_string_lossy().into_owned(),
+                None => self.path.to_string_lossy().into_owned(),
+            },
+            "WORKSPACE_NAME" => self.workspace?.file_name()?.to_string_lossy().into_owned(),
+            "WORKSPACE_FOLDER" => self.workspace?.to_string_lossy().into_owned(),
+            "CURRENT_YEAR" => now.format("%Y").to_string(),
+            "CURRENT_YEAR_SHORT" => now.format("%y").to_string(),
+            "CURRENT_MONTH" => now.format("%m").to_string(),
+            "CURRENT_MONTH_NAME" => now.format("%B").to_string(),
+            "CURRENT_MONTH_NAME_SHORT" => now.format("%b").to_string(),
+            "CURRENT_DATE" => now.format("%d").to_string(),
+            "CURRENT_DAY_NAME" => now.format("%A").to_string(),
+            "CURRENT_DAY_NAME_SHORT" => now.format("%a").to_string(),
+            "CURRENT_HOUR" => now.format("%H").to_string(),
+            "CURRENT_MINUTE" => now.format("%M").to_string(),
+            "CURRENT_SECOND" => now.format("%S").to_string(),
+            "CURRENT_SECONDS_UNIX" => now.timestamp().to_string(),
+            "CURRENT_TIMEZONE_OFFSET" => now.format("%:z").to_string(),
+            "LINE_COMMENT" => comments().line?.trim().to_string(),
+            "BLOCK_COMMENT_START" => comments().block?.0.trim().to_string(),
+            "BLOCK_COMMENT_END" => comments().block?.1.trim().to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// A snippet body with its variables filled in, ready to insert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    /// The char ranges of each tab stop, in the order Tab visits them with `$0` last.
+    /// A stop with several ranges is mirrored: typing in one types in all of them.
+    pub tab_stops: Vec<Vec<Range<usize>>>,
+}
+
+impl Expansion {
+    /// Parses a snippet body like `for ${1:i} in $2 {\n\t$0\n}`.
+    pub fn parse(body: &str, context: &Context) -> Self {
+        // The first pass finds each stop's placeholder, so the second can copy it into mirrors
+        // that come before it, like the `$1` in `$1 = ${1:value}`
+        let mut first = Parser::new(body, context, HashMap::new());
+        first.parse(None);
+        let mut parser = Parser::new(body, context, first.placeholders);
+        parser.parse(None);
+
+        let end = parser.length;
+        let mut stops: Vec<(usize, Vec<Range<usize>>)> = parser.stops.into_iter().collect();
+        stops.sort_by_key(|(index, _)| if *index == 0 { usize::MAX } else { *index });
+        if stops.last().is_none_or(|(index, _)| *index != 0) {
+            // Without a `$0` the cursor ends up after the snippet
+            stops.push((0, std::iter::once(end..end).collect()));
+        }
+        Self { text: parser.text, tab_stops: stops.into_iter().map(|(_, ranges)| ranges).collect() }
+    }
+
+    /// Text with no tab stops, that leaves the cursor after it.
+    pub fn plain(text: &str) -> Self {
+        let end = text.chars().count();
+        Self { text: text.to_string(), tab_stops: vec![std::iter::once(end..end).collect()] }
+    }
+
+    /// Indents every line after the first to match the line the snippet goes on, and turns
+    /// the body's tabs into the document's indentation.
+    pub fn indented(&self, leading: &str, unit: &str) -> Self {
+        let mut text = String::new();
+        // Where each char of the old text ended up, plus the end
+        let mut moved = Vec::with_capacity(self.text.len() + 1);
+        let mut length = 0;
+        for c in self.text.chars() {
+            moved.push(length);
+            let replacement = match c {
+                '\n' => format!("\n{}", leading),
+                '\t' => unit.to_string(),
+                c => c.to_string(),
+            };
+            length += replacement.chars().count();
+            text.push_str(&replacement);
+        }
+        moved.push(length);
+
+        let tab_stops = self
+            .tab_stops
+            .iter()
+            .map(|ranges| ranges.iter().map(|range| moved[range.start]..moved[range.end]).collect())
+            .collect();
+        Self { text, tab_stops }
+    }
+}
+
+/// Reads snippet syntax, writing out the text and noting where each tab stop lands.
+struct Parser<'a, 'b> {
+    chars: Peekable<Chars<'a>>,
+    context: &'b Context<'b>,
+    text: String,
+    length: usize, // Of `text`, in chars
+    stops: HashMap<usize, Vec<Range<usize>>>,
+    placeholders: HashMap<usize, String>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn new(body: &'a str, context: &'b Context<'b>, placeholders: HashMap<usize, String>) -> Self {
+        Self { chars: body.chars().peekable(), context, text: String::new(), length: 0, stops: HashMap::new(), placeholders }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.length += text.chars().count();
+    }
+
+    /// Reads until the `}` closing a placeholder, or the end when `closing` is `None`.
+    fn parse(&mut self, closing: Option<char>) {
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => match self.chars.peek() {
+                    Some(&escaped @ ('$' | '}' | '\\')) => {
+                        self.chars.next();
+                        self.push(&escaped.to_string());
+                    }
+                    _ => self.push("\\"),
+                },
+                '$' => self.dollar(),
+                c if Some(c) == closing => return,
+                c => self.push(&c.to_string()),
+            }
+        }
+    }
+
+    /// Everything that starts with `$`: tab stops, placeholders, choices and variables.
+    fn dollar(&mut self) {
+        match self.chars.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let index = self.number();
+                self.mirror(index);
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                let name = self.name();
+                self.variable(&name, false);
+            }
+            Some('{') => {
+                self.chars.next();
+                match self.chars.peek() {
+                    Some(c) if c.is_ascii_digit() => {
+                        let index = self.number();
+                        self.braced_stop(index);
+                    }
+                    Some(c) if c.is_alphabetic() || *c == '_' => {
+                        let name = self.name();
+                        self.variable(&name, true);
+                    }
+                    _ => self.push("${"),
+                }
+            }
+            _ => self.push("$"),
+        }
+    }
+
+    fn number(&mut self) -> usize {
+        let mut number = 0usize;
+        while let Some(digit) = self.chars.peek().and_then(|c| c.to_digit(10)) {
+            number = number.saturating_mul(10).saturating_add(digit as usize);
+            self.chars.next();
+        }
+        number
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+            name.push(c);
+            self.chars.next();
+        }
+        name
+    }
+
+    /// A stop with nothing of its own, which shows its placeholder if it has one elsewhere.
+    fn mirror(&mut self, index: usize) {
+        let start = self.length;
+        if let Some(placeholder) = self.placeholders.get(&index).cloned() {
+            self.push(&placeholder);
+        }
+        self.stops.entry(index).or_default().push(start..self.length);
+    }
+
+    /// What follows `${1`: `}`, `:placeholder}`, `|one,two|}` or a `/transform/}`.
+    fn braced_stop(&mut self, index: usize) {
+        let start = self.length;
+        match self.chars.next() {
+            Some(':') => {
+                self.parse(Some('}'));
+                let placeholder = self.text.chars().skip(start).collect::<String>();
+                if !placeholder.is_empty() {
+                    self.placeholders.entry(index).or_insert(placeholder);
+                }
+                self.stops.entry(index).or_default().push(start..self.length);
+            }
+            Some('|') => {
+                // There's no picker for the options, so the first one goes in and gets selected
+                let choices = self.choices();
+                if let Some(first) = choices.first() {
+                    self.push(first);
+                    self.placeholders.entry(index).or_insert(first.clone());
+                }
+                self.stops.entry(index).or_default().push(start..self.length);
+            }
+            Some('/') => {
+                // Transforms aren't supported, the stop just mirrors as it is
+                self.skip_transform();
+                self.mirror(index);
+            }
+            _ => self.mirror(index),
+        }
+    }
+
+    /// The options of a choice, up to and including its closing `|}`.
+    fn choices(&mut self) -> Vec<String> {
+        let mut choices = vec![String::new()];
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(escaped) = self.chars.next() {
+                        choices.last_mut().unwrap().push(escaped);
+                    }
+                }
+                ',' => choices.push(String::new()),
+                '|' if self.chars.peek() == Some(&'}') => {
+                    self.chars.next();
+                    break;
+                }
+                c => choices.last_mut().unwrap().push(c),
+            }
+        }
+        choices
+    }
+
+    /// Skips the rest of `/regex/format/options}`.
+    fn skip_transform(&mut self) {
+        let mut slashes = 1;
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => {
+                    self.chars.next();
+                }
+                '/' => slashes += 1,
+                '}' if slashes >= 3 => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// A variable, filled in from the context. Unknown ones show their name, like in VS Code.
+    fn variable(&mut self, name: &str, braced: bool) {
+        let value = self.context.variable(name);
+        if !braced {
+            self.push(value.as_deref().unwrap_or(name));
+            return;
+        }
+        match self.chars.next() {
+            Some(':') => match value {
+                // The default is only for when the variable is empty or unknown
+                Some(value) if !value.is_empty() => {
+                    let (text, length, stops) = (self.text.clone(), self.length, self.stops.clone());
+                    self.parse(Some('}'));
+                    (self.text, self.length, self.stops) = (text, length, stops);
+                    self.push(&value);
+                }
+                _ => self.parse(Some('}')),
+            },
+            Some('/') => {
+                self.skip_transform();
+                self.push(value.as_deref().unwrap_or(name));
+            }
+            _ => self.push(value.as_deref().unwrap_or(name)),
+        }
+    }
+}
+
@@ -0,0 +1,150 @@
+//! The workspace symbol index behind the `#` symbol finder. Universal Ctags fills it when it's
+//! installed. Otherwise it goes by the syntect grammars the highlighter already loads, in place
+//! of tree-sitter queries, so every language that gets colored gets indexed without a parser
+//! and a query file per language. The grammars only mark names being defined with
+//! `entity.name` scopes, one line at a time, so this finds fewer symbols than a parser would:
+//! - nothing in languages without a bundled grammar, or whose grammar doesn't scope definitions
+//! - kinds only as fine as the scope names, e.g. a method can come out as a function
+//! - no nesting, so a symbol doesn't know what it's defined inside of
+//! - nothing a macro defines
+
+use lsp_types::SymbolKind;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::document::Position;
+use crate::outline::kind_of_scope;
+use crate::search;
+use crate::syntax;
+
+/// Files bigger than this are left out when going by the grammar, they're usually generated.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Something defined somewhere in the workspace, for the `#` symbol finder.
+#[derive(Debug, Clone)]
+pub struct IndexedSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub path: PathBuf,
+    /// Where its name is
+    pub at: Position,
+}
+
+/// Whether Universal Ctags is installed. Other ctags don't have JSON output.
+static HAS_CTAGS: Lazy<bool> = Lazy::new(|| {
+    Command::new("ctags")
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("Universal Ctags"))
+});
+
+/// Finds the definitions in a folder, walked the way the file finder walks it, or in one file.
+/// Universal Ctags does it if it's installed, and the grammars do it if not. This reads every
+/// file, so it's for a background thread.
+pub fn index(path: &Path) -> Vec<IndexedSymbol> {
+    let files: Vec<PathBuf> = if path.is_dir() {
+        search::walk(path)
+            .flatten()
+            .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+            .map(|entry| entry.into_path())
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    if *HAS_CTAGS {
+        match with_ctags(&files) {
+            Ok(symbols) => return symbols,
+            Err(e) => eprintln!("ctags: {}", e),
+        }
+    }
+    files.iter().flat_map(|file| with_syntax(file)).collect()
+}
+
+/// A line of ctags' JSON output. Lines for anything other than tags have no name.
+#[derive(Deserialize)]
+struct Tag {
+    name: Option<String>,
+    path: Option<PathBuf>,
+    line: Option<usize>,
+    kind: Option<String>,
+    pattern: Option<String>,
+}
+
+fn with_ctags(files: &[PathBuf]) -> Result<Vec<IndexedSymbol>, String> {
+    let mut child = Command::new("ctags")
+        .args(["--output-format=json", "--fields=+nK", "--extras=-F", "-f", "-", "-L", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    // It reads the list of files from stdin while it writes tags, so the list goes in from a thread of its own
+    let list: String = files.iter().map(|file| format!("{}\n", file.display())).collect();
+    let mut stdin = child.stdin.take().ok_or("no stdin")?;
+    std::thread::spawn(move || {
+        let _ = stdin.write_all(list.as_bytes());
+    });
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+
+    let symbols = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let tag: Tag = serde_json::from_str(line).ok()?;
+            let (name, path, line) = (tag.name?, tag.path?, tag.line?.checked_sub(1)?);
+            let kind = kind_of_tag(tag.kind.as_deref()?)?;
+            // The pattern is the line it's on, which is where the column comes from
+            let column = tag
+                .pattern
+                .map(|pattern| {
+                    let text = pattern.trim_start_matches("/^").trim_end_matches('/').trim_end_matches('$').replace("\\/", "/");
+                    text.find(&name).map_or(0, |start| text[..start].chars().count())
+                })
+                .unwrap_or(0);
+            Some(IndexedSymbol { name, kind, path, at: Position::new(line, column) })
+        })
+        .collect();
+    Ok(symbols)
+}
+
+/// The kind of symbol a ctags kind is, going by the same words the grammars' scopes use.
+/// Variables, fields and the like aren't indexed.
+fn kind_of_tag(kind: &str) -> Option<SymbolKind> {
+    let kind = match kind {
+        "member" => "method",
+        "typedef" | "alias" => "type",
+        "implementation" => "impl",
+        "package" => "module",
+        kind => kind,
+    };
+    kind_of_scope(kind)
+}
+
+fn with_syntax(file: &Path) -> Vec<IndexedSymbol> {
+    if std::fs::metadata(file).map_or(true, |metadata| metadata.len() > MAX_FILE_SIZE) {
+        return Vec::new();
+    }
+    // Binary files don't read as text, so they're skipped too
+    let Ok(text) = std::fs::read_to_string(file) else { return Vec::new(); };
+    let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let lines: Vec<String> = text.lines().map(String::from).collect();
+    syntax::defined_names(extension, &lines)
+        .into_iter()
+        .filter_map(|(line, range, scope)| {
+            let kind = kind_of_scope(&scope)?;
+            let column = lines[line][..range.start].chars().count();
+            Some(IndexedSymbol {
+                name: lines[line][range].trim().to_string(),
+                kind,
+                path: file.to_path_buf(),
+                at: Position::new(line, column),
+            })
+        })
+        .collect()
+}
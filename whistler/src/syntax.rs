@@ -2,6 +2,7 @@ use iced::advanced::text::highlighter;
 use iced::advanced::text::highlighter::Highlighter as IcedHighlighter;
 use iced::{Color, Font};
 
+use once_cell::sync::Lazy;
 use syntect::highlighting::{
     HighlightIterator,
     HighlightState,
@@ -11,7 +12,9 @@ use syntect::highlighting::{
 };
 use syntect::parsing::{
     ParseState,
+    Scope,
     ScopeStack,
+    SyntaxReference,
     SyntaxSet
 };
 
@@ -20,6 +23,25 @@ use std::sync::Arc;
 
 use crate::theme::THEME;
 
+/// Loading the bundled grammars takes a while, so every highlighter shares one set.
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// Names people write after a Markdown fence that syntect doesn't know by itself.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("shell", "sh"),
+    ("console", "sh"),
+    ("zsh", "sh"),
+    ("c++", "cpp"),
+    ("golang", "go"),
+    ("jsonc", "json"),
+    ("yml", "yaml"),
+    ("jsx", "js"),
+    // No TypeScript grammar ships with syntect, JavaScript is the closest match
+    ("ts", "js"),
+    ("tsx", "js"),
+    ("typescript", "js"),
+];
+
 #[derive(Clone, PartialEq)]
 pub struct Settings {
     pub extension: String, // The file extension, e.g. "rs", "py", "js" to pick the syntax grammar
@@ -37,53 +59,524 @@ impl Highlight {
     }
 }
 
+/// Finds a grammar by file extension first, then by language name or alias.
+/// This is what lets both `main.rs` and a ```rust fence resolve to Rust.
+fn find_syntax(token: &str) -> Option<&'static SyntaxReference> {
+    let token = token.trim().to_lowercase();
+    if token.is_empty() {
+        return None;
+    }
+    let token = LANGUAGE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == token)
+        .map(|(_, target)| target.to_string())
+        .unwrap_or(token);
+
+    SYNTAX_SET
+        .find_syntax_by_extension(&token)
+        .or_else(|| SYNTAX_SET.find_syntax_by_token(&token))
+}
+
+/// Languages that can contain regions written in another language.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Host {
+    Plain,
+    Markdown,
+    Html,
+}
+
+impl Host {
+    fn for_syntax(syntax: &SyntaxReference) -> Self {
+        if syntax.name == "Markdown" {
+            Host::Markdown
+        } else if syntax.name.starts_with("HTML") {
+            Host::Html
+        } else {
+            Host::Plain
+        }
+    }
+}
+
+/// What closes an embedded region.
+#[derive(Clone)]
+enum InjectionEnd {
+    /// A Markdown fence, e.g. "```" or "~~~~"
+    Fence(String),
+    /// An HTML closing tag, e.g. "</script"
+    Tag(&'static str),
+}
+
+/// A region of the file that is highlighted with a different grammar than the host.
+#[derive(Clone)]
+struct Injection {
+    parse: ParseState,
+    highlight: HighlightState,
+    end: InjectionEnd,
+}
+
+impl Injection {
+    fn new(syntax: &SyntaxReference, end: InjectionEnd, theme: &SynTheme) -> Self {
+        let highlighter = SyntectHighlighter::new(theme);
+        Self {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+            end,
+        }
+    }
+}
+
+/// Everything needed to resume highlighting at the start of a line.
+#[derive(Clone)]
+struct LineState {
+    parse: ParseState,
+    highlight: HighlightState,
+    injection: Option<Injection>,
+}
+
+impl LineState {
+    fn new(syntax: &SyntaxReference, theme: &SynTheme) -> Self {
+        let highlighter = SyntectHighlighter::new(theme);
+        Self {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+            injection: None,
+        }
+    }
+}
+
 pub struct VscodeHighlighter {
-    syntax_set: SyntaxSet,
     theme: Arc<SynTheme>,
     syntax_name: String,
-    parse_states: Vec<(ParseState, HighlightState)>,
+    host: Host,
+    parse_states: Vec<LineState>,
     current_line: usize,
 }
 
+impl VscodeHighlighter {
+    fn reset(&mut self, settings: &Settings) {
+        let syntax = find_syntax(&settings.extension)
+            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+        self.syntax_name = syntax.name.clone();
+        self.host = Host::for_syntax(syntax);
+        self.parse_states = vec![LineState::new(syntax, &self.theme)];
+        self.current_line = 0;
+    }
+
+    /// Highlights a Markdown line, switching to the fenced language between the fences.
+    fn highlight_markdown_line(&self, state: &mut LineState, line: &str, out: &mut Vec<(Range<usize>, Highlight)>) {
+        if let Some(injection) = state.injection.as_mut() {
+            let closes = matches!(&injection.end, InjectionEnd::Fence(marker) if is_closing_fence(line, marker));
+            if !closes {
+                highlight_segment(&mut injection.parse, &mut injection.highlight, &self.theme, line, 0, true, out);
+                return;
+            }
+            state.injection = None;
+        }
+
+        highlight_segment(&mut state.parse, &mut state.highlight, &self.theme, line, 0, true, out);
+
+        if let Some((marker, language)) = parse_opening_fence(line)
+            && let Some(syntax) = find_syntax(&language)
+        {
+            state.injection = Some(Injection::new(syntax, InjectionEnd::Fence(marker), &self.theme));
+        }
+    }
+
+    /// Highlights an HTML line, handing `<script>` and `<style>` bodies to their own grammars.
+    /// A line can switch back and forth several times, e.g. `<style>a{}</style>`.
+    fn highlight_html_line(&self, state: &mut LineState, line: &str, out: &mut Vec<(Range<usize>, Highlight)>) {
+        let mut offset = 0;
+        while offset < line.len() {
+            let rest = &line[offset..];
+
+            if let Some(injection) = state.injection.as_mut() {
+                let InjectionEnd::Tag(tag) = injection.end else { break; };
+                match find_ignore_case(rest, tag) {
+                    Some(pos) => {
+                        if pos > 0 {
+                            highlight_segment(&mut injection.parse, &mut injection.highlight, &self.theme, &rest[..pos], offset, false, out);
+                        }
+                        state.injection = None;
+                        offset += pos;
+                    }
+                    None => {
+                        highlight_segment(&mut injection.parse, &mut injection.highlight, &self.theme, rest, offset, true, out);
+                        offset = line.len();
+                    }
+                }
+                continue;
+            }
+
+            match find_embedded_tag(rest) {
+                Some((tag_end, language, closing)) => {
+                    let is_last = tag_end == rest.len();
+                    highlight_segment(&mut state.parse, &mut state.highlight, &self.theme, &rest[..tag_end], offset, is_last, out);
+                    if let Some(syntax) = find_syntax(language) {
+                        state.injection = Some(Injection::new(syntax, InjectionEnd::Tag(closing), &self.theme));
+                    }
+                    offset += tag_end;
+                }
+                None => {
+                    highlight_segment(&mut state.parse, &mut state.highlight, &self.theme, rest, offset, true, out);
+                    offset = line.len();
+                }
+            }
+        }
+
+        // An empty line still has to advance the parser so multi-line constructs stay intact
+        if line.is_empty() {
+            match state.injection.as_mut() {
+                Some(injection) => highlight_segment(&mut injection.parse, &mut injection.highlight, &self.theme, line, 0, true, out),
+                None => highlight_segment(&mut state.parse, &mut state.highlight, &self.theme, line, 0, true, out),
+            }
+        }
+    }
+}
+
+/// Runs one grammar over `text` and appends the colored ranges, shifted by `offset`.
+/// `ends_line` adds back the newline the grammars expect at the end of a line.
+fn highlight_segment(
+    parse_state: &mut ParseState,
+    highlight_state: &mut HighlightState,
+    theme: &SynTheme,
+    text: &str,
+    offset: usize,
+    ends_line: bool,
+    out: &mut Vec<(Range<usize>, Highlight)>,
+) {
+    let highlighter = SyntectHighlighter::new(theme);
+    let source = if ends_line { format!("{}\n", text) } else { text.to_string() };
+
+    let ops = parse_state
+        .parse_line(&source, &SYNTAX_SET)
+        .unwrap_or_default();
+
+    let ranges: Vec<(Style, &str)> =
+        HighlightIterator::new(highlight_state, &ops, &source, &highlighter)
+            .collect();
+
+    let text_len = text.len();
+    let mut start = 0;
+    for (style, piece) in ranges {
+        if start >= text_len {
+            break;
+        }
+        let end = (start + piece.len()).min(text_len);
+        let color = Color::from_rgba8(
+            style.foreground.r,
+            style.foreground.g,
+            style.foreground.b,
+            style.foreground.a as f32 / 255.0,
+        );
+        out.push((offset + start..offset + end, Highlight(color)));
+        start += piece.len();
+    }
+}
+
+/// Returns the fence marker and language of a line like "```rust" or "~~~ {.python}".
+fn parse_opening_fence(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None; // Indented four or more spaces is a code block, not a fence
+    }
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let marker_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+    if marker_len < 3 {
+        return None;
+    }
+    let info = trimmed[marker_len..].trim();
+    let language: String = info
+        .trim_start_matches(['{', '.'])
+        .chars()
+        .take_while(|c| !c.is_whitespace() && !matches!(c, ',' | '{' | '}'))
+        .collect();
+    if language.is_empty() {
+        return None;
+    }
+    Some((trimmed[..marker_len].to_string(), language))
+}
+
+fn is_closing_fence(line: &str, marker: &str) -> bool {
+    let trimmed = line.trim();
+    let Some(fence_char) = marker.chars().next() else { return false; };
+    let run = trimmed.chars().take_while(|c| *c == fence_char).count();
+    run >= marker.len() && trimmed.chars().all(|c| c == fence_char)
+}
+
+/// Finds the next `<script ...>` or `<style ...>` opening tag in `text`.
+/// Returns where the tag ends, the language of its body and the tag that closes it.
+fn find_embedded_tag(text: &str) -> Option<(usize, &'static str, &'static str)> {
+    let script = find_ignore_case(text, "<script").map(|pos| (pos, "</script"));
+    let style = find_ignore_case(text, "<style").map(|pos| (pos, "</style"));
+    let (start, closing) = match (script, style) {
+        (Some(a), Some(b)) => if a.0 < b.0 { a } else { b },
+        (Some(a), None) => a,
+        (None, Some(b)) => b,
+        (None, None) => return None,
+    };
+    let tag_end = start + text[start..].find('>')? + 1;
+    let tag = text[start..tag_end].to_lowercase();
+
+    let language = if closing == "</style" {
+        "css"
+    } else if tag.contains("json") {
+        "json"
+    } else if tag.contains("type=") && !tag.contains("javascript") && !tag.contains("module") {
+        // Templates and other opaque script types stay host-highlighted
+        return Some((tag_end, "", closing));
+    } else {
+        "js"
+    };
+    Some((tag_end, language, closing))
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Highlights a standalone snippet, e.g. a code block in the Markdown preview.
+/// Returns the colored ranges of every line.
+pub fn highlight_code(language: &str, code: &str) -> Vec<Vec<(Range<usize>, Highlight)>> {
+    let mut highlighter = VscodeHighlighter::new(&Settings {
+        extension: language.to_string(),
+    });
+    code.lines()
+        .map(|line| highlighter.highlight_line(line).collect())
+        .collect()
+}
+
+/// Scopes whose brackets don't count, since they're part of text rather than code.
+static NON_CODE_SCOPES: Lazy<[Scope; 2]> =
+    Lazy::new(|| [Scope::new("string").unwrap(), Scope::new("comment").unwrap()]);
+
+/// Finds bracket pairs that span several lines, as `(opening line, closing line)`.
+///
+/// Brackets inside strings and comments are skipped, going by the grammar's scopes.
+pub fn bracket_blocks(extension: &str, lines: &[String]) -> Vec<(usize, usize)> {
+    let Some(syntax) = find_syntax(extension) else { return Vec::new(); };
+    let mut parse = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut blocks = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let text = format!("{line}\n");
+        let Ok(ops) = parse.parse_line(&text, &SYNTAX_SET) else { break; };
+
+        let mut scan = |range: Range<usize>, stack: &ScopeStack| {
+            let in_text = stack
+                .as_slice()
+                .iter()
+                .any(|scope| NON_CODE_SCOPES.iter().any(|prefix| prefix.is_prefix_of(*scope)));
+            if in_text {
+                return;
+            }
+            for c in text[range].chars() {
+                match c {
+                    '{' | '[' | '(' => open.push((c, index)),
+                    '}' | ']' | ')' => {
+                        let opener = match c { '}' => '{', ']' => '[', _ => '(' };
+                        if open.last().is_some_and(|(last, _)| *last == opener) {
+                            let (_, start) = open.pop().unwrap();
+                            if index > start {
+                                blocks.push((start, index));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        };
+
+        let mut last = 0;
+        for (offset, op) in ops {
+            scan(last..offset, &stack);
+            if stack.apply(&op).is_err() {
+                break;
+            }
+            last = offset;
+        }
+        scan(last..text.len(), &stack);
+    }
+    blocks
+}
+
+/// The scope grammars put on the names of things being defined, like functions and classes.
+static DEFINED_NAME_SCOPE: Lazy<Scope> = Lazy::new(|| Scope::new("entity.name").unwrap());
+
+/// The names the grammar says are being defined, as `(line, byte range, scope)` with the whole
+/// scope, e.g. `entity.name.function.rust`.
+pub fn defined_names(extension: &str, lines: &[String]) -> Vec<(usize, Range<usize>, String)> {
+    let Some(syntax) = find_syntax(extension) else { return Vec::new(); };
+    let mut parse = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut names = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let text = format!("{line}\n");
+        let Ok(ops) = parse.parse_line(&text, &SYNTAX_SET) else { break; };
+        // The name being read and its scope, which can take up several pieces between ops
+        let mut current: Option<(Range<usize>, Scope)> = None;
+        let mut last = 0;
+        for next in ops.into_iter().map(Some).chain([None]) {
+            let end = next.as_ref().map_or(line.len(), |(offset, _)| (*offset).min(line.len()));
+            let scope = stack.as_slice().iter().rev().find(|scope| DEFINED_NAME_SCOPE.is_prefix_of(**scope)).copied();
+            match (scope, &mut current) {
+                (Some(scope), Some((range, open))) if *open == scope && range.end == last => range.end = end,
+                _ => {
+                    names.extend(current.take().map(|(range, scope)| (index, range, scope.build_string())));
+                    current = scope.map(|scope| (last..end, scope));
+                }
+            }
+            let Some((offset, op)) = next else { break; };
+            if stack.apply(&op).is_err() {
+                break;
+            }
+            last = offset.min(line.len());
+        }
+        names.extend(current.take().map(|(range, scope)| (index, range, scope.build_string())));
+    }
+    names.retain(|(index, range, _)| !lines[*index][range.clone()].trim().is_empty());
+    names
+}
+
+/// Brackets every language closes as you type.
+const AUTO_CLOSING_BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Quotes that close as you type, by grammar name. Anything not listed only closes `"`.
+const AUTO_CLOSING_QUOTES: &[(&str, &str)] = &[
+    ("Rust", "\""), // `'` also starts lifetimes
+    ("Python", "\"'"),
+    ("JavaScript", "\"'`"),
+    ("HTML", "\"'"),
+    ("XML", "\"'"),
+    ("CSS", "\"'"),
+    ("PHP", "\"'`"),
+    ("Ruby", "\"'`"),
+    ("Go", "\"'`"),
+    ("C", "\"'"),
+    ("C++", "\"'"),
+    ("C#", "\"'"),
+    ("Java", "\"'"),
+    ("YAML", "\"'"),
+    ("SQL", "\"'"),
+    ("Bourne Again Shell (bash)", "\"'`"),
+    ("Markdown", "`"),
+    ("Plain Text", ""),
+];
+
+/// Extensions where typing the `>` of an opening tag adds the closing tag.
+const TAG_CLOSING_EXTENSIONS: &[&str] = &["html", "htm", "xhtml", "xml", "svg", "vue", "svelte", "jsx", "tsx"];
+
+/// The pairs that get closed automatically as you type in files with this extension,
+/// like the `autoClosingPairs` of a VS Code language configuration.
+pub fn auto_closing_pairs(extension: &str) -> Vec<(char, char)> {
+    let name = find_syntax(extension).map_or("", |syntax| syntax.name.as_str());
+    let quotes = AUTO_CLOSING_QUOTES.iter().find(|(grammar, _)| *grammar == name).map_or("\"", |(_, quotes)| quotes);
+    if name == "Plain Text" || name.is_empty() {
+        return Vec::new();
+    }
+    AUTO_CLOSING_BRACKETS.into_iter().chain(quotes.chars().map(|quote| (quote, quote))).collect()
+}
+
+/// HTML elements that never have a closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// The closing tag to add once `>` is typed after `before`, if that finishes an opening tag.
+pub fn closing_tag(extension: &str, before: &str) -> Option<String> {
+    let extension = extension.to_lowercase();
+    if !TAG_CLOSING_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    let open = before.rfind('<')?;
+    let tag = &before[open + 1..];
+    // Closing and self-closing tags, comments and `<!DOCTYPE>` don't get one
+    if tag.ends_with('/') || tag.starts_with(['/', '!', '?']) {
+        return None;
+    }
+    // `Array<string>` in a .tsx file is a type, not a tag
+    if before[..open].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let name: String = tag.chars().take_while(|c| c.is_alphanumeric() || matches!(c, '-' | ':' | '.' | '_')).collect();
+    let is_jsx = matches!(extension.as_str(), "jsx" | "tsx");
+    if name.is_empty() {
+        // `<>` opens a fragment in JSX
+        return (is_jsx && tag.is_empty()).then(|| "</>".to_string());
+    }
+    if !name.starts_with(char::is_alphabetic) || (!is_jsx && VOID_ELEMENTS.contains(&name.to_lowercase().as_str())) {
+        return None;
+    }
+    Some(format!("</{name}>"))
+}
+
+/// Whether `column` of a line is inside a string or a comment. Only the line itself is
+/// parsed, which is enough to tell whether a quote opens a string or ends one.
+pub fn in_string_or_comment(extension: &str, line: &str, column: usize) -> bool {
+    let Some(syntax) = find_syntax(extension) else { return false; };
+    let mut parse = ParseState::new(syntax);
+    let text = format!("{line}\n");
+    let Ok(ops) = parse.parse_line(&text, &SYNTAX_SET) else { return false; };
+    let byte = line.char_indices().nth(column).map_or(line.len(), |(byte, _)| byte);
+
+    let mut stack = ScopeStack::new();
+    for (offset, op) in ops {
+        // Scope changes right at the cursor count, so just after a closing quote is outside the string
+        if offset > byte {
+            break;
+        }
+        if stack.apply(&op).is_err() {
+            return false;
+        }
+    }
+    stack
+        .as_slice()
+        .iter()
+        .any(|scope| NON_CODE_SCOPES.iter().any(|prefix| prefix.is_prefix_of(*scope)))
+}
+
+/// How a language writes comments, from the `TM_COMMENT_START` and `TM_COMMENT_END`
+/// shell variables in its grammar's metadata.
+#[derive(Debug, Clone, Default)]
+pub struct CommentTokens {
+    /// e.g. `"// "`, with the space that goes after it
+    pub line: Option<String>,
+    pub block: Option<(String, String)>,
+}
+
+pub fn comment_tokens(extension: &str) -> CommentTokens {
+    let Some(syntax) = find_syntax(extension) else { return CommentTokens::default(); };
+    let metadata = SYNTAX_SET.metadata().metadata_for_scope(&[syntax.scope]);
+    CommentTokens {
+        line: metadata.line_comment().map(str::to_string),
+        block: metadata.block_comment().map(|(open, close)| (open.to_string(), close.to_string())),
+    }
+}
+
 impl IcedHighlighter for VscodeHighlighter {
     type Settings = Settings;
     type Highlight = Highlight;
     type Iterator<'a> = Box<dyn Iterator<Item = (Range<usize>, Self::Highlight)> + 'a>;
 
     fn new(settings: &Self::Settings) -> Self {
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme = Arc::new(THEME.syntax_theme.clone());
-
-        let syntax = syntax_set
-            .find_syntax_by_extension(&settings.extension)
-            .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
-        let syntax_name = syntax.name.clone();
-
-        let highlighter = SyntectHighlighter::new(&theme);
-        let initial_parse = ParseState::new(syntax);
-        let initial_highlight = HighlightState::new(&highlighter, ScopeStack::new());
-
-        Self {
-            syntax_set,
-            theme,
-            syntax_name,
-            parse_states: vec![(initial_parse, initial_highlight)],
+        let mut highlighter = Self {
+            theme: Arc::new(THEME.syntax_theme.clone()),
+            syntax_name: String::new(),
+            host: Host::Plain,
+            parse_states: Vec::new(),
             current_line: 0,
-        }
+        };
+        highlighter.reset(settings);
+        highlighter
     }
 
     fn update(&mut self, new_settings: &Self::Settings) {
-        let syntax = self.syntax_set
-            .find_syntax_by_extension(&new_settings.extension)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        self.syntax_name = syntax.name.clone();
-
-        let highlighter = SyntectHighlighter::new(&self.theme);
-        let initial_parse = ParseState::new(syntax);
-        let initial_highlight = HighlightState::new(&highlighter, ScopeStack::new());
-
-        self.parse_states = vec![(initial_parse, initial_highlight)];
-        self.current_line = 0;
+        self.reset(new_settings);
     }
 
     fn change_line(&mut self, line: usize) {
@@ -94,62 +587,87 @@ impl IcedHighlighter for VscodeHighlighter {
     }
 
     fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
-        if self.current_line >= self.parse_states.len() {
-            if let Some(last) = self.parse_states.last() {
-                self.parse_states.push(last.clone());
-            }
+        if self.current_line >= self.parse_states.len()
+            && let Some(last) = self.parse_states.last()
+        {
+            self.parse_states.push(last.clone());
         }
         let idx = self.current_line;
-        let highlighter = SyntectHighlighter::new(&self.theme);
 
         // Clone so the stored beginning-of-line state isn't corrupted by
         // in-place mutation. Without this, re-highlighting a line (after
         // change_line) would start from the end-of-line state instead of
         // the beginning-of-line state, breaking multi-line constructs
         // like Python's triple-quoted strings.
-        let (mut parse_state, mut highlight_state) = self.parse_states[idx].clone();
-
-        let line_with_newline = format!("{}\n", line);
-
-        let ops = parse_state
-            .parse_line(&line_with_newline, &self.syntax_set)
-            .unwrap_or_default();
+        let mut state = self.parse_states[idx].clone();
 
-        let ranges: Vec<(Style, &str)> =
-            HighlightIterator::new(&mut highlight_state, &ops, &line_with_newline, &highlighter)
-                .collect();
+        let mut result = Vec::new();
+        match self.host {
+            Host::Markdown => self.highlight_markdown_line(&mut state, line, &mut result),
+            Host::Html => self.highlight_html_line(&mut state, line, &mut result),
+            Host::Plain => highlight_segment(&mut state.parse, &mut state.highlight, &self.theme, line, 0, true, &mut result),
+        }
 
-        let next_state = (parse_state, highlight_state);
         if idx + 1 < self.parse_states.len() {
-            self.parse_states[idx + 1] = next_state;
+            self.parse_states[idx + 1] = state;
         } else {
-            self.parse_states.push(next_state);
+            self.parse_states.push(state);
         }
 
         self.current_line += 1;
 
-        let line_len = line.len();
-        let mut result = Vec::new();
-        let mut offset = 0;
-        for (style, text) in ranges {
-            let len = text.len();
-            if offset >= line_len {
-                break;
-            }
-            let capped_end = (offset + len).min(line_len);
-            let color = Color::from_rgba8(
-                style.foreground.r,
-                style.foreground.g,
-                style.foreground.b,
-                style.foreground.a as f32 / 255.0,
-            );
-            result.push((offset..capped_end, Highlight(color)));
-            offset += len;
-        }
-
         Box::new(result.into_iter())
     }
     fn current_line(&self) -> usize {
         self.current_line
     }
 }
+
+/// Highlight results for every line of a buffer. An edit only throws away
+/// the results from the edited line down, the same way `change_line` works.
+pub struct HighlightCache {
+    settings: Option<Settings>,
+    highlighter: Option<VscodeHighlighter>,
+    lines: Vec<Vec<(Range<usize>, Highlight)>>,
+}
+
+impl HighlightCache {
+    pub fn new() -> Self {
+        Self {
+            settings: None,
+            highlighter: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Forgets everything from `line` onwards, call this whenever that line changes.
+    pub fn invalidate(&mut self, line: usize) {
+        if line < self.lines.len() {
+            self.lines.truncate(line);
+        }
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            highlighter.change_line(line);
+        }
+    }
+
+    /// Makes sure every line up to and including `last` is highlighted.
+    pub fn update(&mut self, settings: &Settings, lines: &[String], last: usize) {
+        if self.settings.as_ref() != Some(settings) {
+            self.highlighter = Some(VscodeHighlighter::new(settings));
+            self.settings = Some(settings.clone());
+            self.lines.clear();
+        }
+        let Some(highlighter) = self.highlighter.as_mut() else { return; };
+
+        let last = last.min(lines.len().saturating_sub(1));
+        while self.lines.len() <= last && self.lines.len() < lines.len() {
+            let index = self.lines.len();
+            let highlights = highlighter.highlight_line(&lines[index]).collect();
+            self.lines.push(highlights);
+        }
+    }
+
+    pub fn line(&self, index: usize) -> &[(Range<usize>, Highlight)] {
+        self.lines.get(index).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
@@ -13,6 +13,7 @@ pub const SIDEBAR_MAX_WIDTH: f32 = 500.0;
 pub const RESIZE_HIT_WIDTH: f32 = 12.0;
 pub const ICON_SIZE: f32 = 16.0;
 pub const INDENT_WIDTH: f32 = 16.0;
+pub const PROBLEMS_PANEL_HEIGHT: f32 = 200.0;
 pub const BORDER_RADIUS: f32 = 14.0;
 pub const BORDER_RADIUS_TAB: f32 = 10.0;
 
@@ -35,6 +36,48 @@ pub struct ThemeColors {
     pub border_subtle: Color,
     pub border_very_subtle: Color,
     pub selection: Color,
+    pub line_highlight: Color,
+    pub line_number: Color,
+    pub line_number_active: Color,
+    pub cursor: Color,
+    pub bracket_match: Color,
+    pub bracket_match_border: Color,
+    pub selection_highlight: Color,
+    pub word_highlight: Color,
+    pub fold_background: Color,
+    pub whitespace: Color,
+    pub indent_guide: Color,
+    pub indent_guide_active: Color,
+    pub snippet_tab_stop: Color,
+    pub suggest_background: Color,
+    pub suggest_border: Color,
+    pub suggest_foreground: Color,
+    pub suggest_highlight: Color,
+    pub suggest_selected: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub info: Color,
+    pub hint: Color,
+    pub hover_background: Color,
+    pub hover_border: Color,
+    pub symbol_function: Color,
+    pub symbol_variable: Color,
+    pub symbol_class: Color,
+    pub inlay_hint: Color,
+    pub inlay_hint_background: Color,
+    pub git_modified: Color,
+    pub git_added: Color,
+    pub git_deleted: Color,
+    pub git_untracked: Color,
+    pub git_ignored: Color,
+    pub git_conflicted: Color,
+    pub gutter_added: Color,
+    pub gutter_modified: Color,
+    pub gutter_deleted: Color,
+    pub diff_inserted: Color,
+    pub diff_removed: Color,
+    pub diff_inserted_line: Color,
+    pub diff_removed_line: Color,
     pub shadow_dark: Color,
     pub shadow_light: Color,
     pub syntax_theme: SynTheme,
@@ -146,6 +189,90 @@ struct VscodeColors {
     editor_selection: Option<String>,
     #[serde(rename = "editor.lineHighlightBackground")]
     line_highlight: Option<String>,
+    #[serde(rename = "editor.selectionHighlightBackground")]
+    selection_highlight: Option<String>,
+    #[serde(rename = "editor.wordHighlightBackground")]
+    word_highlight: Option<String>,
+    #[serde(rename = "editorLineNumber.foreground")]
+    line_number: Option<String>,
+    #[serde(rename = "editorLineNumber.activeForeground")]
+    line_number_active: Option<String>,
+    #[serde(rename = "editorCursor.foreground")]
+    cursor: Option<String>,
+    #[serde(rename = "editorBracketMatch.background")]
+    bracket_match: Option<String>,
+    #[serde(rename = "editorBracketMatch.border")]
+    bracket_match_border: Option<String>,
+    #[serde(rename = "editor.foldBackground")]
+    fold_background: Option<String>,
+    #[serde(rename = "editorWhitespace.foreground")]
+    whitespace: Option<String>,
+    #[serde(rename = "editorIndentGuide.background")]
+    indent_guide: Option<String>,
+    #[serde(rename = "editorIndentGuide.activeBackground")]
+    indent_guide_active: Option<String>,
+    #[serde(rename = "editor.snippetTabstopHighlightBackground")]
+    snippet_tab_stop: Option<String>,
+    #[serde(rename = "editorSuggestWidget.background")]
+    suggest_background: Option<String>,
+    #[serde(rename = "editorSuggestWidget.border")]
+    suggest_border: Option<String>,
+    #[serde(rename = "editorSuggestWidget.foreground")]
+    suggest_foreground: Option<String>,
+    #[serde(rename = "editorSuggestWidget.highlightForeground")]
+    suggest_highlight: Option<String>,
+    #[serde(rename = "editorSuggestWidget.selectedBackground")]
+    suggest_selected: Option<String>,
+    #[serde(rename = "editorError.foreground")]
+    error: Option<String>,
+    #[serde(rename = "editorWarning.foreground")]
+    warning: Option<String>,
+    #[serde(rename = "editorInfo.foreground")]
+    info: Option<String>,
+    #[serde(rename = "editorHint.foreground")]
+    hint: Option<String>,
+    #[serde(rename = "editorHoverWidget.background")]
+    hover_background: Option<String>,
+    #[serde(rename = "editorHoverWidget.border")]
+    hover_border: Option<String>,
+    #[serde(rename = "symbolIcon.functionForeground")]
+    symbol_function: Option<String>,
+    #[serde(rename = "symbolIcon.variableForeground")]
+    symbol_variable: Option<String>,
+    #[serde(rename = "symbolIcon.classForeground")]
+    symbol_class: Option<String>,
+    #[serde(rename = "editorInlayHint.foreground")]
+    inlay_hint: Option<String>,
+    #[serde(rename = "editorInlayHint.background")]
+    inlay_hint_background: Option<String>,
+    #[serde(rename = "gitDecoration.modifiedResourceForeground")]
+    git_modified: Option<String>,
+    #[serde(rename = "gitDecoration.addedResourceForeground")]
+    git_added: Option<String>,
+    #[serde(rename = "gitDecoration.deletedResourceForeground")]
+    git_deleted: Option<String>,
+    #[serde(rename = "gitDecoration.untrackedResourceForeground")]
+    git_untracked: Option<String>,
+    #[serde(rename = "gitDecoration.ignoredResourceForeground")]
+    git_ignored: Option<String>,
+    #[serde(rename = "gitDecoration.conflictingResourceForeground")]
+    git_conflicted: Option<String>,
+    #[serde(rename = "editorGutter.addedBackground")]
+    gutter_added: Option<String>,
+    #[serde(rename = "editorGutter.modifiedBackground")]
+    gutter_modified: Option<String>,
+    #[serde(rename = "editorGutter.deletedBackground")]
+    gutter_deleted: Option<String>,
+    #[serde(rename = "diffEditor.insertedTextBackground")]
+    diff_inserted: Option<String>,
+    #[serde(rename = "diffEditor.removedTextBackground")]
+    diff_removed: Option<String>,
+    #[serde(rename = "diffEditor.insertedLineBackground")]
+    diff_inserted_line: Option<String>,
+    #[serde(rename = "diffEditor.removedLineBackground")]
+    diff_removed_line: Option<String>,
+    #[serde(rename = "editorWidget.background")]
+    widget_background: Option<String>,
     #[serde(rename = "sideBar.background")]
     sidebar_background: Option<String>,
     #[serde(rename = "sideBar.foreground")]
@@ -164,7 +291,6 @@ struct VscodeColors {
     status_bar_bg: Option<String>,
     #[serde(rename = "list.hoverBackground")]
     list_hover_bg: Option<String>,
-    foreground: Option<String>,
     #[serde(rename = "input.placeholderForeground")]
     placeholder_fg: Option<String>,
 }
@@ -201,7 +327,7 @@ fn load_theme() -> ThemeColors {
     );
 
     ThemeColors {
-        bg_primary:       c.color(&c.line_highlight, d.bg_primary),
+        bg_primary:       c.color(&c.widget_background, d.bg_primary),
         bg_secondary:     c.color(&c.sidebar_background, d.bg_secondary),
         bg_editor:        c.color(&c.editor_background, d.bg_editor),
         bg_tab_active:    c.color(&c.tab_active_bg, d.bg_tab_active),
@@ -215,6 +341,48 @@ fn load_theme() -> ThemeColors {
         text_dim:         c.color(&c.tab_inactive_fg, d.text_dim),
         text_placeholder: c.color(&c.placeholder_fg, d.text_placeholder),
         selection:        c.color(&c.editor_selection, d.selection),
+        line_highlight:   c.color(&c.line_highlight, d.line_highlight),
+        line_number:      c.color(&c.line_number, d.line_number),
+        line_number_active: c.color(&c.line_number_active, d.line_number_active),
+        cursor:           c.color(&c.cursor, d.cursor),
+        bracket_match:    c.color(&c.bracket_match, d.bracket_match),
+        bracket_match_border: c.color(&c.bracket_match_border, d.bracket_match_border),
+        selection_highlight: c.color(&c.selection_highlight, d.selection_highlight),
+        word_highlight:   c.color(&c.word_highlight, d.word_highlight),
+        fold_background:  c.color(&c.fold_background, d.fold_background),
+        whitespace:       c.color(&c.whitespace, d.whitespace),
+        indent_guide:     c.color(&c.indent_guide, d.indent_guide),
+        indent_guide_active: c.color(&c.indent_guide_active, d.indent_guide_active),
+        snippet_tab_stop: c.color(&c.snippet_tab_stop, d.snippet_tab_stop),
+        suggest_background: c.color(&c.suggest_background, d.suggest_background),
+        suggest_border:   c.color(&c.suggest_border, d.suggest_border),
+        suggest_foreground: c.color(&c.suggest_foreground, d.suggest_foreground),
+        suggest_highlight: c.color(&c.suggest_highlight, d.suggest_highlight),
+        suggest_selected: c.color(&c.suggest_selected, d.suggest_selected),
+        error:            c.color(&c.error, d.error),
+        warning:          c.color(&c.warning, d.warning),
+        info:             c.color(&c.info, d.info),
+        hint:             c.color(&c.hint, d.hint),
+        hover_background: c.color(&c.hover_background, d.hover_background),
+        hover_border:     c.color(&c.hover_border, d.hover_border),
+        symbol_function:  c.color(&c.symbol_function, d.symbol_function),
+        symbol_variable:  c.color(&c.symbol_variable, d.symbol_variable),
+        symbol_class:     c.color(&c.symbol_class, d.symbol_class),
+        inlay_hint:       c.color(&c.inlay_hint, d.inlay_hint),
+        inlay_hint_background: c.color(&c.inlay_hint_background, d.inlay_hint_background),
+        git_modified:     c.color(&c.git_modified, d.git_modified),
+        git_added:        c.color(&c.git_added, d.git_added),
+        git_deleted:      c.color(&c.git_deleted, d.git_deleted),
+        git_untracked:    c.color(&c.git_untracked, d.git_untracked),
+        git_ignored:      c.color(&c.git_ignored, d.git_ignored),
+        git_conflicted:   c.color(&c.git_conflicted, d.git_conflicted),
+        gutter_added:     c.color(&c.gutter_added, d.gutter_added),
+        gutter_modified:  c.color(&c.gutter_modified, d.gutter_modified),
+        gutter_deleted:   c.color(&c.gutter_deleted, d.gutter_deleted),
+        diff_inserted:    c.color(&c.diff_inserted, d.diff_inserted),
+        diff_removed:     c.color(&c.diff_removed, d.diff_removed),
+        diff_inserted_line: c.color(&c.diff_inserted_line, d.diff_inserted_line),
+        diff_removed_line: c.color(&c.diff_removed_line, d.diff_removed_line),
         // No VSCode equivalents for these
         bg_pressed:       d.bg_pressed,
         bg_drag_handle:   d.bg_drag_handle,
@@ -247,6 +415,48 @@ impl Default for ThemeColors {
             border_subtle:    Color::from_rgba(1.0, 1.0, 1.0, 0.05),
             border_very_subtle: Color::from_rgba(1.0, 1.0, 1.0, 0.03),
             selection:        Color::from_rgba(0.3, 0.5, 0.8, 0.4),
+            line_highlight:   Color::from_rgba(1.0, 1.0, 1.0, 0.04),
+            line_number:      Color::from_rgb(0.5, 0.5, 0.5),
+            line_number_active: Color::from_rgb(0.8, 0.8, 0.8),
+            cursor:           Color::from_rgb(0.9, 0.9, 0.9),
+            bracket_match:    Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+            bracket_match_border: Color::from_rgba(1.0, 1.0, 1.0, 0.3),
+            selection_highlight: Color::from_rgba(0.3, 0.5, 0.8, 0.2),
+            word_highlight:   Color::from_rgba(1.0, 1.0, 1.0, 0.08),
+            fold_background:  Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+            whitespace:       Color::from_rgba(1.0, 1.0, 1.0, 0.2),
+            indent_guide:     Color::from_rgba(1.0, 1.0, 1.0, 0.08),
+            indent_guide_active: Color::from_rgba(1.0, 1.0, 1.0, 0.25),
+            snippet_tab_stop: Color::from_rgba(1.0, 1.0, 1.0, 0.12),
+            suggest_background: Color::from_rgb(0.12, 0.12, 0.14),
+            suggest_border:   Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+            suggest_foreground: Color::from_rgb(0.9, 0.9, 0.9),
+            suggest_highlight: Color::from_rgb(0.4, 0.6, 0.9),
+            suggest_selected: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+            error:            Color::from_rgb(0.95, 0.3, 0.3),
+            warning:          Color::from_rgb(0.8, 0.65, 0.0),
+            info:             Color::from_rgb(0.22, 0.58, 1.0),
+            hint:             Color::from_rgba(0.93, 0.93, 0.93, 0.7),
+            hover_background: Color::from_rgb(0.12, 0.12, 0.14),
+            hover_border:     Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+            symbol_function:  Color::from_rgb(0.69, 0.5, 0.84),
+            symbol_variable:  Color::from_rgb(0.46, 0.75, 1.0),
+            symbol_class:     Color::from_rgb(0.93, 0.62, 0.16),
+            inlay_hint:       Color::from_rgba(1.0, 1.0, 1.0, 0.45),
+            inlay_hint_background: Color::from_rgba(1.0, 1.0, 1.0, 0.05),
+            git_modified:     Color::from_rgb(0.88, 0.76, 0.42),
+            git_added:        Color::from_rgb(0.51, 0.78, 0.45),
+            git_deleted:      Color::from_rgb(0.9, 0.42, 0.4),
+            git_untracked:    Color::from_rgb(0.45, 0.79, 0.57),
+            git_ignored:      Color::from_rgba(1.0, 1.0, 1.0, 0.35),
+            git_conflicted:   Color::from_rgb(0.89, 0.45, 0.75),
+            gutter_added:     Color::from_rgb(0.51, 0.78, 0.45),
+            gutter_modified:  Color::from_rgb(0.38, 0.6, 0.86),
+            gutter_deleted:   Color::from_rgb(0.9, 0.42, 0.4),
+            diff_inserted:    Color::from_rgba(0.51, 0.78, 0.45, 0.15),
+            diff_removed:     Color::from_rgba(0.9, 0.42, 0.4, 0.15),
+            diff_inserted_line: Color::from_rgba(0.51, 0.78, 0.45, 0.08),
+            diff_removed_line: Color::from_rgba(0.9, 0.42, 0.4, 0.08),
             shadow_dark:      Color::from_rgba(0.0, 0.0, 0.0, 0.3),
             shadow_light:     Color::from_rgba(1.0, 1.0, 1.0, 0.02),
             syntax_theme: SynTheme::default(),
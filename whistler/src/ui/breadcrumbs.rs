@@ -0,0 +1,80 @@
+use iced::widget::svg::{Handle, Svg};
+use iced::widget::{button, column, container, row, scrollable, text};
+use iced::{Element, Length};
+
+use crate::icons::{get_file_icon, get_folder_icon};
+use crate::message::Message;
+use crate::outline::Crumb;
+use crate::theme::*;
+use crate::ui::dropdown::Dropdown;
+use crate::ui::sidebar::symbol_icon;
+use crate::ui::styles::{file_finder_item_style, file_finder_panel_style, tab_bar_style, tree_button_style};
+
+/// The bar above the editor: the folders down to the file, then the symbols the cursor is in.
+/// `menu` is the segment whose dropdown is open and what it lists.
+pub fn view_breadcrumbs<'a>(segments: Vec<Crumb>, menu: Option<&'a (usize, Vec<Crumb>)>) -> Element<'a, Message> {
+    let mut items: Vec<Element<'a, Message>> = Vec::new();
+    for (index, segment) in segments.into_iter().enumerate() {
+        if index > 0 {
+            items.push(text("›").size(12).color(THEME.text_placeholder).into());
+        }
+        let anchor = button(crumb_label(&segment, false))
+            .style(tree_button_style)
+            .on_press(Message::BreadcrumbClicked(index))
+            .padding(iced::Padding { top: 2.0, right: 4.0, bottom: 2.0, left: 4.0 });
+        let open = menu.filter(|(open, _)| *open == index).map(|(_, siblings)| view_menu(siblings, &segment));
+        items.push(Dropdown::new(anchor).menu(open).on_dismiss(Message::BreadcrumbDismissed).into());
+    }
+    container(row(items).spacing(2).align_y(iced::Alignment::Center))
+        .padding(iced::Padding { top: 2.0, right: 10.0, bottom: 2.0, left: 10.0 })
+        .width(Length::Fill)
+        .style(tab_bar_style)
+        .into()
+}
+
+/// What's next to a segment, with the segment itself picked out.
+fn view_menu<'a>(siblings: &'a [Crumb], segment: &Crumb) -> Element<'a, Message> {
+    let items: Vec<Element<'a, Message>> = siblings
+        .iter()
+        .map(|sibling| {
+            let is_current = is_same(sibling, segment);
+            button(crumb_label(sibling, is_current))
+                .style(file_finder_item_style(is_current))
+                .on_press(Message::BreadcrumbChosen(sibling.clone()))
+                .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
+                .width(Length::Fill)
+                .into()
+        })
+        .collect();
+    container(scrollable(column(items).spacing(1).padding(4)))
+        .width(Length::Fixed(280.0))
+        .style(file_finder_panel_style)
+        .into()
+}
+
+fn crumb_label<'a>(crumb: &Crumb, is_current: bool) -> Element<'a, Message> {
+    let icon: Element<'a, Message> = match crumb {
+        Crumb::Symbol { kind, .. } => symbol_icon(*kind),
+        Crumb::Folder(_) | Crumb::File(_) => {
+            let name = crumb.name();
+            let icon_path = match crumb {
+                Crumb::Folder(_) => get_folder_icon(&name, false),
+                _ => get_file_icon(&name),
+            };
+            Svg::new(Handle::from_path(icon_path)).width(Length::Fixed(14.0)).height(Length::Fixed(14.0)).into()
+        }
+    };
+    let color = if is_current { THEME.text_primary } else { THEME.text_muted };
+    row![icon, text(crumb.name()).size(12).color(color)]
+        .spacing(5)
+        .align_y(iced::Alignment::Center)
+        .into()
+}
+
+fn is_same(a: &Crumb, b: &Crumb) -> bool {
+    match (a, b) {
+        (Crumb::Folder(a), Crumb::Folder(b)) | (Crumb::File(a), Crumb::File(b)) => a == b,
+        (Crumb::Symbol { at: a, .. }, Crumb::Symbol { at: b, .. }) => a == b,
+        _ => false,
+    }
+}
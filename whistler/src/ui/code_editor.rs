@@ -0,0 +1,1847 @@
+use iced::advanced::input_method::{self, InputMethod};
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::mouse;
+use iced::advanced::overlay;
+use iced::advanced::renderer::{self, Quad, Renderer as _};
+use iced::advanced::text::{self, Paragraph as _, Renderer as _};
+use iced::advanced::widget::{self, operation, tree, Tree, Widget};
+use iced::advanced::{clipboard, Clipboard, Shell};
+use iced::alignment;
+use iced::keyboard;
+use iced::widget::text_editor::{Binding, Edit, KeyPress, Status};
+use iced::window;
+use iced::{Background, Border, Color, Element, Event, Length, Padding, Pixels, Point, Rectangle, Size, Theme, Vector};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::completion::{Completion, CompletionKind, VISIBLE_ITEMS};
+use crate::config::{EditorSettings, LineNumbers, RenderWhitespace, WordWrap};
+use crate::decoration::Decorations;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::document::{byte_index, display_width, Action, Document, Position};
+use crate::git::{self, Blame, BlameCommit, Hunk, LineChange};
+use crate::syntax::Settings;
+
+const LINE_HEIGHT: f32 = 1.3;
+const GUTTER_PADDING_LEFT: f32 = 14.0;
+const GUTTER_PADDING_RIGHT: f32 = 18.0;
+const TEXT_PADDING: f32 = 4.0;
+const CURSOR_WIDTH: f32 = 2.0;
+const SCROLLBAR_WIDTH: f32 = 8.0;
+const SCROLLBAR_MIN_THUMB: f32 = 24.0;
+const INDENT_GUIDE_WIDTH: f32 = 1.0;
+const EMPTY_TAB_STOP_WIDTH: f32 = 2.0;
+const COMPLETION_PADDING: f32 = 6.0;
+/// The most chars of a label and its detail the completion popup makes room for
+const COMPLETION_MAX_CHARS: usize = 60;
+/// The kind's letter and a space before each label
+const COMPLETION_ICON_CHARS: usize = 2;
+/// The selected item's documentation goes next to the popup, cut off past this size
+const DOCUMENTATION_MAX_SIZE: Size = Size::new(360.0, 300.0);
+/// Language server hovers and signature help are cut off past these
+const INFO_MAX_SIZE: Size = Size::new(500.0, 300.0);
+const SIGNATURE_MAX_SIZE: Size = Size::new(500.0, 200.0);
+/// The lines a change replaced, shown under it
+const HUNK_MAX_SIZE: Size = Size::new(700.0, 320.0);
+/// Between a popover and what it's next to
+const POPOVER_GAP: f32 = 2.0;
+/// How long the mouse rests on something before the language server is asked about it
+const HOVER_DELAY: Duration = Duration::from_millis(300);
+const DIAGNOSTIC_DOT_SIZE: f32 = 6.0;
+/// Changed lines have a bar this wide at the left edge of the gutter
+const CHANGE_BAR_WIDTH: f32 = 3.0;
+/// Deleted lines are a wedge between the lines that were around them
+const CHANGE_WEDGE_SIZE: f32 = 6.0;
+/// How many chars of blame the gutter has room for.
+const BLAME_CHARS: usize = 36;
+/// The chars between the end of the cursor's line and its blame.
+const ANNOTATION_GAP: usize = 4;
+/// Squiggles are drawn a pixel at a time, going up and down over this many pixels
+const SQUIGGLE_PERIOD: usize = 4;
+const HOVER_PADDING: f32 = 6.0;
+/// Longer hover lines wrap
+const HOVER_MAX_CHARS: usize = 80;
+
+type KeyBindingFn<'a, Message> = Box<dyn Fn(KeyPress) -> Option<Binding<Message>> + 'a>;
+type StyleFn<'a> = Box<dyn Fn(&Theme, Status) -> Style + 'a>;
+
+/// The look of a [`CodeEditor`].
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    pub background: Background,
+    pub gutter_background: Background,
+    pub line_number: Color,
+    pub active_line_number: Color,
+    pub text: Color,
+    pub selection: Color,
+    pub cursor: Color,
+    pub scrollbar: Color,
+    pub current_line: Color,
+    pub bracket_match: Color,
+    pub bracket_match_border: Color,
+    pub selection_highlight: Color,
+    pub word_highlight: Color,
+    pub fold_placeholder: Color,
+    pub whitespace: Color,
+    pub indent_guide: Color,
+    pub active_indent_guide: Color,
+    pub snippet_tab_stop: Color,
+    pub completion_background: Color,
+    pub completion_border: Color,
+    pub completion_text: Color,
+    pub completion_match: Color,
+    pub completion_selected: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub info: Color,
+    pub hint: Color,
+    pub hover_background: Color,
+    pub hover_border: Color,
+    pub symbol_function: Color,
+    pub symbol_variable: Color,
+    pub symbol_class: Color,
+    pub inlay_hint: Color,
+    pub inlay_hint_background: Color,
+    pub line_added: Color,
+    pub line_modified: Color,
+    pub line_deleted: Color,
+}
+
+impl Style {
+    fn change(&self, change: LineChange) -> Color {
+        match change {
+            LineChange::Added => self.line_added,
+            LineChange::Modified => self.line_modified,
+            LineChange::Deleted => self.line_deleted,
+        }
+    }
+
+    fn severity(&self, severity: Severity) -> Color {
+        match severity {
+            Severity::Error => self.error,
+            Severity::Warning => self.warning,
+            Severity::Information => self.info,
+            Severity::Hint => self.hint,
+        }
+    }
+
+    /// The colour of a completion kind's letter, shared by kinds that are alike the way VS Code does.
+    fn kind(&self, kind: CompletionKind) -> Color {
+        match kind {
+            CompletionKind::Method | CompletionKind::Function | CompletionKind::Constructor => self.symbol_function,
+            CompletionKind::Field
+            | CompletionKind::Variable
+            | CompletionKind::Property
+            | CompletionKind::Value
+            | CompletionKind::Reference
+            | CompletionKind::EnumMember
+            | CompletionKind::Constant => self.symbol_variable,
+            CompletionKind::Class
+            | CompletionKind::Interface
+            | CompletionKind::Struct
+            | CompletionKind::Enum
+            | CompletionKind::TypeParameter
+            | CompletionKind::Event => self.symbol_class,
+            _ => self.line_number,
+        }
+    }
+}
+
+/// A code editor: a line number gutter next to the highlighted text of a [`Document`].
+///
+/// It works like iced's `TextEditor`: it never changes the document itself, it
+/// turns input into [`Action`]s and leaves it to `update` to apply them.
+pub struct CodeEditor<'a, Message> {
+    document: &'a Document,
+    id: Option<widget::Id>,
+    text_size: f32,
+    highlight: Settings,
+    settings: EditorSettings,
+    completion: Option<&'a Completion>,
+    documentation: Option<Element<'a, Message>>,
+    info: Option<(Position, Position, Element<'a, Message>)>,
+    signature_help: Option<Element<'a, Message>>,
+    diagnostics: &'a [Diagnostic],
+    changes: &'a [Hunk],
+    hunk: Option<(&'a Hunk, Element<'a, Message>)>,
+    blame: Option<&'a Blame>,
+    annotation: Option<String>,
+    on_action: Option<Box<dyn Fn(Action) -> Message + 'a>>,
+    on_command_click: Option<Box<dyn Fn() -> Message + 'a>>,
+    on_change_click: Option<Box<dyn Fn(usize) -> Message + 'a>>,
+    on_hover: Option<Box<dyn Fn(Option<Position>) -> Message + 'a>>,
+    key_binding: Option<KeyBindingFn<'a, Message>>,
+    style: StyleFn<'a>,
+}
+
+impl<'a, Message> CodeEditor<'a, Message> {
+    pub fn new(document: &'a Document, highlight: Settings) -> Self {
+        Self {
+            document,
+            id: None,
+            text_size: 16.0,
+            highlight,
+            settings: EditorSettings::default(),
+            completion: None,
+            documentation: None,
+            info: None,
+            signature_help: None,
+            diagnostics: &[],
+            changes: &[],
+            hunk: None,
+            blame: None,
+            annotation: None,
+            on_action: None,
+            on_command_click: None,
+            on_change_click: None,
+            on_hover: None,
+            key_binding: None,
+            style: Box::new(|_theme, _status| Style {
+                background: Background::Color(Color::BLACK),
+                gutter_background: Background::Color(Color::BLACK),
+                line_number: Color::from_rgb(0.5, 0.5, 0.5),
+                active_line_number: Color::WHITE,
+                text: Color::WHITE,
+                selection: Color::from_rgba(0.3, 0.5, 0.8, 0.4),
+                cursor: Color::WHITE,
+                scrollbar: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                current_line: Color::from_rgba(1.0, 1.0, 1.0, 0.04),
+                bracket_match: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                bracket_match_border: Color::from_rgba(1.0, 1.0, 1.0, 0.3),
+                selection_highlight: Color::from_rgba(0.3, 0.5, 0.8, 0.2),
+                word_highlight: Color::from_rgba(1.0, 1.0, 1.0, 0.08),
+                fold_placeholder: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                whitespace: Color::from_rgba(1.0, 1.0, 1.0, 0.2),
+                indent_guide: Color::from_rgba(1.0, 1.0, 1.0, 0.08),
+                active_indent_guide: Color::from_rgba(1.0, 1.0, 1.0, 0.25),
+                snippet_tab_stop: Color::from_rgba(1.0, 1.0, 1.0, 0.12),
+                completion_background: Color::from_rgb(0.12, 0.12, 0.14),
+                completion_border: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                completion_text: Color::WHITE,
+                completion_match: Color::from_rgb(0.4, 0.6, 0.9),
+                completion_selected: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                error: Color::from_rgb(0.95, 0.3, 0.3),
+                warning: Color::from_rgb(0.8, 0.65, 0.0),
+                info: Color::from_rgb(0.22, 0.58, 1.0),
+                hint: Color::from_rgba(1.0, 1.0, 1.0, 0.6),
+                hover_background: Color::from_rgb(0.12, 0.12, 0.14),
+                hover_border: Color::from_rgba(1.0, 1.0, 1.0, 0.1),
+                symbol_function: Color::from_rgb(0.69, 0.5, 0.84),
+                symbol_variable: Color::from_rgb(0.46, 0.75, 1.0),
+                symbol_class: Color::from_rgb(0.93, 0.62, 0.16),
+                inlay_hint: Color::from_rgba(1.0, 1.0, 1.0, 0.45),
+                inlay_hint_background: Color::from_rgba(1.0, 1.0, 1.0, 0.05),
+                line_added: Color::from_rgb(0.51, 0.78, 0.45),
+                line_modified: Color::from_rgb(0.38, 0.6, 0.86),
+                line_deleted: Color::from_rgb(0.9, 0.42, 0.4),
+            }),
+        }
+    }
+
+    pub fn id(mut self, id: impl Into<widget::Id>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn size(mut self, size: f32) -> Self {
+        self.text_size = size;
+        self
+    }
+
+    /// Line numbers, wrapping, whitespace and indent guides.
+    pub fn settings(mut self, settings: EditorSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// The completion popup to show at the cursor, if it's open.
+    pub fn completion(mut self, completion: Option<&'a Completion>) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// The selected completion item's documentation, shown next to the popup.
+    pub fn documentation(mut self, documentation: Option<Element<'a, Message>>) -> Self {
+        self.documentation = documentation;
+        self
+    }
+
+    /// What the language server says about the code under the mouse, and the range it's about.
+    /// It goes under the start of the range.
+    pub fn info(mut self, info: Option<(Position, Position, Element<'a, Message>)>) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// The signature of the call the cursor is in, shown above the cursor.
+    pub fn signature_help(mut self, signature_help: Option<Element<'a, Message>>) -> Self {
+        self.signature_help = signature_help;
+        self
+    }
+
+    /// The file's problems, underlined in the text and marked in the gutter.
+    pub fn diagnostics(mut self, diagnostics: &'a [Diagnostic]) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// How the file differs from what git has of it, marked at the left of the gutter.
+    pub fn changes(mut self, changes: &'a [Hunk]) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    /// One of the [`changes`](Self::changes), opened to show what it replaced. It goes under
+    /// the change's last line.
+    pub fn hunk(mut self, hunk: Option<(&'a Hunk, Element<'a, Message>)>) -> Self {
+        self.hunk = hunk;
+        self
+    }
+
+    /// Who last changed each line, in a column left of the line numbers.
+    pub fn blame(mut self, blame: Option<&'a Blame>) -> Self {
+        self.blame = blame;
+        self
+    }
+
+    /// Faint text after the end of the cursor's line.
+    pub fn annotation(mut self, annotation: Option<String>) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
+    pub fn on_action(mut self, on_action: impl Fn(Action) -> Message + 'a) -> Self {
+        self.on_action = Some(Box::new(on_action));
+        self
+    }
+
+    /// What Cmd+clicking the text sends, once the cursor is where it was clicked.
+    pub fn on_command_click(mut self, on_command_click: impl Fn() -> Message + 'a) -> Self {
+        self.on_command_click = Some(Box::new(on_command_click));
+        self
+    }
+
+    /// What clicking a line's change marker sends, with the line.
+    pub fn on_change_click(mut self, on_change_click: impl Fn(usize) -> Message + 'a) -> Self {
+        self.on_change_click = Some(Box::new(on_change_click));
+        self
+    }
+
+    /// What the mouse resting on a char sends, and what moving it off the range of the
+    /// [`info`](Self::info) sends.
+    pub fn on_hover(mut self, on_hover: impl Fn(Option<Position>) -> Message + 'a) -> Self {
+        self.on_hover = Some(Box::new(on_hover));
+        self
+    }
+
+    pub fn key_binding(mut self, key_binding: impl Fn(KeyPress) -> Option<Binding<Message>> + 'a) -> Self {
+        self.key_binding = Some(Box::new(key_binding));
+        self
+    }
+
+    pub fn style(mut self, style: impl Fn(&Theme, Status) -> Style + 'a) -> Self {
+        self.style = Box::new(style);
+        self
+    }
+
+    fn line_height(&self) -> f32 {
+        (self.text_size * LINE_HEIGHT).round()
+    }
+
+    /// Splits the widget into the blame, the gutter, the text area and the scrollbar track.
+    fn regions(&self, bounds: Rectangle, char_width: f32) -> Regions {
+        let digits = match self.settings.line_numbers {
+            LineNumbers::Off => 0,
+            _ => self.document.line_count().to_string().len().max(3),
+        };
+        let gutter_width = GUTTER_PADDING_LEFT + digits as f32 * char_width + GUTTER_PADDING_RIGHT;
+        let blame_width = match self.blame {
+            Some(_) => GUTTER_PADDING_LEFT + BLAME_CHARS as f32 * char_width,
+            None => 0.0,
+        };
+
+        let blame = Rectangle { width: blame_width, ..bounds };
+        let gutter = Rectangle { x: bounds.x + blame_width, width: gutter_width, ..bounds };
+        let text = Rectangle {
+            x: gutter.x + gutter_width,
+            width: (bounds.width - blame_width - gutter_width).max(0.0),
+            ..bounds
+        };
+        let scrollbar = Rectangle {
+            x: text.x + text.width - SCROLLBAR_WIDTH,
+            width: SCROLLBAR_WIDTH,
+            ..text
+        };
+        Regions { blame, gutter, text, scrollbar }
+    }
+
+    /// Where the thumb of the scrollbar sits, if the document is long enough to need one.
+    fn scrollbar_thumb(&self, track: Rectangle) -> Option<Rectangle> {
+        let row_count = self.document.row_count();
+        if row_count <= 1 {
+            return None;
+        }
+        let visible = track.height / self.line_height();
+        let total = row_count as f32 - 1.0 + visible;
+        let height = (track.height * visible / total).max(SCROLLBAR_MIN_THUMB).min(track.height);
+        let progress = self.document.scroll_row() / (row_count as f32 - 1.0);
+        Some(Rectangle {
+            y: track.y + (track.height - height) * progress,
+            height,
+            ..track
+        })
+    }
+
+    /// The on-screen column under `x`, counted from the start of the row.
+    fn column_at(&self, x: f32, text: Rectangle, char_width: f32) -> usize {
+        ((x - text.x - TEXT_PADDING) / char_width + self.document.scroll_column()).round().max(0.0) as usize
+    }
+
+    /// Turns a point inside the text area into a line and an on-screen column.
+    fn cell_at(&self, point: Point, text: Rectangle, char_width: f32) -> (usize, usize) {
+        let (line, start, _) = self.document.row_segment(self.row_at(point.y, text));
+        (line, self.document.display_column(line, start) + self.column_at(point.x, text, char_width))
+    }
+
+    /// Turns a point inside the text area into a document position.
+    fn position_at(&self, point: Point, text: Rectangle, char_width: f32) -> Position {
+        self.document.position_in_row(self.row_at(point.y, text), self.column_at(point.x, text, char_width))
+    }
+
+    /// The diagnostics under the mouse: the ones it's over in the text, or the ones starting on
+    /// the line whose gutter marker it's over.
+    fn diagnostics_at(&self, point: Point, regions: &Regions, char_width: f32) -> Vec<usize> {
+        let hits = |covers: &dyn Fn(&Diagnostic) -> bool| {
+            self.diagnostics.iter().enumerate().filter(|(_, diagnostic)| covers(diagnostic)).map(|(index, _)| index).collect()
+        };
+        if regions.gutter.contains(point) {
+            let line = self.line_at(point.y, regions.text);
+            return hits(&|diagnostic| diagnostic.start.line == line && diagnostic.severity != Severity::Hint);
+        }
+        match self.char_at(point, regions, char_width) {
+            Some(position) => hits(&|diagnostic| diagnostic.contains(position)),
+            None => Vec::new(),
+        }
+    }
+
+    /// The char a point in the text area is over, rather than the gap between chars nearest to it.
+    fn char_at(&self, point: Point, regions: &Regions, char_width: f32) -> Option<Position> {
+        if !regions.text.contains(point) || regions.scrollbar.contains(point) {
+            return None;
+        }
+        let row = self.row_at(point.y, regions.text);
+        let column = ((point.x - regions.text.x - TEXT_PADDING) / char_width + self.document.scroll_column()).max(0.0) as usize;
+        let (line, start, end) = self.document.row_segment(row);
+        if self.document.display_column(line, start) + column > self.document.display_column(line, end) {
+            return None;
+        }
+        Some(self.document.position_in_row(row, column))
+    }
+
+    /// Where a char is on screen, if its row is in view.
+    fn char_bounds(&self, position: Position, bounds: Rectangle, char_width: f32) -> Option<Rectangle> {
+        let document = self.document;
+        if document.is_hidden(position.line) {
+            return None;
+        }
+        let text = self.regions(bounds, char_width).text;
+        let (row, column) = document.visual_position(position);
+        let line_height = self.line_height();
+        let y = text.y + (row as f32 - document.scroll_row()) * line_height;
+        if y < text.y || y + line_height > text.y + text.height {
+            return None;
+        }
+        let x = text.x + TEXT_PADDING + (column as f32 - document.scroll_column()) * char_width;
+        Some(Rectangle { x, y, width: char_width, height: line_height })
+    }
+
+    /// The elements shown floating over the editor, in the order their trees are kept in.
+    fn popovers(&self) -> impl Iterator<Item = &Element<'a, Message>> {
+        let info = self.info.iter().map(|(_, _, content)| content);
+        let hunk = self.hunk.iter().map(|(_, content)| content);
+        self.documentation.iter().chain(info).chain(self.signature_help.iter()).chain(hunk)
+    }
+
+    /// The strip at the left edge of the gutter where the change markers go.
+    fn change_strip(gutter: Rectangle) -> Rectangle {
+        Rectangle { width: GUTTER_PADDING_LEFT, ..gutter }
+    }
+
+    /// The change marked next to a line, if there's one.
+    fn change_on(&self, line: usize) -> Option<&Hunk> {
+        self.changes.iter().find(|hunk| hunk.is_on(line))
+    }
+
+    /// Where a hunk's popover goes: under its last line, or the line above where its lines were
+    /// deleted, across the text.
+    fn hunk_bounds(&self, hunk: &Hunk, bounds: Rectangle, char_width: f32) -> Option<Rectangle> {
+        let line = match hunk.current.is_empty() {
+            true => hunk.current.start.saturating_sub(1),
+            false => hunk.current.end - 1,
+        };
+        let line = line.min(self.document.line_count() - 1);
+        let end = Position { line, column: self.document.line_len(line) };
+        let text = self.regions(bounds, char_width).text;
+        self.char_bounds(end, bounds, char_width).map(|row| Rectangle { x: text.x + TEXT_PADDING, ..row })
+    }
+
+    fn row_at(&self, y: f32, text: Rectangle) -> usize {
+        let row = ((y - text.y) / self.line_height() + self.document.scroll_row()).max(0.0) as usize;
+        row.min(self.document.row_count() - 1)
+    }
+
+    fn line_at(&self, y: f32, text: Rectangle) -> usize {
+        self.document.line_of_row(self.row_at(y, text))
+    }
+
+    /// The strip at the right edge of the gutter where the fold chevrons go.
+    fn fold_strip(gutter: Rectangle) -> Rectangle {
+        Rectangle {
+            x: gutter.x + gutter.width - GUTTER_PADDING_RIGHT,
+            width: GUTTER_PADDING_RIGHT,
+            ..gutter
+        }
+    }
+
+    fn text_style(&self, renderer: &iced::Renderer, content: String) -> iced::advanced::Text {
+        iced::advanced::Text {
+            content,
+            bounds: Size::new(f32::INFINITY, self.line_height()),
+            size: Pixels(self.text_size),
+            line_height: text::LineHeight::Absolute(Pixels(self.line_height())),
+            font: renderer.default_font(),
+            align_x: text::Alignment::Left,
+            align_y: alignment::Vertical::Top,
+            shaping: text::Shaping::Advanced,
+            wrapping: text::Wrapping::None,
+        }
+    }
+}
+
+struct Regions {
+    blame: Rectangle,
+    gutter: Rectangle,
+    text: Rectangle,
+    scrollbar: Rectangle,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Drag {
+    Text,
+    /// Alt+drag, from the `(line, display column)` it started on
+    Box { from: (usize, usize) },
+    Gutter,
+    Scrollbar { grab: f32 },
+}
+
+#[derive(Debug, Clone)]
+struct Focus {
+    updated_at: Instant,
+    now: Instant,
+    is_window_focused: bool,
+}
+
+impl Focus {
+    const CURSOR_BLINK_INTERVAL_MILLIS: u128 = 500;
+
+    fn now() -> Self {
+        let now = Instant::now();
+        Self { updated_at: now, now, is_window_focused: true }
+    }
+
+    fn is_cursor_visible(&self) -> bool {
+        self.is_window_focused
+            && ((self.now - self.updated_at).as_millis() / Self::CURSOR_BLINK_INTERVAL_MILLIS).is_multiple_of(2)
+    }
+}
+
+/// The diagnostics whose messages are showing, and where the mouse was when they came up.
+#[derive(Debug, Clone, PartialEq)]
+struct Hover {
+    diagnostics: Vec<usize>,
+    at: Point,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    focus: Option<Focus>,
+    hover: Option<Hover>,
+    last_click: Option<mouse::Click>,
+    drag: Option<Drag>,
+    preedit: Option<input_method::Preedit>,
+    modifiers: keyboard::Modifiers,
+    char_width: f32,
+    /// Where the mouse stopped and when, until it's been there long enough to ask what's under it
+    rest: Option<(Point, Instant)>,
+}
+
+impl operation::Focusable for State {
+    fn is_focused(&self) -> bool {
+        self.focus.is_some()
+    }
+
+    fn focus(&mut self) {
+        self.focus = Some(Focus::now());
+    }
+
+    fn unfocus(&mut self) {
+        self.focus = None;
+    }
+}
+
+impl<'a, Message> Widget<Message, Theme, iced::Renderer> for CodeEditor<'a, Message> {
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        self.popovers().map(Tree::new).collect()
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(&self.popovers().collect::<Vec<_>>());
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(&mut self, tree: &mut Tree, renderer: &iced::Renderer, limits: &layout::Limits) -> layout::Node {
+        let state = tree.state.downcast_mut::<State>();
+
+        if state.char_width == 0.0 {
+            // Every glyph of a monospace font has the same advance, so measure it once
+            let sample = <iced::Renderer as text::Renderer>::Paragraph::with_text(
+                self.text_style(renderer, "0".repeat(10)).as_ref(),
+            );
+            state.char_width = sample.min_width() / 10.0;
+        }
+
+        let size = limits.width(Length::Fill).height(Length::Fill).max();
+        let regions = self.regions(Rectangle::with_size(size), state.char_width);
+        let columns = ((regions.text.width - TEXT_PADDING - SCROLLBAR_WIDTH) / state.char_width) as usize;
+        self.document.set_viewport((regions.text.height / self.line_height()) as usize, columns);
+        self.document.set_wrap_width(match self.settings.word_wrap {
+            WordWrap::Off => None,
+            WordWrap::On => Some(columns),
+            WordWrap::Column => Some(self.settings.word_wrap_column),
+            WordWrap::Bounded => Some(columns.min(self.settings.word_wrap_column)),
+        });
+
+        layout::Node::new(size)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &iced::Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) {
+        let Some(on_action) = self.on_action.as_ref() else { return; };
+        let state = tree.state.downcast_mut::<State>();
+        let bounds = layout.bounds();
+        let regions = self.regions(bounds, state.char_width);
+
+        match event {
+            Event::Window(window::Event::Unfocused) => {
+                if let Some(focus) = &mut state.focus {
+                    focus.is_window_focused = false;
+                }
+            }
+            Event::Window(window::Event::Focused) => {
+                if let Some(focus) = &mut state.focus {
+                    focus.is_window_focused = true;
+                    focus.updated_at = Instant::now();
+                    shell.request_redraw();
+                }
+            }
+            Event::Window(window::Event::RedrawRequested(now)) => {
+                if let Some(focus) = &mut state.focus
+                    && focus.is_window_focused
+                {
+                    focus.now = *now;
+                    let millis_until_redraw = Focus::CURSOR_BLINK_INTERVAL_MILLIS
+                        - (focus.now - focus.updated_at).as_millis() % Focus::CURSOR_BLINK_INTERVAL_MILLIS;
+                    shell.request_redraw_at(focus.now + Duration::from_millis(millis_until_redraw as u64));
+                }
+                if let (Some(on_hover), Some((point, since))) = (&self.on_hover, state.rest) {
+                    if *now >= since + HOVER_DELAY {
+                        state.rest = None;
+                        if let Some(position) = self.char_at(point, &regions, state.char_width) {
+                            shell.publish(on_hover(Some(position)));
+                        }
+                    } else {
+                        shell.request_redraw_at(since + HOVER_DELAY);
+                    }
+                }
+                shell.request_input_method(&self.input_method(state, &regions));
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    state.focus = None;
+                    state.drag = None;
+                    return;
+                };
+                state.focus = Some(Focus::now());
+                state.rest = None;
+
+                if let Some(thumb) = self.scrollbar_thumb(regions.scrollbar).filter(|_| regions.scrollbar.contains(position)) {
+                    let grab = if thumb.contains(position) { position.y - thumb.y } else { thumb.height / 2.0 };
+                    state.drag = Some(Drag::Scrollbar { grab });
+                    shell.publish(on_action(self.scroll_to(position.y, grab, regions.scrollbar)));
+                } else if Self::fold_strip(regions.gutter).contains(position)
+                    && self.document.fold_regions().iter().any(|region| region.start == self.line_at(position.y, regions.text))
+                {
+                    shell.publish(on_action(Action::ToggleFold(self.line_at(position.y, regions.text))));
+                } else if let Some(on_change_click) = self.on_change_click.as_ref().filter(|_| {
+                    Self::change_strip(regions.gutter).contains(position) && self.change_on(self.line_at(position.y, regions.text)).is_some()
+                }) {
+                    shell.publish(on_change_click(self.line_at(position.y, regions.text)));
+                } else if regions.gutter.contains(position) || regions.blame.contains(position) {
+                    let line = self.line_at(position.y, regions.text);
+                    let extend = state.modifiers.shift();
+                    state.drag = Some(Drag::Gutter);
+                    shell.publish(on_action(Action::ClickLine { line, extend }));
+                } else if let Some(on_command_click) = self.on_command_click.as_ref().filter(|_| state.modifiers.command()) {
+                    let target = self.position_at(position, regions.text, state.char_width);
+                    shell.publish(on_action(Action::Click(target)));
+                    shell.publish(on_command_click());
+                } else if state.modifiers.alt() {
+                    let target = self.position_at(position, regions.text, state.char_width);
+                    state.drag = Some(Drag::Box { from: self.cell_at(position, regions.text, state.char_width) });
+                    shell.publish(on_action(Action::AddCursor(target)));
+                } else {
+                    let click = mouse::Click::new(position, mouse::Button::Left, state.last_click);
+                    let target = self.position_at(position, regions.text, state.char_width);
+                    match click.kind() {
+                        mouse::click::Kind::Single => shell.publish(on_action(Action::Click(target))),
+                        mouse::click::Kind::Double => {
+                            shell.publish(on_action(Action::Click(target)));
+                            shell.publish(on_action(Action::SelectWord));
+                        }
+                        mouse::click::Kind::Triple => shell.publish(on_action(Action::SelectLine)),
+                    }
+                    state.last_click = Some(click);
+                    state.drag = Some(Drag::Text);
+                }
+                shell.capture_event();
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => match state.drag {
+                Some(Drag::Text) => {
+                    let target = self.position_at(*position, regions.text, state.char_width);
+                    shell.publish(on_action(Action::Drag(target)));
+                }
+                Some(Drag::Box { from }) => {
+                    let to = self.cell_at(*position, regions.text, state.char_width);
+                    if to != from {
+                        shell.publish(on_action(Action::BoxSelect { from, to }));
+                    }
+                }
+                Some(Drag::Gutter) => {
+                    shell.publish(on_action(Action::DragLine(self.line_at(position.y, regions.text))));
+                }
+                Some(Drag::Scrollbar { grab }) => {
+                    shell.publish(on_action(self.scroll_to(position.y, grab, regions.scrollbar)));
+                }
+                None => {
+                    // The tooltip stays where it came up until the mouse moves off what it's about
+                    let diagnostics = match bounds.contains(*position) {
+                        true => self.diagnostics_at(*position, &regions, state.char_width),
+                        false => Vec::new(),
+                    };
+                    if state.hover.as_ref().map(|hover| &hover.diagnostics) != Some(&diagnostics) {
+                        state.hover = (!diagnostics.is_empty()).then_some(Hover { diagnostics, at: *position });
+                        shell.request_redraw();
+                    }
+                    let Some(on_hover) = self.on_hover.as_ref() else { return; };
+                    state.rest = cursor.position_over(regions.text).map(|point| (point, Instant::now()));
+                    if let Some((_, since)) = state.rest {
+                        shell.request_redraw_at(since + HOVER_DELAY);
+                    }
+                    // Moving off what the info is about closes it, but moving onto the info itself doesn't
+                    if let Some((start, end, _)) = &self.info {
+                        let over = cursor.position().and_then(|point| self.char_at(point, &regions, state.char_width));
+                        if !cursor.is_levitating() && !over.is_some_and(|over| *start <= over && over < *end) {
+                            shell.publish(on_hover(None));
+                        }
+                    }
+                }
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                state.drag = None;
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) if cursor.is_over(bounds) => {
+                let (x, y) = match delta {
+                    mouse::ScrollDelta::Lines { x, y } => (*x * 3.0, *y * 3.0),
+                    mouse::ScrollDelta::Pixels { x, y } => (*x / state.char_width, *y / self.line_height()),
+                };
+                shell.publish(on_action(Action::Scroll { rows: -y, columns: -x }));
+                shell.capture_event();
+            }
+            Event::InputMethod(event) => match event {
+                input_method::Event::Opened | input_method::Event::Closed => {
+                    state.preedit = matches!(event, input_method::Event::Opened).then(input_method::Preedit::new);
+                    shell.request_redraw();
+                }
+                input_method::Event::Preedit(content, selection) if state.focus.is_some() => {
+                    state.preedit = Some(input_method::Preedit {
+                        content: content.clone(),
+                        selection: selection.clone(),
+                        text_size: Some(Pixels(self.text_size)),
+                    });
+                    shell.request_redraw();
+                }
+                input_method::Event::Commit(content) if state.focus.is_some() => {
+                    shell.publish(on_action(Action::Edit(Edit::Paste(Arc::new(content.clone())))));
+                    shell.capture_event();
+                }
+                _ => {}
+            },
+            Event::Keyboard(keyboard::Event::KeyPressed { key, modified_key, physical_key, modifiers, text, .. }) => {
+                if state.focus.is_none() {
+                    return;
+                }
+                let key_press = KeyPress {
+                    key: key.clone(),
+                    modified_key: modified_key.clone(),
+                    physical_key: *physical_key,
+                    modifiers: *modifiers,
+                    text: text.clone(),
+                    status: Status::Focused { is_hovered: cursor.is_over(bounds) },
+                };
+                let binding = match self.key_binding.as_ref() {
+                    Some(key_binding) => key_binding(key_press),
+                    None => Binding::from_key_press(key_press),
+                };
+                if let Some(binding) = binding {
+                    if !matches!(binding, Binding::Unfocus) {
+                        shell.capture_event();
+                    }
+                    self.apply_binding(binding, state, clipboard, shell);
+                    if let Some(focus) = &mut state.focus {
+                        focus.updated_at = Instant::now();
+                    }
+                }
+            }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = *modifiers;
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut iced::Renderer,
+        theme: &Theme,
+        _defaults: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_ref::<State>();
+        let bounds = layout.bounds();
+        let status = match state.focus {
+            Some(_) => Status::Focused { is_hovered: cursor.is_over(bounds) },
+            None if cursor.is_over(bounds) => Status::Hovered,
+            None => Status::Active,
+        };
+        let style = (self.style)(theme, status);
+        let regions = self.regions(bounds, state.char_width);
+        let line_height = self.line_height();
+        let char_width = state.char_width;
+
+        let document = self.document;
+        let scroll_row = document.scroll_row();
+        let first_row = scroll_row.floor() as usize;
+        let last_row = ((scroll_row + regions.text.height / line_height).ceil() as usize)
+            .min(document.row_count().saturating_sub(1));
+        let rows: Vec<Row> = (first_row..=last_row)
+            .map(|row| {
+                let (line, start, end) = document.row_segment(row);
+                Row {
+                    line,
+                    start,
+                    end,
+                    offset: document.display_column(line, start),
+                    end_display: document.display_column(line, end),
+                    y: regions.text.y + (row as f32 - scroll_row) * line_height,
+                }
+            })
+            .collect();
+        // Only past the end of the text, which the document keeps from happening
+        if rows.is_empty() {
+            return;
+        }
+        let (first_line, last_line) = (rows[0].line, rows[rows.len() - 1].line);
+        let text_x = regions.text.x + TEXT_PADDING - document.scroll_column() * char_width;
+        let selection = document.selection();
+        let cursor_line = selection.head.line;
+
+        renderer.fill_quad(Quad { bounds, ..Quad::default() }, style.background);
+        renderer.fill_quad(Quad { bounds: regions.gutter, ..Quad::default() }, style.gutter_background);
+
+        // Blame, named on the first line of each run of lines from the same commit
+        if let Some(blame) = self.blame {
+            renderer.fill_quad(Quad { bounds: regions.blame, ..Quad::default() }, style.gutter_background);
+            renderer.with_layer(regions.blame, |renderer| {
+                for row in rows.iter().filter(|row| row.is_first() && blame.starts_run(row.line)) {
+                    let Some(commit) = blame.line(row.line) else { continue; };
+                    renderer.fill_text(
+                        self.text_style(renderer, blame_label(commit)),
+                        Point::new(regions.blame.x + GUTTER_PADDING_LEFT, row.y),
+                        style.line_number,
+                        regions.blame,
+                    );
+                }
+            });
+        }
+
+        // Line numbers, on the first row of each line
+        if self.settings.line_numbers != LineNumbers::Off {
+            renderer.with_layer(regions.gutter, |renderer| {
+                let right = regions.gutter.x + regions.gutter.width - GUTTER_PADDING_RIGHT;
+                for row in rows.iter().filter(|row| row.is_first()) {
+                    let number = match self.settings.line_numbers {
+                        // Folded lines don't count, so the numbers don't jump when something folds
+                        LineNumbers::Relative if row.line != cursor_line => document.visible_distance(row.line, cursor_line),
+                        _ => row.line + 1,
+                    };
+                    let label = number.to_string();
+                    let x = right - label.len() as f32 * char_width;
+                    let color = if row.line == cursor_line { style.active_line_number } else { style.line_number };
+                    renderer.fill_text(self.text_style(renderer, label), Point::new(x, row.y), color, regions.gutter);
+                }
+            });
+        }
+
+        // A dot in the severity's colour next to lines where problems start, for the worst of them
+        renderer.with_layer(regions.gutter, |renderer| {
+            for row in rows.iter().filter(|row| row.is_first()) {
+                let worst = self
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.start.line == row.line && diagnostic.severity != Severity::Hint)
+                    .map(|diagnostic| diagnostic.severity)
+                    .min();
+                let Some(severity) = worst else { continue; };
+                let bounds = Rectangle {
+                    x: regions.gutter.x + (GUTTER_PADDING_LEFT - DIAGNOSTIC_DOT_SIZE) / 2.0,
+                    y: row.y + (line_height - DIAGNOSTIC_DOT_SIZE) / 2.0,
+                    width: DIAGNOSTIC_DOT_SIZE,
+                    height: DIAGNOSTIC_DOT_SIZE,
+                };
+                renderer.fill_quad(
+                    Quad { bounds, border: Border { radius: (DIAGNOSTIC_DOT_SIZE / 2.0).into(), ..Border::default() }, ..Quad::default() },
+                    style.severity(severity),
+                );
+            }
+        });
+
+        // A bar next to added and modified lines, and a wedge where lines were deleted
+        renderer.with_layer(regions.gutter, |renderer| {
+            for hunk in self.changes.iter().filter(|hunk| hunk.current.start <= last_line + 1 && hunk.current.end >= first_line) {
+                let color = style.change(hunk.change());
+                if hunk.current.is_empty() {
+                    // On the boundary between the lines, or under the last line if they were at the end
+                    let y = match rows.iter().find(|row| row.line >= hunk.current.start && row.is_first()) {
+                        Some(row) => row.y,
+                        None if last_line + 1 == hunk.current.start => rows[rows.len() - 1].y + line_height,
+                        None => continue,
+                    };
+                    let bounds = Rectangle {
+                        x: regions.gutter.x,
+                        y: y - CHANGE_WEDGE_SIZE / 2.0,
+                        width: CHANGE_WEDGE_SIZE,
+                        height: CHANGE_WEDGE_SIZE,
+                    };
+                    let border = Border { radius: iced::border::right(CHANGE_WEDGE_SIZE / 2.0), ..Border::default() };
+                    renderer.fill_quad(Quad { bounds, border, ..Quad::default() }, color);
+                    continue;
+                }
+                for row in rows.iter().filter(|row| hunk.current.contains(&row.line)) {
+                    let bounds = Rectangle { x: regions.gutter.x, y: row.y, width: CHANGE_BAR_WIDTH, height: line_height };
+                    renderer.fill_quad(Quad { bounds, ..Quad::default() }, color);
+                }
+            }
+        });
+
+        // Fold chevrons, the open ones only while the mouse is over the gutter
+        let hovering_gutter = cursor.is_over(regions.gutter);
+        renderer.with_layer(regions.gutter, |renderer| {
+            let strip = Self::fold_strip(regions.gutter);
+            let fold_regions = document.fold_regions();
+            for row in rows.iter().filter(|row| row.is_first()) {
+                if fold_regions.binary_search_by_key(&row.line, |region| region.start).is_err() {
+                    continue;
+                }
+                let folded = document.is_folded(row.line);
+                if !folded && !hovering_gutter {
+                    continue;
+                }
+                let chevron = if folded { "›" } else { "⌄" };
+                let x = strip.x + (strip.width - char_width) / 2.0;
+                let y = row.y - if folded { 0.0 } else { line_height * 0.2 };
+                renderer.fill_text(
+                    self.text_style(renderer, chevron.to_string()),
+                    Point::new(x, y),
+                    if folded { style.active_line_number } else { style.line_number },
+                    regions.gutter,
+                );
+            }
+        });
+
+        renderer.with_layer(regions.text, |renderer| {
+            // The parts of a line between two display columns, split across the rows it wraps onto
+            let spans = |line: usize, from: usize, to: usize| {
+                rows.iter().filter(move |row| row.line == line).filter_map(move |row| {
+                    let start = from.max(row.offset);
+                    let end = if row.is_last(document) { to } else { to.min(row.end_display) };
+                    (end > start).then(|| Rectangle {
+                        x: text_x + (start - row.offset) as f32 * char_width,
+                        y: row.y,
+                        width: (end - start) as f32 * char_width,
+                        height: line_height,
+                    })
+                })
+            };
+            let columns = |line: usize, from: usize, to: usize| {
+                spans(line, document.display_column(line, from), document.display_column(line, to))
+            };
+            let decorations = Decorations::compute(document, first_line..last_line + 1);
+
+            // Decorations go under the selection and the text
+            for row in rows.iter().filter(|row| decorations.current_lines.contains(&row.line)) {
+                let bounds = Rectangle { y: row.y, height: line_height, ..regions.text };
+                renderer.fill_quad(Quad { bounds, ..Quad::default() }, style.current_line);
+            }
+            if self.settings.indent_guides {
+                for row in rows.iter().filter(|row| row.is_first()) {
+                    let indent = decorations.guide_indents[row.line - first_line];
+                    for column in (0..indent).step_by(document.indentation().width()) {
+                        let active = self.settings.highlight_active_indent
+                            && decorations
+                                .active_guide
+                                .as_ref()
+                                .is_some_and(|(active, lines)| *active == column && lines.contains(&row.line));
+                        let bounds = Rectangle {
+                            x: text_x + column as f32 * char_width,
+                            y: row.y,
+                            width: INDENT_GUIDE_WIDTH,
+                            height: line_height,
+                        };
+                        let color = if active { style.active_indent_guide } else { style.indent_guide };
+                        renderer.fill_quad(Quad { bounds, ..Quad::default() }, color);
+                    }
+                }
+            }
+            let occurrence = if selection.is_empty() { style.word_highlight } else { style.selection_highlight };
+            for (start, end) in &decorations.occurrences {
+                for bounds in columns(start.line, start.column, end.column) {
+                    renderer.fill_quad(Quad { bounds, ..Quad::default() }, occurrence);
+                }
+            }
+            for bracket in &decorations.brackets {
+                for bounds in columns(bracket.line, bracket.column, bracket.column + 1) {
+                    renderer.fill_quad(
+                        Quad {
+                            bounds,
+                            border: Border { color: style.bracket_match_border, width: 1.0, ..Border::default() },
+                            ..Quad::default()
+                        },
+                        style.bracket_match,
+                    );
+                }
+            }
+            // The snippet stops Tab still goes to, with empty ones as a thin bar
+            for (start, end) in document.upcoming_tab_stops() {
+                for line in start.line.max(first_line)..=end.line.min(last_line) {
+                    let from = if line == start.line { start.column } else { 0 };
+                    let to = if line == end.line { end.column } else { document.line_len(line) };
+                    let bounds: Vec<Rectangle> = if from == to {
+                        let display = document.display_column(line, from);
+                        spans(line, display, display + 1)
+                            .map(|bounds| Rectangle { width: EMPTY_TAB_STOP_WIDTH, ..bounds })
+                            .collect()
+                    } else {
+                        columns(line, from, to).collect()
+                    };
+                    for bounds in bounds {
+                        renderer.fill_quad(Quad { bounds, ..Quad::default() }, style.snippet_tab_stop);
+                    }
+                }
+            }
+
+            // Selections
+            for selection in document.selections().iter().filter(|selection| !selection.is_empty()) {
+                let (start, end) = (selection.start(), selection.end());
+                for line in start.line.max(first_line)..=end.line.min(last_line) {
+                    let from = if line == start.line { document.display_column(line, start.column) } else { 0 };
+                    let to = if line == end.line {
+                        document.display_column(line, end.column)
+                    } else {
+                        // Include the line break so empty lines still show as selected
+                        document.display_column(line, document.line_len(line)) + 1
+                    };
+                    for bounds in spans(line, from, to) {
+                        renderer.fill_quad(Quad { bounds, ..Quad::default() }, style.selection);
+                    }
+                }
+            }
+
+            // A "…" after folded lines, standing in for what's hidden
+            for row in rows.iter().filter(|row| row.is_last(document) && document.is_folded(row.line)) {
+                let bounds = Rectangle {
+                    x: text_x + (row.end_display - row.offset + 1) as f32 * char_width,
+                    y: row.y + 2.0,
+                    width: char_width * 2.0,
+                    height: line_height - 4.0,
+                };
+                renderer.fill_quad(
+                    Quad { bounds, border: Border { radius: 3.0.into(), ..Border::default() }, ..Quad::default() },
+                    style.fold_placeholder,
+                );
+                renderer.fill_text(
+                    self.text_style(renderer, "…".to_string()),
+                    Point::new(bounds.x + char_width / 2.0, row.y),
+                    style.line_number,
+                    regions.text,
+                );
+            }
+
+            // Text, clipped to the part of each line its row shows. It's split where inlay hints go,
+            // so the text after a hint makes room for it.
+            let mut highlights = document.highlights().borrow_mut();
+            highlights.update(&self.highlight, document.lines(), last_line);
+            for row in &rows {
+                let content = document.line(row.line);
+                let (row_start, row_end) = (byte_index(content, row.start), byte_index(content, row.end));
+                let hints = document.inlay_hints_on(row.line);
+                let spans = highlights.line(row.line);
+                let pieces: Vec<(usize, usize, Color)> = match spans.is_empty() {
+                    true => vec![(row_start, row_end, style.text)],
+                    false => spans
+                        .iter()
+                        .map(|(range, highlight)| (range.start.max(row_start), range.end.min(row_end), highlight.0))
+                        .filter(|(start, end, _)| start < end)
+                        .collect(),
+                };
+
+                for (start, end, color) in pieces {
+                    let cuts = hints
+                        .iter()
+                        .map(|hint| byte_index(content, hint.position.column))
+                        .filter(|cut| start < *cut && *cut < end);
+                    let mut from = start;
+                    for to in cuts.chain([end]) {
+                        if let Some(piece) = content.get(from..to).filter(|piece| !piece.trim().is_empty()) {
+                            let display = document.char_display_column(row.line, content[..from].chars().count());
+                            renderer.fill_text(
+                                self.text_style(renderer, expand_tabs(piece, display)),
+                                Point::new(text_x + (display - row.offset) as f32 * char_width, row.y),
+                                color,
+                                regions.text,
+                            );
+                        }
+                        from = to;
+                    }
+                }
+
+                // The hints themselves, faint. Ones at the same column go one after the other.
+                let mut same_column = (usize::MAX, 0);
+                for hint in hints {
+                    let column = hint.position.column;
+                    if column < row.start || column > row.end || (column == row.end && !row.is_last(document)) {
+                        continue;
+                    }
+                    if same_column.0 != column {
+                        same_column = (column, 0);
+                    }
+                    let display = document.display_column(row.line, column) + same_column.1;
+                    same_column.1 += hint.width();
+                    let bounds = Rectangle {
+                        x: text_x + (display - row.offset) as f32 * char_width,
+                        y: row.y + 1.0,
+                        width: hint.width() as f32 * char_width,
+                        height: line_height - 2.0,
+                    };
+                    renderer.fill_quad(
+                        Quad { bounds, border: Border { radius: 3.0.into(), ..Border::default() }, ..Quad::default() },
+                        style.inlay_hint_background,
+                    );
+                    renderer.fill_text(
+                        self.text_style(renderer, hint.label.clone()),
+                        Point::new(bounds.x, row.y),
+                        style.inlay_hint,
+                        regions.text,
+                    );
+                }
+            }
+            drop(highlights);
+
+            // Squiggles under problems, worst drawn last so it ends up on top. Hints only get a
+            // few dots at their start.
+            let mut diagnostics: Vec<&Diagnostic> = self
+                .diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.start.line <= last_line && diagnostic.end.line >= first_line)
+                .collect();
+            diagnostics.sort_by_key(|diagnostic| std::cmp::Reverse(diagnostic.severity));
+            for diagnostic in diagnostics {
+                let (start, end) = (diagnostic.start, diagnostic.end);
+                let color = style.severity(diagnostic.severity);
+                if diagnostic.severity == Severity::Hint {
+                    for bounds in columns(start.line, start.column, start.column + 1).take(1) {
+                        draw_hint_dots(renderer, bounds, color);
+                    }
+                    continue;
+                }
+                for line in start.line.max(first_line)..=end.line.min(last_line) {
+                    let from = if line == start.line { start.column } else { 0 };
+                    let to = if line == end.line { end.column } else { document.line_len(line) };
+                    // An empty range still gets a char's worth of squiggle so it can be seen
+                    let to = if start == end { to + 1 } else { to };
+                    for bounds in columns(line, from, to) {
+                        draw_squiggle(renderer, bounds, color);
+                    }
+                }
+            }
+
+            // Whitespace, as "·" for spaces and "→" for tabs
+            if self.settings.render_whitespace != RenderWhitespace::None {
+                for row in &rows {
+                    let content = document.line(row.line);
+                    let trailing = match self.settings.render_whitespace {
+                        RenderWhitespace::Trailing => content.trim_end().chars().count(),
+                        _ => 0,
+                    };
+                    let mut hints = document.inlay_hints_on(row.line).iter().peekable();
+                    let mut display = 0;
+                    for (column, c) in content.chars().enumerate().take(row.end) {
+                        while let Some(hint) = hints.next_if(|hint| hint.position.column <= column) {
+                            display += hint.width();
+                        }
+                        let symbol = match c {
+                            ' ' => Some("·"),
+                            '\t' => Some("→"),
+                            _ => None,
+                        };
+                        if let Some(symbol) = symbol.filter(|_| column >= trailing && column >= row.start) {
+                            renderer.fill_text(
+                                self.text_style(renderer, symbol.to_string()),
+                                Point::new(text_x + (display - row.offset) as f32 * char_width, row.y),
+                                style.whitespace,
+                                regions.text,
+                            );
+                        }
+                        display = display_width(std::iter::once(c), display);
+                    }
+                }
+            }
+
+            // The annotation after the cursor's line, past anything already drawn at its end
+            if let Some(annotation) = &self.annotation
+                && let Some(row) = rows.iter().find(|row| row.line == cursor_line && row.is_last(document))
+            {
+                let end = document.char_display_column(row.line, document.line_len(row.line)) - row.offset;
+                let end = if document.is_folded(row.line) { end + 3 } else { end };
+                renderer.fill_text(
+                    self.text_style(renderer, annotation.clone()),
+                    Point::new(text_x + (end + ANNOTATION_GAP) as f32 * char_width, row.y),
+                    style.inlay_hint,
+                    regions.text,
+                );
+            }
+
+            // Cursors
+            if state.focus.as_ref().is_some_and(Focus::is_cursor_visible) {
+                for head in document.selections().iter().map(|selection| selection.head) {
+                    if document.is_hidden(head.line) {
+                        continue;
+                    }
+                    let (row, column) = document.visual_position(head);
+                    if !(first_row..=last_row).contains(&row) {
+                        continue;
+                    }
+                    renderer.fill_quad(
+                        Quad {
+                            bounds: Rectangle {
+                                x: text_x + column as f32 * char_width,
+                                y: rows[row - first_row].y,
+                                width: CURSOR_WIDTH,
+                                height: line_height,
+                            },
+                            ..Quad::default()
+                        },
+                        style.cursor,
+                    );
+                }
+            }
+        });
+
+        if let Some(thumb) = self.scrollbar_thumb(regions.scrollbar) {
+            renderer.fill_quad(
+                Quad {
+                    bounds: thumb.shrink(1.0),
+                    border: Border { radius: (SCROLLBAR_WIDTH / 2.0).into(), ..Border::default() },
+                    ..Quad::default()
+                },
+                style.scrollbar,
+            );
+        }
+
+        if let (Some(hover), None) = (&state.hover, self.completion) {
+            let diagnostics: Vec<&Diagnostic> = hover.diagnostics.iter().filter_map(|index| self.diagnostics.get(*index)).collect();
+            if !diagnostics.is_empty() {
+                let row = self.row_at(hover.at.y, regions.text) as f32;
+                let anchor = Point::new(hover.at.x, regions.text.y + (row - scroll_row) * line_height);
+                self.draw_hover(renderer, &style, &diagnostics, anchor, bounds, char_width);
+            }
+        }
+
+        if let Some(completion) = self.completion {
+            self.draw_completion(renderer, &style, completion, bounds, char_width);
+        }
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'b>,
+        _renderer: &iced::Renderer,
+        _viewport: &Rectangle,
+        translation: Vector,
+    ) -> Option<overlay::Element<'b, Message, Theme, iced::Renderer>> {
+        let char_width = tree.state.downcast_ref::<State>().char_width;
+        let bounds = layout.bounds();
+        // What each popover goes next to, if it's in view
+        let popup = self.completion.map(|completion| self.completion_popup(completion, bounds, char_width).0);
+        let info = self.info.as_ref().and_then(|(start, _, _)| self.char_bounds(*start, bounds, char_width));
+        let cursor = self.char_bounds(self.document.cursor(), bounds, char_width);
+        let hunk = self.hunk.as_ref().and_then(|(hunk, _)| self.hunk_bounds(hunk, bounds, char_width));
+        let targets = [
+            (popup, Side::Right, DOCUMENTATION_MAX_SIZE, Popover::<Message>::completion_colors as ColorsFn),
+            (info, Side::Below, INFO_MAX_SIZE, Popover::<Message>::hover_colors),
+            (cursor, Side::Above, SIGNATURE_MAX_SIZE, Popover::<Message>::hover_colors),
+            (hunk, Side::Below, HUNK_MAX_SIZE, Popover::<Message>::hover_colors),
+        ];
+
+        let contents = [
+            self.documentation.as_mut(),
+            self.info.as_mut().map(|(_, _, content)| content),
+            self.signature_help.as_mut(),
+            self.hunk.as_mut().map(|(_, content)| content),
+        ];
+        let mut trees = tree.children.iter_mut();
+        let mut popovers = Vec::new();
+        for (content, (target, side, max_size, colors)) in contents.into_iter().zip(targets) {
+            let Some(content) = content else { continue; };
+            let Some(tree) = trees.next() else { break; };
+            let Some(target) = target else { continue; };
+            popovers.push(overlay::Element::new(Box::new(Popover {
+                content,
+                tree,
+                target: target + translation,
+                side,
+                max_size,
+                colors,
+                style: &self.style,
+            })));
+        }
+        (!popovers.is_empty()).then(|| overlay::Group::with_children(popovers).overlay())
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &iced::Renderer,
+    ) -> mouse::Interaction {
+        let state = tree.state.downcast_ref::<State>();
+        let regions = self.regions(layout.bounds(), state.char_width);
+        match cursor.position() {
+            Some(position) if regions.scrollbar.contains(position) => mouse::Interaction::Idle,
+            Some(position)
+                if self.on_change_click.is_some()
+                    && Self::change_strip(regions.gutter).contains(position)
+                    && self.change_on(self.line_at(position.y, regions.text)).is_some() =>
+            {
+                mouse::Interaction::Pointer
+            }
+            Some(position) if regions.text.contains(position) => mouse::Interaction::Text,
+            _ => mouse::Interaction::default(),
+        }
+    }
+
+    fn operate(
+        &mut self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        _renderer: &iced::Renderer,
+        operation: &mut dyn widget::Operation,
+    ) {
+        let state = tree.state.downcast_mut::<State>();
+        operation.focusable(self.id.as_ref(), layout.bounds(), state);
+    }
+}
+
+impl<Message> CodeEditor<'_, Message> {
+    /// Where the completion popup goes: under the cursor's row, or above it if there's no room
+    /// below. Also how many chars of label and detail it has room for.
+    fn completion_popup(&self, completion: &Completion, bounds: Rectangle, char_width: f32) -> (Rectangle, usize) {
+        let regions = self.regions(bounds, char_width);
+        let line_height = self.line_height();
+        let document = self.document;
+        let (row, column) = document.visual_position(document.selection().head);
+        // The labels line up with the text they replace, and the kind's letter goes before it
+        let text_x = regions.text.x + TEXT_PADDING - document.scroll_column() * char_width;
+        let column = column.saturating_sub(completion.selected().replace) as f32 - COMPLETION_ICON_CHARS as f32;
+        let anchor = Point::new(
+            text_x + column * char_width,
+            regions.text.y + (row as f32 - document.scroll_row()) * line_height,
+        );
+
+        let first = completion.first_visible();
+        let shown = &completion.items[first..completion.items.len().min(first + VISIBLE_ITEMS)];
+        // Room for the longest label, then its detail or kind on the right
+        let chars = shown
+            .iter()
+            .map(|ranked| {
+                let detail = ranked.item.detail.as_deref().unwrap_or(ranked.item.kind.label());
+                ranked.item.label.chars().count() + 2 + detail.chars().count()
+            })
+            .max()
+            .unwrap_or(0)
+            .min(COMPLETION_MAX_CHARS);
+        let width = (COMPLETION_ICON_CHARS + chars) as f32 * char_width + COMPLETION_PADDING * 2.0;
+        let height = shown.len() as f32 * line_height;
+        let below = anchor.y + line_height;
+        let y = if below + height > bounds.y + bounds.height && anchor.y - height >= bounds.y {
+            anchor.y - height
+        } else {
+            below
+        };
+        let popup = Rectangle {
+            x: anchor.x.min(bounds.x + bounds.width - width).max(bounds.x) - COMPLETION_PADDING,
+            y,
+            width,
+            height,
+        };
+        (popup, chars)
+    }
+
+    /// Draws the completion popup, with each item's kind, label and detail.
+    fn draw_completion(
+        &self,
+        renderer: &mut iced::Renderer,
+        style: &Style,
+        completion: &Completion,
+        bounds: Rectangle,
+        char_width: f32,
+    ) {
+        let line_height = self.line_height();
+        let first = completion.first_visible();
+        let shown = &completion.items[first..completion.items.len().min(first + VISIBLE_ITEMS)];
+        let (popup, chars) = self.completion_popup(completion, bounds, char_width);
+
+        renderer.with_layer(bounds, |renderer| {
+            renderer.fill_quad(
+                Quad {
+                    bounds: popup,
+                    border: Border { color: style.completion_border, width: 1.0, radius: 3.0.into() },
+                    ..Quad::default()
+                },
+                style.completion_background,
+            );
+            for (index, ranked) in shown.iter().enumerate() {
+                let y = popup.y + index as f32 * line_height;
+                if first + index == completion.selected {
+                    let bounds = Rectangle { y, height: line_height, ..popup };
+                    renderer.fill_quad(Quad { bounds, ..Quad::default() }, style.completion_selected);
+                }
+
+                // The matched chars go in their own colour, drawn over the gaps left for them
+                let label = &ranked.item.label;
+                let blank_unless = |matched: bool| -> String {
+                    label
+                        .chars()
+                        .enumerate()
+                        .map(|(i, c)| if ranked.matches.contains(&i) == matched { c } else { ' ' })
+                        .collect()
+                };
+                let icon = ranked.item.kind.icon().to_string();
+                let icon_position = Point::new(popup.x + COMPLETION_PADDING, y);
+                renderer.fill_text(self.text_style(renderer, icon), icon_position, style.kind(ranked.item.kind), popup);
+                let x = popup.x + COMPLETION_PADDING + COMPLETION_ICON_CHARS as f32 * char_width;
+                for (text, color) in [(blank_unless(false), style.completion_text), (blank_unless(true), style.completion_match)] {
+                    renderer.fill_text(self.text_style(renderer, text), Point::new(x, y), color, popup);
+                }
+
+                let detail = ranked.item.detail.as_deref().unwrap_or(ranked.item.kind.label());
+                let room = chars.saturating_sub(label.chars().count() + 2);
+                let detail: String = match detail.chars().count() > room {
+                    true => detail.chars().take(room.saturating_sub(1)).chain(['…']).collect(),
+                    false => detail.to_string(),
+                };
+                let detail_x = popup.x + popup.width - COMPLETION_PADDING - detail.chars().count() as f32 * char_width;
+                renderer.fill_text(self.text_style(renderer, detail), Point::new(detail_x, y), style.line_number, popup);
+            }
+        });
+    }
+
+    /// Draws the messages of the diagnostics under the mouse above the row at `anchor`, or below
+    /// it if there's no room above.
+    fn draw_hover(
+        &self,
+        renderer: &mut iced::Renderer,
+        style: &Style,
+        diagnostics: &[&Diagnostic],
+        anchor: Point,
+        bounds: Rectangle,
+        char_width: f32,
+    ) {
+        let line_height = self.line_height();
+        // Each message's lines, wrapped, with where it came from after the last one. The first
+        // line of each gets the severity's colour next to it.
+        let lines: Vec<(String, Option<String>, Option<Color>)> = diagnostics
+            .iter()
+            .flat_map(|diagnostic| {
+                let mut lines: Vec<(String, Option<String>, Option<Color>)> =
+                    diagnostic.message.lines().flat_map(wrap_line).map(|line| (line, None, None)).collect();
+                if let Some(first) = lines.first_mut() {
+                    first.2 = Some(style.severity(diagnostic.severity));
+                }
+                if let (Some(last), Some(origin)) = (lines.last_mut(), diagnostic.origin()) {
+                    match last.0.chars().count() + 1 + origin.chars().count() <= HOVER_MAX_CHARS {
+                        true => last.1 = Some(origin),
+                        false => lines.push((String::new(), Some(origin), None)),
+                    }
+                }
+                lines
+            })
+            .collect();
+
+        let chars = lines
+            .iter()
+            .map(|(line, origin, _)| line.chars().count() + origin.as_ref().map_or(0, |origin| origin.chars().count() + 1))
+            .max()
+            .unwrap_or(0);
+        let width = chars as f32 * char_width + HOVER_PADDING * 2.0;
+        let height = lines.len() as f32 * line_height + HOVER_PADDING * 2.0;
+        let y = if anchor.y - height >= bounds.y { anchor.y - height } else { anchor.y + line_height };
+        let tooltip = Rectangle {
+            x: anchor.x.min(bounds.x + bounds.width - width).max(bounds.x),
+            y,
+            width,
+            height,
+        };
+
+        renderer.with_layer(bounds, |renderer| {
+            renderer.fill_quad(
+                Quad {
+                    bounds: tooltip,
+                    border: Border { color: style.hover_border, width: 1.0, radius: 3.0.into() },
+                    ..Quad::default()
+                },
+                style.hover_background,
+            );
+            for (index, (line, origin, severity)) in lines.iter().enumerate() {
+                let point = Point::new(tooltip.x + HOVER_PADDING, tooltip.y + HOVER_PADDING + index as f32 * line_height);
+                if let Some(color) = severity {
+                    let bar = Rectangle { x: tooltip.x + 1.0, y: point.y, width: 2.0, height: line_height };
+                    renderer.fill_quad(Quad { bounds: bar, ..Quad::default() }, *color);
+                }
+                renderer.fill_text(self.text_style(renderer, line.clone()), point, style.text, tooltip);
+                if let Some(origin) = origin {
+                    let offset = if line.is_empty() { 0 } else { line.chars().count() + 1 };
+                    let point = Point::new(point.x + offset as f32 * char_width, point.y);
+                    renderer.fill_text(self.text_style(renderer, origin.clone()), point, style.line_number, tooltip);
+                }
+            }
+        });
+    }
+
+    fn scroll_to(&self, y: f32, grab: f32, track: Rectangle) -> Action {
+        let line_count = self.document.row_count().max(2);
+        let thumb = self.scrollbar_thumb(track).map(|t| t.height).unwrap_or(SCROLLBAR_MIN_THUMB);
+        let progress = ((y - grab - track.y) / (track.height - thumb).max(1.0)).clamp(0.0, 1.0);
+        Action::ScrollTo { row: progress * (line_count as f32 - 1.0) }
+    }
+
+    fn input_method<'b>(&self, state: &'b State, regions: &Regions) -> InputMethod<&'b str> {
+        let Some(Focus { is_window_focused: true, .. }) = &state.focus else {
+            return InputMethod::Disabled;
+        };
+        let (row, column) = self.document.visual_position(self.document.cursor());
+        let column = column as f32 - self.document.scroll_column();
+        let row = row as f32 - self.document.scroll_row();
+        InputMethod::Enabled {
+            cursor: Rectangle {
+                x: regions.text.x + TEXT_PADDING + column * state.char_width,
+                y: regions.text.y + row * self.line_height(),
+                width: 1.0,
+                height: self.line_height(),
+            },
+            purpose: input_method::Purpose::Normal,
+            preedit: state.preedit.as_ref().map(input_method::Preedit::as_ref),
+        }
+    }
+
+    fn apply_binding(
+        &self,
+        binding: Binding<Message>,
+        state: &mut State,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) {
+        let Some(on_action) = self.on_action.as_ref() else { return; };
+        let mut publish = |action| shell.publish(on_action(action));
+
+        match binding {
+            Binding::Unfocus if self.document.selections().len() > 1 || self.document.in_snippet() => {
+                publish(Action::CollapseCursors)
+            }
+            Binding::Unfocus => {
+                state.focus = None;
+                state.drag = None;
+            }
+            Binding::Copy => {
+                if let Some(selection) = self.document.selected_text() {
+                    clipboard.write(clipboard::Kind::Standard, selection);
+                }
+            }
+            Binding::Cut => {
+                if let Some(selection) = self.document.selected_text() {
+                    clipboard.write(clipboard::Kind::Standard, selection);
+                    publish(Action::Cut);
+                }
+            }
+            Binding::Paste => {
+                if let Some(contents) = clipboard.read(clipboard::Kind::Standard) {
+                    publish(Action::Edit(Edit::Paste(Arc::new(contents))));
+                }
+            }
+            Binding::Move(motion) => publish(Action::Move(motion)),
+            Binding::Select(motion) => publish(Action::Select(motion)),
+            Binding::SelectWord => publish(Action::SelectWord),
+            Binding::SelectLine => publish(Action::SelectLine),
+            Binding::SelectAll => publish(Action::SelectAll),
+            Binding::Insert(c) => publish(Action::Edit(Edit::Insert(c))),
+            Binding::Enter => publish(Action::Edit(Edit::Enter)),
+            Binding::Backspace => publish(Action::Edit(Edit::Backspace)),
+            Binding::Delete => publish(Action::Edit(Edit::Delete)),
+            Binding::Sequence(sequence) => {
+                for binding in sequence {
+                    self.apply_binding(binding, state, clipboard, shell);
+                }
+            }
+            Binding::Custom(message) => shell.publish(message),
+        }
+    }
+}
+
+/// A commit as the blame column shows it: its id, date and author, cut off to fit.
+fn blame_label(commit: &BlameCommit) -> String {
+    let label = match &commit.id {
+        Some(id) => format!("{} {} {}", id, git::date(commit.time), commit.author),
+        None => commit.summary.clone(),
+    };
+    label.chars().take(BLAME_CHARS - 1).collect()
+}
+
+/// A wavy line along the bottom of `bounds`.
+fn draw_squiggle(renderer: &mut iced::Renderer, bounds: Rectangle, color: Color) {
+    let top = bounds.y + bounds.height - 3.0;
+    for step in 0..bounds.width.ceil() as usize {
+        // Up and down like a triangle wave: 0, 1, 2, 1, 0, ...
+        let phase = step % SQUIGGLE_PERIOD;
+        let offset = phase.min(SQUIGGLE_PERIOD - phase) as f32;
+        let pixel = Rectangle { x: bounds.x + step as f32, y: top + offset, width: 1.0, height: 1.0 };
+        renderer.fill_quad(Quad { bounds: pixel, ..Quad::default() }, color);
+    }
+}
+
+/// Three dots under the start of `bounds`, the way VS Code marks hints.
+fn draw_hint_dots(renderer: &mut iced::Renderer, bounds: Rectangle, color: Color) {
+    for dot in 0..3 {
+        let bounds = Rectangle { x: bounds.x + dot as f32 * 3.0, y: bounds.y + bounds.height - 2.0, width: 1.5, height: 1.5 };
+        renderer.fill_quad(Quad { bounds, ..Quad::default() }, color);
+    }
+}
+
+/// Splits a line of a hover message into pieces that fit the tooltip.
+fn wrap_line(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(HOVER_MAX_CHARS).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// Replaces tabs with spaces up to the next tab stop, `start` being the display column of the first char.
+fn expand_tabs(text: &str, start: usize) -> String {
+    if !text.contains('\t') {
+        return text.to_string();
+    }
+    let mut shown = String::with_capacity(text.len());
+    let mut column = start;
+    for c in text.chars() {
+        let next = display_width(std::iter::once(c), column);
+        if c == '\t' {
+            shown.extend(std::iter::repeat_n(' ', next - column));
+        } else {
+            shown.push(c);
+        }
+        column = next;
+    }
+    shown
+}
+
+/// A screen row of the editor: a whole line, or the part of a wrapped line that fits.
+struct Row {
+    line: usize,
+    /// The chars of the line shown on this row
+    start: usize,
+    end: usize,
+    /// Where `start` and `end` fall on screen, in display columns from the start of the line
+    offset: usize,
+    end_display: usize,
+    y: f32,
+}
+
+impl Row {
+    fn is_first(&self) -> bool {
+        self.start == 0
+    }
+
+    fn is_last(&self, document: &Document) -> bool {
+        self.end == document.line_len(self.line)
+    }
+}
+
+/// Which side of what it's about a popover goes on. It goes on the other side if there's no room.
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Right,
+    Below,
+    Above,
+}
+
+/// A popover's background, border and text colours.
+type ColorsFn = fn(&Style) -> (Color, Color, Color);
+
+/// Something shown floating next to a part of the editor, like the selected completion item's
+/// documentation next to the popup, or what the language server says about a symbol.
+struct Popover<'a, 'b, Message> {
+    content: &'b mut Element<'a, Message>,
+    tree: &'b mut Tree,
+    target: Rectangle,
+    side: Side,
+    max_size: Size,
+    colors: ColorsFn,
+    style: &'b StyleFn<'a>,
+}
+
+impl<Message> Popover<'_, '_, Message> {
+    fn completion_colors(style: &Style) -> (Color, Color, Color) {
+        (style.completion_background, style.completion_border, style.completion_text)
+    }
+
+    fn hover_colors(style: &Style) -> (Color, Color, Color) {
+        (style.hover_background, style.hover_border, style.text)
+    }
+}
+
+impl<Message> overlay::Overlay<Message, Theme, iced::Renderer> for Popover<'_, '_, Message> {
+    fn layout(&mut self, renderer: &iced::Renderer, bounds: Size) -> layout::Node {
+        let limits = layout::Limits::new(Size::ZERO, self.max_size).shrink(Padding::new(COMPLETION_PADDING));
+        let content = self.content.as_widget_mut().layout(self.tree, renderer, &limits);
+        let size = content.size().expand(Padding::new(COMPLETION_PADDING)).min(self.max_size);
+
+        let target = self.target;
+        let (x, y) = match self.side {
+            Side::Right => {
+                let right = target.x + target.width + POPOVER_GAP;
+                let x = match right + size.width <= bounds.width {
+                    true => right,
+                    false => (target.x - POPOVER_GAP - size.width).max(0.0),
+                };
+                (x, target.y.min(bounds.height - size.height).max(0.0))
+            }
+            Side::Below | Side::Above => {
+                let below = target.y + target.height + POPOVER_GAP;
+                let above = target.y - POPOVER_GAP - size.height;
+                let y = match (self.side, below + size.height <= bounds.height, above >= 0.0) {
+                    (Side::Below, true, _) | (_, _, false) => below,
+                    _ => above,
+                };
+                (target.x.min(bounds.width - size.width).max(0.0), y)
+            }
+        };
+        layout::Node::with_children(size, vec![content.translate(Vector::new(COMPLETION_PADDING, COMPLETION_PADDING))])
+            .translate(Vector::new(x, y))
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut iced::Renderer,
+        theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+    ) {
+        let (background, border, text_color) = (self.colors)(&(self.style)(theme, Status::Active));
+        let bounds = layout.bounds();
+        renderer.fill_quad(
+            Quad {
+                bounds,
+                border: Border { color: border, width: 1.0, radius: 3.0.into() },
+                ..Quad::default()
+            },
+            background,
+        );
+        // Long content is cut off at the bottom
+        let Some(content) = layout.children().next() else { return; };
+        renderer.with_layer(bounds.shrink(1.0), |renderer| {
+            let defaults = renderer::Style { text_color };
+            self.content.as_widget().draw(self.tree, renderer, theme, &defaults, content, cursor, &bounds);
+        });
+    }
+
+    fn update(
+        &mut self,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &iced::Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) {
+        // For the links in it
+        let Some(content) = layout.children().next() else { return; };
+        self.content.as_widget_mut().update(self.tree, event, content, cursor, renderer, clipboard, shell, &layout.bounds());
+    }
+
+    fn mouse_interaction(&self, layout: Layout<'_>, cursor: mouse::Cursor, renderer: &iced::Renderer) -> mouse::Interaction {
+        let Some(content) = layout.children().next() else { return mouse::Interaction::None; };
+        self.content.as_widget().mouse_interaction(self.tree, content, cursor, &layout.bounds(), renderer)
+    }
+}
+
+impl<'a, Message: 'a> From<CodeEditor<'a, Message>> for Element<'a, Message> {
+    fn from(editor: CodeEditor<'a, Message>) -> Self {
+        Element::new(editor)
+    }
+}
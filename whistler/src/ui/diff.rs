@@ -0,0 +1,187 @@
+use iced::widget::text::Span;
+use iced::widget::{button, column, container, rich_text, row, scrollable, span, text, Space};
+use iced::{Background, Color, Element, Length};
+
+use crate::diff::{Row, Side, TextDiff};
+use crate::message::Message;
+use crate::theme::*;
+use crate::ui::styles::{tab_button_style, tree_button_style};
+
+/// Every row is as tall as this, so both sides line up and a hunk's row says where it is.
+pub const DIFF_ROW_HEIGHT: f32 = 20.0;
+
+const DIFF_TEXT_SIZE: f32 = 13.0;
+const NUMBER_WIDTH: f32 = 44.0;
+
+/// How a line differs from the other side.
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Same,
+    Removed,
+    Added,
+}
+
+impl Mark {
+    fn sign(self) -> &'static str {
+        match self {
+            Mark::Same => " ",
+            Mark::Removed => "-",
+            Mark::Added => "+",
+        }
+    }
+
+    fn line_color(self) -> Option<Color> {
+        match self {
+            Mark::Same => None,
+            Mark::Removed => Some(THEME.diff_removed_line),
+            Mark::Added => Some(THEME.diff_inserted_line),
+        }
+    }
+
+    fn word_color(self) -> Color {
+        match self {
+            Mark::Removed => THEME.diff_removed,
+            _ => THEME.diff_inserted,
+        }
+    }
+}
+
+/// Two texts compared, with the changes between them picked out. `preview` adds the buttons of
+/// the workspace edit whose changes it shows.
+pub fn view_diff<'a>(diff: &'a TextDiff, scroll_id: &iced::widget::Id, preview: bool) -> Element<'a, Message> {
+    let position = match (diff.hunks.len(), diff.current) {
+        (0, _) => "No changes".to_string(),
+        (count, Some(current)) => format!("{} of {} changes", current + 1, count),
+        (count, None) => format!("{} change{}", count, if count == 1 { "" } else { "s" }),
+    };
+    let mut toolbar = row![
+        text(&diff.old.title).size(12).color(THEME.text_muted),
+        text("↔").size(12).color(THEME.text_dim),
+        text(&diff.new.title).size(12).color(THEME.text_muted),
+        Space::new().width(Length::Fill),
+        text(position).size(11).color(THEME.text_dim),
+        toolbar_button("↑", Message::PreviousChange),
+        toolbar_button("↓", Message::NextChange),
+        toolbar_button(if diff.side_by_side { "Inline" } else { "Side by Side" }, Message::DiffLayoutToggled),
+        toolbar_button(if diff.collapsed { "Show All" } else { "Hide Unchanged" }, Message::DiffUnchangedToggled),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+    if preview {
+        toolbar = toolbar
+            .push(toolbar_button("Discard", Message::DiscardEditPreview))
+            .push(toolbar_button("Apply", Message::ApplyEditPreview));
+    }
+
+    let (rows, _) = diff.rows();
+    let lines: Vec<Element<'a, Message>> = rows.into_iter().map(|row| diff_row(diff, row)).collect();
+    // One column for both sides, so they scroll together
+    let body = scrollable(column(lines).width(Length::Fill)).id(scroll_id.clone()).height(Length::Fill);
+
+    column![container(toolbar).padding(iced::Padding { top: 0.0, right: 4.0, bottom: 6.0, left: 4.0 }), body].into()
+}
+
+/// Where to scroll to show a hunk, with a few lines above it.
+pub fn hunk_offset(row: usize) -> f32 {
+    row.saturating_sub(3) as f32 * DIFF_ROW_HEIGHT
+}
+
+fn diff_row(diff: &TextDiff, row: Row) -> Element<'_, Message> {
+    match row {
+        Row::Hidden(count) => button(
+            text(format!("⋯ {} unchanged line{}", count, if count == 1 { "" } else { "s" })).size(11).color(THEME.text_dim),
+        )
+        .style(tree_button_style)
+        .on_press(Message::DiffUnchangedToggled)
+        .padding(iced::Padding { top: 0.0, right: 0.0, bottom: 0.0, left: NUMBER_WIDTH })
+        .width(Length::Fill)
+        .height(Length::Fixed(DIFF_ROW_HEIGHT))
+        .into(),
+        Row::Same { old, new } if diff.side_by_side => {
+            row![cell(&diff.old, Some(old), Mark::Same), cell(&diff.new, Some(new), Mark::Same)].into()
+        }
+        Row::Changed { old, new } if diff.side_by_side => {
+            row![cell(&diff.old, old, Mark::Removed), cell(&diff.new, new, Mark::Added)].into()
+        }
+        // Inline, a line shows its number on both sides, or on the side it's there on
+        Row::Same { old, new } => inline_row(&diff.new, Some(old), Some(new), new, Mark::Same),
+        Row::Changed { old: Some(old), .. } => inline_row(&diff.old, Some(old), None, old, Mark::Removed),
+        Row::Changed { new, .. } => inline_row(&diff.new, None, new, new.unwrap_or_default(), Mark::Added),
+    }
+}
+
+/// One side's half of a side-by-side row. A changed row with nothing on this side is left empty.
+fn cell(side: &Side, line: Option<usize>, mark: Mark) -> Element<'_, Message> {
+    let Some(line) = line else {
+        return Space::new().width(Length::Fill).height(Length::Fixed(DIFF_ROW_HEIGHT)).into();
+    };
+    let content = row![number(Some(line)), sign(mark), line_text(side, line, mark)].spacing(4);
+    line_container(content.into(), mark)
+}
+
+fn inline_row(side: &Side, old: Option<usize>, new: Option<usize>, line: usize, mark: Mark) -> Element<'_, Message> {
+    let content = row![number(old), number(new), sign(mark), line_text(side, line, mark)].spacing(4);
+    line_container(content.into(), mark)
+}
+
+fn line_container(content: Element<'_, Message>, mark: Mark) -> Element<'_, Message> {
+    container(content)
+        .width(Length::Fill)
+        .height(Length::Fixed(DIFF_ROW_HEIGHT))
+        .center_y(Length::Fixed(DIFF_ROW_HEIGHT))
+        .clip(true)
+        .style(move |_theme| container::Style { background: mark.line_color().map(Background::Color), ..Default::default() })
+        .into()
+}
+
+fn number<'a>(line: Option<usize>) -> Element<'a, Message> {
+    let label = line.map(|line| (line + 1).to_string()).unwrap_or_default();
+    text(label)
+        .size(DIFF_TEXT_SIZE - 1.0)
+        .color(THEME.line_number)
+        .width(Length::Fixed(NUMBER_WIDTH))
+        .align_x(iced::alignment::Horizontal::Right)
+        .into()
+}
+
+fn sign<'a>(mark: Mark) -> Element<'a, Message> {
+    let color = match mark {
+        Mark::Same => THEME.text_dim,
+        Mark::Removed => THEME.git_deleted,
+        Mark::Added => THEME.git_added,
+    };
+    text(mark.sign()).size(DIFF_TEXT_SIZE).color(color).into()
+}
+
+/// A line coloured by the grammar, with the words that changed on it picked out.
+fn line_text(side: &Side, line: usize, mark: Mark) -> Element<'_, Message> {
+    let content = &side.lines[line];
+    let words = side.words(line);
+    let mut cuts: Vec<usize> = vec![0, content.len()];
+    cuts.extend(side.highlights(line).iter().flat_map(|(range, _)| [range.start, range.end]));
+    cuts.extend(words.iter().flat_map(|range| [range.start, range.end]));
+    cuts.retain(|&cut| cut <= content.len() && content.is_char_boundary(cut));
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    let spans: Vec<Span<'_>> = cuts
+        .windows(2)
+        .map(|cut| {
+            let color = side.highlights(line).iter().find(|(range, _)| range.contains(&cut[0])).map(|(_, highlight)| highlight.0);
+            let changed = words.iter().any(|range| range.contains(&cut[0]));
+            // Tabs would otherwise be as wide as a space
+            span(content[cut[0]..cut[1]].replace('\t', "    "))
+                .color_maybe(color)
+                .background_maybe(changed.then(|| mark.word_color()))
+        })
+        .collect();
+    rich_text(spans).size(DIFF_TEXT_SIZE).wrapping(text::Wrapping::None).into()
+}
+
+fn toolbar_button(label: &str, message: Message) -> Element<'_, Message> {
+    button(text(label).size(11))
+        .style(tab_button_style(false))
+        .on_press(message)
+        .padding(iced::Padding { top: 2.0, right: 8.0, bottom: 2.0, left: 8.0 })
+        .into()
+}
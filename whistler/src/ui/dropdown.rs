@@ -0,0 +1,197 @@
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::mouse;
+use iced::advanced::overlay;
+use iced::advanced::renderer;
+use iced::advanced::widget::{self, Tree, Widget};
+use iced::advanced::{Clipboard, Shell};
+use iced::{Element, Event, Length, Rectangle, Size, Theme, Vector};
+
+/// Room left between the anchor and its menu.
+const MENU_GAP: f32 = 2.0;
+
+/// Something to click, like a breadcrumb, with a menu that opens under it. Whether the menu is
+/// open is up to the app, which passes it in while it is.
+pub struct Dropdown<'a, Message> {
+    anchor: Element<'a, Message>,
+    menu: Option<Element<'a, Message>>,
+    on_dismiss: Option<Message>,
+    max_size: Size,
+}
+
+impl<'a, Message: Clone + 'a> Dropdown<'a, Message> {
+    pub fn new(anchor: impl Into<Element<'a, Message>>) -> Self {
+        Self { anchor: anchor.into(), menu: None, on_dismiss: None, max_size: Size::new(320.0, 360.0) }
+    }
+
+    /// The menu to show under the anchor, if it's open.
+    pub fn menu(mut self, menu: Option<Element<'a, Message>>) -> Self {
+        self.menu = menu;
+        self
+    }
+
+    /// Sent when there's a click outside the open menu.
+    pub fn on_dismiss(mut self, message: Message) -> Self {
+        self.on_dismiss = Some(message);
+        self
+    }
+
+    /// The most room the menu takes up before it's cut off.
+    pub fn max_size(mut self, max_size: Size) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl<'a, Message: Clone + 'a> Widget<Message, Theme, iced::Renderer> for Dropdown<'a, Message> {
+    fn children(&self) -> Vec<Tree> {
+        std::iter::once(&self.anchor).chain(&self.menu).map(Tree::new).collect()
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        let children: Vec<&Element<'a, Message>> = std::iter::once(&self.anchor).chain(&self.menu).collect();
+        tree.diff_children(&children);
+    }
+
+    fn size(&self) -> Size<Length> {
+        self.anchor.as_widget().size()
+    }
+
+    fn layout(&mut self, tree: &mut Tree, renderer: &iced::Renderer, limits: &layout::Limits) -> layout::Node {
+        self.anchor.as_widget_mut().layout(&mut tree.children[0], renderer, limits)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &iced::Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) {
+        self.anchor.as_widget_mut().update(&mut tree.children[0], event, layout, cursor, renderer, clipboard, shell, viewport);
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut iced::Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.anchor.as_widget().draw(&tree.children[0], renderer, theme, style, layout, cursor, viewport);
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &iced::Renderer,
+    ) -> mouse::Interaction {
+        self.anchor.as_widget().mouse_interaction(&tree.children[0], layout, cursor, viewport, renderer)
+    }
+
+    fn operate(
+        &mut self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &iced::Renderer,
+        operation: &mut dyn widget::Operation,
+    ) {
+        self.anchor.as_widget_mut().operate(&mut tree.children[0], layout, renderer, operation);
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'b>,
+        _renderer: &iced::Renderer,
+        _viewport: &Rectangle,
+        translation: Vector,
+    ) -> Option<overlay::Element<'b, Message, Theme, iced::Renderer>> {
+        let menu = self.menu.as_mut()?;
+        let tree = tree.children.get_mut(1)?;
+        Some(overlay::Element::new(Box::new(Menu {
+            content: menu,
+            tree,
+            anchor: layout.bounds() + translation,
+            max_size: self.max_size,
+            on_dismiss: self.on_dismiss.clone(),
+        })))
+    }
+}
+
+/// The open menu, under its anchor or above it if there's no room below.
+struct Menu<'a, 'b, Message> {
+    content: &'b mut Element<'a, Message>,
+    tree: &'b mut Tree,
+    anchor: Rectangle,
+    max_size: Size,
+    on_dismiss: Option<Message>,
+}
+
+impl<Message: Clone> overlay::Overlay<Message, Theme, iced::Renderer> for Menu<'_, '_, Message> {
+    fn layout(&mut self, renderer: &iced::Renderer, bounds: Size) -> layout::Node {
+        let limits = layout::Limits::new(Size::ZERO, self.max_size);
+        let node = self.content.as_widget_mut().layout(self.tree, renderer, &limits);
+        let size = node.size();
+        let below = self.anchor.y + self.anchor.height + MENU_GAP;
+        let y = match below + size.height <= bounds.height {
+            true => below,
+            false => (self.anchor.y - MENU_GAP - size.height).max(0.0),
+        };
+        let x = self.anchor.x.min(bounds.width - size.width).max(0.0);
+        node.translate(Vector::new(x, y))
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut iced::Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+    ) {
+        self.content.as_widget().draw(self.tree, renderer, theme, style, layout, cursor, &layout.bounds());
+    }
+
+    fn update(
+        &mut self,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &iced::Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) {
+        let clicked = matches!(event, Event::Mouse(mouse::Event::ButtonPressed(_)));
+        if clicked && !cursor.is_over(layout.bounds()) && !cursor.is_over(self.anchor) {
+            if let Some(on_dismiss) = self.on_dismiss.clone() {
+                shell.publish(on_dismiss);
+            }
+            return;
+        }
+        self.content.as_widget_mut().update(self.tree, event, layout, cursor, renderer, clipboard, shell, &layout.bounds());
+    }
+
+    fn mouse_interaction(&self, layout: Layout<'_>, cursor: mouse::Cursor, renderer: &iced::Renderer) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(self.tree, layout, cursor, &layout.bounds(), renderer)
+    }
+
+    fn operate(&mut self, layout: Layout<'_>, renderer: &iced::Renderer, operation: &mut dyn widget::Operation) {
+        self.content.as_widget_mut().operate(self.tree, layout, renderer, operation);
+    }
+}
+
+impl<'a, Message: Clone + 'a> From<Dropdown<'a, Message>> for Element<'a, Message> {
+    fn from(dropdown: Dropdown<'a, Message>) -> Self {
+        Element::new(dropdown)
+    }
+}
@@ -1,32 +1,202 @@
 use iced::keyboard::{key, Key};
-use iced::widget::text_editor::{TextEditor, Content, Binding, KeyPress, Motion};
-use iced::{Element, Length};
+use iced::widget::text::Span;
+use iced::widget::text_editor::{Binding, Edit, KeyPress, Motion};
+use iced::widget::{button, column, container, markdown, rich_text, row, span, text, Space};
+use iced::{Background, Element, Length};
 
+use crate::completion::{Completion, VISIBLE_ITEMS};
+use crate::config::EditorSettings;
+use crate::diagnostics::Diagnostic;
+use crate::document::{Action, Document};
+use crate::git::{self, Blame, Hunk, LineChange};
+use crate::hover::{Hover, Signature};
 use crate::message::Message;
-use crate::syntax::{VscodeHighlighter, Settings};
-use crate::ui::styles::text_editor_style;
+use crate::syntax::Settings;
+use crate::theme::THEME;
+use crate::ui::code_editor::CodeEditor;
+use crate::ui::styles::{code_editor_style, tab_button_style};
 
-pub fn create_editor<'a>(content: &'a Content, extension: &str) -> Element<'a, Message> {
-    TextEditor::new(content) // Creates a new TextEditor object
-        .on_action(Message::EditorAction) // Sends a Message when an edit is made
-        .key_binding(editor_key_bindings) // Uses key bindings from the below function
-        .highlight_with::<VscodeHighlighter>(
-            Settings {
-                extension: extension.to_string(),
-            },
-        |highlight, _theme| highlight.to_format(),
-        )
-        .style(text_editor_style) // Uses the editor styles determined in the styles.rs file
-        .height(Length::Fill)
-        .into()
+/// The text size of documentation, hovers and signatures, a bit smaller than the editor's.
+const DOCUMENTATION_TEXT_SIZE: f32 = 13.0;
+
+/// What's shown floating over the editor: the completion popup, the selected item's docs, and
+/// what the language server says about the code, and the change whose marker was clicked.
+pub struct Popups<'a> {
+    pub completion: Option<&'a Completion>,
+    pub completion_docs: &'a [markdown::Item],
+    pub hover: Option<&'a Hover>,
+    pub signature: Option<&'a Signature>,
+    pub hunk: Option<(&'a Hunk, &'a [String])>,
+}
+
+/// Where the file stands with git: what's changed since git's version, and who last changed each
+/// line, which goes in the gutter too while `blame_gutter` is on.
+pub struct GitLines<'a> {
+    pub changes: &'a [Hunk],
+    pub blame: Option<&'a Blame>,
+    pub blame_gutter: bool,
+}
+
+pub fn create_editor<'a>(
+    document: &'a Document,
+    extension: &str,
+    settings: &EditorSettings,
+    popups: Popups<'a>,
+    diagnostics: &'a [Diagnostic],
+    git: GitLines<'a>,
+) -> Element<'a, Message> {
+    let Popups { completion, completion_docs, hover, signature, hunk } = popups;
+    let GitLines { changes, blame, blame_gutter } = git;
+    let annotation = blame.and_then(|blame| blame.line(document.cursor().line)).map(|commit| match commit.id {
+        Some(_) => format!("{}, {} • {}", commit.author, git::age(commit.time), commit.summary),
+        None => commit.summary.clone(),
+    });
+    let completing = completion.is_some();
+    let documentation = (!completion_docs.is_empty()).then(|| documentation_view(completion_docs));
+    let info = hover.map(|hover| (hover.start, hover.end, documentation_view(&hover.contents)));
+    CodeEditor::new(
+        document, // Creates a new CodeEditor showing the document
+        Settings {
+            extension: extension.to_string(),
+        },
+    )
+    .settings(settings.clone()) // Line numbers, wrapping and guides, from the user settings for this language
+    .completion(completion) // The completion popup, drawn at the cursor
+    .documentation(documentation) // The selected item's docs, next to the popup
+    .info(info) // What the language server says about the symbol under the mouse
+    .signature_help(signature.map(signature_view)) // The call the cursor is in, above it
+    .diagnostics(diagnostics) // Problems from the language server, underlined with their messages on hover
+    .changes(changes) // What's changed since the last commit or stage, marked in the gutter
+    .hunk(hunk.map(|(hunk, base)| (hunk, hunk_view(hunk, base)))) // A clicked change, with what it replaced
+    .on_change_click(Message::ShowHunk)
+    .blame(blame.filter(|_| blame_gutter)) // Who last changed each line, while it's turned on
+    .annotation(annotation) // And who last changed the cursor's line, after its end
+    .on_action(Message::EditorAction) // Sends a Message when an edit is made
+    .on_command_click(|| Message::GoToDefinition) // Cmd+click goes to the definition, like F12
+    .on_hover(Message::HoverAt) // Resting the mouse on a symbol asks the language server about it
+    .key_binding(move |key_press| editor_key_bindings(key_press, completing)) // Uses key bindings from the below function
+    .style(code_editor_style) // Uses the editor styles determined in the styles.rs file
+    .into()
+}
+
+/// Markdown from a language server, in the editor's smaller text size.
+fn documentation_view(items: &[markdown::Item]) -> Element<'_, Message> {
+    let style = markdown::Style::from_palette(iced::theme::Palette::CATPPUCCIN_MOCHA);
+    markdown::view(items, markdown::Settings::with_text_size(DOCUMENTATION_TEXT_SIZE, style)).map(Message::MarkdownLinkClicked)
+}
+
+/// A signature with the parameter the cursor is at highlighted, and the docs under it.
+fn signature_view(signature: &Signature) -> Element<'_, Message> {
+    let label = signature.label.as_str();
+    let active = signature.active.clone().filter(|active| label.get(active.clone()).is_some()).unwrap_or(0..0);
+    let mut spans: Vec<Span<'_>> = Vec::new();
+    if let Some((index, count)) = signature.overload {
+        spans.push(span(format!("{}/{}  ", index, count)).color(THEME.text_placeholder));
+    }
+    spans.push(span(&label[..active.start]));
+    spans.push(span(&label[active.clone()]).color(THEME.suggest_highlight).underline(true));
+    spans.push(span(&label[active.end..]));
+
+    let mut content = column![rich_text(spans).size(DOCUMENTATION_TEXT_SIZE)].spacing(6);
+    if !signature.documentation.is_empty() {
+        content = content.push(documentation_view(&signature.documentation));
+    }
+    content.into()
+}
+
+/// The lines a change replaced, in red, with buttons to put them back or stage the change.
+fn hunk_view<'a>(hunk: &Hunk, base: &'a [String]) -> Element<'a, Message> {
+    let (added, removed) = (hunk.current.len(), hunk.base.len());
+    let summary = match hunk.change() {
+        LineChange::Added => format!("{} added line{}", added, if added == 1 { "" } else { "s" }),
+        LineChange::Deleted => format!("{} deleted line{}", removed, if removed == 1 { "" } else { "s" }),
+        LineChange::Modified => format!("{} line{} changed from", removed, if removed == 1 { "" } else { "s" }),
+    };
+    let action = |label, message| {
+        button(text(label).size(12))
+            .style(tab_button_style(false))
+            .on_press(message)
+            .padding(iced::Padding { top: 2.0, right: 8.0, bottom: 2.0, left: 8.0 })
+    };
+    let header = row![
+        text(summary).size(12).color(THEME.text_dim),
+        Space::new().width(Length::Fill),
+        action("Revert Hunk", Message::RevertHunk),
+        action("Stage Hunk", Message::StageHunk),
+        action("×", Message::CloseHunk),
+    ]
+    .spacing(6)
+    .align_y(iced::Alignment::Center);
+
+    let lines = base[hunk.base.clone()].iter().map(|line| {
+        container(text(line.replace('\t', "    ")).size(13).color(THEME.text_secondary))
+            .width(Length::Fill)
+            .style(|_theme| container::Style {
+                background: Some(Background::Color(THEME.diff_removed)),
+                ..Default::default()
+            })
+            .into()
+    });
+    column![header, column(lines)].spacing(6).width(Length::Fixed(560.0)).into()
 }
 
-fn editor_key_bindings(key_press: KeyPress) -> Option<Binding<Message>> {
+fn editor_key_bindings(key_press: KeyPress, completing: bool) -> Option<Binding<Message>> {
     let modifiers = key_press.modifiers;
 
+    // While the completion popup is open, the arrows move through it and Enter or Tab accepts
+    if completing && !modifiers.command() && !modifiers.alt() && !modifiers.shift() {
+        let message = match key_press.key.as_ref() {
+            Key::Named(key::Named::ArrowUp) => Some(Message::CompletionNavigate(-1)),
+            Key::Named(key::Named::ArrowDown) => Some(Message::CompletionNavigate(1)),
+            Key::Named(key::Named::PageUp) => Some(Message::CompletionNavigate(-(VISIBLE_ITEMS as i32))),
+            Key::Named(key::Named::PageDown) => Some(Message::CompletionNavigate(VISIBLE_ITEMS as i32)),
+            Key::Named(key::Named::Enter | key::Named::Tab) => Some(Message::CompletionAccept),
+            Key::Named(key::Named::Escape) => Some(Message::CompletionDismiss),
+            _ => None,
+        };
+        if let Some(message) = message {
+            return Some(Binding::Custom(message));
+        }
+    }
+    if key_press.key == Key::Named(key::Named::Space) && modifiers.control() {
+        let message = if modifiers.shift() { Message::TriggerSignatureHelp } else { Message::TriggerCompletion };
+        return Some(Binding::Custom(message));
+    }
+
     if let Key::Character(_c) = key_press.key.as_ref() {
         if modifiers.command() {
-            return None;
+            // The physical key, since Alt changes which character gets typed
+            let action = match key_press.key.to_latin(key_press.physical_key) {
+                Some('z') if modifiers.shift() => Action::Redo,
+                Some('z') => Action::Undo,
+                Some('d') => Action::SelectNextOccurrence, // Adds the next match as another cursor
+                Some('l') if modifiers.shift() => Action::SelectAllOccurrences,
+                Some('[') if modifiers.alt() => Action::Fold,
+                Some(']') if modifiers.alt() => Action::Unfold,
+                Some('k') if modifiers.shift() => Action::DeleteLines,
+                Some('/') => Action::ToggleLineComment,
+                Some('.') => return Some(Binding::Custom(Message::ShowCodeActions)),
+                // Where Ctrl is the command key, Ctrl+J and Ctrl+T below are app shortcuts, so
+                // joining and transposing take Shift as well
+                Some('j') if modifiers.shift() && modifiers.control() => Action::JoinLines,
+                Some('t') if modifiers.shift() && modifiers.control() => Action::Transpose,
+                Some('c' | 'x' | 'v' | 'a') => return Binding::from_key_press(key_press), // Clipboard and select all
+                _ => return None, // Leaves the rest to the app shortcuts
+            };
+            return Some(Binding::Custom(Message::EditorAction(action)));
+        }
+        if key_press.key.to_latin(key_press.physical_key) == Some('f') && modifiers.alt() && modifiers.shift() {
+            return Some(Binding::Custom(Message::FormatDocument));
+        }
+        // Ctrl shortcuts from macOS, where Ctrl isn't the command key
+        let action = match key_press.key.to_latin(key_press.physical_key) {
+            Some('j') if modifiers.control() => Some(Action::JoinLines),
+            Some('t') if modifiers.control() => Some(Action::Transpose),
+            Some('a') if modifiers.alt() && modifiers.shift() => Some(Action::ToggleBlockComment),
+            _ => None,
+        };
+        if let Some(action) = action {
+            return Some(Binding::Custom(Message::EditorAction(action)));
         }
     }
 
@@ -61,6 +231,39 @@ fn editor_key_bindings(key_press: KeyPress) -> Option<Binding<Message>> {
                 Binding::from_key_press(key_press) // Again, ensures default actions
             }
         }
+        Key::Named(key @ (key::Named::ArrowUp | key::Named::ArrowDown)) if modifiers.alt() && !modifiers.command() => {
+            // Alt moves the selected lines, Shift+Alt copies them
+            let down = key == key::Named::ArrowDown;
+            let action = if modifiers.shift() { Action::CopyLines { down } } else { Action::MoveLines { down } };
+            Some(Binding::Custom(Message::EditorAction(action)))
+        }
+        // Shift for references, Cmd for implementations, Alt for the type
+        Key::Named(key::Named::F12) => {
+            let message = if modifiers.shift() {
+                Message::FindReferences
+            } else if modifiers.command() {
+                Message::GoToImplementation
+            } else if modifiers.alt() {
+                Message::GoToTypeDefinition
+            } else {
+                Message::GoToDefinition
+            };
+            Some(Binding::Custom(message))
+        }
+        Key::Named(key::Named::F2) => Some(Binding::Custom(Message::RenameSymbol)),
+        Key::Named(key::Named::F5) if modifiers.alt() => {
+            let message = if modifiers.shift() { Message::PreviousChange } else { Message::NextChange };
+            Some(Binding::Custom(message))
+        }
+        Key::Named(key::Named::F9) => {
+            let action = Action::SortLines { descending: modifiers.shift() };
+            Some(Binding::Custom(Message::EditorAction(action)))
+        }
+        Key::Named(key::Named::Tab) if !modifiers.command() && !modifiers.alt() => {
+            // Indents the selected lines, or outdents them with Shift
+            let edit = if modifiers.shift() { Edit::Unindent } else { Edit::Indent };
+            Some(Binding::Custom(Message::EditorAction(Action::Edit(edit))))
+        }
         _ => Binding::from_key_press(key_press),
     }
 }
@@ -1,7 +1,19 @@
 pub mod styles;
+pub mod code_editor;
 pub mod editor;
 pub mod sidebar;
+pub mod preview;
+pub mod refactor;
+pub mod dropdown;
+pub mod breadcrumbs;
+pub mod source_control;
+pub mod diff;
 
 pub use styles::*;
 pub use editor::*;
 pub use sidebar::*;
+pub use preview::*;
+pub use refactor::*;
+pub use breadcrumbs::*;
+pub use source_control::*;
+pub use diff::*;
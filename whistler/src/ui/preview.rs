@@ -0,0 +1,86 @@
+use iced::widget::markdown::{self, Bullet, Item, Uri};
+use iced::widget::{column, container, rich_text, scrollable, span};
+use iced::{Element, Length, Theme};
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::message::Message;
+use crate::syntax::{highlight_code, Highlight};
+
+/// Syntax colors for the code blocks of a Markdown document, keyed by the block's source.
+pub type CodeHighlights = HashMap<String, Vec<Vec<(Range<usize>, Highlight)>>>;
+
+/// Highlights every fenced code block once, so the preview doesn't redo it on each redraw.
+pub fn highlight_code_blocks(items: &[Item]) -> CodeHighlights {
+    let mut highlights = HashMap::new();
+    collect_code_blocks(items, &mut highlights);
+    highlights
+}
+
+fn collect_code_blocks(items: &[Item], highlights: &mut CodeHighlights) {
+    for item in items {
+        match item {
+            Item::CodeBlock { language: Some(language), code, .. } => {
+                highlights
+                    .entry(code.clone())
+                    .or_insert_with(|| highlight_code(language, code));
+            }
+            Item::Quote(items) => collect_code_blocks(items, highlights),
+            Item::List { bullets, .. } => {
+                for bullet in bullets {
+                    let (Bullet::Point { items } | Bullet::Task { items, .. }) = bullet;
+                    collect_code_blocks(items, highlights);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders Markdown like iced does, except code blocks use the editor's highlighter.
+pub struct PreviewViewer<'a> {
+    pub highlights: &'a CodeHighlights,
+}
+
+impl<'a> markdown::Viewer<'a, Message> for PreviewViewer<'a> {
+    fn on_link_click(url: Uri) -> Message {
+        Message::MarkdownLinkClicked(url)
+    }
+
+    fn code_block(
+        &self,
+        settings: markdown::Settings,
+        _language: Option<&'a str>,
+        code: &'a str,
+        lines: &'a [markdown::Text],
+    ) -> Element<'a, Message> {
+        let Some(highlighted) = self.highlights.get(code) else {
+            return markdown::code_block(settings, lines, Self::on_link_click);
+        };
+
+        let rows = code.lines().zip(highlighted).map(|(line, ranges)| {
+            let spans: Vec<_> = ranges
+                .iter()
+                .map(|(range, highlight)| span(&line[range.clone()]).color(highlight.0))
+                .collect();
+
+            rich_text::<(), Message, Theme, iced::Renderer>(spans)
+                .font(settings.style.code_block_font)
+                .size(settings.code_size)
+                .into()
+        });
+
+        container(
+            scrollable(container(column(rows)).padding(settings.code_size))
+                .direction(scrollable::Direction::Horizontal(
+                    scrollable::Scrollbar::default()
+                        .width(settings.code_size / 2)
+                        .scroller_width(settings.code_size / 2),
+                )),
+        )
+        .width(Length::Fill)
+        .padding(settings.code_size / 4)
+        .style(container::dark)
+        .into()
+    }
+}
@@ -0,0 +1,173 @@
+use iced::widget::{button, center, column, container, mouse_area, opaque, row, scrollable, stack, text, text_input, Space};
+use iced::{Background, Color, Element, Length};
+use std::path::Path;
+
+use crate::message::Message;
+use crate::refactor::{self, CodeActionMenu, EditPreview, FileChange, Rename};
+use crate::theme::*;
+use crate::ui::styles::{file_finder_item_style, file_finder_panel_style, search_input_style, tab_button_style};
+
+/// The box the new name is typed in, with what it's called now above it.
+pub fn view_rename<'a>(rename: &'a Rename, input_id: &iced::widget::Id) -> Element<'a, Message> {
+    let input = text_input("New name", &rename.new_name)
+        .id(input_id.clone())
+        .on_input(Message::RenameInputChanged)
+        .on_submit(Message::RenameSubmit)
+        .size(15)
+        .padding(iced::Padding { top: 12.0, right: 14.0, bottom: 12.0, left: 14.0 })
+        .style(search_input_style)
+        .width(Length::Fill);
+    let content = column![
+        text(format!("Rename {}", rename.old_name)).size(11).color(THEME.text_dim),
+        input,
+        text("Enter to preview the changes").size(10).color(THEME.text_placeholder),
+    ]
+    .spacing(8)
+    .padding(10);
+    modal(content.into(), 420.0, Message::RenameCancel)
+}
+
+/// Every line a workspace edit changes, file by file, before it's applied.
+pub fn view_edit_preview<'a>(preview: &'a EditPreview, root: Option<&Path>) -> Element<'a, Message> {
+    let relative = |path: &Path| -> String {
+        root.and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path).to_string_lossy().into_owned()
+    };
+
+    let mut items: Vec<Element<'_, Message>> = Vec::new();
+    for change in &preview.changes {
+        // Files made, moved or removed get a line of their own, the edits come per file below
+        let operation = match change {
+            FileChange::Edit { .. } => continue,
+            FileChange::Create(path) => format!("Create {}", relative(path)),
+            FileChange::Rename { from, to } => format!("Rename {} to {}", relative(from), relative(to)),
+            FileChange::Delete(path) => format!("Delete {}", relative(path)),
+        };
+        items.push(text(operation).size(12).color(THEME.text_secondary).into());
+    }
+    for (path, lines) in &preview.files {
+        items.push(
+            container(
+                row![
+                    text(relative(path)).size(12).color(THEME.text_secondary),
+                    text(lines.len().to_string()).size(10).color(THEME.text_dim),
+                    Space::new().width(Length::Fill),
+                    button(text("Diff").size(11))
+                        .style(tab_button_style(false))
+                        .on_press(Message::PreviewFileDiff(path.clone()))
+                        .padding(iced::Padding { top: 2.0, right: 8.0, bottom: 2.0, left: 8.0 }),
+                ]
+                .spacing(8)
+                .align_y(iced::Alignment::Center),
+            )
+            .padding(iced::Padding { top: 8.0, right: 0.0, bottom: 2.0, left: 0.0 })
+            .into(),
+        );
+        for line in lines {
+            let number = text(format!("{:>5}", line.line + 1)).size(11).color(THEME.line_number);
+            items.push(
+                column![
+                    row![number, text(format!("- {}", line.before.trim_end())).size(11).font(iced::Font::MONOSPACE).color(THEME.error)]
+                        .spacing(8),
+                    row![
+                        text("     ").size(11),
+                        text(format!("+ {}", line.after.trim_end())).size(11).font(iced::Font::MONOSPACE).color(THEME.info),
+                    ]
+                    .spacing(8),
+                ]
+                .into(),
+            );
+        }
+    }
+
+    let header = row![
+        column![
+            text(&preview.title).size(13).color(THEME.text_primary),
+            text(preview.summary()).size(10).color(THEME.text_dim),
+        ]
+        .spacing(2),
+        Space::new().width(Length::Fill),
+        button(text("Discard").size(12))
+            .style(tab_button_style(false))
+            .on_press(Message::DiscardEditPreview)
+            .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 }),
+        button(text("Apply").size(12))
+            .style(tab_button_style(true))
+            .on_press(Message::ApplyEditPreview)
+            .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 }),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let content = column![header, scrollable(column(items).spacing(2)).height(Length::Shrink)]
+        .spacing(10)
+        .padding(12);
+    modal(content.into(), 720.0, Message::DiscardEditPreview)
+}
+
+/// The actions on offer at the cursor, with a filter on top. The ones the server says can't be
+/// used right now are greyed out, with why.
+pub fn view_code_actions<'a>(menu: &'a CodeActionMenu, input_id: &iced::widget::Id) -> Element<'a, Message> {
+    let input = text_input("Filter actions", &menu.filter)
+        .id(input_id.clone())
+        .on_input(Message::CodeActionFilterChanged)
+        .size(14)
+        .padding(iced::Padding { top: 12.0, right: 14.0, bottom: 12.0, left: 14.0 })
+        .style(search_input_style)
+        .width(Length::Fill);
+
+    let items: Vec<Element<'_, Message>> = menu
+        .shown()
+        .enumerate()
+        .map(|(shown, (index, action))| {
+            let is_selected = shown == menu.selected;
+            let reason = refactor::disabled_reason(action);
+            let kind = match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action) => action.kind.as_ref().map(|kind| kind.as_str().to_string()),
+                lsp_types::CodeActionOrCommand::Command(_) => None,
+            };
+            let color = match (reason, is_selected) {
+                (Some(_), _) => THEME.text_placeholder,
+                (None, true) => THEME.text_primary,
+                (None, false) => THEME.text_muted,
+            };
+            let mut label = row![text(refactor::title(action)).size(13).color(color)].spacing(10);
+            if let Some(detail) = reason.map(str::to_string).or(kind) {
+                label = label.push(text(detail).size(11).color(THEME.text_dim));
+            }
+            button(label)
+                .style(file_finder_item_style(is_selected))
+                .on_press_maybe(reason.is_none().then_some(Message::CodeActionChosen(index)))
+                .padding(iced::Padding { top: 7.0, right: 10.0, bottom: 7.0, left: 10.0 })
+                .width(Length::Fill)
+                .into()
+        })
+        .collect();
+
+    let list: Element<'_, Message> = if items.is_empty() {
+        container(text("No matching actions").size(12).color(THEME.text_dim)).padding(12).into()
+    } else {
+        scrollable(column(items).spacing(2).padding(6)).height(Length::Shrink).into()
+    };
+    modal(column![input, list].into(), 520.0, Message::CodeActionsClose)
+}
+
+/// A panel in the middle of a dimmed window, closed by clicking outside it like the file finder.
+fn modal<'a>(content: Element<'a, Message>, width: f32, on_dismiss: Message) -> Element<'a, Message> {
+    let panel = container(content)
+        .width(Length::Fixed(width))
+        .max_height(520.0)
+        .style(file_finder_panel_style);
+
+    let backdrop = mouse_area(
+        container(Space::new())
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .style(|_theme| container::Style {
+                background: Some(Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.45))),
+                ..Default::default()
+            }),
+    )
+    .on_press(on_dismiss);
+
+    stack![backdrop, center(opaque(panel))].into()
+}
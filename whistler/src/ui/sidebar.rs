@@ -1,21 +1,90 @@
-use iced::widget::{text, column, row, button, scrollable, container};
+use iced::widget::{text, column, row, button, mouse_area, scrollable, container, Space};
 use iced::widget::svg::{Svg, Handle};
-use iced::{Element, Length};
+use iced::{Color, Element, Length};
+use lsp_types::SymbolKind;
+use std::path::Path;
 
+use crate::document::{Action, Position};
 use crate::file_tree::{FileEntry, FileTree};
+use crate::git::{self, FileCommit, FileStatus, Statuses};
 use crate::icons::{get_file_icon, get_folder_icon};
 use crate::message::Message;
+use crate::outline::{self, Symbol};
 use crate::theme::*;
-use crate::ui::styles::{tree_button_style, sidebar_container_style};
+use crate::ui::dropdown::Dropdown;
+use crate::ui::source_control::{view_source_control, SourceControlView};
+use crate::ui::styles::{tree_button_style, sidebar_container_style, file_finder_item_style, file_finder_panel_style, tab_button_style};
 
-pub fn view_sidebar<'a>(file_tree: Option<&'a FileTree>, width: f32) -> Element<'a, Message> {
-    let sidebar_content: Element<'a, Message> = match file_tree {
-        Some(tree) => view_file_tree(tree),
-        None => view_empty_sidebar(),
+/// What the sidebar is showing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SidebarView {
+    #[default]
+    Explorer,
+    Outline,
+    SourceControl,
+    History,
+}
+
+/// The active file's symbols and where its cursor is, for the outline.
+pub struct OutlineView<'a> {
+    pub symbols: Option<&'a [Symbol]>,
+    pub cursor: Position,
+}
+
+/// What the File History view has to show for the active file.
+pub enum HistoryView<'a> {
+    NoFile,
+    Loading,
+    NotInRepository,
+    Commits(&'a Path, &'a [FileCommit]),
+}
+
+/// The file whose right-click menu is open, and the one picked to compare others with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileMenu<'a> {
+    pub open: Option<&'a Path>,
+    pub compare: Option<&'a Path>,
+}
+
+/// The open folder's files, where they stand with git, and the menu open on one of them.
+pub struct ExplorerView<'a> {
+    pub tree: Option<&'a FileTree>,
+    pub git_status: Option<&'a Statuses>,
+    pub menu: FileMenu<'a>,
+}
+
+pub fn view_sidebar<'a>(
+    view: SidebarView,
+    explorer: ExplorerView<'a>,
+    outline: OutlineView<'a>,
+    source_control: SourceControlView<'a>,
+    history: HistoryView<'a>,
+    width: f32,
+) -> Element<'a, Message> {
+    let sidebar_content: Element<'a, Message> = match (view, explorer.tree) {
+        (SidebarView::Explorer, Some(tree)) => view_file_tree(tree, explorer.git_status, explorer.menu),
+        (SidebarView::Explorer, None) => view_empty_sidebar(),
+        (SidebarView::Outline, _) => view_outline(outline),
+        (SidebarView::SourceControl, _) => view_source_control(source_control),
+        (SidebarView::History, _) => view_history(history),
+    };
+
+    let tab = |label: &'a str, tab: SidebarView| {
+        button(text(label).size(11))
+            .style(tab_button_style(view == tab))
+            .on_press(Message::SidebarViewChanged(tab))
+            .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
     };
+    let header = row![
+        tab("EXPLORER", SidebarView::Explorer),
+        tab("OUTLINE", SidebarView::Outline),
+        tab("GIT", SidebarView::SourceControl),
+        tab("HISTORY", SidebarView::History),
+    ]
+    .spacing(4);
 
     let sidebar = container(
-        scrollable(sidebar_content).height(Length::Fill)
+        column![header, scrollable(sidebar_content).height(Length::Fill)].spacing(8)
     )
     .width(Length::Fixed(width))
     .height(Length::Fill)
@@ -27,9 +96,109 @@ pub fn view_sidebar<'a>(file_tree: Option<&'a FileTree>, width: f32) -> Element<
         .into()
 }
 
-fn view_file_tree(tree: &FileTree) -> Element<'_, Message> {
+/// The letter for a kind of symbol, coloured the way the completion popup colours it.
+pub fn symbol_icon<'a>(kind: SymbolKind) -> Element<'a, Message> {
+    text(outline::icon(kind).to_string())
+        .size(12)
+        .font(iced::Font::MONOSPACE)
+        .color(symbol_color(kind))
+        .into()
+}
+
+fn symbol_color(kind: SymbolKind) -> Color {
+    match kind {
+        SymbolKind::FUNCTION | SymbolKind::METHOD | SymbolKind::CONSTRUCTOR => THEME.symbol_function,
+        SymbolKind::CLASS
+        | SymbolKind::STRUCT
+        | SymbolKind::ENUM
+        | SymbolKind::INTERFACE
+        | SymbolKind::OBJECT
+        | SymbolKind::MODULE
+        | SymbolKind::NAMESPACE
+        | SymbolKind::PACKAGE
+        | SymbolKind::EVENT => THEME.symbol_class,
+        _ => THEME.symbol_variable,
+    }
+}
+
+/// The symbols of the active file, nested, with the innermost one the cursor is in picked out.
+fn view_outline(outline: OutlineView<'_>) -> Element<'_, Message> {
+    let Some(symbols) = outline.symbols.filter(|symbols| !symbols.is_empty()) else {
+        let message = match outline.symbols {
+            Some(_) => "No symbols in this file",
+            None => "No file open",
+        };
+        return container(text(message).size(12).color(THEME.text_dim)).padding(6).into();
+    };
+    let current = outline::path_to(symbols, outline.cursor);
+    let mut items = Vec::new();
+    render_symbols(symbols, Some(&current), 0, &mut items);
+    column(items).spacing(2).into()
+}
+
+fn render_symbols<'a>(symbols: &'a [Symbol], current: Option<&[usize]>, depth: usize, items: &mut Vec<Element<'a, Message>>) {
+    for (index, symbol) in symbols.iter().enumerate() {
+        // Only the innermost symbol on the way to the cursor is picked out
+        let on_path = current.filter(|current| current.first() == Some(&index));
+        let is_current = on_path.is_some_and(|current| current.len() == 1);
+        let mut label = row![
+            Space::new().width(Length::Fixed(INDENT_WIDTH * depth as f32)),
+            symbol_icon(symbol.kind),
+            text(&symbol.name).size(13),
+        ]
+        .spacing(6)
+        .align_y(iced::Alignment::Center);
+        if let Some(detail) = &symbol.detail {
+            label = label.push(text(detail).size(11).color(THEME.text_dim));
+        }
+        items.push(
+            button(label)
+                .style(file_finder_item_style(is_current))
+                .on_press(Message::EditorAction(Action::GoTo(symbol.selection)))
+                .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
+                .width(Length::Fill)
+                .into(),
+        );
+        render_symbols(&symbol.children, on_path.map(|current| &current[1..]), depth + 1, items);
+    }
+}
+
+/// The commits that changed the active file, newest first. Clicking one shows what it changed.
+fn view_history(history: HistoryView<'_>) -> Element<'_, Message> {
+    let note = |message: &'static str| container(text(message).size(12).color(THEME.text_dim)).padding(6).into();
+    let (path, commits) = match history {
+        HistoryView::NoFile => return note("No file open"),
+        HistoryView::Loading => return note("Reading history..."),
+        HistoryView::NotInRepository => return note("This file isn't in a git repository"),
+        HistoryView::Commits(path, commits) => (path, commits),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut items: Vec<Element<'_, Message>> = vec![text(format!("{}  {}", name, commits.len())).size(11).color(THEME.text_muted).into()];
+    if commits.is_empty() {
+        items.push(note("No commits have changed this file"));
+    }
+    for commit in commits {
+        items.push(
+            button(
+                column![
+                    text(&commit.summary).size(13).wrapping(text::Wrapping::None),
+                    text(format!("{}  {}  {}", commit.short_id(), commit.author, git::age(commit.time))).size(11).color(THEME.text_dim),
+                ]
+                .spacing(2),
+            )
+            .style(tree_button_style)
+            .on_press(Message::HistoryCommitChosen(commit.id.clone()))
+            .padding(iced::Padding { top: 4.0, right: 6.0, bottom: 4.0, left: 6.0 })
+            .width(Length::Fill)
+            .into(),
+        );
+    }
+    column(items).spacing(2).into()
+}
+
+fn view_file_tree<'a>(tree: &'a FileTree, git_status: Option<&'a Statuses>, menu: FileMenu<'a>) -> Element<'a, Message> {
     let mut items: Vec<Element<'_, Message>> = Vec::new();
-    render_entries(&tree.entries, tree, 0, &mut items);
+    render_entries(&tree.entries, tree, git_status, menu, 0, &mut items);
     column(items).spacing(4).into()
 }
 
@@ -52,6 +221,8 @@ fn view_empty_sidebar<'a>() -> Element<'a, Message> {
 fn render_entries<'a>(
     entries: &'a [FileEntry],
     tree: &'a FileTree,
+    git_status: Option<&'a Statuses>,
+    menu: FileMenu<'a>,
     depth: usize,
     items: &mut Vec<Element<'a, Message>>,
 ) {
@@ -68,11 +239,17 @@ fn render_entries<'a>(
                     .height(Length::Fixed(ICON_SIZE))
                     .into();
 
+                // Folders with changes in them get a dot instead of a letter
+                let status = git_status.and_then(|statuses| statuses.folder(path));
+                let badge = status.and_then(FileStatus::badge).map(|_| '•');
+
                 let btn = button(
                     row![
                         container(text("")).width(Length::Fixed(indent_width)),
                         icon,
-                        text(name).size(13),
+                        text(name).size(13).color_maybe(status.map(status_color)),
+                        Space::new().width(Length::Fill),
+                        text(badge.map(String::from).unwrap_or_default()).size(11).color_maybe(status.map(status_color)),
                     ]
                     .spacing(6)
                     .align_y(iced::Alignment::Center)
@@ -85,7 +262,7 @@ fn render_entries<'a>(
                 items.push(btn.into());
 
                 if is_expanded {
-                    render_entries(children, tree, depth + 1, items);
+                    render_entries(children, tree, git_status, menu, depth + 1, items);
                 }
             }
             FileEntry::File { path, name } => {
@@ -96,11 +273,16 @@ fn render_entries<'a>(
                     .height(Length::Fixed(ICON_SIZE))
                     .into();
 
+                let status = git_status.and_then(|statuses| statuses.file(path));
+                let badge = status.and_then(FileStatus::badge);
+
                 let btn = button(
                     row![
                         container(text("")).width(Length::Fixed(indent_width)),
                         icon,
-                        text(name).size(13),
+                        text(name).size(13).color_maybe(status.map(status_color)),
+                        Space::new().width(Length::Fill),
+                        text(badge.map(String::from).unwrap_or_default()).size(11).color_maybe(status.map(status_color)),
                     ]
                     .spacing(6)
                     .align_y(iced::Alignment::Center)
@@ -110,8 +292,54 @@ fn render_entries<'a>(
                 .padding(iced::Padding { top: 6.0, right: 10.0, bottom: 6.0, left: 10.0 })
                 .width(Length::Fill);
 
-                items.push(btn.into());
+                let btn = mouse_area(btn).on_right_press(Message::FileMenuToggled(Some(path.clone())));
+                let is_open = menu.open == Some(path.as_path());
+                items.push(
+                    Dropdown::new(btn)
+                        .menu(is_open.then(|| file_menu(path, status, menu.compare)))
+                        .on_dismiss(Message::FileMenuToggled(None))
+                        .into(),
+                );
             }
         }
     }
 }
+
+/// What can be done with a file from its right-click menu.
+fn file_menu<'a>(path: &'a Path, status: Option<FileStatus>, compare: Option<&'a Path>) -> Element<'a, Message> {
+    let item = |label: String, message: Message| -> Element<'a, Message> {
+        button(text(label).size(12))
+            .style(file_finder_item_style(false))
+            .on_press(message)
+            .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
+            .width(Length::Fill)
+            .into()
+    };
+    let mut items = Vec::new();
+    // Ignored files have nothing in git to compare with
+    if status.is_some_and(|status| status != FileStatus::Ignored) {
+        items.push(item("Open Changes".to_string(), Message::OpenChanges(path.to_path_buf(), false)));
+    }
+    items.push(item("Compare with Saved".to_string(), Message::CompareWithSaved(path.to_path_buf())));
+    items.push(item("Select for Compare".to_string(), Message::SelectForCompare(path.to_path_buf())));
+    if let Some(selected) = compare.filter(|selected| *selected != path) {
+        let name = selected.file_name().unwrap_or_default().to_string_lossy();
+        items.push(item(format!("Compare with {}", name), Message::CompareWithSelected(path.to_path_buf())));
+    }
+    container(column(items).spacing(1).padding(4))
+        .width(Length::Fixed(220.0))
+        .style(file_finder_panel_style)
+        .into()
+}
+
+/// The colour a file's name is shown in for where it stands with git.
+pub fn status_color(status: FileStatus) -> Color {
+    match status {
+        FileStatus::Ignored => THEME.git_ignored,
+        FileStatus::Untracked => THEME.git_untracked,
+        FileStatus::Added => THEME.git_added,
+        FileStatus::Deleted => THEME.git_deleted,
+        FileStatus::Modified => THEME.git_modified,
+        FileStatus::Conflicted => THEME.git_conflicted,
+    }
+}
@@ -0,0 +1,234 @@
+use iced::keyboard::{key, Key};
+use iced::widget::text_editor::{self, Binding};
+use iced::widget::{button, checkbox, column, container, row, scrollable, text, text_editor as editor, text_input, Space};
+use iced::{Element, Length};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::git::{FileDiff, FileStatus, Hunk, Statuses};
+use crate::message::Message;
+use crate::theme::*;
+use crate::ui::dropdown::Dropdown;
+use crate::ui::sidebar::status_color;
+use crate::ui::styles::{
+    commit_message_style, file_finder_item_style, file_finder_panel_style, sidebar_input_style, tab_button_style,
+    tree_button_style,
+};
+
+/// The lines of each side of a hunk shown in the list before the rest are left out.
+const HUNK_PREVIEW_LINES: usize = 8;
+
+/// What the Source Control view shows, and what's been typed into it.
+pub struct SourceControlView<'a> {
+    pub root: Option<&'a Path>,
+    pub status: Option<&'a Statuses>,
+    pub commit_message: &'a text_editor::Content,
+    pub amend: bool,
+    /// The files opened up to show their hunks, staged or not
+    pub expanded: &'a HashMap<(PathBuf, bool), FileDiff>,
+    pub branch_menu: bool,
+    pub new_branch: Option<&'a str>,
+    pub new_branch_input_id: &'a iced::widget::Id,
+    pub error: Option<&'a str>,
+}
+
+/// The branch, the commit box, then the staged changes and the ones that aren't.
+pub fn view_source_control(scm: SourceControlView<'_>) -> Element<'_, Message> {
+    let Some(root) = scm.root else { return note("No folder open"); };
+    let Some(status) = scm.status else {
+        return column![
+            note("This folder isn't a git repository"),
+            small_button("Initialize Repository", Message::InitRepository),
+        ]
+        .spacing(6)
+        .into();
+    };
+
+    let head = status.head.as_deref().unwrap_or("(no branch)");
+    let anchor = button(text(format!("⎇ {}", head)).size(12).color(THEME.text_secondary))
+        .style(tree_button_style)
+        .on_press(Message::BranchMenuToggled)
+        .padding(iced::Padding { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 });
+    let branches = Dropdown::new(anchor)
+        .menu(scm.branch_menu.then(|| branch_menu(status)))
+        .on_dismiss(Message::BranchMenuToggled);
+    let mut content = column![branches].spacing(8);
+    if let Some(name) = scm.new_branch {
+        content = content.push(
+            text_input("New branch name", name)
+                .id(scm.new_branch_input_id.clone())
+                .on_input(Message::NewBranchNameChanged)
+                .on_submit(Message::CreateBranch)
+                .size(12)
+                .padding(6)
+                .style(sidebar_input_style),
+        );
+    }
+
+    let message = editor(scm.commit_message)
+        .placeholder("Message (Cmd+Enter to commit)")
+        .on_action(Message::CommitMessageEdited)
+        .key_binding(|key_press| match key_press.key.as_ref() {
+            Key::Named(key::Named::Enter) if key_press.modifiers.command() => Some(Binding::Custom(Message::Commit)),
+            _ => Binding::from_key_press(key_press),
+        })
+        .size(12)
+        .padding(6)
+        .min_height(60.0)
+        .style(commit_message_style);
+    let label = if scm.amend { "Amend" } else { "Commit" };
+    let actions = row![
+        checkbox(scm.amend).label("Amend last commit").on_toggle(Message::AmendToggled).size(12).text_size(11),
+        Space::new().width(Length::Fill),
+        small_button(label, Message::Commit),
+    ]
+    .align_y(iced::Alignment::Center);
+    content = content.push(message).push(actions);
+    if let Some(error) = scm.error {
+        content = content.push(text(error).size(11).color(THEME.error));
+    }
+
+    content = content.push(section("STAGED CHANGES", &status.staged, true, root, scm.expanded));
+    content.push(section("CHANGES", &status.unstaged, false, root, scm.expanded)).into()
+}
+
+fn branch_menu(status: &Statuses) -> Element<'_, Message> {
+    let mut items: Vec<Element<'_, Message>> = status
+        .branches
+        .iter()
+        .map(|branch| {
+            let is_current = status.head.as_ref() == Some(branch);
+            button(text(branch).size(12))
+                .style(file_finder_item_style(is_current))
+                .on_press(Message::BranchChosen(branch.clone()))
+                .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
+                .width(Length::Fill)
+                .into()
+        })
+        .collect();
+    items.push(
+        button(text("+ Create Branch...").size(12).color(THEME.text_muted))
+            .style(file_finder_item_style(false))
+            .on_press(Message::NewBranchStarted)
+            .padding(iced::Padding { top: 4.0, right: 10.0, bottom: 4.0, left: 10.0 })
+            .width(Length::Fill)
+            .into(),
+    );
+    container(scrollable(column(items).spacing(1).padding(4)))
+        .width(Length::Fixed(240.0))
+        .style(file_finder_panel_style)
+        .into()
+}
+
+/// The staged or unstaged files, with buttons for all of them in the header.
+fn section<'a>(
+    title: &'a str,
+    files: &'a [(PathBuf, FileStatus)],
+    staged: bool,
+    root: &'a Path,
+    expanded: &'a HashMap<(PathBuf, bool), FileDiff>,
+) -> Element<'a, Message> {
+    let all = match staged {
+        true => small_button("−", Message::UnstageAll),
+        false => small_button("+", Message::StageAll),
+    };
+    let header = row![
+        text(format!("{}  {}", title, files.len())).size(11).color(THEME.text_muted),
+        Space::new().width(Length::Fill),
+    ]
+    .push((!files.is_empty()).then_some(all))
+    .align_y(iced::Alignment::Center);
+
+    let mut items: Vec<Element<'a, Message>> = vec![header.into()];
+    for (path, status) in files {
+        let diff = expanded.get(&(path.clone(), staged));
+        items.push(file_row(path, *status, staged, root, diff.is_some()));
+        if let Some(diff) = diff {
+            // Hunks can only go in and out of the index when there's a version on both sides
+            let movable = *status == FileStatus::Modified;
+            for (index, hunk) in diff.hunks.iter().enumerate() {
+                items.push(hunk_view(path, diff, hunk, index, staged, movable));
+            }
+        }
+    }
+    column(items).spacing(2).into()
+}
+
+fn file_row<'a>(path: &'a Path, status: FileStatus, staged: bool, root: &Path, is_expanded: bool) -> Element<'a, Message> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let folder = path.parent().and_then(|parent| parent.strip_prefix(root).ok()).unwrap_or(Path::new(""));
+    let chevron = button(text(if is_expanded { "⌄" } else { "›" }).size(12).color(THEME.text_dim))
+        .style(tree_button_style)
+        .on_press(Message::FileDiffToggled(path.to_path_buf(), staged))
+        .padding(iced::Padding { top: 0.0, right: 4.0, bottom: 0.0, left: 4.0 });
+    let label = button(
+        row![
+            text(name).size(13).color(status_color(status)),
+            text(folder.to_string_lossy().into_owned()).size(11).color(THEME.text_dim),
+        ]
+        .spacing(6)
+        .align_y(iced::Alignment::Center),
+    )
+    .style(tree_button_style)
+    .on_press(Message::OpenChanges(path.to_path_buf(), staged))
+    .padding(iced::Padding { top: 4.0, right: 4.0, bottom: 4.0, left: 4.0 })
+    .width(Length::Fill);
+
+    let mut line = row![chevron, label].spacing(2).align_y(iced::Alignment::Center);
+    if status != FileStatus::Deleted {
+        line = line.push(small_button("↗", Message::FileClicked(path.to_path_buf())));
+    }
+    line = match staged {
+        true => line.push(small_button("−", Message::UnstageFile(path.to_path_buf()))),
+        false => line
+            .push(small_button("↺", Message::DiscardFile(path.to_path_buf())))
+            .push(small_button("+", Message::StageFile(path.to_path_buf()))),
+    };
+    let badge = status.badge().map(String::from).unwrap_or_default();
+    line.push(text(badge).size(11).color(status_color(status)).width(Length::Fixed(12.0))).into()
+}
+
+/// A hunk's lines before and after, under its file.
+fn hunk_view<'a>(path: &Path, diff: &'a FileDiff, hunk: &Hunk, index: usize, staged: bool, movable: bool) -> Element<'a, Message> {
+    let header = row![
+        text(format!(
+            "@@ -{},{} +{},{} @@",
+            hunk.base.start + 1,
+            hunk.base.len(),
+            hunk.current.start + 1,
+            hunk.current.len()
+        ))
+        .size(11)
+        .color(THEME.text_dim),
+        Space::new().width(Length::Fill),
+    ]
+    .push(movable.then(|| match staged {
+        true => small_button("−", Message::UnstageFileHunk(path.to_path_buf(), index)),
+        false => small_button("+", Message::StageFileHunk(path.to_path_buf(), index)),
+    }))
+    .align_y(iced::Alignment::Center);
+
+    let mut lines = column![header].spacing(1);
+    let sides = [(&diff.old.lines[hunk.base.clone()], '-', THEME.git_deleted), (&diff.new.lines[hunk.current.clone()], '+', THEME.git_added)];
+    for (side, sign, color) in sides {
+        for line in side.iter().take(HUNK_PREVIEW_LINES) {
+            lines = lines.push(text(format!("{} {}", sign, line.replace('\t', "    "))).size(11).color(color).wrapping(text::Wrapping::None));
+        }
+        if side.len() > HUNK_PREVIEW_LINES {
+            lines = lines.push(text(format!("  {} more", side.len() - HUNK_PREVIEW_LINES)).size(11).color(THEME.text_placeholder));
+        }
+    }
+    container(lines).padding(iced::Padding { top: 2.0, right: 0.0, bottom: 4.0, left: 24.0 }).into()
+}
+
+fn small_button(label: &str, message: Message) -> Element<'_, Message> {
+    button(text(label).size(11))
+        .style(tab_button_style(false))
+        .on_press(message)
+        .padding(iced::Padding { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 })
+        .into()
+}
+
+fn note(message: &str) -> Element<'_, Message> {
+    container(text(message).size(12).color(THEME.text_dim)).padding(6).into()
+}
@@ -5,6 +5,7 @@ use iced::border::Radius;
 use iced::{Background, Border, Color, Theme, Vector};
 
 use crate::theme::*;
+use crate::ui::code_editor;
 
 fn lighten(color: Color, amount: f32) -> Color {
     Color::from_rgba(
@@ -131,33 +132,45 @@ pub fn tab_bar_style(_theme: &Theme) -> container::Style {
     }
 }
 
-pub fn text_editor_style(_theme: &Theme, _status: text_editor::Status) -> text_editor::Style {
-    text_editor::Style {
+pub fn code_editor_style(_theme: &Theme, _status: text_editor::Status) -> code_editor::Style {
+    code_editor::Style {
         background: Background::Color(THEME.bg_editor),
-        border: Border {
-            color: Color::TRANSPARENT,
-            width: 0.0,
-            radius: 0.0.into(),
-        },
-        placeholder: THEME.text_placeholder,
-        value: THEME.text_primary,
+        gutter_background: Background::Color(THEME.bg_editor),
+        line_number: THEME.line_number,
+        active_line_number: THEME.line_number_active,
+        text: THEME.text_primary,
         selection: THEME.selection,
-    }
-}
-
-pub fn drag_handle_style(_theme: &Theme, status: ButtonStatus) -> ButtonStyle {
-    let background = match status {
-        ButtonStatus::Hovered => Some(Background::Color(THEME.bg_hover)),
-        ButtonStatus::Pressed => Some(Background::Color(THEME.bg_pressed)),
-        _ => Some(Background::Color(THEME.bg_drag_handle)),
-    };
-
-    ButtonStyle {
-        background,
-        text_color: Color::TRANSPARENT,
-        border: Border::default(),
-        shadow: Default::default(),
-        snap: false,
+        cursor: THEME.cursor,
+        current_line: THEME.line_highlight,
+        bracket_match: THEME.bracket_match,
+        bracket_match_border: THEME.bracket_match_border,
+        selection_highlight: THEME.selection_highlight,
+        word_highlight: THEME.word_highlight,
+        fold_placeholder: THEME.fold_background,
+        whitespace: THEME.whitespace,
+        indent_guide: THEME.indent_guide,
+        active_indent_guide: THEME.indent_guide_active,
+        snippet_tab_stop: THEME.snippet_tab_stop,
+        completion_background: THEME.suggest_background,
+        completion_border: THEME.suggest_border,
+        completion_text: THEME.suggest_foreground,
+        completion_match: THEME.suggest_highlight,
+        completion_selected: THEME.suggest_selected,
+        error: THEME.error,
+        warning: THEME.warning,
+        info: THEME.info,
+        hint: THEME.hint,
+        hover_background: THEME.hover_background,
+        hover_border: THEME.hover_border,
+        symbol_function: THEME.symbol_function,
+        symbol_variable: THEME.symbol_variable,
+        symbol_class: THEME.symbol_class,
+        inlay_hint: THEME.inlay_hint,
+        inlay_hint_background: THEME.inlay_hint_background,
+        line_added: THEME.gutter_added,
+        line_modified: THEME.gutter_modified,
+        line_deleted: THEME.gutter_deleted,
+        scrollbar: THEME.bg_hover,
     }
 }
 
@@ -198,6 +211,38 @@ pub fn search_input_style(_theme: &Theme, _status: iced::widget::text_input::Sta
     }
 }
 
+/// Inputs in the sidebar, with a border so they stand out from it.
+pub fn sidebar_input_style(_theme: &Theme, status: iced::widget::text_input::Status) -> iced::widget::text_input::Style {
+    let focused = matches!(status, iced::widget::text_input::Status::Focused { .. });
+    iced::widget::text_input::Style {
+        background: Background::Color(THEME.bg_hover),
+        border: Border {
+            color: if focused { THEME.suggest_highlight } else { THEME.border_subtle },
+            width: 1.0,
+            radius: 3.0.into(),
+        },
+        icon: THEME.text_dim,
+        placeholder: THEME.text_placeholder,
+        value: THEME.text_primary,
+        selection: THEME.selection,
+    }
+}
+
+pub fn commit_message_style(_theme: &Theme, status: text_editor::Status) -> text_editor::Style {
+    let focused = matches!(status, text_editor::Status::Focused { .. });
+    text_editor::Style {
+        background: Background::Color(THEME.bg_hover),
+        border: Border {
+            color: if focused { THEME.suggest_highlight } else { THEME.border_subtle },
+            width: 1.0,
+            radius: 3.0.into(),
+        },
+        placeholder: THEME.text_placeholder,
+        value: THEME.text_primary,
+        selection: THEME.selection,
+    }
+}
+
 pub fn file_finder_panel_style(_theme: &Theme) -> container::Style {
     container::Style {
         background: Some(Background::Color(Color::from_rgba(
@@ -0,0 +1,337 @@
+// Runs the language server client against this same binary, which plays a scripted server when
+// it's started with `--fake-server`.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/lsp/mod.rs"]
+mod lsp;
+
+use lsp::{Change, Event, Servers};
+use lsp_types::request::{Completion, HoverRequest};
+use lsp_types::{CompletionParams, HoverParams, Position, TextDocumentIdentifier, TextDocumentPositionParams, Url};
+use serde_json::{json, Value};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+const FAKE_SERVER_FLAG: &str = "--fake-server";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some(FAKE_SERVER_FLAG) {
+        fake_server(args.get(2).map(String::as_str).unwrap_or_default());
+        return;
+    }
+
+    let tests: &[(&str, fn())] = &[
+        ("syncs_incremental_changes", syncs_incremental_changes),
+        ("syncs_full_text_when_asked", syncs_full_text_when_asked),
+        ("answers_server_requests", answers_server_requests),
+        ("passes_on_edit_requests", passes_on_edit_requests),
+        ("matches_responses_to_requests", matches_responses_to_requests),
+        ("shares_servers_per_root", shares_servers_per_root),
+        ("remembers_servers_that_fail", remembers_servers_that_fail),
+        ("shuts_down", shuts_down),
+    ];
+    for (name, test) in tests {
+        print!("test {} ... ", name);
+        test();
+        println!("ok");
+    }
+}
+
+/// A connected set of servers and the events they send.
+struct Harness {
+    servers: Servers,
+    events: mpsc::Receiver<Event>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let (sender, events) = mpsc::channel();
+        let mut servers = Servers::default();
+        servers.connect(Arc::new(move |event| {
+            let _ = sender.send(event);
+        }));
+        Self { servers, events }
+    }
+
+    fn start(&mut self, mode: &str) -> lsp::ServerId {
+        let exe = std::env::current_exe().unwrap();
+        let args = vec![FAKE_SERVER_FLAG.to_string(), mode.to_string()];
+        self.servers.start(exe.to_str().unwrap(), &args, &std::env::temp_dir()).expect("fake server didn't start")
+    }
+
+    /// Waits for an event the servers also get to handle, like the app does.
+    fn next(&mut self) -> Event {
+        let event = self.events.recv_timeout(TIMEOUT).expect("no event from the fake server");
+        self.servers.handle(&event);
+        event
+    }
+
+    /// Waits for a notification with this method and returns its params.
+    fn notification(&mut self, expected: &str) -> Value {
+        loop {
+            match self.next() {
+                Event::Notification { method, params, .. } if method == expected => return params,
+                Event::Notification { method, params, .. } if method == "fake/error" => {
+                    panic!("fake server complained: {}", params)
+                }
+                Event::Exited { .. } => panic!("fake server exited while waiting for {}", expected),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn document_uri(name: &str) -> Url {
+    Url::from_file_path(std::env::temp_dir().join(name)).unwrap()
+}
+
+fn change(start: (usize, usize), end: (usize, usize), text: &str) -> Change {
+    Change { start, end, text: text.to_string() }
+}
+
+fn syncs_incremental_changes() {
+    let mut harness = Harness::new();
+    let server = harness.start("incremental");
+    let uri = document_uri("incremental.rs");
+    // Opened before the server is initialized, so it has to wait its turn
+    harness.servers.open(server, uri.clone(), "rust", 0, "fn main() {\n    println!(\"😀 hi\");\n}\n".to_string());
+    let opened = harness.notification("fake/text");
+    assert_eq!(opened["version"], 0);
+
+    let changes = vec![
+        // After the emoji, which is two UTF-16 code units
+        change((1, 16), (1, 18), "hello"),
+        change((0, 3), (0, 7), "start"),
+        change((1, 0), (2, 1), "}"),
+    ];
+    harness.servers.change(&uri, 3, changes, || panic!("incremental servers don't need the whole text"));
+    let changed = harness.notification("fake/text");
+    assert_eq!(changed["version"], 3);
+    assert_eq!(changed["text"], "fn start() {\n}\n");
+    assert_eq!(changed["ranged"], true);
+
+    harness.servers.close(&uri);
+    assert_eq!(harness.notification("fake/closed")["uri"], uri.as_str());
+    harness.servers.shutdown_all();
+}
+
+fn syncs_full_text_when_asked() {
+    let mut harness = Harness::new();
+    let server = harness.start("full");
+    let uri = document_uri("full.py");
+    harness.servers.open(server, uri.clone(), "python", 0, "print('a')\n".to_string());
+    harness.notification("fake/text");
+
+    harness.servers.change(&uri, 1, vec![change((0, 7), (0, 8), "b")], || "print('b')\n".to_string());
+    let changed = harness.notification("fake/text");
+    assert_eq!(changed["text"], "print('b')\n");
+    assert_eq!(changed["ranged"], false);
+    harness.servers.shutdown_all();
+}
+
+fn answers_server_requests() {
+    let mut harness = Harness::new();
+    harness.start("incremental");
+    let answers = harness.notification("fake/answers");
+    assert_eq!(answers["configuration"], json!([null, null]));
+    assert_eq!(answers["unknown"]["code"], -32601);
+    harness.servers.shutdown_all();
+}
+
+fn passes_on_edit_requests() {
+    let mut harness = Harness::new();
+    let server = harness.start("edit");
+    let id = loop {
+        match harness.next() {
+            Event::Request { id, method, params, .. } => {
+                assert_eq!(method, "workspace/applyEdit");
+                assert_eq!(params["label"], "fake edit");
+                break id;
+            }
+            Event::Exited { .. } => panic!("fake server exited before asking for an edit"),
+            _ => {}
+        }
+    };
+    harness.servers.get(server).unwrap().respond(id, json!({ "applied": true }));
+    assert_eq!(harness.notification("fake/edited")["applied"], true);
+    harness.servers.shutdown_all();
+}
+
+fn matches_responses_to_requests() {
+    let mut harness = Harness::new();
+    let server = harness.start("incremental");
+    let uri = document_uri("requests.rs");
+    harness.servers.open(server, uri.clone(), "rust", 0, String::new());
+    harness.notification("fake/text");
+
+    let position = TextDocumentPositionParams { text_document: TextDocumentIdentifier { uri }, position: Position::new(0, 0) };
+    let client = harness.servers.get_mut(server).unwrap();
+    let completion = client.request::<Completion>(CompletionParams {
+        text_document_position: position.clone(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    });
+    let hover = client.request::<HoverRequest>(HoverParams {
+        text_document_position_params: position,
+        work_done_progress_params: Default::default(),
+    });
+    assert_ne!(completion, hover);
+
+    let mut answered = 0;
+    while answered < 2 {
+        if let Event::Response { id, result, .. } = harness.next() {
+            answered += 1;
+            if id == completion {
+                assert_eq!(result.unwrap()[0]["label"], "fake");
+            } else {
+                assert_eq!(id, hover);
+                assert_eq!(result.unwrap_err().code, -32601);
+            }
+        }
+    }
+    harness.servers.shutdown_all();
+}
+
+fn shares_servers_per_root() {
+    let mut harness = Harness::new();
+    let first = harness.start("incremental");
+    assert_eq!(harness.start("incremental"), first);
+    let exe = std::env::current_exe().unwrap();
+    let other_root = exe.parent().unwrap().to_path_buf();
+    let args = vec![FAKE_SERVER_FLAG.to_string(), "incremental".to_string()];
+    let second = harness.servers.start(exe.to_str().unwrap(), &args, &other_root);
+    assert!(second.is_some_and(|second| second != first));
+    harness.servers.shutdown_all();
+}
+
+fn remembers_servers_that_fail() {
+    let mut harness = Harness::new();
+    let missing = PathBuf::from("/nonexistent/language-server");
+    let missing = missing.to_str().unwrap();
+    assert!(harness.servers.start(missing, &[], &std::env::temp_dir()).is_none());
+    assert!(harness.servers.start(missing, &[], &std::env::temp_dir()).is_none());
+}
+
+fn shuts_down() {
+    let mut harness = Harness::new();
+    let server = harness.start("incremental");
+    let uri = document_uri("shutdown.rs");
+    harness.servers.open(server, uri.clone(), "rust", 0, String::new());
+    harness.notification("fake/text");
+
+    harness.servers.shutdown_all();
+    loop {
+        if let Event::Exited { server: exited } = harness.next() {
+            assert_eq!(exited, server);
+            break;
+        }
+    }
+    assert!(harness.servers.server_for(&uri).is_none());
+}
+
+/// A language server that keeps the text of the documents it's given and sends it back after
+/// every change, so the client's side of the sync can be checked. In `full` mode it wants the
+/// whole text every time, and in `edit` mode it asks for a workspace edit once it's initialized.
+fn fake_server(mode: &str) {
+    let mut input = BufReader::new(io::stdin());
+    let mut output = io::stdout();
+    let mut send = |message: Value| lsp::transport::write_message(&mut output, &message).unwrap();
+    let mut initialized = false;
+    let mut text = String::new();
+    let mut answers = json!({});
+
+    while let Ok(Some(message)) = lsp::transport::read_message(&mut input) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        if !initialized && !matches!(method, "initialize" | "initialized" | "") {
+            send(json!({ "jsonrpc": "2.0", "method": "fake/error", "params": format!("{} before initialized", method) }));
+        }
+        match method {
+            "initialize" => {
+                let sync = if mode == "full" { 1 } else { 2 };
+                send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": { "capabilities": { "textDocumentSync": sync } } }));
+            }
+            "initialized" => {
+                initialized = true;
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "id": "configuration",
+                    "method": "workspace/configuration",
+                    "params": { "items": [{ "section": "a" }, { "section": "b" }] },
+                }));
+                send(json!({ "jsonrpc": "2.0", "id": "unknown", "method": "fake/unknown", "params": null }));
+                if mode == "edit" {
+                    let params = json!({ "label": "fake edit", "edit": { "changes": {} } });
+                    send(json!({ "jsonrpc": "2.0", "id": "edit", "method": "workspace/applyEdit", "params": params }));
+                }
+            }
+            "textDocument/didOpen" => {
+                text = params["textDocument"]["text"].as_str().unwrap_or_default().to_string();
+                send(text_notification(&text, &params["textDocument"]["version"], false));
+            }
+            "textDocument/didChange" => {
+                let mut ranged = false;
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let inserted = change["text"].as_str().unwrap_or_default();
+                    match change.get("range") {
+                        Some(range) => {
+                            ranged = true;
+                            let start = offset(&text, &range["start"]);
+                            let end = offset(&text, &range["end"]);
+                            text.replace_range(start..end, inserted);
+                        }
+                        None => text = inserted.to_string(),
+                    }
+                }
+                send(text_notification(&text, &params["textDocument"]["version"], ranged));
+            }
+            "textDocument/didClose" => {
+                send(json!({ "jsonrpc": "2.0", "method": "fake/closed", "params": { "uri": params["textDocument"]["uri"] } }));
+            }
+            "textDocument/completion" => {
+                send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": [{ "label": "fake" }] }));
+            }
+            "shutdown" => send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": null })),
+            "exit" => return,
+            // A reply to one of the requests sent after `initialized`
+            "" if message["id"] == "edit" => {
+                send(json!({ "jsonrpc": "2.0", "method": "fake/edited", "params": message["result"] }));
+            }
+            "" => {
+                let id = message["id"].as_str().unwrap_or_default().to_string();
+                answers[&id] = message.get("result").or(message.get("error")).cloned().unwrap_or(Value::Null);
+                if answers.as_object().is_some_and(|answers| answers.len() == 2) {
+                    send(json!({ "jsonrpc": "2.0", "method": "fake/answers", "params": answers }));
+                }
+            }
+            _ if message.get("id").is_some() => {
+                let error = json!({ "code": -32601, "message": format!("{} isn't faked", method) });
+                send(json!({ "jsonrpc": "2.0", "id": message["id"], "error": error }));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn text_notification(text: &str, version: &Value, ranged: bool) -> Value {
+    json!({ "jsonrpc": "2.0", "method": "fake/text", "params": { "text": text, "version": version, "ranged": ranged } })
+}
+
+/// The byte offset of an LSP position, whose columns count UTF-16 code units.
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let column = position["character"].as_u64().unwrap_or_default() as usize;
+    let line_start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (index, c) in text[line_start..].char_indices() {
+        if units >= column || c == '\n' {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}